@@ -0,0 +1,234 @@
+use bevy_ecs::prelude::*;
+use regex::Regex;
+
+use super::{parser, CommandError, CommandRegistry, Invocation};
+use crate::{input::data::RowBuffer, options::Options, search, window::View};
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register("align", 2, align);
+}
+
+/// A row split on every delimiter, `fields` has one more entry than `delimiters`.
+struct Split<'a> {
+    fields: Vec<&'a str>,
+    delimiters: Vec<&'a str>,
+}
+
+fn split<'a>(contents: &'a str, regex: &Regex) -> Split<'a> {
+    let mut split = Split {
+        fields: Vec::new(),
+        delimiters: Vec::new(),
+    };
+    let mut start = 0;
+
+    for found in regex.find_iter(contents).filter(|it| !it.is_empty()) {
+        split.fields.push(&contents[start..found.start()]);
+        split.delimiters.push(found.as_str());
+        start = found.end();
+    }
+
+    split.fields.push(&contents[start..]);
+    split
+}
+
+/// A field without the whitespace around it, the first field keeps its indentation.
+fn trim_field(field: &str, index: usize) -> &str {
+    match (index, field.trim().is_empty()) {
+        (0, true) => field,
+        (0, false) => field.trim_end(),
+        _ => field.trim(),
+    }
+}
+
+/// `:[range]align {delimiter}` or `:[range]align /pattern/` lines up every delimiter in the rows
+/// of the range, the current row without a range. The whitespace around the delimiters is
+/// replaced with a single space and the padding to line them up. A delimiter that doesn't have
+/// whitespace in front of it in any row, like the `:` in `key: value`, sticks to the text before
+/// it, and the text after it is lined up instead.
+fn align(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let args = invocation.args.trim();
+
+    // a pattern is enclosed in delimiters, anything else like `=` or `=>` is a literal delimiter
+    let pattern = match args.chars().next() {
+        Some(delimiter)
+            if !delimiter.is_alphanumeric()
+                && args.len() > delimiter.len_utf8()
+                && args.ends_with(delimiter) =>
+        {
+            match parser::split_pattern(&args[delimiter.len_utf8()..], delimiter) {
+                (pattern, "") => Some(pattern),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    let regex = match pattern {
+        Some(pattern) if !pattern.is_empty() => {
+            search::compile(&pattern, world.resource::<Options>())?
+        }
+        _ if args.is_empty() => return Err(CommandError::InvalidArgument(String::from("align"))),
+        _ => Regex::new(&regex::escape(args))
+            .map_err(|_| CommandError::InvalidPattern(args.to_string()))?,
+    };
+
+    let range = invocation.range_or_current(world);
+    let position = &View::active(world).position;
+    let cursor = (position.x, position.y);
+    let rows = world.resource::<RowBuffer>();
+
+    if range.end >= rows.rows_count() {
+        return Err(CommandError::InvalidRange);
+    }
+
+    let contents: Vec<&str> = range
+        .rows()
+        .map(|y| rows.get_row_at(y).contents())
+        .collect();
+    let aligned = aligned(&contents, &regex);
+
+    let mut rows = world.resource_mut::<RowBuffer>();
+    let mut changed = false;
+
+    for (y, contents) in range.rows().zip(aligned) {
+        let Some(contents) = contents else {
+            continue;
+        };
+
+        if rows.get_row_at(y).contents() == contents {
+            continue;
+        }
+
+        if !changed {
+            rows.begin_change(cursor);
+            changed = true;
+        }
+
+        rows.set_row(y, &contents);
+    }
+
+    Ok(())
+}
+
+/// The rows of `contents` with every match of `regex` lined up, `None` for a row without a
+/// match, which is left alone.
+fn aligned(contents: &[&str], regex: &Regex) -> Vec<Option<String>> {
+    let splits: Vec<Split> = contents.iter().map(|it| split(it, regex)).collect();
+
+    let columns = splits
+        .iter()
+        .map(|it| it.delimiters.len())
+        .max()
+        .unwrap_or(0);
+    let mut sticky = vec![true; columns];
+    let mut widths = vec![0; columns];
+
+    for split in &splits {
+        let fields = &split.fields[..split.delimiters.len()];
+
+        for (field, sticky) in fields.iter().zip(&mut sticky) {
+            *sticky &= !field.trim().is_empty() && !field.ends_with(char::is_whitespace);
+        }
+    }
+
+    for split in &splits {
+        for (index, delimiter) in split.delimiters.iter().enumerate() {
+            let width = trim_field(split.fields[index], index).chars().count();
+
+            widths[index] = widths[index].max(match sticky[index] {
+                true => width + delimiter.chars().count(),
+                false => width,
+            });
+        }
+    }
+
+    splits
+        .iter()
+        .map(|split| {
+            // rows without a delimiter are left alone
+            if split.delimiters.is_empty() {
+                return None;
+            }
+
+            let mut row = String::new();
+
+            for (index, delimiter) in split.delimiters.iter().enumerate() {
+                let field = trim_field(split.fields[index], index);
+                let width = widths[index];
+
+                match sticky[index] {
+                    true => row.push_str(&format!("{:width$}", format!("{field}{delimiter}"))),
+                    false => {
+                        row.push_str(&format!("{field:width$}"));
+
+                        if !row.trim().is_empty() {
+                            row.push(' ');
+                        }
+
+                        row.push_str(delimiter);
+                    }
+                }
+
+                row.push(' ');
+            }
+
+            let last = split.fields.len() - 1;
+            row.push_str(trim_field(split.fields[last], last));
+
+            Some(row.trim_end().to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn align(rows: &[&str], delimiter: &str) -> Vec<Option<String>> {
+        aligned(rows, &Regex::new(&regex::escape(delimiter)).unwrap())
+    }
+
+    fn some(rows: &[&str]) -> Vec<Option<String>> {
+        rows.iter().map(|it| Some(it.to_string())).collect()
+    }
+
+    #[test]
+    fn lines_up_delimiters() {
+        assert_eq!(
+            align(&["  a = 1", "  long=2", "  bc  =  3"], "="),
+            some(&["  a    = 1", "  long = 2", "  bc   = 3"])
+        );
+    }
+
+    #[test]
+    fn sticky_delimiters_line_up_the_text_after_them() {
+        assert_eq!(
+            align(&["a: 1", "long:2"], ":"),
+            some(&["a:    1", "long: 2"])
+        );
+        assert_eq!(
+            align(&["a: 1", "long :2"], ":"),
+            some(&["a    : 1", "long : 2"])
+        );
+    }
+
+    #[test]
+    fn lines_up_every_column() {
+        assert_eq!(
+            align(&["a | bb | c", "ccc | d | e"], "|"),
+            some(&["a   | bb | c", "ccc | d  | e"])
+        );
+    }
+
+    #[test]
+    fn leaves_rows_without_a_delimiter_alone() {
+        assert_eq!(
+            align(&["a = 1", "none", "bb = 2"], "="),
+            [
+                Some(String::from("a  = 1")),
+                None,
+                Some(String::from("bb = 2"))
+            ]
+        );
+    }
+}
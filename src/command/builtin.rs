@@ -0,0 +1,287 @@
+use std::path::PathBuf;
+
+use bevy_ecs::{
+    event::Events,
+    world::{Mut, World},
+};
+
+use super::{parser, parser::LineRange, CommandError, CommandRegistry, Invocation};
+use crate::{
+    cursor::multi::Selection,
+    edit::{self, operator::Operator},
+    input::data::RowBuffer,
+    input::QuitWriter,
+    options::Options,
+    status::StatusBuffer,
+    window::{self, View, Windows},
+};
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register("quit", 1, quit);
+    registry.register("write", 1, write);
+    registry.register("wq", 2, write_quit);
+    registry.register("xit", 1, write_quit);
+    registry.register("delete", 1, delete);
+    registry.register("copy", 2, copy);
+    registry.register("t", 1, copy);
+    registry.register("move", 1, move_rows);
+    registry.register("print", 1, print);
+    registry.register("join", 1, join);
+    registry.register("=", 1, reindent);
+}
+
+fn quit(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    if invocation.range.is_some() {
+        return Err(CommandError::NoRangeAllowed);
+    }
+
+    // with more than a single window only the active one is closed, the buffer is still shown
+    // in the others.
+    if world.resource::<Windows>().count() > 1 {
+        return window::close_window(world, invocation.bang);
+    }
+
+    if !invocation.bang && world.resource::<RowBuffer>().is_modified() {
+        return Err(CommandError::Unsaved);
+    }
+
+    world.resource_mut::<Events<QuitWriter>>().send_default();
+    Ok(())
+}
+
+fn write(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let mut rows = world.resource_mut::<RowBuffer>();
+
+    let path = match invocation.arguments().as_slice() {
+        [] => rows
+            .get_path()
+            .map(PathBuf::from)
+            .ok_or(CommandError::NoFileName)?,
+        [path] => PathBuf::from(path),
+        [_, rest @ ..] => return Err(CommandError::TrailingCharacters(rest.join(" "))),
+    };
+
+    // without a bang, existing files other than the buffer's own file are never overwritten.
+    if !invocation.bang && path.exists() && rows.get_path() != Some(path.as_path()) {
+        return Err(CommandError::InvalidArgument(format!(
+            "File exists (add ! to override): {}",
+            path.display()
+        )));
+    }
+
+    let written = rows.write_to(&path)?;
+    let message = format!(
+        "\"{}\" {}L, {}B written",
+        path.display(),
+        rows.rows_count(),
+        written
+    );
+
+    world.resource_mut::<StatusBuffer>().info(message);
+    Ok(())
+}
+
+fn write_quit(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    write(world, invocation)?;
+    quit(
+        world,
+        &Invocation {
+            range: None,
+            ..invocation.clone()
+        },
+    )
+}
+
+/// The range of a line command, which defaults to the row the cursor is on.
+fn line_range(world: &World, invocation: &Invocation) -> Result<LineRange, CommandError> {
+    let range = invocation.range_or_current(world);
+
+    // only an empty buffer has no row under the cursor.
+    match range.end < world.resource::<RowBuffer>().rows_count() {
+        true => Ok(range),
+        false => Err(CommandError::InvalidRange),
+    }
+}
+
+/// Like [line_range], but followed by `count` rows from its last row when the command is given a
+/// count like `:d 3`.
+fn counted_range(world: &World, invocation: &Invocation) -> Result<LineRange, CommandError> {
+    let range = line_range(world, invocation)?;
+    let count = invocation.args.trim();
+
+    if count.is_empty() {
+        return Ok(range);
+    }
+
+    let count = count
+        .parse()
+        .ok()
+        .filter(|it| *it > 0)
+        .ok_or_else(|| CommandError::TrailingCharacters(count.to_string()))?;
+
+    Ok(range.with_count(count, world.resource::<RowBuffer>().rows_count()))
+}
+
+/// The 1-based destination line of `:t` and `:m`, the rows are put below it.
+fn destination(world: &World, invocation: &Invocation) -> Result<usize, CommandError> {
+    let address = parser::parse_standalone_address(&invocation.args)?
+        .ok_or_else(|| CommandError::InvalidArgument(invocation.args.clone()))?;
+
+    super::resolve_address(world, &address)
+}
+
+fn cursor(world: &World) -> (usize, usize) {
+    let position = &View::active(world).position;
+    (position.x, position.y)
+}
+
+/// Shows how many rows were added or removed, if it's more than a couple.
+pub(super) fn report_rows(world: &mut World, count: usize, kind: &str) {
+    if count > 2 {
+        world
+            .resource_mut::<StatusBuffer>()
+            .info(format!("{count} {kind} lines"));
+    }
+}
+
+/// `:[range]d [count]` removes rows, the buffer always keeps at least a single empty row.
+fn delete(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let range = counted_range(world, invocation)?;
+    let cursor = cursor(world);
+    let mut rows = world.resource_mut::<RowBuffer>();
+
+    rows.begin_change(cursor);
+
+    for _ in range.rows() {
+        rows.remove_row(range.start);
+    }
+
+    if rows.rows_count() == 0 {
+        rows.insert_row(0, "");
+    }
+
+    super::jump_to_row(world, range.start);
+    report_rows(world, range.end - range.start + 1, "fewer");
+
+    Ok(())
+}
+
+/// `:[range]t {address}` and `:[range]co {address}` put a copy of the rows below `address`.
+fn copy(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let range = line_range(world, invocation)?;
+    let destination = destination(world, invocation)?;
+    let cursor = cursor(world);
+    let mut rows = world.resource_mut::<RowBuffer>();
+
+    let copied: Vec<String> = range
+        .rows()
+        .map(|y| rows.get_row_at(y).contents().to_string())
+        .collect();
+
+    rows.begin_change(cursor);
+
+    for (index, contents) in copied.iter().enumerate() {
+        rows.insert_row(destination + index, contents);
+    }
+
+    super::jump_to_row(world, destination + copied.len() - 1);
+    report_rows(world, copied.len(), "more");
+
+    Ok(())
+}
+
+/// `:[range]m {address}` moves the rows below `address`, which can't be inside of the range.
+fn move_rows(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let range = line_range(world, invocation)?;
+    let destination = destination(world, invocation)?;
+
+    if destination > range.start && destination <= range.end {
+        return Err(CommandError::MoveIntoItself);
+    }
+
+    let cursor = cursor(world);
+    let mut rows = world.resource_mut::<RowBuffer>();
+    let count = range.end - range.start + 1;
+
+    rows.begin_change(cursor);
+
+    // the rows above the destination moved up by the amount of rows that were taken out.
+    let target = match destination > range.end {
+        true => destination - count,
+        false => destination,
+    };
+
+    rows.move_rows(range.start, range.end, target);
+
+    super::jump_to_row(world, target + count - 1);
+
+    if count > 2 {
+        world
+            .resource_mut::<StatusBuffer>()
+            .info(format!("{count} lines moved"));
+    }
+
+    Ok(())
+}
+
+/// `:[range]j[oin][!]` joins the rows of the range into one, the current row and the one below
+/// it without a range. With a bang, the rows are joined as-is like `gJ`, otherwise like `J`.
+fn join(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let range = line_range(world, invocation)?;
+    let cursor = cursor(world);
+    let mut rows = world.resource_mut::<RowBuffer>();
+
+    if range.start + 1 >= rows.rows_count() {
+        return Ok(());
+    }
+
+    rows.begin_change(cursor);
+    edit::rows::join(
+        &mut rows,
+        range.start,
+        range.end - range.start + 1,
+        !invocation.bang,
+    );
+
+    super::jump_to_row(world, range.start);
+
+    Ok(())
+}
+
+/// `:[range]=` reindents the rows of the range, like `=` does in normal mode.
+fn reindent(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let range = counted_range(world, invocation)?;
+    let (x, y) = cursor(world);
+
+    world
+        .resource_scope(|world, mut rows: Mut<RowBuffer>| {
+            edit::operate(
+                &mut rows,
+                &mut [Selection::at(x, y)],
+                Operator::Reindent,
+                &[range],
+                (x, y),
+                world.resource::<Options>(),
+            )
+        })
+        .map_err(|error| CommandError::InvalidArgument(error.to_string()))?;
+
+    super::jump_to_row(world, range.start);
+
+    Ok(())
+}
+
+/// `:[range]p` shows the last row of the range and moves the cursor to it.
+fn print(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let range = counted_range(world, invocation)?;
+    let contents = world
+        .resource::<RowBuffer>()
+        .get_row_at(range.end)
+        .contents()
+        .to_string();
+
+    super::jump_to_row(world, range.end);
+    world.resource_mut::<StatusBuffer>().info(contents);
+
+    Ok(())
+}
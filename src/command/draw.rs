@@ -0,0 +1,19 @@
+use bevy_ecs::system::{Res, ResMut};
+
+use super::line::CommandLine;
+use crate::output::{buffer::OutputBuffer, OutputSize};
+
+pub fn draw_command_line(
+    command_line: Res<CommandLine>,
+    size: Res<OutputSize>,
+    mut buffer: ResMut<OutputBuffer>,
+) {
+    let line: String = std::iter::once(command_line.kind().prompt())
+        .chain(command_line.content().chars())
+        .skip(command_line.scroll(size.columns))
+        .take(size.columns)
+        .collect();
+
+    buffer.move_to(0, size.text_rows());
+    buffer.push_str(&line);
+}
@@ -0,0 +1,202 @@
+use bevy_ecs::prelude::*;
+
+use super::{parser, CommandError, CommandRegistry, Invocation};
+use crate::{
+    input::{data::RowBuffer, InputMode},
+    options::Options,
+    search::{self, SearchState},
+    window::View,
+};
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register("global", 1, global);
+    registry.register("vglobal", 1, vglobal);
+}
+
+/// `:[range]g/pattern/cmd` runs `cmd` on every row matching `pattern`, `:g!` runs it on every
+/// row that doesn't match instead.
+fn global(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    run(world, invocation, invocation.bang)
+}
+
+/// `:[range]v/pattern/cmd`, the same as `:g!`.
+fn vglobal(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    run(world, invocation, true)
+}
+
+fn is_global(name: &str) -> bool {
+    !name.is_empty() && ("global".starts_with(name) || "vglobal".starts_with(name))
+}
+
+fn run(world: &mut World, invocation: &Invocation, invert: bool) -> Result<(), CommandError> {
+    let args = invocation.args.as_str();
+
+    let delimiter = args
+        .chars()
+        .next()
+        .filter(|it| !it.is_alphanumeric() && !it.is_whitespace() && !"\\\"|".contains(*it))
+        .ok_or_else(|| CommandError::InvalidArgument(args.to_string()))?;
+
+    let (pattern, command) = parser::split_pattern(&args[delimiter.len_utf8()..], delimiter);
+
+    // like vim, every matching row is printed if no command is given.
+    let command = match command.trim() {
+        "" => "p",
+        command => command,
+    };
+
+    if is_global(&parser::parse(command)?.name) {
+        return Err(CommandError::GlobalRecursive);
+    }
+
+    let pattern = match (
+        pattern.is_empty(),
+        world.resource::<SearchState>().pattern(),
+    ) {
+        (true, Some(last)) => last.to_string(),
+        (true, None) => return Err(CommandError::NoPreviousPattern),
+        (false, _) => pattern,
+    };
+
+    let regex = search::compile(&pattern, world.resource::<Options>())?;
+
+    world.resource_scope(|world, mut search: Mut<SearchState>| {
+        search.set_pattern(&pattern, world.resource::<Options>())
+    })?;
+
+    let range = invocation.range_or_whole(world);
+    let rows = world.resource::<RowBuffer>();
+
+    // every row is marked before running anything, so rows the command adds are never visited.
+    let marked: Vec<usize> = range
+        .rows()
+        .take_while(|y| *y < rows.rows_count())
+        .filter(|y| regex.is_match(rows.get_row_at(*y).contents()) != invert)
+        .collect();
+
+    if marked.is_empty() {
+        return Err(match invert {
+            true => CommandError::PatternFoundEverywhere(pattern),
+            false => CommandError::PatternNotFound(pattern),
+        });
+    }
+
+    let count = marked.len();
+    let cursor = View::active(world).position.clone();
+
+    let mut rows = world.resource_mut::<RowBuffer>();
+    rows.track_rows(marked);
+    rows.begin_group((cursor.x, cursor.y));
+
+    // like vim, a row the command fails on doesn't stop the other rows from being visited. A
+    // pattern the command can't find is only reported if it failed on every row.
+    let mut succeeded = false;
+    let mut not_found = None;
+    let mut error = None;
+
+    for index in 0..count {
+        // rows removed by the command for an earlier row are skipped.
+        let Some(y) = world.resource::<RowBuffer>().tracked_row(index) else {
+            continue;
+        };
+
+        // a substitution asking for confirmation can't be continued from here, so it stops at
+        // the first row it asks about.
+        if *world.resource::<InputMode>() == InputMode::Confirm {
+            break;
+        }
+
+        super::jump_to_row(world, y);
+
+        match super::run(world, command) {
+            Ok(()) => succeeded = true,
+            Err(CommandError::PatternNotFound(pattern)) => {
+                not_found.get_or_insert(CommandError::PatternNotFound(pattern));
+            }
+            Err(other) => {
+                error.get_or_insert(other);
+            }
+        }
+    }
+
+    let result = match (error, succeeded, not_found) {
+        (Some(error), _, _) => Err(error),
+        (None, false, Some(not_found)) => Err(not_found),
+        _ => Ok(()),
+    };
+
+    let mut rows = world.resource_mut::<RowBuffer>();
+    rows.end_group();
+    rows.clear_tracked();
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        command::{self, builtin, CommandRegistry},
+        status::StatusBuffer,
+        window,
+    };
+
+    fn world(rows: &[&str]) -> World {
+        let mut world = World::new();
+        let mut buffer = RowBuffer::new();
+        let mut registry = CommandRegistry::default();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        builtin::register(&mut registry);
+        register(&mut registry);
+
+        world.insert_resource(buffer);
+        world.insert_resource(registry);
+        window::init(&mut world, &mut Schedule::default());
+        world.insert_resource(Options::default());
+        world.insert_resource(SearchState::default());
+        world.insert_resource(StatusBuffer::default());
+        world.insert_resource(InputMode::Normal);
+
+        world
+    }
+
+    fn contents(world: &World) -> Vec<String> {
+        let rows = world.resource::<RowBuffer>();
+
+        (0..rows.rows_count())
+            .map(|y| rows.get_row_at(y).contents().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn runs_the_command_on_every_matching_row_as_one_undo_step() {
+        let before = ["fn main() {", "x();", "x();", "y();", "}"];
+        let mut world = world(&before);
+
+        command::run(&mut world, "g/x/=").unwrap();
+
+        assert_eq!(
+            contents(&world),
+            ["fn main() {", "    x();", "    x();", "y();", "}"]
+        );
+
+        let mut rows = world.resource_mut::<RowBuffer>();
+        assert_eq!(rows.undo_count(), 1);
+        rows.undo((0, 0));
+
+        assert_eq!(contents(&world), before);
+    }
+
+    #[test]
+    fn inverts_the_match() {
+        let mut world = world(&["fn main() {", "y();", "x();", "}"]);
+
+        command::run(&mut world, "v/^[fx}]/=").unwrap();
+
+        assert_eq!(contents(&world), ["fn main() {", "    y();", "x();", "}"]);
+    }
+}
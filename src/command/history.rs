@@ -0,0 +1,125 @@
+const HISTORY_SIZE: usize = 100;
+
+/// Previously submitted command lines. While browsing, only entries starting with whatever was
+/// typed before browsing started are shown.
+#[derive(Clone, Default)]
+pub struct History {
+    entries: Vec<String>,
+    /// The index into `entries` that is currently shown, `None` when not browsing.
+    index: Option<usize>,
+    /// The text typed before browsing started, restored once browsing past the newest entry.
+    prefix: String,
+}
+
+impl History {
+    pub fn push(&mut self, entry: &str) {
+        self.reset();
+
+        if entry.is_empty() {
+            return;
+        }
+
+        // the same entry is only kept once, at its most recent position.
+        self.entries.retain(|it| it != entry);
+        self.entries.push(entry.to_string());
+
+        if self.entries.len() > HISTORY_SIZE {
+            self.entries.remove(0);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.index = None;
+        self.prefix.clear();
+    }
+
+    /// Moves to the next older entry matching the prefix, `current` becomes the prefix if
+    /// browsing hasn't started yet.
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        if self.index.is_none() {
+            self.prefix = current.to_string();
+        }
+
+        let end = self.index.unwrap_or(self.entries.len());
+        let found = self.entries[..end]
+            .iter()
+            .rposition(|it| it.starts_with(&self.prefix))?;
+
+        self.index = Some(found);
+        Some(&self.entries[found])
+    }
+
+    /// Moves to the next newer entry matching the prefix, going past the newest entry restores
+    /// the prefix itself.
+    pub fn newer(&mut self) -> Option<&str> {
+        let start = self.index? + 1;
+
+        match self.entries[start..]
+            .iter()
+            .position(|it| it.starts_with(&self.prefix))
+        {
+            Some(found) => {
+                self.index = Some(start + found);
+                Some(&self.entries[start + found])
+            }
+            None => {
+                self.index = None;
+                Some(&self.prefix)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(entries: &[&str]) -> History {
+        let mut history = History::default();
+
+        for entry in entries {
+            history.push(entry);
+        }
+
+        history
+    }
+
+    #[test]
+    fn browses_entries_starting_with_the_prefix() {
+        let mut history = history(&["set wrap", "sort", "s/a/b/", "set nowrap"]);
+
+        assert_eq!(history.older("se"), Some("set nowrap"));
+        assert_eq!(history.older("ignored"), Some("set wrap"));
+        assert_eq!(history.older(""), None);
+        assert_eq!(history.newer(), Some("set nowrap"));
+        assert_eq!(history.newer(), Some("se"));
+        assert_eq!(history.newer(), None);
+    }
+
+    #[test]
+    fn an_empty_prefix_browses_every_entry() {
+        let mut history = history(&["a", "b"]);
+
+        assert_eq!(history.older(""), Some("b"));
+        assert_eq!(history.older(""), Some("a"));
+        assert_eq!(history.newer(), Some("b"));
+    }
+
+    #[test]
+    fn keeps_an_entry_once_at_its_latest_position() {
+        let mut history = history(&["a", "b", "a", ""]);
+
+        assert_eq!(history.older(""), Some("a"));
+        assert_eq!(history.older(""), Some("b"));
+        assert_eq!(history.older(""), None);
+    }
+
+    #[test]
+    fn pushing_stops_browsing() {
+        let mut history = history(&["sort", "set"]);
+
+        assert_eq!(history.older("so"), Some("sort"));
+        history.push("s");
+        assert_eq!(history.older("se"), Some("set"));
+    }
+}
@@ -0,0 +1,206 @@
+use bevy_ecs::system::Resource;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use super::history::History;
+use crate::input::InputMode;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum CommandKind {
+    #[default]
+    Ex,
+    Search {
+        backward: bool,
+    },
+}
+
+impl CommandKind {
+    pub fn prompt(&self) -> char {
+        match self {
+            CommandKind::Ex => ':',
+            CommandKind::Search { backward: false } => '/',
+            CommandKind::Search { backward: true } => '?',
+        }
+    }
+}
+
+pub enum LineAction {
+    Submit(CommandKind, String),
+    Cancel,
+}
+
+/// The line being typed after pressing `:`, `/` or `?`, drawn in place of the status bar.
+#[derive(Resource)]
+pub struct CommandLine {
+    kind: CommandKind,
+    /// The mode to go back to once the line is submitted or cancelled.
+    origin: InputMode,
+    content: String,
+    /// A byte offset into `content`.
+    cursor: usize,
+    ex_history: History,
+    search_history: History,
+}
+
+impl Default for CommandLine {
+    fn default() -> Self {
+        Self {
+            kind: CommandKind::Ex,
+            origin: InputMode::Normal,
+            content: String::new(),
+            cursor: 0,
+            ex_history: History::default(),
+            search_history: History::default(),
+        }
+    }
+}
+
+impl CommandLine {
+    pub fn open(&mut self, kind: CommandKind, initial: &str, origin: InputMode) {
+        self.kind = kind;
+        self.origin = origin;
+        self.content = initial.to_string();
+        self.cursor = self.content.len();
+        self.history_mut().reset();
+    }
+
+    pub fn kind(&self) -> CommandKind {
+        self.kind
+    }
+
+    pub fn origin(&self) -> InputMode {
+        self.origin
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// The column the cursor is drawn at, including the prompt.
+    pub fn cursor_column(&self) -> usize {
+        1 + self.content[..self.cursor].chars().count()
+    }
+
+    /// The amount of columns the line is scrolled by, so the cursor always stays visible.
+    pub fn scroll(&self, columns: usize) -> usize {
+        (self.cursor_column() + 1).saturating_sub(columns)
+    }
+
+    fn history_mut(&mut self) -> &mut History {
+        match self.kind {
+            CommandKind::Ex => &mut self.ex_history,
+            CommandKind::Search { .. } => &mut self.search_history,
+        }
+    }
+
+    /// Applies a single key to the line, returns an action once the line is either submitted or
+    /// cancelled.
+    pub fn handle_key(&mut self, event: KeyEvent) -> Option<LineAction> {
+        match (event.code, event.modifiers) {
+            (KeyCode::Enter, _) => {
+                let content = std::mem::take(&mut self.content);
+                self.history_mut().push(&content);
+
+                return Some(LineAction::Submit(self.kind, content));
+            }
+            (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                self.history_mut().reset();
+                return Some(LineAction::Cancel);
+            }
+            // removing a character from an empty line leaves the command line, like vim does.
+            (KeyCode::Backspace, _) | (KeyCode::Char('h'), KeyModifiers::CONTROL)
+                if self.content.is_empty() =>
+            {
+                self.history_mut().reset();
+                return Some(LineAction::Cancel);
+            }
+            (KeyCode::Up, _) => {
+                let current = self.content.clone();
+
+                if let Some(entry) = self.history_mut().older(&current).map(String::from) {
+                    self.set_content(entry);
+                }
+
+                return None;
+            }
+            (KeyCode::Down, _) => {
+                if let Some(entry) = self.history_mut().newer().map(String::from) {
+                    self.set_content(entry);
+                }
+
+                return None;
+            }
+            (KeyCode::Backspace, _) | (KeyCode::Char('h'), KeyModifiers::CONTROL) => {
+                if let Some(previous) = self.previous_boundary() {
+                    self.content.replace_range(previous..self.cursor, "");
+                    self.cursor = previous;
+                }
+            }
+            (KeyCode::Delete, _) => {
+                if let Some(next) = self.next_boundary() {
+                    self.content.replace_range(self.cursor..next, "");
+                }
+            }
+            (KeyCode::Left, _) => self.cursor = self.previous_boundary().unwrap_or(self.cursor),
+            (KeyCode::Right, _) => self.cursor = self.next_boundary().unwrap_or(self.cursor),
+            (KeyCode::Home, _) | (KeyCode::Char('b'), KeyModifiers::CONTROL) => self.cursor = 0,
+            (KeyCode::End, _) | (KeyCode::Char('e'), KeyModifiers::CONTROL) => {
+                self.cursor = self.content.len()
+            }
+            (KeyCode::Char('u'), KeyModifiers::CONTROL) => {
+                self.content.replace_range(..self.cursor, "");
+                self.cursor = 0;
+            }
+            (KeyCode::Char('w'), KeyModifiers::CONTROL) => {
+                let start = self.word_start();
+
+                self.content.replace_range(start..self.cursor, "");
+                self.cursor = start;
+            }
+            (KeyCode::Char(char), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                self.content.insert(self.cursor, char);
+                self.cursor += char.len_utf8();
+            }
+            _ => return None,
+        }
+
+        // editing the line starts browsing the history from scratch.
+        self.history_mut().reset();
+        None
+    }
+
+    fn set_content(&mut self, content: String) {
+        self.content = content;
+        self.cursor = self.content.len();
+    }
+
+    fn previous_boundary(&self) -> Option<usize> {
+        self.content[..self.cursor]
+            .char_indices()
+            .last()
+            .map(|(index, _)| index)
+    }
+
+    fn next_boundary(&self) -> Option<usize> {
+        self.content[self.cursor..]
+            .chars()
+            .next()
+            .map(|char| self.cursor + char.len_utf8())
+    }
+
+    /// The start of the word before the cursor, used by `Ctrl-W`.
+    fn word_start(&self) -> usize {
+        let before = &self.content[..self.cursor];
+        let trimmed = before.trim_end();
+
+        let is_word = |char: char| char.is_alphanumeric() || char == '_';
+        let word = trimmed.chars().last().is_some_and(is_word);
+
+        trimmed
+            .char_indices()
+            .rev()
+            .take_while(|(_, char)| !char.is_whitespace() && is_word(*char) == word)
+            .last()
+            .map(|(index, _)| index)
+            .unwrap_or(trimmed.len())
+    }
+}
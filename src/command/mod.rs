@@ -0,0 +1,263 @@
+use std::fmt::Display;
+
+use bevy_ecs::prelude::*;
+
+use crate::{
+    cursor::{update::clamp_to_row, CursorPosition},
+    input::{self, data::RowBuffer},
+    options::Options,
+    output,
+    search::SearchState,
+    status::StatusBuffer,
+    window::View,
+    SystemType,
+};
+
+use self::{
+    line::CommandLine,
+    parser::{Address, AddressContext, LineRange},
+};
+
+pub mod align;
+pub mod builtin;
+pub mod draw;
+pub mod global;
+pub mod history;
+pub mod line;
+pub mod parser;
+pub mod sort;
+
+pub fn init(world: &mut World, schedule: &mut Schedule) {
+    let mut registry = CommandRegistry::default();
+    builtin::register(&mut registry);
+    global::register(&mut registry);
+    sort::register(&mut registry);
+    align::register(&mut registry);
+
+    world.insert_resource(registry);
+    world.insert_resource(CommandLine::default());
+    world.insert_resource(Events::<CommandEvent>::default());
+
+    schedule.add_systems(
+        draw::draw_command_line
+            .after(output::draw_rows)
+            .run_if(input::in_command_mode)
+            .in_set(SystemType::Output),
+    );
+
+    // commands can move the cursor, so they have to run before the cursor systems get to it.
+    schedule.add_systems(execute.after(SystemType::Input).before(SystemType::Cursor));
+}
+
+/// Sent once a command line has been submitted with enter.
+#[derive(Event)]
+pub struct CommandEvent {
+    pub(crate) line: String,
+}
+
+/// The arguments a [CommandHandler] gets invoked with.
+#[derive(Clone)]
+pub struct Invocation {
+    pub(crate) range: Option<LineRange>,
+    pub(crate) bang: bool,
+    pub(crate) args: String,
+}
+
+impl Invocation {
+    pub fn range_or_current(&self, world: &World) -> LineRange {
+        self.range
+            .unwrap_or_else(|| LineRange::single(View::active(world).position.y))
+    }
+
+    pub fn range_or_whole(&self, world: &World) -> LineRange {
+        self.range.unwrap_or(LineRange {
+            start: 0,
+            end: world.resource::<RowBuffer>().rows_count().saturating_sub(1),
+        })
+    }
+
+    pub fn arguments(&self) -> Vec<String> {
+        parser::split_arguments(&self.args)
+    }
+}
+
+pub type CommandHandler = fn(&mut World, &Invocation) -> Result<(), CommandError>;
+
+struct CommandEntry {
+    name: &'static str,
+    abbreviation: usize,
+    handler: CommandHandler,
+}
+
+/// All Ex commands that can be invoked from the command line. Subsystems register their own
+/// commands in their `init` function.
+#[derive(Resource, Default)]
+pub struct CommandRegistry {
+    entries: Vec<CommandEntry>,
+}
+
+impl CommandRegistry {
+    /// Registers `handler` under `name`, it can be invoked by any prefix of `name` that is at
+    /// least `abbreviation` characters long, e.g. `substitute` with an abbreviation of 1 can be
+    /// invoked with `:s`, `:sub` and `:substitute`.
+    pub fn register(&mut self, name: &'static str, abbreviation: usize, handler: CommandHandler) {
+        self.entries.push(CommandEntry {
+            name,
+            abbreviation,
+            handler,
+        });
+    }
+
+    pub fn find(&self, name: &str) -> Option<CommandHandler> {
+        self.entries
+            .iter()
+            .find(|it| it.name == name)
+            .or_else(|| {
+                self.entries
+                    .iter()
+                    .find(|it| name.len() >= it.abbreviation && it.name.starts_with(name))
+            })
+            .map(|it| it.handler)
+    }
+}
+
+#[derive(Debug)]
+pub enum CommandError {
+    NotACommand(String),
+    InvalidRange,
+    NoRangeAllowed,
+    MarkNotSet(char),
+    InvalidPattern(String),
+    PatternNotFound(String),
+    PatternFoundEverywhere(String),
+    NoPreviousPattern,
+    NoPreviousSubstitute,
+    TrailingCharacters(String),
+    InvalidArgument(String),
+    NoFileName,
+    Unsaved,
+    GlobalRecursive,
+    MoveIntoItself,
+    LastWindow,
+    LastTab,
+    Io(std::io::Error),
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::NotACommand(name) => write!(f, "Not an editor command: {name}"),
+            CommandError::InvalidRange => write!(f, "Invalid range"),
+            CommandError::NoRangeAllowed => write!(f, "No range allowed"),
+            CommandError::MarkNotSet(name) => write!(f, "Mark not set: {name}"),
+            CommandError::InvalidPattern(pattern) => write!(f, "Invalid pattern: {pattern}"),
+            CommandError::PatternNotFound(pattern) => write!(f, "Pattern not found: {pattern}"),
+            CommandError::PatternFoundEverywhere(pattern) => {
+                write!(f, "Pattern found in every line: {pattern}")
+            }
+            CommandError::NoPreviousPattern => write!(f, "No previous regular expression"),
+            CommandError::NoPreviousSubstitute => {
+                write!(f, "No previous substitute regular expression")
+            }
+            CommandError::TrailingCharacters(rest) => write!(f, "Trailing characters: {rest}"),
+            CommandError::InvalidArgument(argument) => write!(f, "Invalid argument: {argument}"),
+            CommandError::NoFileName => write!(f, "No file name"),
+            CommandError::Unsaved => write!(f, "No write since last change (add ! to override)"),
+            CommandError::GlobalRecursive => write!(f, "Cannot do :global recursive"),
+            CommandError::MoveIntoItself => write!(f, "Move lines into themselves"),
+            CommandError::LastWindow => write!(f, "Cannot close last window"),
+            CommandError::LastTab => write!(f, "Cannot close last tab page"),
+            CommandError::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for CommandError {
+    fn from(value: std::io::Error) -> Self {
+        CommandError::Io(value)
+    }
+}
+
+/// Runs every command line submitted since the last run, errors are shown in the status bar.
+pub fn execute(world: &mut World) {
+    let events: Vec<CommandEvent> = world
+        .resource_mut::<Events<CommandEvent>>()
+        .drain()
+        .collect();
+
+    for CommandEvent { line } in events {
+        if let Err(error) = run(world, &line) {
+            world
+                .resource_mut::<StatusBuffer>()
+                .error(error.to_string());
+        }
+    }
+}
+
+/// Parses and runs a single command line.
+pub fn run(world: &mut World, line: &str) -> Result<(), CommandError> {
+    let parsed = parser::parse(line)?;
+
+    let range = match &parsed.range {
+        Some(range) => Some(range.resolve(&address_context(world))?),
+        None => None,
+    };
+
+    // a command consisting of only a range jumps to the last line of it.
+    if parsed.name.is_empty() {
+        if let Some(range) = range {
+            jump_to_row(world, range.end);
+        }
+
+        return Ok(());
+    }
+
+    let handler = world
+        .resource::<CommandRegistry>()
+        .find(&parsed.name)
+        .ok_or_else(|| CommandError::NotACommand(parsed.name.clone()))?;
+
+    handler(
+        world,
+        &Invocation {
+            range,
+            bang: parsed.bang,
+            args: parsed.args,
+        },
+    )
+}
+
+fn address_context(world: &World) -> AddressContext<'_> {
+    AddressContext {
+        rows: world.resource::<RowBuffer>(),
+        cursor: View::active(world).position.y,
+        last_pattern: world.resource::<SearchState>().pattern(),
+        options: world.resource::<Options>(),
+    }
+}
+
+/// Resolves an address given as an argument, e.g. the destination of `:t` and `:m`, to a 1-based
+/// line number where `0` means "before the first line".
+pub fn resolve_address(world: &World, address: &Address) -> Result<usize, CommandError> {
+    address.resolve(&address_context(world))
+}
+
+/// Moves the cursor to the first non-blank character of `row`.
+pub fn jump_to_row(world: &mut World, row: usize) {
+    let rows = world.resource::<RowBuffer>();
+    let y = row.min(rows.rows_count().saturating_sub(1));
+
+    let mut position = CursorPosition {
+        x: match y < rows.rows_count() {
+            true => {
+                let contents = rows.get_row_at(y).contents();
+                contents.len() - contents.trim_start().len()
+            }
+            false => 0,
+        },
+        y,
+    };
+
+    clamp_to_row(&mut position, rows);
+    View::active_mut(world).position = position;
+}
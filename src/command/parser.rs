@@ -0,0 +1,656 @@
+use super::CommandError;
+use crate::{input::data::RowBuffer, options::Options, search};
+
+/// A single Ex command line, split into its range, name, bang and arguments.
+///
+/// `:'<,'>s/foo/bar/g` is parsed into a range of `'<` to `'>`, the name `s` and the arguments
+/// `/foo/bar/g`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsedCommand {
+    pub(crate) range: Option<RangeSpec>,
+    pub(crate) name: String,
+    pub(crate) bang: bool,
+    pub(crate) args: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AddressBase {
+    /// `.`, also used when an address only consists of an offset.
+    Current,
+    /// `$`
+    Last,
+    /// A 1-based line number.
+    Line(usize),
+    /// `'x`
+    Mark(char),
+    /// `/pattern/` or `?pattern?`
+    Search { pattern: String, backward: bool },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Address {
+    pub(crate) base: AddressBase,
+    pub(crate) offset: isize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RangeSpec {
+    /// `%`
+    Whole,
+    Single(Address),
+    /// `start,end`, or `start;end` when `relative` is set, which moves the cursor to `start`
+    /// before `end` is resolved.
+    Pair {
+        start: Address,
+        end: Address,
+        relative: bool,
+    },
+}
+
+/// A resolved range of rows, 0-based and inclusive on both ends.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LineRange {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+}
+
+impl LineRange {
+    pub fn single(line: usize) -> Self {
+        Self {
+            start: line,
+            end: line,
+        }
+    }
+
+    pub fn contains(&self, line: usize) -> bool {
+        (self.start..=self.end).contains(&line)
+    }
+
+    pub fn rows(&self) -> std::ops::RangeInclusive<usize> {
+        self.start..=self.end
+    }
+
+    /// The range of `count` rows starting at the last row of this range, like `:d 3` or `:s/a/b/ 3`
+    /// use it. It never goes past the last of `rows`.
+    pub fn with_count(&self, count: usize, rows: usize) -> Self {
+        Self {
+            start: self.end,
+            end: self
+                .end
+                .saturating_add(count - 1)
+                .min(rows.saturating_sub(1)),
+        }
+    }
+}
+
+/// Everything an address needs to be resolved to a line number.
+pub struct AddressContext<'a> {
+    pub(crate) rows: &'a RowBuffer,
+    /// The 0-based row the cursor is on.
+    pub(crate) cursor: usize,
+    /// The last search pattern, used by the empty `//` and `??` addresses.
+    pub(crate) last_pattern: Option<&'a str>,
+    pub(crate) options: &'a Options,
+}
+
+impl Address {
+    pub fn current() -> Self {
+        Self {
+            base: AddressBase::Current,
+            offset: 0,
+        }
+    }
+
+    /// Resolves the address to a 1-based line number, where `0` means "before the first line".
+    pub fn resolve(&self, context: &AddressContext) -> Result<usize, CommandError> {
+        let count = context.rows.rows_count();
+
+        let base = match &self.base {
+            AddressBase::Current => context.cursor + 1,
+            AddressBase::Last => count,
+            AddressBase::Line(line) => *line,
+            AddressBase::Mark(name) => match context.rows.get_mark(*name) {
+                Some(mark) => mark.y + 1,
+                None => return Err(CommandError::MarkNotSet(*name)),
+            },
+            AddressBase::Search { pattern, backward } => {
+                search_line(context, pattern, *backward)? + 1
+            }
+        };
+
+        isize::try_from(base)
+            .ok()
+            .and_then(|it| it.checked_add(self.offset))
+            .and_then(|it| usize::try_from(it).ok())
+            .filter(|it| *it <= count)
+            .ok_or(CommandError::InvalidRange)
+    }
+}
+
+impl RangeSpec {
+    pub fn resolve(&self, context: &AddressContext) -> Result<LineRange, CommandError> {
+        let count = context.rows.rows_count();
+
+        if count == 0 {
+            return Err(CommandError::InvalidRange);
+        }
+
+        // most commands treat line 0 as the first line.
+        let to_row = |line: usize| line.max(1) - 1;
+
+        let (start, end) = match self {
+            RangeSpec::Whole => (0, count - 1),
+            RangeSpec::Single(address) => {
+                let line = to_row(address.resolve(context)?);
+                (line, line)
+            }
+            RangeSpec::Pair {
+                start,
+                end,
+                relative,
+            } => {
+                let start = to_row(start.resolve(context)?);
+
+                let end = if *relative {
+                    end.resolve(&AddressContext {
+                        cursor: start,
+                        ..*context
+                    })?
+                } else {
+                    end.resolve(context)?
+                };
+
+                (start, to_row(end))
+            }
+        };
+
+        Ok(LineRange {
+            start: start.min(end),
+            end: start.max(end),
+        })
+    }
+}
+
+fn search_line(
+    context: &AddressContext,
+    pattern: &str,
+    backward: bool,
+) -> Result<usize, CommandError> {
+    let pattern = match (pattern.is_empty(), context.last_pattern) {
+        (true, Some(last)) => last,
+        (true, None) => return Err(CommandError::NoPreviousPattern),
+        (false, _) => pattern,
+    };
+
+    let regex = search::compile(pattern, context.options)?;
+    let count = context.rows.rows_count();
+
+    // searching starts on the line after (or before) the cursor, and wraps around the buffer.
+    (1..=count)
+        .map(|distance| match backward {
+            true => (context.cursor + count - distance) % count,
+            false => (context.cursor + distance) % count,
+        })
+        .find(|&row| regex.is_match(context.rows.get_row_at(row).contents()))
+        .ok_or_else(|| CommandError::PatternNotFound(pattern.into()))
+}
+
+/// A small cursor over the command line, used by the parsing functions.
+struct Scanner<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let current = self.peek()?;
+        self.position += current.len_utf8();
+        Some(current)
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        let matches = self.peek() == Some(expected);
+
+        if matches {
+            self.bump();
+        }
+
+        matches
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.position;
+
+        while self.peek().is_some_and(|it| it.is_ascii_digit()) {
+            self.bump();
+        }
+
+        self.input[start..self.position].parse().ok()
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+}
+
+/// Parses a full command line, e.g. `'<,'>s/foo/bar/g` or `%sort! n`.
+pub fn parse(line: &str) -> Result<ParsedCommand, CommandError> {
+    let mut scanner = Scanner {
+        input: line,
+        position: 0,
+    };
+
+    // like vim, any amount of leading colons and whitespace are ignored.
+    while scanner
+        .peek()
+        .is_some_and(|it| it == ':' || it.is_whitespace())
+    {
+        scanner.bump();
+    }
+
+    let range = parse_range(&mut scanner)?;
+    scanner.skip_whitespace();
+
+    let name = match scanner.peek() {
+        Some(current) if current.is_ascii_alphabetic() => {
+            let start = scanner.position;
+
+            while scanner.peek().is_some_and(|it| it.is_ascii_alphabetic()) {
+                scanner.bump();
+            }
+
+            scanner.input[start..scanner.position].to_string()
+        }
+        Some(current @ ('!' | '&' | '<' | '>' | '=' | '#' | '~')) => {
+            scanner.bump();
+            current.to_string()
+        }
+        Some(_) => return Err(CommandError::NotACommand(scanner.rest().into())),
+        None => String::new(),
+    };
+
+    let bang = name != "!" && !name.is_empty() && scanner.eat('!');
+
+    Ok(ParsedCommand {
+        range,
+        name,
+        bang,
+        args: scanner.rest().trim_start().to_string(),
+    })
+}
+
+fn parse_range(scanner: &mut Scanner) -> Result<Option<RangeSpec>, CommandError> {
+    if scanner.eat('%') {
+        return Ok(Some(RangeSpec::Whole));
+    }
+
+    let start = parse_address(scanner)?;
+    scanner.skip_whitespace();
+
+    let relative = match scanner.peek() {
+        Some(',') => false,
+        Some(';') => true,
+        _ => return Ok(start.map(RangeSpec::Single)),
+    };
+
+    scanner.bump();
+    scanner.skip_whitespace();
+
+    let end = parse_address(scanner)?;
+
+    Ok(Some(RangeSpec::Pair {
+        start: start.unwrap_or_else(Address::current),
+        end: end.unwrap_or_else(Address::current),
+        relative,
+    }))
+}
+
+/// Parses a single address with its offsets, returns `None` if there is no address at all.
+fn parse_address(scanner: &mut Scanner) -> Result<Option<Address>, CommandError> {
+    let base = match scanner.peek() {
+        Some(current) if current.is_ascii_digit() => {
+            AddressBase::Line(scanner.number().ok_or(CommandError::InvalidRange)?)
+        }
+        Some('.') => {
+            scanner.bump();
+            AddressBase::Current
+        }
+        Some('$') => {
+            scanner.bump();
+            AddressBase::Last
+        }
+        Some('\'') => {
+            scanner.bump();
+            AddressBase::Mark(scanner.bump().ok_or(CommandError::InvalidRange)?)
+        }
+        Some(delimiter @ ('/' | '?')) => {
+            scanner.bump();
+
+            AddressBase::Search {
+                pattern: parse_pattern(scanner, delimiter),
+                backward: delimiter == '?',
+            }
+        }
+        // an address consisting of only an offset is relative to the current line
+        Some('+' | '-') => AddressBase::Current,
+        _ => return Ok(None),
+    };
+
+    let mut offset: isize = 0;
+
+    loop {
+        match scanner.peek() {
+            Some(sign @ ('+' | '-')) => {
+                scanner.bump();
+
+                let amount = parse_offset(scanner)?;
+                let amount = match sign {
+                    '+' => Some(amount),
+                    _ => amount.checked_neg(),
+                };
+
+                offset = amount
+                    .and_then(|it| offset.checked_add(it))
+                    .ok_or(CommandError::InvalidRange)?;
+            }
+            // `.5` is the same as `.+5`
+            Some(current) if current.is_ascii_digit() => {
+                offset = offset
+                    .checked_add(parse_offset(scanner)?)
+                    .ok_or(CommandError::InvalidRange)?;
+            }
+            _ => break,
+        }
+    }
+
+    Ok(Some(Address { base, offset }))
+}
+
+/// Reads the number of an offset, a sign without a number counts as `1`.
+fn parse_offset(scanner: &mut Scanner) -> Result<isize, CommandError> {
+    if !scanner.peek().is_some_and(|it| it.is_ascii_digit()) {
+        return Ok(1);
+    }
+
+    scanner
+        .number()
+        .and_then(|it| isize::try_from(it).ok())
+        .ok_or(CommandError::InvalidRange)
+}
+
+/// Reads a pattern up until an unescaped `delimiter`, or the end of the input. Escaped delimiters
+/// are unescaped, every other escape is kept as-is for the regex.
+fn parse_pattern(scanner: &mut Scanner, delimiter: char) -> String {
+    let mut pattern = String::new();
+
+    while let Some(current) = scanner.bump() {
+        match current {
+            '\\' if scanner.peek() == Some(delimiter) => {
+                scanner.bump();
+                pattern.push(delimiter);
+            }
+            '\\' => {
+                pattern.push('\\');
+
+                if let Some(escaped) = scanner.bump() {
+                    pattern.push(escaped);
+                }
+            }
+            current if current == delimiter => break,
+            current => pattern.push(current),
+        }
+    }
+
+    pattern
+}
+
+/// Splits `input` at the first unescaped `delimiter`, returning the unescaped pattern before it and
+/// whatever comes after it, e.g. `foo\/bar/e+1` is split into `foo/bar` and `e+1`.
+pub fn split_pattern(input: &str, delimiter: char) -> (String, &str) {
+    let mut scanner = Scanner { input, position: 0 };
+    let pattern = parse_pattern(&mut scanner, delimiter);
+
+    (pattern, scanner.rest())
+}
+
+/// Parses a standalone address, as used in the arguments of commands like `:move` and `:copy`.
+pub fn parse_standalone_address(input: &str) -> Result<Option<Address>, CommandError> {
+    let mut scanner = Scanner {
+        input: input.trim(),
+        position: 0,
+    };
+
+    let address = parse_address(&mut scanner)?;
+
+    match scanner.rest().is_empty() {
+        true => Ok(address),
+        false => Err(CommandError::TrailingCharacters(scanner.rest().into())),
+    }
+}
+
+/// Splits the arguments of a command on whitespace, a backslash can be used to escape whitespace
+/// that should be part of an argument.
+pub fn split_arguments(args: &str) -> Vec<String> {
+    let mut arguments = Vec::new();
+    let mut current = String::new();
+    let mut chars = args.chars();
+
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => current.extend(chars.next()),
+            char if char.is_whitespace() => {
+                if !current.is_empty() {
+                    arguments.push(std::mem::take(&mut current));
+                }
+            }
+            char => current.push(char),
+        }
+    }
+
+    if !current.is_empty() {
+        arguments.push(current);
+    }
+
+    arguments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(rows: &[&str]) -> RowBuffer {
+        let mut buffer = RowBuffer::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        buffer
+    }
+
+    fn line(base: AddressBase, offset: isize) -> Address {
+        Address { base, offset }
+    }
+
+    /// Resolves the range of `command` in a buffer of `rows`, with the cursor on row 1.
+    fn resolve(rows: &RowBuffer, command: &str) -> Result<LineRange, CommandError> {
+        let options = Options::default();
+        let context = AddressContext {
+            rows,
+            cursor: 1,
+            last_pattern: None,
+            options: &options,
+        };
+
+        parse(command)?
+            .range
+            .expect("The command has no range")
+            .resolve(&context)
+    }
+
+    #[test]
+    fn parses_name_bang_and_arguments() {
+        let parsed = parse(":: sort! n").unwrap();
+
+        assert_eq!(parsed.range, None);
+        assert_eq!(parsed.name, "sort");
+        assert!(parsed.bang);
+        assert_eq!(parsed.args, "n");
+
+        let parsed = parse("!ls -a").unwrap();
+        assert_eq!(parsed.name, "!");
+        assert!(!parsed.bang);
+        assert_eq!(parsed.args, "ls -a");
+
+        assert!(matches!(parse("1,2*"), Err(CommandError::NotACommand(_))));
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse("%d").unwrap().range, Some(RangeSpec::Whole));
+        assert_eq!(
+            parse(".,$s/a/b/").unwrap().range,
+            Some(RangeSpec::Pair {
+                start: line(AddressBase::Current, 0),
+                end: line(AddressBase::Last, 0),
+                relative: false,
+            })
+        );
+        assert_eq!(
+            parse("'<,'>s/foo/bar/g").unwrap(),
+            ParsedCommand {
+                range: Some(RangeSpec::Pair {
+                    start: line(AddressBase::Mark('<'), 0),
+                    end: line(AddressBase::Mark('>'), 0),
+                    relative: false,
+                }),
+                name: String::from("s"),
+                bang: false,
+                args: String::from("/foo/bar/g"),
+            }
+        );
+        assert_eq!(
+            parse("/a\\/b/;+2d").unwrap().range,
+            Some(RangeSpec::Pair {
+                start: line(
+                    AddressBase::Search {
+                        pattern: String::from("a/b"),
+                        backward: false,
+                    },
+                    0
+                ),
+                end: line(AddressBase::Current, 2),
+                relative: true,
+            })
+        );
+    }
+
+    #[test]
+    fn parses_offsets() {
+        let address = |command| match parse(command).unwrap().range {
+            Some(RangeSpec::Single(address)) => address,
+            range => panic!("Not a single address: {range:?}"),
+        };
+
+        assert_eq!(address("3+2-d"), line(AddressBase::Line(3), 1));
+        assert_eq!(address(".5d"), line(AddressBase::Current, 5));
+        assert_eq!(address("--d"), line(AddressBase::Current, -2));
+        assert_eq!(address("$-1d"), line(AddressBase::Last, -1));
+        assert_eq!(
+            address(&format!("-{}d", isize::MAX)),
+            line(AddressBase::Current, -isize::MAX)
+        );
+    }
+
+    #[test]
+    fn offsets_too_large_are_invalid() {
+        let invalid = |command: &str| matches!(parse(command), Err(CommandError::InvalidRange));
+
+        assert!(invalid(".+9223372036854775807+1d"));
+        assert!(invalid("$-9223372036854775807-2d"));
+        assert!(invalid(".9223372036854775808d"));
+        assert!(invalid(".+99999999999999999999d"));
+        assert!(invalid("99999999999999999999d"));
+
+        let rows = buffer(&["a", "b"]);
+
+        assert!(matches!(
+            resolve(&rows, "$+9223372036854775807d"),
+            Err(CommandError::InvalidRange)
+        ));
+        assert!(matches!(
+            resolve(&rows, "18446744073709551615d"),
+            Err(CommandError::InvalidRange)
+        ));
+    }
+
+    #[test]
+    fn resolves_ranges() {
+        let mut rows = buffer(&["a", "b", "c", "d", "e"]);
+        rows.set_mark('<', 0, 3);
+        rows.set_mark('>', 0, 1);
+
+        assert_eq!(resolve(&rows, ".d").ok(), Some(LineRange::single(1)));
+        assert_eq!(resolve(&rows, "$d").ok(), Some(LineRange::single(4)));
+        assert_eq!(
+            resolve(&rows, "%d").ok(),
+            Some(LineRange { start: 0, end: 4 })
+        );
+        assert_eq!(
+            resolve(&rows, "'<,'>d").ok(),
+            Some(LineRange { start: 1, end: 3 })
+        );
+        assert_eq!(
+            resolve(&rows, ".+1,$-1d").ok(),
+            Some(LineRange { start: 2, end: 3 })
+        );
+        assert_eq!(resolve(&rows, "/d/d").ok(), Some(LineRange::single(3)));
+        assert_eq!(resolve(&rows, "?a?d").ok(), Some(LineRange::single(0)));
+        assert_eq!(
+            resolve(&rows, "/b/;+1d").ok(),
+            Some(LineRange { start: 1, end: 2 })
+        );
+        assert!(matches!(
+            resolve(&rows, "$+1d"),
+            Err(CommandError::InvalidRange)
+        ));
+        assert!(matches!(
+            resolve(&rows, "0-1d"),
+            Err(CommandError::InvalidRange)
+        ));
+        assert!(matches!(
+            resolve(&rows, "'xd"),
+            Err(CommandError::MarkNotSet('x'))
+        ));
+    }
+
+    #[test]
+    fn counts_rows_from_the_end_of_a_range() {
+        let range = LineRange { start: 1, end: 2 };
+
+        assert_eq!(range.with_count(1, 5), LineRange::single(2));
+        assert_eq!(range.with_count(2, 5), LineRange { start: 2, end: 3 });
+        assert_eq!(range.with_count(10, 5), LineRange { start: 2, end: 4 });
+        assert_eq!(
+            range.with_count(usize::MAX, 5),
+            LineRange { start: 2, end: 4 }
+        );
+    }
+
+    #[test]
+    fn splits_arguments_on_unescaped_whitespace() {
+        assert_eq!(split_arguments("  a b\tc "), ["a", "b", "c"]);
+        assert_eq!(split_arguments(r"a\ b c\\"), ["a b", r"c\"]);
+        assert!(split_arguments("   ").is_empty());
+    }
+}
@@ -0,0 +1,254 @@
+use std::cmp::Ordering;
+
+use bevy_ecs::prelude::*;
+use regex::Regex;
+
+use super::{builtin, parser, CommandError, CommandRegistry, Invocation};
+use crate::{
+    input::data::RowBuffer,
+    options::Options,
+    search::{self, SearchState},
+    window::View,
+};
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register("sort", 3, sort);
+}
+
+#[derive(Default)]
+struct SortArgs {
+    numeric: bool,
+    ignore_case: bool,
+    unique: bool,
+    /// Sort on the text matching the pattern, instead of the text after it.
+    on_match: bool,
+    pattern: Option<String>,
+}
+
+/// Parses flags like `nu` and an optional `/pattern/`, in any order.
+fn parse_args(args: &str) -> Result<SortArgs, CommandError> {
+    let mut parsed = SortArgs::default();
+    let mut rest = args;
+
+    while let Some(char) = rest.chars().next() {
+        rest = &rest[char.len_utf8()..];
+
+        match char {
+            'n' => parsed.numeric = true,
+            'i' => parsed.ignore_case = true,
+            'u' => parsed.unique = true,
+            'r' => parsed.on_match = true,
+            char if char.is_whitespace() => {}
+            char if char.is_alphanumeric() || "\\\"|".contains(char) => {
+                return Err(CommandError::InvalidArgument(args.to_string()))
+            }
+            delimiter => {
+                let (pattern, after) = parser::split_pattern(rest, delimiter);
+                parsed.pattern = Some(pattern);
+                rest = after;
+            }
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// What a row is sorted on.
+enum Key {
+    Text(String),
+    /// Rows without a number sort before every other row.
+    Number(Option<i64>),
+}
+
+impl Key {
+    fn new(text: &str, args: &SortArgs) -> Self {
+        match (args.numeric, args.ignore_case) {
+            (true, _) => Key::Number(first_number(text)),
+            (false, true) => Key::Text(text.to_lowercase()),
+            (false, false) => Key::Text(text.to_string()),
+        }
+    }
+
+    fn compare(&self, other: &Key) -> Ordering {
+        match (self, other) {
+            (Key::Text(left), Key::Text(right)) => left.cmp(right),
+            (Key::Number(left), Key::Number(right)) => left.cmp(right),
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+/// The first decimal number in `text`, along with its sign.
+fn first_number(text: &str) -> Option<i64> {
+    let start = text.find(|it: char| it.is_ascii_digit())?;
+    let digits = text[start..]
+        .find(|it: char| !it.is_ascii_digit())
+        .map_or(&text[start..], |end| &text[start..start + end]);
+
+    let number: i64 = digits.parse().unwrap_or(i64::MAX);
+
+    match text[..start].ends_with('-') {
+        true => Some(-number),
+        false => Some(number),
+    }
+}
+
+/// `:[range]sort[!] [n][i][u][r] [/pattern/]` sorts the rows, the whole buffer without a range.
+/// With a bang the order is reversed. `n` sorts on the first number in a row, `i` ignores case
+/// and `u` only keeps the first of rows that are equal. With a pattern, rows are sorted on what
+/// comes after its match, or on the match itself with `r`. Rows that don't match keep their
+/// order and go before the sorted ones, or after them when reversed.
+fn sort(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let args = parse_args(&invocation.args)?;
+
+    let regex = match args.pattern.as_deref() {
+        Some("") => {
+            let last = world
+                .resource::<SearchState>()
+                .pattern()
+                .ok_or(CommandError::NoPreviousPattern)?;
+
+            Some(search::compile(last, world.resource::<Options>())?)
+        }
+        Some(pattern) => Some(search::compile(pattern, world.resource::<Options>())?),
+        None => None,
+    };
+
+    let range = invocation.range_or_whole(world);
+    let position = &View::active(world).position;
+    let cursor = (position.x, position.y);
+    let rows = world.resource::<RowBuffer>();
+
+    if range.end >= rows.rows_count() {
+        return Err(CommandError::InvalidRange);
+    }
+
+    let contents = range
+        .rows()
+        .map(|y| rows.get_row_at(y).contents().to_string());
+    let sorted = sorted(contents, &args, regex.as_ref(), invocation.bang);
+
+    let mut rows = world.resource_mut::<RowBuffer>();
+    let removed = range.end - range.start + 1 - sorted.len();
+
+    rows.begin_change(cursor);
+
+    for (index, contents) in sorted.iter().enumerate() {
+        rows.set_row(range.start + index, contents);
+    }
+
+    for _ in 0..removed {
+        rows.remove_row(range.start + sorted.len());
+    }
+
+    super::jump_to_row(world, range.start);
+    builtin::report_rows(world, removed, "fewer");
+
+    Ok(())
+}
+
+/// The rows of `contents` in the order `args` sorts them in, reversed when `reverse` is set.
+fn sorted(
+    contents: impl Iterator<Item = String>,
+    args: &SortArgs,
+    regex: Option<&Regex>,
+    reverse: bool,
+) -> Vec<String> {
+    let mut unmatched = Vec::new();
+    let mut keyed = Vec::new();
+
+    for contents in contents {
+        match sort_text(&contents, regex, args.on_match) {
+            Some(text) => keyed.push((Key::new(text, args), contents)),
+            None => unmatched.push(contents),
+        }
+    }
+
+    keyed.sort_by(|(left, _), (right, _)| left.compare(right));
+
+    if args.unique {
+        keyed.dedup_by(|(right, _), (left, _)| left.compare(right) == Ordering::Equal);
+    }
+
+    let mut sorted: Vec<String> = unmatched
+        .into_iter()
+        .chain(keyed.into_iter().map(|(_, contents)| contents))
+        .collect();
+
+    if reverse {
+        sorted.reverse();
+    }
+
+    sorted
+}
+
+/// The part of `contents` a row is sorted on, `None` if it doesn't match `regex`.
+fn sort_text<'a>(contents: &'a str, regex: Option<&Regex>, on_match: bool) -> Option<&'a str> {
+    let Some(regex) = regex else {
+        return Some(contents);
+    };
+
+    let found = regex.find(contents)?;
+
+    match on_match {
+        true => Some(found.as_str()),
+        false => Some(&contents[found.end()..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sort_rows(rows: &[&str], args: &str, reverse: bool) -> Vec<String> {
+        let args = parse_args(args).unwrap();
+        let regex = args.pattern.as_deref().map(|it| Regex::new(it).unwrap());
+        let contents = rows.iter().map(|it| it.to_string());
+
+        sorted(contents, &args, regex.as_ref(), reverse)
+    }
+
+    #[test]
+    fn parses_flags_and_a_pattern_in_any_order() {
+        let args = parse_args("n /a\\/b/ ui").unwrap();
+
+        assert!(args.numeric && args.unique && args.ignore_case && !args.on_match);
+        assert_eq!(args.pattern.as_deref(), Some("a/b"));
+        assert!(parse_args("x").is_err());
+    }
+
+    #[test]
+    fn finds_the_first_number() {
+        assert_eq!(first_number("a12b3"), Some(12));
+        assert_eq!(first_number("x-7"), Some(-7));
+        assert_eq!(first_number("none"), None);
+    }
+
+    #[test]
+    fn sorts_text() {
+        assert_eq!(sort_rows(&["b", "C", "a"], "", false), ["C", "a", "b"]);
+        assert_eq!(sort_rows(&["b", "C", "a"], "i", false), ["a", "b", "C"]);
+        assert_eq!(sort_rows(&["b", "C", "a"], "", true), ["b", "a", "C"]);
+        assert_eq!(sort_rows(&["b", "a", "b", "B"], "iu", false), ["a", "b"]);
+    }
+
+    #[test]
+    fn sorts_numbers() {
+        assert_eq!(
+            sort_rows(&["x10", "-2", "none", "3"], "n", false),
+            ["none", "-2", "3", "x10"]
+        );
+    }
+
+    #[test]
+    fn sorts_after_or_on_a_pattern() {
+        let rows = ["b=2", "no", "a=3", "c=1"];
+
+        assert_eq!(sort_rows(&rows, "/=/", false), ["no", "c=1", "b=2", "a=3"]);
+        assert_eq!(
+            sort_rows(&rows, r"/\w=/ r", false),
+            ["no", "a=3", "b=2", "c=1"]
+        );
+        assert_eq!(sort_rows(&rows, "/=/", true), ["a=3", "b=2", "c=1", "no"]);
+    }
+}
@@ -0,0 +1,74 @@
+use bevy_ecs::system::{Res, ResMut};
+
+use super::Completion;
+use crate::{
+    input::data::RowBuffer,
+    options::Options,
+    output::{buffer::OutputBuffer, wrap, OutputSize},
+    theme::Theme,
+    window::{ActiveView, Windows},
+};
+
+/// The most words shown at once, the rest are scrolled to.
+const MAX_ITEMS: usize = 10;
+
+/// Draws the completion popup right below the start of the completed word, or above it when
+/// there's no room left below.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_popup(
+    completion: Res<Completion>,
+    rows: Res<RowBuffer>,
+    view: ActiveView,
+    size: Res<OutputSize>,
+    options: Res<Options>,
+    windows: Res<Windows>,
+    theme: Res<Theme>,
+    mut buffer: ResMut<OutputBuffer>,
+) {
+    let Some((x, y)) = completion.start() else {
+        return;
+    };
+
+    let area = windows.area();
+    let matches = completion.matches();
+    let height = matches
+        .len()
+        .min(MAX_ITEMS)
+        .min(area.rows.saturating_sub(1));
+    let (column, row) = wrap::screen_position(&rows, (x, y), &view.get().offset, &area, &options);
+
+    let top = match row + 1 + height <= area.y + area.rows {
+        true => row + 1,
+        false => row.saturating_sub(height),
+    };
+
+    // the words are padded with a space on both sides
+    let width = matches
+        .iter()
+        .map(|it| it.chars().count() + 2)
+        .max()
+        .unwrap_or(0)
+        .min(size.columns);
+
+    let left = column.min(size.columns.saturating_sub(width));
+
+    // the selected word is kept in view
+    let first = completion
+        .selected()
+        .map_or(0, |it| (it + 1).saturating_sub(height));
+
+    for (index, word) in matches.iter().enumerate().skip(first).take(height) {
+        let text: String = format!(" {word:width$}", width = width.saturating_sub(1))
+            .chars()
+            .take(width)
+            .collect();
+
+        let group = match completion.selected() == Some(index) {
+            true => "PmenuSel",
+            false => "Pmenu",
+        };
+
+        buffer.move_to(left, top + index - first);
+        buffer.push_styled(&text, theme.style(group));
+    }
+}
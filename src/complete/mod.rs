@@ -0,0 +1,420 @@
+use std::collections::HashSet;
+
+use bevy_ecs::prelude::*;
+
+use crate::{
+    cursor::{multi::Selection, CursorPosition, VisualAnchor},
+    edit::{
+        self,
+        change::{apply_each, Edit},
+    },
+    input::{data::RowBuffer, InputMode},
+    options::Options,
+    output, status,
+    status::StatusBuffer,
+    window::{self, ActiveView, View},
+    SystemType,
+};
+
+pub mod draw;
+
+pub fn init(world: &mut World, schedule: &mut Schedule) {
+    world.insert_resource(Completion::default());
+    world.insert_resource(Events::<CompletionEvent>::default());
+
+    schedule.add_systems(
+        (
+            handle_completion,
+            // typing more of the word filters the popup, so it has to see what was typed
+            update.after(handle_completion).after(edit::handle_edit),
+        )
+            .after(SystemType::Input)
+            .before(SystemType::Cursor),
+    );
+
+    // the popup is drawn over everything else, the cursor is moved back into place afterwards
+    schedule.add_systems(
+        draw::draw_popup
+            .after(output::draw_rows)
+            .after(status::draw::draw_status_bar)
+            .after(window::draw::draw_windows)
+            .in_set(SystemType::Output),
+    );
+}
+
+/// Keys of the completion popup in insert mode.
+#[derive(Event, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionEvent {
+    /// `Ctrl-N`, opens the popup on the closest word, or selects the next one.
+    Next,
+    /// `Ctrl-P`, opens the popup on the last word, or selects the previous one.
+    Previous,
+    /// `Ctrl-Y`, keeps the selected word and closes the popup.
+    Accept,
+    /// `Ctrl-E`, puts back what was typed before a word was selected and closes the popup.
+    Cancel,
+}
+
+/// The words completing the one in front of the cursor, shown in a popup below it.
+#[derive(Resource, Default)]
+pub struct Completion {
+    popup: Option<Popup>,
+    dictionary: Dictionary,
+}
+
+/// The words of the `dictionary` file, which is only read again once the option changes.
+#[derive(Default)]
+struct Dictionary {
+    path: String,
+    words: Vec<String>,
+}
+
+struct Popup {
+    /// Where the completed word starts.
+    start: (usize, usize),
+    /// Every word that was found, the closest ones first.
+    words: Vec<String>,
+    /// The words starting with what was typed, these are the ones that are shown.
+    matches: Vec<String>,
+    selected: Option<usize>,
+    /// What was typed, put back when moving past the first or last match.
+    typed: String,
+    /// The text between the start of the word and the cursor.
+    current: String,
+}
+
+impl Completion {
+    /// Where the completed word starts, the popup is drawn from there.
+    pub fn start(&self) -> Option<(usize, usize)> {
+        self.popup.as_ref().map(|it| it.start)
+    }
+
+    /// The words shown in the popup.
+    pub fn matches(&self) -> &[String] {
+        self.popup.as_ref().map_or(&[], |it| &it.matches)
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.popup.as_ref().and_then(|it| it.selected)
+    }
+}
+
+impl Popup {
+    /// Keeps the words that start with `typed`, and that aren't exactly what was typed.
+    fn filter(&mut self, typed: String) {
+        self.matches = self
+            .words
+            .iter()
+            .filter(|it| it.starts_with(&typed) && **it != typed)
+            .cloned()
+            .collect();
+        self.selected = None;
+        self.current = typed.clone();
+        self.typed = typed;
+    }
+}
+
+impl Dictionary {
+    /// The words of the file at `path`, no words if it's empty. Fails if the file can't be read,
+    /// it's read again the next time.
+    fn load(&mut self, path: &str) -> Result<&[String], String> {
+        if path.is_empty() {
+            return Ok(&[]);
+        }
+
+        if self.path != path {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|_| format!("Can't read dictionary {path}"))?;
+            let mut seen = HashSet::new();
+
+            self.words = contents
+                .split_whitespace()
+                .filter(|it| seen.insert(*it))
+                .map(String::from)
+                .collect();
+            self.path = path.to_string();
+        }
+
+        Ok(&self.words)
+    }
+}
+
+fn is_word_char(char: char) -> bool {
+    char.is_alphanumeric() || char == '_'
+}
+
+/// Every word in the buffer, ordered by how close they are to the cursor, followed by the words
+/// of the `dictionary` file. The word the cursor is on is left out, since that's the one being
+/// completed. The editor only ever has the one buffer open, so there are no other buffers to
+/// collect words from.
+fn collect_words(rows: &RowBuffer, start: (usize, usize), dictionary: &[String]) -> Vec<String> {
+    let mut found = Vec::new();
+
+    for y in 0..rows.rows_count() {
+        let contents = rows.get_row_at(y).contents();
+        let mut word_start = None;
+
+        for (x, char) in contents.char_indices().chain([(contents.len(), ' ')]) {
+            match (word_start, is_word_char(char)) {
+                (None, true) => word_start = Some(x),
+                (Some(from), false) => {
+                    word_start = None;
+
+                    if (from, y) != start {
+                        let distance = (y.abs_diff(start.1), from.abs_diff(start.0));
+                        found.push((distance, &contents[from..x]));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    found.sort_by_key(|(distance, _)| *distance);
+
+    let mut seen = HashSet::new();
+    let mut words: Vec<String> = found
+        .into_iter()
+        .filter(|(_, word)| seen.insert(*word))
+        .map(|(_, word)| word.to_string())
+        .collect();
+
+    words.extend(
+        dictionary
+            .iter()
+            .filter(|it| !seen.contains(it.as_str()))
+            .cloned(),
+    );
+
+    words
+}
+
+/// The text typed since the start of the completed word, `None` once the cursor left the word.
+fn typed_text(
+    rows: &RowBuffer,
+    start: (usize, usize),
+    position: &CursorPosition,
+) -> Option<String> {
+    if position.y != start.1 || position.x < start.0 || position.y >= rows.rows_count() {
+        return None;
+    }
+
+    let typed = rows
+        .get_row_at(position.y)
+        .contents()
+        .get(start.0..position.x)?;
+
+    typed.chars().all(is_word_char).then(|| typed.to_string())
+}
+
+/// Opens the popup for the word in front of the cursor.
+fn open(
+    rows: &RowBuffer,
+    position: &CursorPosition,
+    dictionary: &[String],
+) -> Result<Popup, String> {
+    let contents = match position.y < rows.rows_count() {
+        true => rows.get_row_at(position.y).contents(),
+        false => "",
+    };
+    let before = &contents[..position.x.min(contents.len())];
+    let word = before.len() - before.trim_end_matches(is_word_char).len();
+    let start = (before.len() - word, position.y);
+
+    let mut popup = Popup {
+        start,
+        words: collect_words(rows, start, dictionary),
+        matches: Vec::new(),
+        selected: None,
+        typed: String::new(),
+        current: String::new(),
+    };
+
+    popup.filter(before[start.0..].to_string());
+
+    match popup.matches.is_empty() {
+        true => Err(String::from("Pattern not found")),
+        false => Ok(popup),
+    }
+}
+
+/// Replaces the completed word with `text` at every cursor the same word was typed at.
+fn replace(
+    rows: &mut RowBuffer,
+    selections: &mut [Selection],
+    origin: (usize, usize),
+    current: &str,
+    text: &str,
+) {
+    apply_each(rows, selections, origin, |rows, selection| {
+        let head = &selection.head;
+        let start = head.x.checked_sub(current.len())?;
+        let contents = rows.get_row_at(head.y).contents();
+
+        (contents.get(start..head.x)? == current).then(|| Edit::Replace {
+            start: (start, head.y),
+            end: (head.x, head.y),
+            text: text.to_string(),
+        })
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn handle_completion(
+    mut events: EventReader<CompletionEvent>,
+    mut completion: ResMut<Completion>,
+    mut rows: ResMut<RowBuffer>,
+    mut view: ActiveView,
+    mut anchor: ResMut<VisualAnchor>,
+    mut status: ResMut<StatusBuffer>,
+    options: Res<Options>,
+) {
+    let completion = &mut *completion;
+
+    for event in events.iter() {
+        if completion.popup.is_none() {
+            if !matches!(event, CompletionEvent::Next | CompletionEvent::Previous) {
+                continue;
+            }
+
+            // a dictionary that can't be read is reported, the words of the buffer are still
+            // offered
+            let (dictionary, failed) = match completion.dictionary.load(&options.dictionary) {
+                Ok(words) => (words, None),
+                Err(error) => (&[][..], Some(error)),
+            };
+
+            match (open(&rows, &view.get().position, dictionary), failed) {
+                (Ok(popup), failed) => {
+                    completion.popup = Some(popup);
+
+                    if let Some(error) = failed {
+                        status.error(error);
+                    }
+                }
+                (Err(error), failed) => {
+                    status.error(failed.unwrap_or(error));
+                    continue;
+                }
+            }
+        }
+
+        let Some(popup) = completion.popup.as_mut() else {
+            continue;
+        };
+
+        // moving past either end selects nothing, which puts back what was typed
+        let count = popup.matches.len();
+        let selected = match (event, popup.selected) {
+            (CompletionEvent::Next, None) => Some(0),
+            (CompletionEvent::Next, Some(index)) => Some(index + 1).filter(|it| *it < count),
+            (CompletionEvent::Previous, None) => Some(count - 1),
+            (CompletionEvent::Previous, Some(index)) => index.checked_sub(1),
+            (CompletionEvent::Accept, _) => {
+                completion.popup = None;
+                continue;
+            }
+            (CompletionEvent::Cancel, _) => None,
+        };
+
+        let text = selected.map_or(popup.typed.clone(), |it| popup.matches[it].clone());
+        let mut view = view.get_mut();
+        let View {
+            position, cursors, ..
+        } = &mut *view;
+        let mut selections = cursors.collect(&anchor, position);
+        let origin = (position.x, position.y);
+
+        replace(&mut rows, &mut selections, origin, &popup.current, &text);
+        cursors.store(selections, &mut anchor, position);
+
+        popup.selected = selected;
+        popup.current = text;
+
+        if *event == CompletionEvent::Cancel {
+            completion.popup = None;
+        }
+    }
+}
+
+/// Filters the popup down to the words starting with what's typed, and closes it once the cursor
+/// leaves the word or insert mode is left.
+pub fn update(
+    mut completion: ResMut<Completion>,
+    rows: Res<RowBuffer>,
+    view: ActiveView,
+    input_mode: Res<InputMode>,
+) {
+    let Some(popup) = completion.popup.as_mut() else {
+        return;
+    };
+
+    let typed = match *input_mode {
+        InputMode::Insert => typed_text(&rows, popup.start, &view.get().position),
+        _ => None,
+    };
+
+    match typed {
+        Some(typed) if typed == popup.current => {}
+        Some(typed) => {
+            popup.filter(typed);
+
+            if popup.matches.is_empty() {
+                completion.popup = None;
+            }
+        }
+        None => completion.popup = None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(rows: &[&str]) -> RowBuffer {
+        let mut buffer = RowBuffer::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        buffer
+    }
+
+    #[test]
+    fn collects_the_closest_words_first() {
+        let rows = buffer(&["far away", "near by", "wo"]);
+
+        assert_eq!(
+            collect_words(&rows, (0, 2), &[]),
+            ["near", "by", "far", "away"]
+        );
+        assert_eq!(collect_words(&buffer(&[]), (0, 0), &[]), [] as [String; 0]);
+    }
+
+    #[test]
+    fn dictionary_words_come_last_without_duplicates() {
+        let rows = buffer(&["alpha beta"]);
+        let dictionary = [String::from("beta"), String::from("gamma")];
+
+        assert_eq!(
+            collect_words(&rows, (0, 1), &dictionary),
+            ["alpha", "beta", "gamma"]
+        );
+    }
+
+    #[test]
+    fn keeps_the_dictionary_until_the_option_changes() {
+        let path = std::env::temp_dir().join(format!("heracross-words-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut dictionary = Dictionary::default();
+
+        std::fs::write(path, "one two\none").unwrap();
+        assert_eq!(dictionary.load(path).unwrap(), ["one", "two"]);
+
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(dictionary.load(path).unwrap(), ["one", "two"]);
+        assert!(dictionary.load(&format!("{path}-missing")).is_err());
+        assert_eq!(dictionary.load("").unwrap(), [] as [String; 0]);
+    }
+}
@@ -4,14 +4,14 @@ use bevy_ecs::{
     event::{EventReader, EventWriter},
     system::ResMut,
 };
-use crossterm::{cursor, queue};
 
 pub fn handle_cursor_hide(
     mut events: EventReader<CursorHideEvent>,
     mut buffer: ResMut<OutputBuffer>,
 ) {
     for _ in events.iter() {
-        queue!(buffer, cursor::Hide, cursor::MoveTo(0, 0)).expect("Couldn't update cursor");
+        buffer.hide_cursor();
+        buffer.move_to(0, 0);
     }
 }
 
@@ -1,40 +1,71 @@
-use crate::SystemType;
+use crate::{output, SystemType};
 use bevy_ecs::prelude::*;
 
 pub mod hide;
+pub mod multi;
 pub mod scroll;
+pub mod shape;
 pub mod update;
 
 pub fn init(world: &mut World, schedule: &mut Schedule) {
-    world.insert_resource(CursorPosition::default());
-    world.insert_resource(CursorOffset::default());
+    world.insert_resource(VisualAnchor::default());
+    world.insert_resource(Events::<multi::MultiCursorEvent>::default());
     world.insert_resource(Events::<CursorHideEvent>::default());
     world.insert_resource(Events::<CursorMoveEvent>::default());
 
     schedule.add_systems(
         (
             update::handle_cursor_move,
-            hide::handle_cursor_hide,
-            update::update_cursor,
-            scroll::scroll,
+            multi::clamp_cursors.after(update::handle_cursor_move),
+            scroll::scroll.after(update::handle_cursor_move),
         )
             .in_set(SystemType::Cursor),
     );
+
+    schedule.add_systems(
+        multi::handle_multi_cursor
+            .after(SystemType::Input)
+            .before(SystemType::Cursor),
+    );
+
+    // the cursor has to be hidden and moved to the top before anything is drawn, and only shown
+    // again once everything has been drawn.
+    schedule.add_systems((
+        hide::handle_cursor_hide
+            .after(hide::dispatch_hide)
+            .before(output::draw_rows),
+        update::update_cursor
+            .after(SystemType::Output)
+            .before(SystemType::Flush),
+        shape::update_shape
+            .after(SystemType::Output)
+            .before(SystemType::Flush),
+    ));
 }
 
-#[derive(Resource, Default)]
+/// Where a cursor is in the buffer, every window has one in its [View](crate::window::View).
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
 pub struct CursorPosition {
-    x: usize,
-    y: usize,
+    pub(crate) x: usize,
+    pub(crate) y: usize,
 }
 
-#[derive(Clone, Resource, Default)]
+/// How far a window is scrolled down and to the side.
+#[derive(Clone, Default)]
 pub struct CursorOffset {
     pub(crate) row: usize,
     pub(crate) column: usize,
     pub(crate) render: usize,
 }
 
+/// The position visual mode was started at, the selection spans from here to the cursor of the
+/// active window.
+#[derive(Resource, Default)]
+pub struct VisualAnchor {
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+}
+
 #[derive(Event)]
 pub struct CursorMoveEvent {
     direction: CursorDirection,
@@ -48,6 +79,10 @@ pub enum CursorDirection {
     Down,
     Left,
     Right,
+    /// `gj`, down a line on screen, which is a line of the same row when it wraps.
+    ScreenDown,
+    /// `gk`
+    ScreenUp,
 }
 
 impl From<char> for CursorMoveEvent {
@@ -63,3 +98,15 @@ impl From<char> for CursorMoveEvent {
         Self { direction }
     }
 }
+
+impl CursorMoveEvent {
+    /// `gj` and `gk`
+    pub fn screen_line(down: bool) -> Self {
+        let direction = match down {
+            true => CursorDirection::ScreenDown,
+            false => CursorDirection::ScreenUp,
+        };
+
+        Self { direction }
+    }
+}
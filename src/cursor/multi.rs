@@ -0,0 +1,475 @@
+use bevy_ecs::prelude::*;
+use regex::Regex;
+
+use super::{update::clamp_to_row, CursorPosition, VisualAnchor};
+use crate::{
+    input::{data::RowBuffer, InputMode},
+    search::{self, SearchMatch},
+    status::StatusBuffer,
+    window::{ActiveView, View},
+};
+
+/// A cursor along with the anchor of its visual selection. Outside of visual mode, only the
+/// `head` is used.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Selection {
+    pub(crate) anchor: CursorPosition,
+    pub(crate) head: CursorPosition,
+}
+
+impl Selection {
+    pub fn at(x: usize, y: usize) -> Self {
+        Self {
+            anchor: CursorPosition { x, y },
+            head: CursorPosition { x, y },
+        }
+    }
+
+    /// The first and last position of the selection, both inclusive.
+    pub fn ordered(&self) -> (&CursorPosition, &CursorPosition) {
+        match (self.anchor.y, self.anchor.x) <= (self.head.y, self.head.x) {
+            true => (&self.anchor, &self.head),
+            false => (&self.head, &self.anchor),
+        }
+    }
+}
+
+/// The cursors of a window besides the primary one, which stays in the [View]'s position and the
+/// [VisualAnchor] so everything that only cares about a single cursor keeps working as-is.
+/// Motions and edits are applied to every cursor, the view only follows the primary one.
+#[derive(Clone, Default)]
+pub struct Cursors {
+    secondary: Vec<Selection>,
+}
+
+impl Cursors {
+    pub fn secondary(&self) -> &[Selection] {
+        &self.secondary
+    }
+
+    pub fn secondary_mut(&mut self) -> &mut Vec<Selection> {
+        &mut self.secondary
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.secondary.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.secondary.clear();
+    }
+
+    /// Every selection, starting with the primary one.
+    pub fn collect(&self, anchor: &VisualAnchor, position: &CursorPosition) -> Vec<Selection> {
+        let primary = Selection {
+            anchor: CursorPosition {
+                x: anchor.x,
+                y: anchor.y,
+            },
+            head: position.clone(),
+        };
+
+        std::iter::once(primary)
+            .chain(self.secondary.iter().cloned())
+            .collect()
+    }
+
+    /// The opposite of [Cursors::collect], the first selection becomes the primary one. Cursors
+    /// that ended up on top of another one are merged into it.
+    pub fn store(
+        &mut self,
+        selections: Vec<Selection>,
+        anchor: &mut VisualAnchor,
+        position: &mut CursorPosition,
+    ) {
+        let mut selections = selections.into_iter();
+
+        let Some(primary) = selections.next() else {
+            return;
+        };
+
+        *position = primary.head;
+        *anchor = VisualAnchor {
+            x: primary.anchor.x,
+            y: primary.anchor.y,
+        };
+
+        self.secondary.clear();
+
+        for selection in selections {
+            if selection.head != *position
+                && !self.secondary.iter().any(|it| it.head == selection.head)
+            {
+                self.secondary.push(selection);
+            }
+        }
+    }
+
+    /// Adds a selection that becomes the primary one, the old primary selection becomes a
+    /// secondary one. This way, the view follows the cursor that was added last.
+    pub fn push_primary(
+        &mut self,
+        selection: Selection,
+        anchor: &mut VisualAnchor,
+        position: &mut CursorPosition,
+    ) {
+        let mut selections = self.collect(anchor, position);
+        selections.insert(0, selection);
+
+        self.store(selections, anchor, position);
+    }
+}
+
+#[derive(Event)]
+pub enum MultiCursorEvent {
+    /// Adds a cursor on the row above the topmost cursor.
+    Above,
+    /// Adds a cursor on the row below the bottommost cursor.
+    Below,
+    /// Adds a cursor at the next occurrence of the word under the cursor, or of the selected text
+    /// in visual mode.
+    NextMatch,
+    /// Splits a visual selection spanning multiple rows into a selection per row.
+    SplitSelection,
+}
+
+pub fn handle_multi_cursor(
+    mut events: EventReader<MultiCursorEvent>,
+    mut view: ActiveView,
+    mut anchor: ResMut<VisualAnchor>,
+    mut status: ResMut<StatusBuffer>,
+    input_mode: Res<InputMode>,
+    rows: Res<RowBuffer>,
+) {
+    for event in events.iter() {
+        let mut view = view.get_mut();
+        let View {
+            position, cursors, ..
+        } = &mut *view;
+        let selections = cursors.collect(&anchor, position);
+
+        match event {
+            MultiCursorEvent::Above | MultiCursorEvent::Below => {
+                let below = matches!(event, MultiCursorEvent::Below);
+
+                let furthest = match below {
+                    true => selections.iter().max_by_key(|it| (it.head.y, it.head.x)),
+                    false => selections.iter().min_by_key(|it| (it.head.y, it.head.x)),
+                }
+                .map(|it| it.head.clone())
+                .unwrap_or_default();
+
+                let y = match below {
+                    true if furthest.y + 1 < rows.rows_count() => furthest.y + 1,
+                    false if furthest.y > 0 => furthest.y - 1,
+                    _ => continue,
+                };
+
+                let mut head = CursorPosition { x: furthest.x, y };
+                clamp_to_row(&mut head, &rows);
+
+                cursors.push_primary(Selection::at(head.x, head.y), &mut anchor, position);
+            }
+            MultiCursorEvent::NextMatch => {
+                let visual = *input_mode == InputMode::Visual;
+
+                let Some(regex) = selection_regex(&rows, &selections[0], visual) else {
+                    status.error("No string under cursor");
+                    continue;
+                };
+
+                let Some(found) = next_free_match(&rows, &regex, &selections) else {
+                    status.error("No more matches");
+                    continue;
+                };
+
+                // a visual selection is added over the whole match, otherwise only a cursor at
+                // its start is.
+                let selection = match visual {
+                    true => Selection {
+                        anchor: CursorPosition {
+                            x: found.start,
+                            y: found.y,
+                        },
+                        head: last_char(&rows, found),
+                    },
+                    false => Selection::at(found.start, found.y),
+                };
+
+                cursors.push_primary(selection, &mut anchor, position);
+            }
+            MultiCursorEvent::SplitSelection => {
+                let mut split = Vec::new();
+
+                for selection in &selections {
+                    let (start, end) = selection.ordered();
+
+                    for y in start.y..=end.y {
+                        let mut head = CursorPosition {
+                            x: match y == end.y {
+                                true => end.x,
+                                false => rows.get_row_at(y).contents().len(),
+                            },
+                            y,
+                        };
+
+                        clamp_to_row(&mut head, &rows);
+
+                        split.push(Selection {
+                            anchor: CursorPosition {
+                                x: if y == start.y { start.x } else { 0 },
+                                y,
+                            },
+                            head,
+                        });
+                    }
+                }
+
+                cursors.store(split, &mut anchor, position);
+            }
+        }
+    }
+}
+
+/// The regex the next occurrence is searched with: the word under the primary cursor, or the
+/// text of its selection in visual mode.
+fn selection_regex(rows: &RowBuffer, primary: &Selection, visual: bool) -> Option<Regex> {
+    let pattern = match visual {
+        true => {
+            let (start, end) = primary.ordered();
+
+            if end.y >= rows.rows_count() {
+                return None;
+            }
+
+            let contents = rows.get_row_at(end.y).contents();
+            let end_x = end.x
+                + contents
+                    .get(end.x..)?
+                    .chars()
+                    .next()
+                    .map_or(0, char::len_utf8);
+
+            regex::escape(&rows.text((start.x, start.y), (end_x, end.y)))
+        }
+        false => {
+            let word = search::word_under_cursor(rows, primary.head.x, primary.head.y)?;
+            format!(r"\b{}\b", regex::escape(word))
+        }
+    };
+
+    (!pattern.is_empty()).then(|| Regex::new(&pattern).ok())?
+}
+
+/// The next match after the primary cursor that doesn't have a cursor on it yet.
+fn next_free_match(
+    rows: &RowBuffer,
+    regex: &Regex,
+    selections: &[Selection],
+) -> Option<SearchMatch> {
+    let mut from = (selections[0].head.x, selections[0].head.y);
+
+    for _ in 0..=selections.len() {
+        let (found, _) = search::find_match(rows, regex, from, false, true)?;
+
+        let taken = selections.iter().any(|it| {
+            let (start, _) = it.ordered();
+            (start.x, start.y) == (found.start, found.y)
+        });
+
+        if !taken {
+            return Some(found);
+        }
+
+        from = (found.start, found.y);
+    }
+
+    None
+}
+
+/// The position of the last character of a (non-empty) match.
+fn last_char(rows: &RowBuffer, found: SearchMatch) -> CursorPosition {
+    let contents = rows.get_row_at(found.y).contents();
+
+    let x = contents[..found.end]
+        .char_indices()
+        .next_back()
+        .map_or(found.start, |(index, _)| index.max(found.start));
+
+    CursorPosition { x, y: found.y }
+}
+
+/// Keeps every secondary cursor inside of the buffer after the rows changed underneath them, e.g.
+/// after undoing a change. Only insert mode allows a cursor right after the end of a row.
+pub fn clamp_cursors(
+    mut view: ActiveView,
+    anchor: Res<VisualAnchor>,
+    input_mode: Res<InputMode>,
+    rows: Res<RowBuffer>,
+) {
+    let View {
+        position, cursors, ..
+    } = view.get();
+
+    if cursors.is_empty() {
+        return;
+    }
+
+    let insert = *input_mode == InputMode::Insert;
+    let mut selections = cursors.collect(&anchor, position);
+
+    for selection in selections.iter_mut().skip(1) {
+        for position in [&mut selection.anchor, &mut selection.head] {
+            position.y = position.y.min(rows.rows_count().saturating_sub(1));
+
+            match insert {
+                true => clamp_to_row_end(position, &rows),
+                false => clamp_to_row(position, &rows),
+            }
+        }
+    }
+
+    // merges cursors that ended up in the same place. The primary cursor is left as it is, and
+    // the others are only written back when they changed, which keeps the change detection of the
+    // view from firing every frame.
+    let mut clamped = Cursors::default();
+    clamped.store(
+        selections,
+        &mut VisualAnchor::default(),
+        &mut position.clone(),
+    );
+
+    if clamped.secondary != cursors.secondary {
+        view.get_mut().cursors = clamped;
+    }
+}
+
+/// Like [clamp_to_row], but allows the cursor right after the last character of the row.
+pub fn clamp_to_row_end(position: &mut CursorPosition, rows: &RowBuffer) {
+    if position.y >= rows.rows_count() {
+        position.x = 0;
+        return;
+    }
+
+    let contents = rows.get_row_at(position.y).contents();
+    position.x = position.x.min(contents.len());
+
+    while !contents.is_char_boundary(position.x) {
+        position.x -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(rows: &[&str]) -> RowBuffer {
+        let mut buffer = RowBuffer::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        buffer
+    }
+
+    fn heads(selections: &[Selection]) -> Vec<(usize, usize)> {
+        selections.iter().map(|it| (it.head.x, it.head.y)).collect()
+    }
+
+    #[test]
+    fn storing_merges_cursors_on_top_of_each_other() {
+        let mut cursors = Cursors::default();
+        let mut anchor = VisualAnchor::default();
+        let mut position = CursorPosition::default();
+
+        let selections = [(1, 0), (2, 1), (1, 0), (2, 1), (0, 2)]
+            .map(|(x, y)| Selection::at(x, y))
+            .to_vec();
+        cursors.store(selections, &mut anchor, &mut position);
+
+        assert_eq!((position.x, position.y), (1, 0));
+        assert_eq!(heads(cursors.secondary()), [(2, 1), (0, 2)]);
+        assert_eq!(
+            heads(&cursors.collect(&anchor, &position)),
+            [(1, 0), (2, 1), (0, 2)]
+        );
+    }
+
+    #[test]
+    fn the_cursor_added_last_becomes_the_primary_one() {
+        let mut cursors = Cursors::default();
+        let mut anchor = VisualAnchor::default();
+        let mut position = CursorPosition { x: 3, y: 0 };
+
+        cursors.push_primary(Selection::at(3, 1), &mut anchor, &mut position);
+        cursors.push_primary(Selection::at(3, 0), &mut anchor, &mut position);
+
+        assert_eq!((position.x, position.y), (3, 0));
+        assert_eq!(heads(cursors.secondary()), [(3, 1)]);
+    }
+
+    #[test]
+    fn finds_the_next_match_without_a_cursor() {
+        let rows = buffer(&["foo bar", "foobar foo", "foo"]);
+        let primary = Selection::at(1, 0);
+        let regex = selection_regex(&rows, &primary, false).unwrap();
+
+        let found = next_free_match(&rows, &regex, std::slice::from_ref(&primary)).unwrap();
+        assert_eq!((found.start, found.y), (7, 1));
+
+        // matches with a cursor are skipped, and the search wraps around the end
+        let taken = [Selection::at(0, 2), Selection::at(7, 1), primary];
+        let found = next_free_match(&rows, &regex, &taken).unwrap();
+        assert_eq!((found.start, found.y), (0, 0));
+
+        let everywhere = [
+            Selection::at(0, 0),
+            Selection::at(7, 1),
+            Selection::at(0, 2),
+        ];
+        assert!(next_free_match(&rows, &regex, &everywhere).is_none());
+    }
+
+    #[test]
+    fn a_visual_selection_is_searched_for_as_text() {
+        let rows = buffer(&["a.b axb a.b"]);
+        let selection = Selection {
+            anchor: CursorPosition { x: 0, y: 0 },
+            head: CursorPosition { x: 2, y: 0 },
+        };
+        let regex = selection_regex(&rows, &selection, true).unwrap();
+
+        let found = next_free_match(&rows, &regex, &[selection]).unwrap();
+        assert_eq!((found.start, found.y), (8, 0));
+        assert_eq!(last_char(&rows, found), CursorPosition { x: 10, y: 0 });
+
+        assert!(selection_regex(&buffer(&["  "]), &Selection::at(0, 0), false).is_none());
+    }
+
+    #[test]
+    fn nothing_is_searched_for_in_an_empty_buffer() {
+        let rows = RowBuffer::new();
+        let selection = Selection::at(0, 0);
+
+        assert!(selection_regex(&rows, &selection, true).is_none());
+        assert!(selection_regex(&rows, &selection, false).is_none());
+    }
+
+    #[test]
+    fn clamps_to_the_end_of_the_row() {
+        let rows = buffer(&["aé"]);
+        let mut position = CursorPosition { x: 9, y: 0 };
+
+        clamp_to_row_end(&mut position, &rows);
+        assert_eq!(position, CursorPosition { x: 3, y: 0 });
+
+        let mut position = CursorPosition { x: 2, y: 0 };
+        clamp_to_row_end(&mut position, &rows);
+        assert_eq!(position, CursorPosition { x: 1, y: 0 });
+
+        let mut position = CursorPosition { x: 2, y: 1 };
+        clamp_to_row_end(&mut position, &rows);
+        assert_eq!(position, CursorPosition { x: 0, y: 1 });
+    }
+}
@@ -1,34 +1,75 @@
-use super::{CursorOffset, CursorPosition};
-use crate::{input::data::RowBuffer, output::OutputSize};
+use super::CursorOffset;
+use crate::{
+    fold,
+    input::data::RowBuffer,
+    options::Options,
+    output::wrap,
+    window::{ActiveView, View, Windows},
+};
 use bevy_ecs::prelude::*;
 
 pub fn scroll(
-    mut offset: ResMut<CursorOffset>,
-    cursor_pos: Res<CursorPosition>,
-    output_size: Res<OutputSize>,
+    mut view: ActiveView,
+    windows: Res<Windows>,
     rows: Res<RowBuffer>,
+    options: Res<Options>,
 ) {
+    let mut view = view.get_mut();
+    let View {
+        position: cursor_pos,
+        offset,
+        ..
+    } = &mut *view;
+
+    if cursor_pos.y < rows.rows_count() {
+        offset.render = rows
+            .get_row_at(cursor_pos.y)
+            .get_len_with_offset(cursor_pos.x);
+    }
+
     let CursorOffset {
         row: row_offset,
         column: column_offset,
         render: render_offset,
-    } = *offset;
+    } = offset.clone();
 
-    offset.row = row_offset.min(cursor_pos.y);
+    // the view always starts at the start of a line, which a closed fold only has one of
+    let row_offset = fold::line_start(&rows, row_offset.min(cursor_pos.y));
+    offset.row = row_offset;
     offset.column = column_offset.min(render_offset);
 
-    if cursor_pos.y < rows.rows_count() {
-        offset.render = rows
-            .get_row_at(cursor_pos.y)
-            .get_len_with_offset(cursor_pos.y);
+    let area = windows.area();
+    let text_rows = area.rows;
+    let width = wrap::text_width(&rows, &area, &options);
+
+    // the view starts as far down as it has to for the screen lines from its top down to the
+    // cursor's to fit, the cursor can be on any of the lines of its own row when it wraps.
+    let mut used = wrap::line_of(&rows, (cursor_pos.x, cursor_pos.y), width, &options).0 + 1;
+    let mut top = fold::line_start(&rows, cursor_pos.y);
+
+    while top > row_offset {
+        let above = fold::line_start(&rows, top - 1);
+        used += wrap::height(&rows, above, width, &options);
+
+        if used > text_rows {
+            break;
+        }
+
+        top = above;
+    }
+
+    if top > row_offset {
+        offset.row = top;
     }
 
-    if cursor_pos.y >= row_offset + output_size.rows {
-        offset.row = cursor_pos.y - output_size.rows + 1;
+    // a wrapped row is shown in full, so there's nothing to scroll to the side
+    if options.wrap {
+        offset.column = 0;
+        return;
     }
 
     // we use render_dist instead of cursor_x, to properly render the correct characters.
-    if render_offset >= column_offset + output_size.columns {
-        offset.column = render_offset - output_size.columns + 1;
+    if render_offset >= column_offset + width {
+        offset.column = render_offset + 1 - width;
     }
 }
@@ -0,0 +1,221 @@
+use bevy_ecs::prelude::*;
+use crossterm::cursor::SetCursorStyle;
+
+use crate::{
+    input::{InputMode, PendingKeys},
+    options::Options,
+    output::buffer::OutputBuffer,
+    status::StatusBuffer,
+};
+
+/// The shapes the editor starts out with, a block in normal and visual mode, a bar while typing
+/// text and an underline while an operator waits for its motion or `r` for its character.
+pub const DEFAULT: &str = "n-v:block,i-c:bar,o-r:underline";
+
+/// The shape of the terminal's cursor.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CursorShape {
+    Block,
+    Bar,
+    Underline,
+    /// Whatever shape the terminal's cursor has by default, which is the one the user set up.
+    Default,
+}
+
+impl CursorShape {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "block" => CursorShape::Block,
+            "bar" => CursorShape::Bar,
+            "underline" => CursorShape::Underline,
+            _ => return None,
+        })
+    }
+
+    /// The DECSCUSR sequence that gives the cursor this shape.
+    pub fn command(&self) -> SetCursorStyle {
+        match self {
+            CursorShape::Block => SetCursorStyle::SteadyBlock,
+            CursorShape::Bar => SetCursorStyle::SteadyBar,
+            CursorShape::Underline => SetCursorStyle::SteadyUnderScore,
+            CursorShape::Default => SetCursorStyle::DefaultUserShape,
+        }
+    }
+}
+
+/// A `guicursor` value along with the shape of every mode, it's only parsed again once it changes.
+#[derive(Default)]
+pub struct ParsedShapes {
+    source: String,
+    shapes: Vec<(char, CursorShape)>,
+}
+
+/// Parses a `guicursor` value, a comma separated list of `{modes}:{shape}`. The modes are joined
+/// by `-`, `n` is normal mode, `v` visual mode, `i` insert mode, `c` the command line, `o` an
+/// operator waiting for its motion and `r` the character `r` replaces with. The shape is one of `block`, `bar` and `underline`.
+pub fn parse(value: &str) -> Result<Vec<(char, CursorShape)>, String> {
+    let mut shapes = Vec::new();
+
+    for part in value.split(',').filter(|it| !it.is_empty()) {
+        let (modes, name) = part
+            .split_once(':')
+            .ok_or_else(|| format!("Missing : in guicursor: {part}"))?;
+        let shape =
+            CursorShape::from_name(name).ok_or_else(|| format!("Unknown cursor shape: {name}"))?;
+
+        for mode in modes.split('-') {
+            match mode {
+                "n" | "v" | "i" | "c" | "o" | "r" => {
+                    shapes.extend(mode.chars().map(|it| (it, shape)))
+                }
+                _ => return Err(format!("Unknown mode in guicursor: {mode}")),
+            }
+        }
+    }
+
+    Ok(shapes)
+}
+
+/// The letter a mode has in `guicursor`, asking for confirmation keeps the cursor in the text
+/// like normal mode does. Only an operator waiting for its motion in normal mode is `o` and `r`
+/// waiting for its character is `r`, any other pending keys keep the mode's own letter.
+fn mode_letter(input_mode: InputMode, pending: &PendingKeys) -> char {
+    match input_mode {
+        InputMode::Normal if pending.is_operator() => 'o',
+        InputMode::Normal if pending.keys == "r" => 'r',
+        InputMode::Normal | InputMode::Confirm => 'n',
+        InputMode::Visual => 'v',
+        InputMode::Insert => 'i',
+        InputMode::Command => 'c',
+    }
+}
+
+/// Gives the cursor the shape `guicursor` has for the current mode, a mode it leaves out keeps
+/// the terminal's own shape.
+pub fn update_shape(
+    mut buffer: ResMut<OutputBuffer>,
+    input_mode: Res<InputMode>,
+    pending: Res<PendingKeys>,
+    options: Res<Options>,
+    mut status: ResMut<StatusBuffer>,
+    mut parsed: Local<ParsedShapes>,
+) {
+    // a broken value is reported once, the default shapes are used until it's fixed
+    if parsed.source != options.guicursor {
+        parsed.source = options.guicursor.clone();
+        parsed.shapes = parse(&options.guicursor).unwrap_or_else(|error| {
+            status.error(error);
+            parse(DEFAULT).expect("The default guicursor is invalid")
+        });
+    }
+
+    let letter = mode_letter(*input_mode, &pending);
+    buffer.set_cursor_shape(shape_of(&parsed.shapes, letter));
+}
+
+/// The shape of the mode with `letter`, a later entry for the same mode wins over an earlier one.
+fn shape_of(shapes: &[(char, CursorShape)], letter: char) -> CursorShape {
+    shapes
+        .iter()
+        .rev()
+        .find(|(mode, _)| *mode == letter)
+        .map_or(CursorShape::Default, |(_, shape)| *shape)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending(keys: &str) -> PendingKeys {
+        PendingKeys {
+            keys: String::from(keys),
+        }
+    }
+
+    /// The shape `guicursor` gives the mode with `letter`.
+    fn shape(value: &str, letter: char) -> CursorShape {
+        shape_of(&parse(value).unwrap(), letter)
+    }
+
+    #[test]
+    fn parses_the_default_shapes() {
+        assert_eq!(shape(DEFAULT, 'n'), CursorShape::Block);
+        assert_eq!(shape(DEFAULT, 'v'), CursorShape::Block);
+        assert_eq!(shape(DEFAULT, 'i'), CursorShape::Bar);
+        assert_eq!(shape(DEFAULT, 'c'), CursorShape::Bar);
+        assert_eq!(shape(DEFAULT, 'o'), CursorShape::Underline);
+        assert_eq!(shape(DEFAULT, 'r'), CursorShape::Underline);
+    }
+
+    #[test]
+    fn left_out_modes_keep_the_terminal_shape() {
+        assert_eq!(shape("", 'n'), CursorShape::Default);
+        assert_eq!(shape("i:bar,,", 'n'), CursorShape::Default);
+        assert_eq!(shape("i:bar,,", 'i'), CursorShape::Bar);
+    }
+
+    #[test]
+    fn a_later_entry_wins() {
+        assert_eq!(shape("n-i:block,i:underline", 'i'), CursorShape::Underline);
+        assert_eq!(shape("n-i:block,i:underline", 'n'), CursorShape::Block);
+    }
+
+    #[test]
+    fn rejects_broken_values() {
+        assert_eq!(parse("n"), Err(String::from("Missing : in guicursor: n")));
+        assert_eq!(
+            parse("n:box"),
+            Err(String::from("Unknown cursor shape: box"))
+        );
+        assert_eq!(
+            parse("n-x:bar"),
+            Err(String::from("Unknown mode in guicursor: x"))
+        );
+        assert_eq!(
+            parse("n-:bar"),
+            Err(String::from("Unknown mode in guicursor: "))
+        );
+    }
+
+    #[test]
+    fn pending_keys_only_change_the_mode_for_operators_and_r() {
+        assert_eq!(mode_letter(InputMode::Normal, &pending("")), 'n');
+        assert_eq!(mode_letter(InputMode::Normal, &pending("=")), 'o');
+        assert_eq!(mode_letter(InputMode::Normal, &pending("gc")), 'o');
+        assert_eq!(mode_letter(InputMode::Normal, &pending("zf")), 'o');
+        assert_eq!(mode_letter(InputMode::Normal, &pending("r")), 'r');
+        assert_eq!(mode_letter(InputMode::Normal, &pending("gt")), 'n');
+        assert_eq!(mode_letter(InputMode::Visual, &pending("=")), 'v');
+        assert_eq!(mode_letter(InputMode::Confirm, &pending("")), 'n');
+        assert_eq!(mode_letter(InputMode::Insert, &pending("")), 'i');
+        assert_eq!(mode_letter(InputMode::Command, &pending("")), 'c');
+    }
+
+    #[test]
+    fn reports_a_broken_value_once() {
+        let mut world = World::new();
+        world.insert_resource(OutputBuffer::new());
+        world.insert_resource(InputMode::Normal);
+        world.insert_resource(PendingKeys::default());
+        world.insert_resource(StatusBuffer::new());
+        world.insert_resource(Options {
+            guicursor: String::from("n:box"),
+            ..Options::default()
+        });
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(update_shape);
+        schedule.run(&mut world);
+
+        let message = world.resource::<StatusBuffer>().get_message().cloned();
+        assert_eq!(
+            message.map(|it| it.text).as_deref(),
+            Some("Unknown cursor shape: box")
+        );
+
+        world.resource_mut::<StatusBuffer>().clear_message();
+        schedule.run(&mut world);
+
+        assert!(world.resource::<StatusBuffer>().get_message().is_none());
+    }
+}
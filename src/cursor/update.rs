@@ -1,47 +1,167 @@
 use bevy_ecs::prelude::*;
-use crossterm::{cursor, execute};
 
 use crate::{
-    input::data::RowBuffer,
-    output::{buffer::OutputBuffer, OutputSize},
+    command::line::CommandLine,
+    fold,
+    input::{
+        data::{self, RowBuffer},
+        InputMode,
+    },
+    options::Options,
+    output::{buffer::OutputBuffer, wrap, OutputSize},
+    window::{ActiveView, View, Windows},
 };
 
 use super::{CursorDirection, CursorMoveEvent, CursorPosition};
 
 pub fn handle_cursor_move(
     row_buffer: Res<RowBuffer>,
-    output_size: Res<OutputSize>,
-    mut position: ResMut<CursorPosition>,
+    mut view: ActiveView,
     mut events: EventReader<CursorMoveEvent>,
+    windows: Res<Windows>,
+    options: Res<Options>,
 ) {
+    let width = wrap::text_width(&row_buffer, &windows.area(), &options);
+
     for CursorMoveEvent { direction } in events.iter() {
-        match direction {
-            CursorDirection::Left => position.x = position.x.saturating_sub(1),
-            CursorDirection::Down => {
-                position.y = position
-                    .y
-                    .saturating_add(1)
-                    .min(output_size.columns)
-                    .min(row_buffer.rows_count());
-            }
-            CursorDirection::Up => position.y = position.y.saturating_sub(1),
-            CursorDirection::Right => {
-                position.x = position.x.saturating_add(1).min(output_size.rows)
+        let mut view = view.get_mut();
+        let View {
+            position, cursors, ..
+        } = &mut *view;
+
+        // every cursor makes the same motion
+        let secondary = cursors.secondary_mut().iter_mut().map(|it| &mut it.head);
+
+        for position in std::iter::once(position).chain(secondary) {
+            move_cursor(position, direction, &row_buffer, width, &options);
+        }
+    }
+}
+
+fn move_cursor(
+    position: &mut CursorPosition,
+    direction: &CursorDirection,
+    row_buffer: &RowBuffer,
+    width: usize,
+    options: &Options,
+) {
+    match direction {
+        CursorDirection::Left => position.x = position.x.saturating_sub(1),
+        // a closed fold is moved over as if it were a single row, the cursor stays on its first row
+        CursorDirection::Down => {
+            let below = fold::next_line(row_buffer, position.y);
+
+            if below < row_buffer.rows_count() {
+                position.y = below;
             }
         }
+        CursorDirection::Up => {
+            position.y = fold::line_start(row_buffer, position.y.saturating_sub(1));
+        }
+        CursorDirection::Right => position.x = position.x.saturating_add(1),
+        CursorDirection::ScreenDown | CursorDirection::ScreenUp => {
+            move_screen_line(position, direction, row_buffer, width, options)
+        }
+    }
+
+    clamp_to_row(position, row_buffer);
+}
+
+/// Moves the cursor to the same column of the line above or below it on screen, which is on a
+/// different row once it's past the first or last line of its own.
+fn move_screen_line(
+    position: &mut CursorPosition,
+    direction: &CursorDirection,
+    row_buffer: &RowBuffer,
+    width: usize,
+    options: &Options,
+) {
+    if position.y >= row_buffer.rows_count() {
+        return;
     }
+
+    let (line, column) = wrap::line_of(row_buffer, (position.x, position.y), width, options);
+    let down = matches!(direction, CursorDirection::ScreenDown);
+    let height = wrap::height(row_buffer, position.y, width, options);
+
+    let (y, line) = match (down, line) {
+        (true, line) if line + 1 < height => (position.y, line + 1),
+        (false, line) if line > 0 => (position.y, line - 1),
+        (true, _) => match fold::next_line(row_buffer, position.y) {
+            below if below < row_buffer.rows_count() => (below, 0),
+            _ => return,
+        },
+        (false, _) if position.y == 0 => return,
+        (false, _) => {
+            let above = fold::line_start(row_buffer, position.y - 1);
+            (above, wrap::height(row_buffer, above, width, options) - 1)
+        }
+    };
+
+    let render = row_buffer.get_render(y);
+    let render_column = wrap::column_at(
+        &wrap::segments(render, width, options),
+        wrap::prefix(render, width, options),
+        line,
+        column,
+    );
+
+    position.y = y;
+    position.x = data::column_offset(row_buffer.get_row_at(y).contents(), render_column);
 }
 
-pub fn update_cursor(mut buffer: ResMut<OutputBuffer>, position: Res<CursorPosition>) {
-    if position.is_changed() {
-        execute!(
-            buffer,
-            cursor::MoveTo(
-                position.x.try_into().unwrap(),
-                position.y.try_into().unwrap()
-            ),
-            cursor::Show
-        )
-        .expect("Couldn't update cursor");
+/// Makes sure the cursor doesn't point past the end of the row it's on, or into the middle of a
+/// multi-byte character.
+pub fn clamp_to_row(position: &mut CursorPosition, row_buffer: &RowBuffer) {
+    if position.y >= row_buffer.rows_count() {
+        position.x = 0;
+        return;
+    }
+
+    let contents = row_buffer.get_row_at(position.y).contents();
+    let last = contents
+        .char_indices()
+        .last()
+        .map(|(index, _)| index)
+        .unwrap_or(0);
+
+    position.x = position.x.min(last);
+
+    while !contents.is_char_boundary(position.x) {
+        position.x -= 1;
     }
 }
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_cursor(
+    mut buffer: ResMut<OutputBuffer>,
+    view: ActiveView,
+    row_buffer: Res<RowBuffer>,
+    input_mode: Res<InputMode>,
+    command_line: Res<CommandLine>,
+    size: Res<OutputSize>,
+    options: Res<Options>,
+    windows: Res<Windows>,
+) {
+    let View {
+        position, offset, ..
+    } = view.get();
+
+    let (x, y) = match *input_mode {
+        InputMode::Command => (
+            command_line
+                .cursor_column()
+                .saturating_sub(command_line.scroll(size.columns)),
+            size.text_rows(),
+        ),
+        _ => wrap::screen_position(
+            &row_buffer,
+            (position.x, position.y),
+            offset,
+            &windows.area(),
+            &options,
+        ),
+    };
+
+    buffer.show_cursor(x, y);
+}
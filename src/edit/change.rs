@@ -0,0 +1,261 @@
+use crate::{cursor::multi::Selection, cursor::CursorPosition, input::data::RowBuffer};
+
+/// A single edit at one of the cursors, positions are `(x, y)`.
+pub enum Edit {
+    Insert {
+        at: (usize, usize),
+        text: String,
+    },
+    /// Removes the text from `start` up to (but not including) `end`.
+    Remove {
+        start: (usize, usize),
+        end: (usize, usize),
+    },
+    /// Replaces the text from `start` up to `end` with `text`.
+    Replace {
+        start: (usize, usize),
+        end: (usize, usize),
+        text: String,
+    },
+    /// Inserts `before` and `after` at `at`, leaving the cursor in between them.
+    InsertPair {
+        at: (usize, usize),
+        before: String,
+        after: String,
+    },
+}
+
+/// A change that was made to the rows, used to keep every other cursor on the text it was on.
+#[derive(Copy, Clone, Debug)]
+pub enum Change {
+    /// Text was inserted at `at`, and ends right before `end`.
+    Inserted {
+        at: (usize, usize),
+        end: (usize, usize),
+    },
+    /// Like [Change::Inserted], but a position right at `at` stays in front of the text.
+    InsertedAfter {
+        at: (usize, usize),
+        end: (usize, usize),
+    },
+    Removed {
+        start: (usize, usize),
+        end: (usize, usize),
+    },
+}
+
+impl Change {
+    /// Moves a position along with the text it was on. A position inside of removed text ends up
+    /// where the removed text started.
+    pub fn map(&self, position: &mut CursorPosition) {
+        let current = (position.y, position.x);
+
+        match *self {
+            Change::Inserted {
+                at: (x, y),
+                end: (end_x, end_y),
+            }
+            | Change::InsertedAfter {
+                at: (x, y),
+                end: (end_x, end_y),
+            } => {
+                let after = matches!(self, Change::InsertedAfter { .. });
+
+                if current < (y, x) || (after && current == (y, x)) {
+                    return;
+                }
+
+                if position.y == y {
+                    position.x = end_x + position.x - x;
+                }
+
+                position.y += end_y - y;
+            }
+            Change::Removed {
+                start: (start_x, start_y),
+                end: (end_x, end_y),
+            } => {
+                if current < (start_y, start_x) {
+                    return;
+                }
+
+                if current < (end_y, end_x) {
+                    (position.x, position.y) = (start_x, start_y);
+                    return;
+                }
+
+                if position.y == end_y {
+                    position.x = start_x + position.x - end_x;
+                }
+
+                position.y -= end_y - start_y;
+            }
+        }
+    }
+}
+
+/// Makes the edit `edit` returns for every selection, moving every other selection along with
+/// the text. Returns whether anything was changed, the first change begins a new undo step that
+/// puts the cursor back at `origin`.
+pub fn apply_each(
+    rows: &mut RowBuffer,
+    selections: &mut [Selection],
+    origin: (usize, usize),
+    mut edit: impl FnMut(&RowBuffer, &Selection) -> Option<Edit>,
+) -> bool {
+    let mut changed = false;
+
+    for index in 0..selections.len() {
+        let Some(edit) = edit(rows, &selections[index]) else {
+            continue;
+        };
+
+        if !changed {
+            rows.begin_change(origin);
+            changed = true;
+        }
+
+        let changes = match edit {
+            Edit::Insert { at, text } => vec![Change::Inserted {
+                at,
+                end: rows.insert_text(at.0, at.1, &text),
+            }],
+            Edit::Remove { start, end } => {
+                rows.remove_text(start, end);
+                vec![Change::Removed { start, end }]
+            }
+            Edit::Replace { start, end, text } => {
+                rows.remove_text(start, end);
+
+                vec![
+                    Change::Removed { start, end },
+                    Change::Inserted {
+                        at: start,
+                        end: rows.insert_text(start.0, start.1, &text),
+                    },
+                ]
+            }
+            Edit::InsertPair { at, before, after } => vec![
+                Change::InsertedAfter {
+                    at,
+                    end: rows.insert_text(at.0, at.1, &after),
+                },
+                Change::Inserted {
+                    at,
+                    end: rows.insert_text(at.0, at.1, &before),
+                },
+            ],
+        };
+
+        for change in changes {
+            for selection in selections.iter_mut() {
+                change.map(&mut selection.anchor);
+                change.map(&mut selection.head);
+            }
+        }
+    }
+
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(rows: &[&str]) -> RowBuffer {
+        let mut buffer = RowBuffer::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        buffer
+    }
+
+    fn contents(rows: &RowBuffer) -> Vec<&str> {
+        (0..rows.rows_count())
+            .map(|y| rows.get_row_at(y).contents())
+            .collect()
+    }
+
+    fn heads(selections: &[Selection]) -> Vec<(usize, usize)> {
+        selections.iter().map(|it| (it.head.x, it.head.y)).collect()
+    }
+
+    #[test]
+    fn inserts_at_every_cursor_and_moves_the_others_along() {
+        let mut rows = buffer(&["ab ab", "ab"]);
+        let mut selections = [(1, 0), (4, 0), (1, 1)].map(|(x, y)| Selection::at(x, y));
+
+        let changed = apply_each(&mut rows, &mut selections, (1, 0), |_, selection| {
+            Some(Edit::Insert {
+                at: (selection.head.x, selection.head.y),
+                text: String::from("xy"),
+            })
+        });
+
+        assert!(changed);
+        assert_eq!(contents(&rows), ["axyb axyb", "axyb"]);
+        assert_eq!(heads(&selections), [(3, 0), (8, 0), (3, 1)]);
+        assert_eq!(rows.undo_count(), 1);
+    }
+
+    #[test]
+    fn a_newline_moves_the_cursors_below_down() {
+        let mut rows = buffer(&["abcd", "ef"]);
+        let mut selections = [(2, 0), (3, 0), (1, 1)].map(|(x, y)| Selection::at(x, y));
+
+        apply_each(&mut rows, &mut selections, (2, 0), |_, selection| {
+            (selection.head == CursorPosition { x: 2, y: 0 }).then(|| Edit::Insert {
+                at: (2, 0),
+                text: String::from("\n"),
+            })
+        });
+
+        assert_eq!(contents(&rows), ["ab", "cd", "ef"]);
+        assert_eq!(heads(&selections), [(0, 1), (1, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn removing_text_moves_the_cursors_inside_of_it_to_its_start() {
+        let mut rows = buffer(&["abcdef"]);
+        let mut selections = [(1, 0), (3, 0), (5, 0)].map(|(x, y)| Selection::at(x, y));
+        let mut first = true;
+
+        apply_each(&mut rows, &mut selections, (1, 0), |_, _| {
+            std::mem::take(&mut first).then_some(Edit::Remove {
+                start: (1, 0),
+                end: (4, 0),
+            })
+        });
+
+        assert_eq!(contents(&rows), ["aef"]);
+        assert_eq!(heads(&selections), [(1, 0), (1, 0), (2, 0)]);
+    }
+
+    #[test]
+    fn a_pair_leaves_the_cursor_in_between() {
+        let mut rows = buffer(&["ab"]);
+        let mut selections = [Selection::at(1, 0)];
+
+        apply_each(&mut rows, &mut selections, (1, 0), |_, _| {
+            Some(Edit::InsertPair {
+                at: (1, 0),
+                before: String::from("("),
+                after: String::from(")"),
+            })
+        });
+
+        assert_eq!(contents(&rows), ["a()b"]);
+        assert_eq!(heads(&selections), [(2, 0)]);
+    }
+
+    #[test]
+    fn nothing_changes_without_an_edit() {
+        let mut rows = buffer(&["ab"]);
+        let mut selections = [Selection::at(0, 0)];
+
+        assert!(!apply_each(&mut rows, &mut selections, (0, 0), |_, _| None));
+        assert_eq!(rows.undo_count(), 0);
+    }
+}
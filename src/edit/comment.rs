@@ -0,0 +1,145 @@
+use super::indent;
+use crate::{
+    command::parser::LineRange,
+    input::data::{self, RowBuffer},
+    options::Options,
+};
+
+/// The text in front of and after a comment, from the `commentstring` option, where `%s` stands
+/// for the commented text, e.g. `// %s` or `/* %s */`. `None` without a `%s`.
+pub fn markers(options: &Options) -> Option<(&str, &str)> {
+    options.commentstring.split_once("%s")
+}
+
+/// Whether `contents` is commented out, ignoring the whitespace around the markers.
+fn is_commented(contents: &str, (left, right): (&str, &str)) -> bool {
+    let contents = contents.trim();
+    let (left, right) = (left.trim(), right.trim());
+
+    contents.len() >= left.len() + right.len()
+        && contents.starts_with(left)
+        && contents.ends_with(right)
+}
+
+/// Comments out `contents`, with the markers at column `width` of its indentation. A tab that
+/// covers that column is split up into spaces on either side of the markers.
+fn comment(contents: &str, width: usize, (left, right): (&str, &str)) -> String {
+    let offset = data::column_offset(contents, width);
+    let column = data::render_column(contents, offset);
+
+    if column == width {
+        let (indent, text) = contents.split_at(offset);
+        return format!("{indent}{left}{text}{right}");
+    }
+
+    let end = data::render_column(contents, offset + 1);
+    let indent = format!("{}{}", &contents[..offset], " ".repeat(width - column));
+    let text = format!("{}{}", " ".repeat(end - width), &contents[offset + 1..]);
+
+    format!("{indent}{left}{text}{right}")
+}
+
+/// Removes the markers from `contents`, along with the space that separates them from the text.
+fn uncomment(contents: &str, (left, right): (&str, &str)) -> String {
+    let indent = indent::indent_of(contents);
+    let text = contents.trim();
+    let text = &text[left.trim().len()..text.len() - right.trim().len()];
+
+    let text = text.strip_prefix(' ').unwrap_or(text);
+    let text = match right.trim().is_empty() {
+        true => text,
+        false => text.strip_suffix(' ').unwrap_or(text),
+    };
+
+    format!("{indent}{text}")
+}
+
+/// Toggles the comments of the rows in `range`. When every row that isn't blank is commented, they
+/// are all uncommented, otherwise every one of them is commented, including the ones that already
+/// were, so that uncommenting it again gives back the same rows. Markers go right after the
+/// smallest indentation in the range, measured in columns, keeping the rows aligned when they mix
+/// tabs and spaces. Returns the rows that change along with their new contents.
+pub fn toggle(rows: &RowBuffer, range: LineRange, markers: (&str, &str)) -> Vec<(usize, String)> {
+    let filled: Vec<(usize, &str)> = range
+        .rows()
+        .map(|y| (y, rows.get_row_at(y).contents()))
+        .filter(|(_, contents)| !contents.trim().is_empty())
+        .collect();
+
+    let commented = filled.iter().all(|(_, it)| is_commented(it, markers));
+    let indent = filled
+        .iter()
+        .map(|(_, it)| indent::indent_width(it))
+        .min()
+        .unwrap_or(0);
+
+    filled
+        .into_iter()
+        .map(|(y, contents)| match commented {
+            true => (y, uncomment(contents, markers)),
+            false => (y, comment(contents, indent, markers)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(rows: &[&str]) -> RowBuffer {
+        let mut buffer = RowBuffer::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        buffer
+    }
+
+    /// Toggles the comments of every row of `rows`, and returns all of the rows afterwards.
+    fn toggled(rows: &[&str], markers: (&str, &str)) -> Vec<String> {
+        let mut buffer = buffer(rows);
+        let range = LineRange {
+            start: 0,
+            end: rows.len() - 1,
+        };
+
+        for (y, contents) in toggle(&buffer, range, markers) {
+            buffer.set_row(y, &contents);
+        }
+
+        (0..buffer.rows_count())
+            .map(|y| buffer.get_row_at(y).contents().to_string())
+            .collect()
+    }
+
+    const LINE: (&str, &str) = ("// ", "");
+    const BLOCK: (&str, &str) = ("/* ", " */");
+
+    #[test]
+    fn comments_at_the_smallest_indentation() {
+        assert_eq!(
+            toggled(&["    a", "", "  b"], LINE),
+            ["  //   a", "", "  // b"]
+        );
+        assert_eq!(toggled(&["a"], BLOCK), ["/* a */"]);
+    }
+
+    #[test]
+    fn uncomments_when_every_row_is_commented() {
+        assert_eq!(toggled(&["  // a", "//b"], LINE), ["  a", "b"]);
+        assert_eq!(toggled(&["/* a */"], BLOCK), ["a"]);
+        assert_eq!(toggled(&["// a", "b"], LINE), ["// // a", "// b"]);
+    }
+
+    #[test]
+    fn measures_mixed_indentation_in_columns() {
+        assert_eq!(toggled(&["\ta", "    b"], LINE), ["\t// a", "    // b"]);
+        assert_eq!(toggled(&["\ta", "  b"], LINE), ["  //   a", "  // b"]);
+    }
+
+    #[test]
+    fn blank_rows_are_left_alone() {
+        assert_eq!(toggled(&["", "  "], LINE), ["", "  "]);
+    }
+}
@@ -0,0 +1,297 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::{change::Edit, comment, indent};
+use crate::{
+    command::parser::LineRange,
+    input::data::{self, RowBuffer},
+    options::Options,
+};
+
+lazy_static! {
+    static ref BULLET: Regex = Regex::new(r"^([-*+]|\d+[.)])\s+").unwrap();
+}
+
+/// The width text is formatted to with `gq` when `textwidth` isn't set.
+const DEFAULT_WIDTH: usize = 79;
+
+/// What comes in front of the text of a row: its indentation, a line comment marker from
+/// `commentstring` and a list bullet like `-` or `1.`, along with the whitespace after them.
+struct Leader<'a> {
+    /// The leader of the row itself.
+    text: &'a str,
+    /// The leader of the rows the text of this row continues on, where a bullet is replaced with
+    /// spaces to line the text up.
+    next: String,
+    comment: Option<&'a str>,
+    bullet: bool,
+}
+
+fn leader<'a>(contents: &'a str, options: &'a Options) -> Leader<'a> {
+    let mut end = indent::indent_of(contents).len();
+
+    // only line comments continue on the next row, block comments are formatted like text
+    let comment = comment::markers(options)
+        .map(|(left, right)| (left.trim(), right.trim()))
+        .filter(|(left, right)| !left.is_empty() && right.is_empty())
+        .map(|(left, _)| left)
+        .filter(|left| contents[end..].starts_with(left));
+
+    if let Some(comment) = comment {
+        end += comment.len();
+        end += indent::indent_of(&contents[end..]).len();
+    }
+
+    let bullet = BULLET.find(&contents[end..]).map(|it| it.end());
+    let next = match bullet {
+        Some(length) => {
+            let width = contents[end..end + length].chars().count();
+            format!("{}{}", &contents[..end], " ".repeat(width))
+        }
+        None => contents[..end].to_string(),
+    };
+
+    Leader {
+        text: &contents[..end + bullet.unwrap_or(0)],
+        next,
+        comment,
+        bullet: bullet.is_some(),
+    }
+}
+
+/// The width a row is formatted to, `textwidth` or 79 if it isn't set.
+pub fn text_width(options: &Options) -> usize {
+    match options.textwidth {
+        0 => DEFAULT_WIDTH,
+        width => width,
+    }
+}
+
+fn width_of(text: &str) -> usize {
+    data::render_column(text, text.len())
+}
+
+/// Fills rows up to `width` columns with `words`, the first row starts with `first` and every
+/// other row with `next`. A word that doesn't fit on a row of its own gets one anyway.
+fn fill(words: &[&str], first: &str, next: &str, width: usize) -> Vec<String> {
+    let mut filled = Vec::new();
+    let mut row = first.to_string();
+    let mut row_width = width_of(first);
+    let mut empty = true;
+
+    for word in words {
+        let word_width = word.chars().count();
+
+        if !empty && row_width + 1 + word_width > width {
+            filled.push(std::mem::replace(&mut row, next.to_string()));
+            row_width = width_of(next);
+            empty = true;
+        }
+
+        if !empty {
+            row.push(' ');
+            row_width += 1;
+        }
+
+        row.push_str(word);
+        row_width += word_width;
+        empty = false;
+    }
+
+    filled.push(row);
+    filled
+}
+
+/// The rows of `range` reflowed to `textwidth`. Paragraphs are separated by blank rows, by rows
+/// with a list bullet and by rows that start or stop being line comments. Every paragraph keeps
+/// the leader of its first row, which its other rows get as well.
+pub fn reflow(rows: &RowBuffer, range: LineRange, options: &Options) -> Vec<String> {
+    let width = text_width(options);
+    let mut reflowed = Vec::new();
+    let mut paragraph: Option<(Leader, Vec<&str>)> = None;
+
+    for y in range.rows() {
+        let contents = rows.get_row_at(y).contents();
+        let current = leader(contents, options);
+        let text = &contents[current.text.len()..];
+
+        let continues = paragraph.as_ref().is_some_and(|(first, _)| {
+            !current.bullet && current.comment == first.comment && !text.trim().is_empty()
+        });
+
+        if !continues {
+            if let Some((first, words)) = paragraph.take() {
+                reflowed.extend(fill(&words, first.text, &first.next, width));
+            }
+        }
+
+        if text.trim().is_empty() {
+            reflowed.push(contents.trim_end().to_string());
+            continue;
+        }
+
+        paragraph
+            .get_or_insert_with(|| (current, Vec::new()))
+            .1
+            .extend(text.split_whitespace());
+    }
+
+    if let Some((first, words)) = paragraph {
+        reflowed.extend(fill(&words, first.text, &first.next, width));
+    }
+
+    reflowed
+}
+
+/// Wraps the row when `char` typed at byte `x` of `contents` would end up past `textwidth`,
+/// moving the word being typed to a new row with the same leader. Only happens with `textwidth`
+/// set, and when `formatoptions` has `t` for text or `c` for comments.
+pub fn wrap(contents: &str, (x, y): (usize, usize), char: char, options: &Options) -> Option<Edit> {
+    if options.textwidth == 0 || char.is_whitespace() {
+        return None;
+    }
+
+    let before = contents.get(..x)?;
+    let current = leader(contents, options);
+
+    let flag = match current.comment {
+        Some(_) => 'c',
+        None => 't',
+    };
+
+    if !options.formatoptions.contains(flag) || width_of(before) < options.textwidth {
+        return None;
+    }
+
+    // the row breaks at the last blank that leaves the text in front of it within the width, or
+    // at the first blank when a single word is already too wide.
+    let breaks: Vec<usize> = before
+        .match_indices(char::is_whitespace)
+        .filter(|(index, _)| *index >= current.text.len())
+        .map(|(index, _)| before[..index].trim_end().len())
+        .collect();
+
+    let start = breaks
+        .iter()
+        .rev()
+        .find(|it| width_of(&before[..**it]) <= options.textwidth)
+        .or(breaks.first())?;
+
+    let text = before[*start..].trim_start();
+
+    Some(Edit::Replace {
+        start: (*start, y),
+        end: (x, y),
+        text: format!("\n{}{text}{char}", current.next),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(rows: &[&str]) -> RowBuffer {
+        let mut buffer = RowBuffer::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        buffer
+    }
+
+    fn options(textwidth: usize) -> Options {
+        Options {
+            textwidth,
+            commentstring: String::from("// %s"),
+            ..Options::default()
+        }
+    }
+
+    /// Every row of `rows` reflowed to `width` columns.
+    fn reflowed(rows: &[&str], width: usize) -> Vec<String> {
+        let range = LineRange {
+            start: 0,
+            end: rows.len() - 1,
+        };
+
+        reflow(&buffer(rows), range, &options(width))
+    }
+
+    /// Where typing `char` at byte `x` of `contents` breaks the row, and the text that replaces
+    /// everything from there up to `x`.
+    fn wrapped(contents: &str, x: usize, char: char, options: &Options) -> Option<(usize, String)> {
+        match wrap(contents, (x, 0), char, options)? {
+            Edit::Replace { start, end, text } if end == (x, 0) => Some((start.0, text)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn fills_rows_up_to_the_width() {
+        assert_eq!(reflowed(&["aaa bbb ccc ddd"], 10), ["aaa bbb", "ccc ddd"]);
+        assert_eq!(reflowed(&["aaa", "bbb", "ccc"], 0), ["aaa bbb ccc"]);
+        assert_eq!(
+            reflowed(&["a verylongword b"], 4),
+            ["a", "verylongword", "b"]
+        );
+    }
+
+    #[test]
+    fn paragraphs_keep_the_leader_of_their_first_row() {
+        assert_eq!(reflowed(&["  aaa", "bbb ccc"], 10), ["  aaa bbb", "  ccc"]);
+        assert_eq!(
+            reflowed(&["// aaa bbb", "// ccc", "ddd"], 14),
+            ["// aaa bbb ccc", "ddd"]
+        );
+        assert_eq!(reflowed(&["\taaa bbb"], 10), ["\taaa", "\tbbb"]);
+    }
+
+    #[test]
+    fn blank_rows_and_bullets_start_a_paragraph() {
+        assert_eq!(
+            reflowed(&["aaa", "  ", "bbb", "ccc"], 10),
+            ["aaa", "", "bbb ccc"]
+        );
+        assert_eq!(
+            reflowed(&["- aaa bbb ccc", "1. ddd"], 9),
+            ["- aaa bbb", "  ccc", "1. ddd"]
+        );
+    }
+
+    #[test]
+    fn wraps_the_word_being_typed_past_the_width() {
+        let six = options(6);
+
+        assert_eq!(
+            wrapped("aaa bbb", 7, 'c', &six),
+            Some((3, String::from("\nbbbc")))
+        );
+        assert_eq!(
+            wrapped(
+                "// aaa bbb",
+                10,
+                'c',
+                &Options {
+                    textwidth: 9,
+                    ..six.clone()
+                }
+            ),
+            Some((6, String::from("\n// bbbc")))
+        );
+        assert_eq!(wrapped("aaa", 3, 'b', &six), None);
+        assert_eq!(wrapped("aaa bbb", 7, ' ', &six), None);
+        assert_eq!(wrapped("aaa bbb", 7, 'c', &options(0)), None);
+    }
+
+    #[test]
+    fn only_wraps_what_formatoptions_asks_for() {
+        let options = Options {
+            formatoptions: String::from("c"),
+            ..options(6)
+        };
+
+        assert_eq!(wrapped("aaa bbb", 7, 'c', &options), None);
+        assert!(wrapped("// a bbb", 8, 'c', &options).is_some());
+    }
+}
@@ -0,0 +1,341 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::options::Options;
+
+lazy_static! {
+    static ref NUMBER: Regex = Regex::new(
+        r"(?P<date>\d{4}-\d{2}-\d{2})|(?P<bin>0[bB][01]+)|(?P<hex>0[xX][0-9a-fA-F]+)|(?P<dec>-?\d+)"
+    )
+    .unwrap();
+}
+
+pub const TOO_LARGE: &str = "Number too large to add to";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Decimal,
+    Hex,
+    Binary,
+    Date,
+}
+
+/// A number found in a row, from byte `start` up to `end`.
+#[derive(Debug)]
+pub struct Number<'a> {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    text: &'a str,
+    kind: Kind,
+}
+
+/// The first number in `contents` that ends after byte `from` and starts before byte `to`, which
+/// is the one under the cursor or the first one after it. Hex and binary numbers and ISO dates
+/// are only found when they're in the `nrformats` option.
+pub fn find<'a>(
+    contents: &'a str,
+    from: usize,
+    to: usize,
+    options: &Options,
+) -> Option<Number<'a>> {
+    let formats: Vec<&str> = options.nrformats.split(',').collect();
+    let mut position = 0;
+
+    while let Some(captures) = NUMBER.captures_at(contents, position) {
+        let (kind, found) = [
+            (Kind::Date, "date"),
+            (Kind::Binary, "bin"),
+            (Kind::Hex, "hex"),
+            (Kind::Decimal, "dec"),
+        ]
+        .into_iter()
+        .find_map(|(kind, name)| Some((kind, captures.name(name)?)))?;
+
+        // the prefix of a number in a format that isn't enabled is a decimal number of its own,
+        // like the `0` of `0x10`, and a date is a couple of decimal numbers.
+        let (kind, mut start, end) = match kind {
+            Kind::Decimal => (kind, found.start(), found.end()),
+            _ if formats.contains(&kind_name(kind)) => (kind, found.start(), found.end()),
+            _ => {
+                let digits = found.as_str().find(|it: char| !it.is_ascii_digit());
+                (
+                    Kind::Decimal,
+                    found.start(),
+                    found.start() + digits.unwrap_or(1),
+                )
+            }
+        };
+
+        // a dash right after a word is more likely a separator than a minus sign
+        if kind == Kind::Decimal
+            && contents[start..].starts_with('-')
+            && contents[..start]
+                .chars()
+                .next_back()
+                .is_some_and(|it| it.is_alphanumeric() || it == '_')
+        {
+            start += 1;
+        }
+
+        if start >= to {
+            return None;
+        }
+
+        if end > from {
+            return Some(Number {
+                start,
+                end,
+                text: &contents[start..end],
+                kind,
+            });
+        }
+
+        position = end;
+    }
+
+    None
+}
+
+fn kind_name(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Decimal => "",
+        Kind::Hex => "hex",
+        Kind::Binary => "bin",
+        Kind::Date => "date",
+    }
+}
+
+impl Number<'_> {
+    /// The number with `delta` added to it, keeping its format. Leading zeros keep the number as
+    /// wide as it was, and hex digits keep their case. `cursor` decides which part of a date
+    /// changes, the day unless it's on the year or the month. Fails if the number is too large
+    /// to add to.
+    pub fn add(&self, delta: i64, cursor: usize) -> Result<String, &'static str> {
+        Ok(match self.kind {
+            Kind::Decimal => add_decimal(self.text, delta)?,
+            Kind::Hex => {
+                let (prefix, digits) = self.text.split_at(2);
+                let value = u64::from_str_radix(digits, 16).map_err(|_| TOO_LARGE)?;
+                let added = value.wrapping_add_signed(delta);
+                let width = digits.len();
+
+                match digits.chars().any(|it| it.is_ascii_uppercase()) {
+                    true => format!("{prefix}{added:0width$X}"),
+                    false => format!("{prefix}{added:0width$x}"),
+                }
+            }
+            Kind::Binary => {
+                let (prefix, digits) = self.text.split_at(2);
+                let value = u64::from_str_radix(digits, 2).map_err(|_| TOO_LARGE)?;
+                let width = digits.len();
+
+                format!("{prefix}{:0width$b}", value.wrapping_add_signed(delta))
+            }
+            Kind::Date => {
+                let offset = match cursor >= self.start {
+                    true => cursor - self.start,
+                    false => self.text.len(),
+                };
+
+                add_date(self.text, delta, offset)?
+            }
+        })
+    }
+}
+
+fn add_decimal(text: &str, delta: i64) -> Result<String, &'static str> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+
+    let value = digits.parse::<i128>().map_err(|_| TOO_LARGE)?;
+    let value = match negative {
+        true => -value,
+        false => value,
+    };
+    let added = value.checked_add(delta.into()).ok_or(TOO_LARGE)?;
+
+    let width = match digits.starts_with('0') {
+        true => digits.len(),
+        false => 0,
+    };
+    let sign = if added < 0 { "-" } else { "" };
+
+    Ok(format!("{sign}{:0width$}", added.unsigned_abs()))
+}
+
+/// Adds `delta` to the part of the `YYYY-MM-DD` date `text` at byte `offset`: years, months or
+/// days. The day is kept within the month when changing the year or month.
+fn add_date(text: &str, delta: i64, offset: usize) -> Result<String, &'static str> {
+    let part = |range: std::ops::Range<usize>| text[range].parse::<i64>().unwrap_or(1);
+    let (year, month, day) = (part(0..4), part(5..7).clamp(1, 12), part(8..10).max(1));
+
+    let (year, month, day) = match offset {
+        0..=4 => (year.checked_add(delta).ok_or(TOO_LARGE)?, month, day),
+        5..=7 => {
+            let months = (year * 12 + month - 1)
+                .checked_add(delta)
+                .ok_or(TOO_LARGE)?;
+            (months.div_euclid(12), months.rem_euclid(12) + 1, day)
+        }
+        _ => {
+            let days = days_from_civil(year, month, day.min(days_in_month(year, month)))
+                .checked_add(delta)
+                .ok_or(TOO_LARGE)?;
+            civil_from_days(days).ok_or(TOO_LARGE)?
+        }
+    };
+
+    let year = year.clamp(0, 9999);
+    let day = day.min(days_in_month(year, month));
+
+    Ok(format!("{year:04}-{month:02}-{day:02}"))
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+/// The inverse of [days_from_civil], `None` for days too far out to count from 0000-03-01.
+fn civil_from_days(days: i64) -> Option<(i64, i64, i64)> {
+    let days = days.checked_add(719468)?;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+
+    let year = year_of_era + era * 400;
+
+    match month <= 2 {
+        true => Some((year + 1, month, day)),
+        false => Some((year, month, day)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Adds `delta` to the number `find` finds from byte `cursor` of `contents`, like `Ctrl-A`
+    /// does, and returns the changed row.
+    fn increment(contents: &str, cursor: usize, delta: i64, nrformats: &str) -> Option<String> {
+        let options = Options {
+            nrformats: nrformats.to_string(),
+            ..Options::default()
+        };
+        let number = find(contents, cursor, contents.len(), &options)?;
+
+        Some(format!(
+            "{}{}{}",
+            &contents[..number.start],
+            number.add(delta, cursor).ok()?,
+            &contents[number.end..]
+        ))
+    }
+
+    #[test]
+    fn adds_to_decimal_numbers() {
+        let add = |contents, cursor, delta| increment(contents, cursor, delta, "bin,hex");
+
+        assert_eq!(add("x = 9;", 0, 1).as_deref(), Some("x = 10;"));
+        assert_eq!(add("a 1 b 2", 4, 5).as_deref(), Some("a 1 b 7"));
+        assert_eq!(add("-1", 0, 3).as_deref(), Some("2"));
+        assert_eq!(add("1", 0, -3).as_deref(), Some("-2"));
+        assert_eq!(add("007", 0, 1).as_deref(), Some("008"));
+        assert_eq!(add("009", 0, 1).as_deref(), Some("010"));
+        assert_eq!(add("word", 0, 1), None);
+    }
+
+    #[test]
+    fn leaves_numbers_too_large_to_add_to_alone() {
+        let add = |contents, delta| increment(contents, 0, delta, "bin,hex");
+        let large = "9".repeat(40);
+
+        assert_eq!(
+            add("9223372036854775808", 1).as_deref(),
+            Some("9223372036854775809")
+        );
+        assert_eq!(add(&large, 1), None);
+        assert_eq!(add("0x11112222333344445", 1), None);
+        assert_eq!(add(&format!("0b1{}", "0".repeat(64)), 1), None);
+    }
+
+    #[test]
+    fn a_dash_after_a_word_is_no_minus() {
+        let add = |contents, cursor| increment(contents, cursor, 1, "bin,hex");
+
+        assert_eq!(add("foo-1", 0).as_deref(), Some("foo-2"));
+        assert_eq!(add("x -1", 0).as_deref(), Some("x 0"));
+    }
+
+    #[test]
+    fn keeps_the_format_of_hex_and_binary_numbers() {
+        let add = |contents, delta| increment(contents, 0, delta, "bin,hex");
+
+        assert_eq!(add("0xff", 1).as_deref(), Some("0x100"));
+        assert_eq!(add("0x0F", 1).as_deref(), Some("0x10"));
+        assert_eq!(add("0x0a", 1).as_deref(), Some("0x0b"));
+        assert_eq!(add("0b0011", 1).as_deref(), Some("0b0100"));
+        assert_eq!(add("0x00", -1).as_deref(), Some("0xffffffffffffffff"));
+    }
+
+    #[test]
+    fn formats_left_out_of_nrformats_are_decimal() {
+        assert_eq!(increment("0x10", 0, 1, "").as_deref(), Some("1x10"));
+        assert_eq!(increment("0b1", 0, 1, "hex").as_deref(), Some("1b1"));
+        assert_eq!(
+            increment("2024-01-31", 0, 1, "").as_deref(),
+            Some("2025-01-31")
+        );
+    }
+
+    #[test]
+    fn adds_to_the_part_of_a_date_under_the_cursor() {
+        let add = |cursor, delta| increment("on 2024-01-31", cursor, delta, "date");
+
+        assert_eq!(add(0, 1).as_deref(), Some("on 2024-02-01"));
+        assert_eq!(add(4, 1).as_deref(), Some("on 2025-01-31"));
+        assert_eq!(add(8, 1).as_deref(), Some("on 2024-02-29"));
+        assert_eq!(add(8, 13).as_deref(), Some("on 2025-02-28"));
+        assert_eq!(add(11, -31).as_deref(), Some("on 2023-12-31"));
+    }
+
+    #[test]
+    fn leaves_dates_too_far_out_to_add_to_alone() {
+        let add = |cursor, delta| increment("2024-01-31", cursor, delta, "date");
+
+        assert_eq!(add(0, 100_000).as_deref(), Some("9999-01-31"));
+        assert_eq!(add(0, i64::MAX), None);
+        assert_eq!(add(5, i64::MAX), None);
+        assert_eq!(add(8, i64::MAX), None);
+    }
+}
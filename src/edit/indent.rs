@@ -0,0 +1,203 @@
+use crate::{
+    command::parser::LineRange,
+    input::data::{self, RowBuffer, TAB_SIZE},
+    options::Options,
+};
+
+/// The leading whitespace of `contents`.
+pub fn indent_of(contents: &str) -> &str {
+    &contents[..contents.len() - contents.trim_start().len()]
+}
+
+/// The width of the indentation of `contents`, in columns.
+pub fn indent_width(contents: &str) -> usize {
+    data::render_column(contents, indent_of(contents).len())
+}
+
+/// Indentation that is `width` columns wide, made of tabs unless `expandtab` is set.
+pub fn make_indent(width: usize, options: &Options) -> String {
+    match options.expandtab {
+        true => " ".repeat(width),
+        false => "\t".repeat(width / TAB_SIZE) + &" ".repeat(width % TAB_SIZE),
+    }
+}
+
+/// The indentation of a row containing `contents`, right below a row containing `above`. It's
+/// the indentation of `above`, and with `smart` rules, another level after an opening character
+/// like `{` or one level less for a row starting with a closing character like `}`.
+pub fn indent_for(above: &str, contents: &str, smart: bool, options: &Options) -> String {
+    let mut width = indent_width(above);
+
+    if smart {
+        if above
+            .trim_end()
+            .ends_with(|it| options.indentopen.contains(it))
+        {
+            width += options.shiftwidth;
+        }
+
+        if contents
+            .trim_start()
+            .starts_with(|it| options.indentclose.contains(it))
+        {
+            width = width.saturating_sub(options.shiftwidth);
+        }
+    }
+
+    make_indent(width, options)
+}
+
+/// The indentation of a new row that starts with `contents`, below a row whose text before the
+/// new row is `above`. Follows the `autoindent` and `smartindent` options.
+pub fn new_row_indent(above: &str, contents: &str, options: &Options) -> String {
+    match options.autoindent || options.smartindent {
+        true => indent_for(above, contents, options.smartindent, options),
+        false => String::new(),
+    }
+}
+
+/// The closest row above `y` that isn't blank.
+pub fn row_above(rows: &RowBuffer, y: usize) -> Option<&str> {
+    (0..y)
+        .rev()
+        .map(|it| rows.get_row_at(it).contents())
+        .find(|it| !it.trim().is_empty())
+}
+
+/// Reindents every row in `range` with the smart indentation rules, based on the row above it.
+/// Blank rows are emptied, and a range starting at the first row keeps its indentation. Returns
+/// the rows that change along with their new contents.
+pub fn reindent(rows: &RowBuffer, range: LineRange, options: &Options) -> Vec<(usize, String)> {
+    let mut changed = Vec::new();
+    let mut above = row_above(rows, range.start).map(String::from);
+
+    for y in range.rows() {
+        let contents = rows.get_row_at(y).contents();
+
+        let reindented = match (contents.trim().is_empty(), &above) {
+            (true, _) => String::new(),
+            (false, Some(above)) => {
+                indent_for(above, contents, true, options) + contents.trim_start()
+            }
+            (false, None) => contents.to_string(),
+        };
+
+        if !reindented.is_empty() {
+            above = Some(reindented.clone());
+        }
+
+        if reindented != contents {
+            changed.push((y, reindented));
+        }
+    }
+
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(rows: &[&str]) -> RowBuffer {
+        let mut buffer = RowBuffer::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        buffer
+    }
+
+    #[test]
+    fn keeps_the_indentation_of_the_row_above() {
+        let options = Options::default();
+
+        assert_eq!(indent_for("    x();", "y();", true, &options), "    ");
+        assert_eq!(indent_for("    x() {", "y();", false, &options), "    ");
+        assert_eq!(indent_width("\t  x();"), 6);
+    }
+
+    #[test]
+    fn opens_a_level_after_an_opening_character() {
+        let options = Options::default();
+
+        assert_eq!(indent_for("fn main() {", "", true, &options), "    ");
+        assert_eq!(indent_for("    call(  ", "", true, &options), "        ");
+        assert_eq!(indent_for("def main():", "", true, &options), "");
+    }
+
+    #[test]
+    fn dedents_a_row_starting_with_a_closing_character() {
+        let options = Options::default();
+
+        assert_eq!(indent_for("        x();", "}", true, &options), "    ");
+        assert_eq!(indent_for("    call(", ")", true, &options), "    ");
+        assert_eq!(indent_for("x();", "]", true, &options), "");
+    }
+
+    #[test]
+    fn opens_a_level_after_the_characters_of_the_filetype() {
+        // python also opens a level after a `:`, like `:set indentopen` for its filetype does
+        let options = Options {
+            indentopen: String::from("{([:"),
+            ..Options::default()
+        };
+
+        assert_eq!(indent_for("def main():", "pass", true, &options), "    ");
+        assert_eq!(
+            indent_for("    if x:  ", "pass", true, &options),
+            "        "
+        );
+    }
+
+    #[test]
+    fn indents_with_tabs_unless_expandtab_is_set() {
+        let tabs = Options {
+            expandtab: false,
+            ..Options::default()
+        };
+        let spaces = Options::default();
+
+        assert_eq!(make_indent(10, &tabs), "\t\t  ");
+        assert_eq!(make_indent(10, &spaces), " ".repeat(10));
+        assert_eq!(indent_for("\tif x {", "", true, &tabs), "\t\t");
+        assert_eq!(indent_for("\tif x {", "", true, &spaces), " ".repeat(8));
+    }
+
+    #[test]
+    fn new_rows_are_only_indented_with_autoindent_or_smartindent() {
+        let off = Options {
+            autoindent: false,
+            smartindent: false,
+            ..Options::default()
+        };
+        let auto = Options {
+            smartindent: false,
+            ..Options::default()
+        };
+
+        assert_eq!(new_row_indent("    x {", "", &off), "");
+        assert_eq!(new_row_indent("    x {", "", &auto), "    ");
+        assert_eq!(
+            new_row_indent("    x {", "", &Options::default()),
+            "        "
+        );
+    }
+
+    #[test]
+    fn reindents_a_range_from_the_row_above_it() {
+        let rows = buffer(&["fn main() {", "x();", "  ", "if y {", "z();", "  }", "}"]);
+        let range = LineRange { start: 1, end: 6 };
+
+        assert_eq!(
+            reindent(&rows, range, &Options::default()),
+            [
+                (1, String::from("    x();")),
+                (2, String::new()),
+                (3, String::from("    if y {")),
+                (4, String::from("        z();")),
+                (5, String::from("    }")),
+            ]
+        );
+    }
+}
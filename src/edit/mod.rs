@@ -0,0 +1,1035 @@
+use bevy_ecs::prelude::*;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::{
+    command::parser::LineRange,
+    cursor::{
+        multi::{clamp_to_row_end, Selection},
+        update::clamp_to_row,
+        CursorPosition, VisualAnchor,
+    },
+    input::{data::RowBuffer, InputMode},
+    options::Options,
+    status::StatusBuffer,
+    syntax::Highlighter,
+    window::{ActiveView, View},
+    SystemType,
+};
+
+use self::{
+    change::{apply_each, Edit},
+    operator::{Motion, Operator},
+};
+
+pub mod change;
+pub mod comment;
+pub mod format;
+pub mod increment;
+pub mod indent;
+pub mod operator;
+pub mod pairs;
+pub mod rows;
+
+pub fn init(world: &mut World, schedule: &mut Schedule) {
+    world.insert_resource(Events::<EditEvent>::default());
+
+    schedule.add_systems(
+        handle_edit
+            .after(SystemType::Input)
+            .before(SystemType::Cursor),
+    );
+}
+
+/// Where the cursors go when insert mode is started.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InsertPosition {
+    /// `i`
+    Before,
+    /// `a`
+    After,
+    /// `I`, the first non-blank character of the row.
+    RowStart,
+    /// `A`
+    RowEnd,
+}
+
+/// Edits made at every cursor at once.
+#[derive(Event)]
+pub enum EditEvent {
+    StartInsert(InsertPosition),
+    /// A key typed in insert mode.
+    Key(KeyEvent),
+    /// `x` in normal mode, removes the character under the cursor.
+    RemoveChar,
+    /// `d` and `x` in visual mode remove the selection, `c` starts insert mode in its place.
+    RemoveSelection {
+        insert: bool,
+    },
+    /// `r` followed by `char`, replaces `count` characters from the cursor with it. Nothing is
+    /// replaced when the row doesn't have that many characters left.
+    ReplaceChar {
+        char: char,
+        count: usize,
+    },
+    /// `o` and `O`, opens a new row below (or above) the cursor and starts insert mode on it.
+    OpenRow {
+        above: bool,
+    },
+    /// An operator followed by a motion like `=ip`, applied to the rows the motion covers from
+    /// every cursor. `count` is the count typed with it, or 0 without one.
+    Operator {
+        operator: Operator,
+        motion: Motion,
+        count: usize,
+    },
+    /// `=` in visual mode, reindents every selected row.
+    ReindentSelection,
+    /// `gc` in visual mode, toggles the comments of every selected row.
+    ToggleCommentSelection,
+    /// `gq` in visual mode, reflows every selected row.
+    FormatSelection,
+    /// `J` and `gJ` join `count` rows starting at the cursor, `normalize` replaces the
+    /// indentation in between with a single space.
+    Join {
+        count: usize,
+        normalize: bool,
+    },
+    /// `J` and `gJ` in visual mode join the selected rows.
+    JoinSelection {
+        normalize: bool,
+    },
+    /// `Alt-j` and `Alt-k` move the row of every cursor, or every selected row in visual mode,
+    /// a row down or up.
+    MoveRows {
+        down: bool,
+    },
+    /// `Alt-J` and `Alt-K` copy the row of every cursor, or every selected row in visual mode,
+    /// below or above itself. The cursors move along to the copy below.
+    DuplicateRows {
+        down: bool,
+    },
+    /// `Ctrl-A` and `Ctrl-X`, adds `delta` to the number under or after the cursor.
+    Increment {
+        delta: i64,
+    },
+    /// `Ctrl-A` and `Ctrl-X` in visual mode add `delta` to the first number of every selected
+    /// row. `g Ctrl-A` and `g Ctrl-X` are `progressive`, adding `delta` once more for every row.
+    IncrementSelection {
+        delta: i64,
+        progressive: bool,
+    },
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn handle_edit(
+    mut events: EventReader<EditEvent>,
+    mut rows: ResMut<RowBuffer>,
+    mut view: ActiveView,
+    mut anchor: ResMut<VisualAnchor>,
+    mut input_mode: ResMut<InputMode>,
+    options: Res<Options>,
+    mut status: ResMut<StatusBuffer>,
+    highlighter: Res<Highlighter>,
+) {
+    for event in events.iter() {
+        let mut view = view.get_mut();
+        let View {
+            position, cursors, ..
+        } = &mut *view;
+        let mut selections = cursors.collect(&anchor, position);
+        let origin = (position.x, position.y);
+
+        match event {
+            EditEvent::StartInsert(at) => {
+                for selection in &mut selections {
+                    move_for_insert(&rows, &mut selection.head, *at);
+                }
+
+                // everything typed until insert mode is left again is undone as a single step
+                rows.begin_group(origin);
+                *input_mode = InputMode::Insert;
+            }
+            EditEvent::Key(key) => match (key.code, key.modifiers) {
+                (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                    rows.end_group();
+                    *input_mode = InputMode::Normal;
+
+                    // like vim, leaving insert mode puts the cursor on the character before it
+                    for selection in &mut selections {
+                        selection.head.x = previous_char(&rows, &selection.head).unwrap_or(0);
+                    }
+                }
+                (KeyCode::Char(char), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                    apply_each(&mut rows, &mut selections, origin, |rows, selection| {
+                        let head = &selection.head;
+                        let in_text = highlighter.in_text(rows, head.x, head.y);
+
+                        Some(type_char(rows, head, char, in_text, &options))
+                    });
+                }
+                (KeyCode::Tab, _) => insert(&mut rows, &mut selections, origin, "\t"),
+                (KeyCode::Enter, _) => {
+                    apply_each(&mut rows, &mut selections, origin, |rows, selection| {
+                        let head = &selection.head;
+                        let contents = row_contents(rows, head.y);
+
+                        if let Some(edit) = pairs::enter(contents, (head.x, head.y), &options) {
+                            return Some(edit);
+                        }
+
+                        let (before, after) = contents.split_at(head.x.min(contents.len()));
+
+                        Some(Edit::Insert {
+                            at: (head.x, head.y),
+                            text: format!("\n{}", indent::new_row_indent(before, after, &options)),
+                        })
+                    });
+                }
+                (KeyCode::Backspace, _) | (KeyCode::Char('h'), KeyModifiers::CONTROL) => {
+                    apply_each(&mut rows, &mut selections, origin, |rows, selection| {
+                        let head = &selection.head;
+                        let contents = row_contents(rows, head.y);
+
+                        if let Some(edit) = pairs::backspace(contents, (head.x, head.y), &options) {
+                            return Some(edit);
+                        }
+
+                        // at the start of a row, the row is joined with the one above it
+                        let start = match previous_char(rows, head) {
+                            Some(x) => (x, head.y),
+                            None if head.y > 0 => {
+                                (rows.get_row_at(head.y - 1).contents().len(), head.y - 1)
+                            }
+                            None => return None,
+                        };
+
+                        Some(Edit::Remove {
+                            start,
+                            end: (head.x, head.y),
+                        })
+                    });
+                }
+                (KeyCode::Delete, _) => {
+                    apply_each(&mut rows, &mut selections, origin, |rows, selection| {
+                        let head = &selection.head;
+
+                        // at the end of a row, the row below it is joined into it
+                        let end = match next_char(rows, head) {
+                            Some(x) => (x, head.y),
+                            None if head.y + 1 < rows.rows_count() => (0, head.y + 1),
+                            None => return None,
+                        };
+
+                        Some(Edit::Remove {
+                            start: (head.x, head.y),
+                            end,
+                        })
+                    });
+                }
+                _ => {}
+            },
+            EditEvent::RemoveChar => {
+                apply_each(&mut rows, &mut selections, origin, |rows, selection| {
+                    let head = &selection.head;
+
+                    next_char(rows, head).map(|x| Edit::Remove {
+                        start: (head.x, head.y),
+                        end: (x, head.y),
+                    })
+                });
+            }
+            EditEvent::ReplaceChar { char, count } => {
+                let count = (*count).max(1);
+                let mut edited = Vec::new();
+
+                apply_each(&mut rows, &mut selections, origin, |rows, selection| {
+                    let head = &selection.head;
+                    let end = row_contents(rows, head.y)
+                        .get(head.x..)
+                        .and_then(|it| it.char_indices().nth(count - 1))
+                        .map(|(index, char)| head.x + index + char.len_utf8());
+
+                    edited.push(end.is_some());
+
+                    end.map(|end| Edit::Replace {
+                        start: (head.x, head.y),
+                        end: (end, head.y),
+                        text: char.to_string().repeat(count),
+                    })
+                });
+
+                // like vim, the cursor ends up on the last character that was replaced
+                for (selection, edited) in selections.iter_mut().zip(edited) {
+                    if edited {
+                        selection.head.x = previous_char(&rows, &selection.head).unwrap_or(0);
+                    }
+                }
+            }
+            EditEvent::RemoveSelection { insert } => {
+                if *insert {
+                    rows.begin_group(origin);
+                }
+
+                apply_each(&mut rows, &mut selections, origin, |rows, selection| {
+                    let (start, end) = selection.ordered();
+
+                    // the selection includes the character its end is on
+                    let end_x = next_char(rows, end).unwrap_or(end.x);
+
+                    Some(Edit::Remove {
+                        start: (start.x, start.y),
+                        end: (end_x, end.y),
+                    })
+                });
+
+                *input_mode = match insert {
+                    true => InputMode::Insert,
+                    false => InputMode::Normal,
+                };
+            }
+            EditEvent::OpenRow { above } => {
+                rows.begin_group(origin);
+
+                // `o` splits the row right at its end. `O` does the same at the start of the row,
+                // and then moves up onto the row that was opened.
+                for selection in &mut selections {
+                    selection.head.x = match above {
+                        true => 0,
+                        false => row_contents(&rows, selection.head.y).len(),
+                    };
+                }
+
+                apply_each(&mut rows, &mut selections, origin, |rows, selection| {
+                    let head = &selection.head;
+                    let contents = row_contents(rows, head.y);
+
+                    let text = match above {
+                        true => {
+                            let indent = indent_of_new_row_above(rows, head.y, &options);
+                            format!("{indent}\n")
+                        }
+                        false => format!("\n{}", indent::new_row_indent(contents, "", &options)),
+                    };
+
+                    Some(Edit::Insert {
+                        at: (head.x, head.y),
+                        text,
+                    })
+                });
+
+                if *above {
+                    for selection in &mut selections {
+                        selection.head.y -= 1;
+                        selection.head.x = row_contents(&rows, selection.head.y).len();
+                    }
+                }
+
+                *input_mode = InputMode::Insert;
+            }
+            EditEvent::Operator {
+                operator,
+                motion,
+                count,
+            } => {
+                let ranges: Vec<LineRange> = selections
+                    .iter()
+                    .filter_map(|it| operator::range(&rows, it.head.y, *motion, *count))
+                    .collect();
+
+                if ranges.len() == selections.len() {
+                    let applied = operate(
+                        &mut rows,
+                        &mut selections,
+                        *operator,
+                        &ranges,
+                        origin,
+                        &options,
+                    );
+
+                    if let Err(error) = applied {
+                        status.error(error);
+                    }
+                }
+            }
+            EditEvent::ReindentSelection => {
+                let ranges = selected_ranges(&selections);
+                reindent(&mut rows, &mut selections, &ranges, origin, &options);
+                *input_mode = InputMode::Normal;
+            }
+            EditEvent::Increment { delta } => {
+                let mut edited = Vec::new();
+                let mut failed = None;
+
+                apply_each(&mut rows, &mut selections, origin, |rows, selection| {
+                    let head = &selection.head;
+                    let contents = row_contents(rows, head.y);
+                    let found = increment::find(contents, head.x, contents.len(), &options);
+
+                    // a number too large to add to is left alone rather than replaced
+                    let added = found.as_ref().map(|number| number.add(*delta, head.x));
+                    edited.push(matches!(added, Some(Ok(_))));
+
+                    let (number, added) = found.zip(added)?;
+
+                    match added {
+                        Ok(text) => Some(Edit::Replace {
+                            start: (number.start, head.y),
+                            end: (number.end, head.y),
+                            text,
+                        }),
+                        Err(error) => {
+                            failed = Some(error);
+                            None
+                        }
+                    }
+                });
+
+                if let Some(error) = failed {
+                    status.error(error);
+                }
+
+                // like vim, the cursor ends up on the last character of the number
+                for (selection, edited) in selections.iter_mut().zip(edited) {
+                    if edited {
+                        selection.head.x = previous_char(&rows, &selection.head).unwrap_or(0);
+                    }
+                }
+            }
+            EditEvent::IncrementSelection { delta, progressive } => {
+                let applied = increment_selections(
+                    &mut rows,
+                    &mut selections,
+                    origin,
+                    *delta,
+                    *progressive,
+                    &options,
+                );
+
+                if let Err(error) = applied {
+                    status.error(error);
+                }
+
+                *input_mode = InputMode::Normal;
+            }
+            EditEvent::FormatSelection => {
+                let ranges = selected_ranges(&selections);
+                let applied = operate(
+                    &mut rows,
+                    &mut selections,
+                    Operator::Format,
+                    &ranges,
+                    origin,
+                    &options,
+                );
+
+                if let Err(error) = applied {
+                    status.error(error);
+                }
+
+                *input_mode = InputMode::Normal;
+            }
+            EditEvent::Join { count, normalize } => {
+                let ranges = extended_ranges(&rows, &selections, (*count).max(2) as isize - 1);
+                join(&mut rows, &mut selections, &ranges, origin, *normalize);
+            }
+            EditEvent::JoinSelection { normalize } => {
+                let ranges = selected_ranges(&selections);
+                join(&mut rows, &mut selections, &ranges, origin, *normalize);
+                *input_mode = InputMode::Normal;
+            }
+            EditEvent::MoveRows { down } => {
+                let blocks = rows::merge(&selected_ranges(&selections), true);
+
+                if rows::can_move(&rows, &blocks, *down) {
+                    rows.begin_change(origin);
+                    rows::move_blocks(&mut rows, &blocks, *down);
+
+                    for selection in &mut selections {
+                        for position in [&mut selection.anchor, &mut selection.head] {
+                            position.y = match down {
+                                true => position.y + 1,
+                                false => position.y - 1,
+                            };
+                        }
+                    }
+                }
+            }
+            EditEvent::DuplicateRows { down } => {
+                if rows.rows_count() == 0 {
+                    continue;
+                }
+
+                let blocks = rows::merge(&selected_ranges(&selections), true);
+
+                rows.begin_change(origin);
+                rows::duplicate_blocks(&mut rows, &blocks, *down);
+
+                // every copy pushes the rows below it further down
+                for selection in &mut selections {
+                    let index = blocks
+                        .iter()
+                        .position(|it| it.contains(selection.head.y))
+                        .unwrap_or(0);
+                    let above: usize = blocks[..index].iter().map(|it| it.end - it.start + 1).sum();
+                    let own = match down {
+                        true => blocks[index].end - blocks[index].start + 1,
+                        false => 0,
+                    };
+
+                    for position in [&mut selection.anchor, &mut selection.head] {
+                        position.y += above + own;
+                    }
+                }
+            }
+            EditEvent::ToggleCommentSelection => {
+                let ranges = selected_ranges(&selections);
+                let applied = operate(
+                    &mut rows,
+                    &mut selections,
+                    Operator::Comment,
+                    &ranges,
+                    origin,
+                    &options,
+                );
+
+                if let Err(error) = applied {
+                    status.error(error);
+                }
+
+                *input_mode = InputMode::Normal;
+            }
+        }
+
+        for selection in &mut selections {
+            match *input_mode {
+                InputMode::Insert => clamp_to_row_end(&mut selection.head, &rows),
+                _ => clamp_to_row(&mut selection.head, &rows),
+            }
+
+            if *input_mode != InputMode::Visual {
+                selection.anchor = selection.head.clone();
+            }
+        }
+
+        cursors.store(selections, &mut anchor, position);
+    }
+}
+
+/// Types `char` at `head`, completing pairs like `()` along the way. A closing character typed on
+/// a row that's still blank up to the cursor moves the row back to the indentation of the
+/// opening row, following `smartindent`.
+fn type_char(
+    rows: &RowBuffer,
+    head: &CursorPosition,
+    char: char,
+    in_text: bool,
+    options: &Options,
+) -> Edit {
+    let contents = row_contents(rows, head.y);
+    let before = &contents[..head.x.min(contents.len())];
+
+    if let Some(edit) = pairs::type_char(contents, (head.x, head.y), char, in_text, options) {
+        return edit;
+    }
+
+    if options.smartindent && options.indentclose.contains(char) && before.trim().is_empty() {
+        if let Some(above) = indent::row_above(rows, head.y) {
+            return Edit::Replace {
+                start: (0, head.y),
+                end: (head.x, head.y),
+                text: format!(
+                    "{}{char}",
+                    indent::indent_for(above, &char.to_string(), true, options)
+                ),
+            };
+        }
+    }
+
+    if let Some(edit) = format::wrap(contents, (head.x, head.y), char, options) {
+        return edit;
+    }
+
+    Edit::Insert {
+        at: (head.x, head.y),
+        text: char.to_string(),
+    }
+}
+
+/// The indentation of a row opened above row `y` with `O`, which is what a row opened below the
+/// row above it would get. There's nothing to base it on above the first row, so it gets the
+/// indentation of the first row.
+fn indent_of_new_row_above(rows: &RowBuffer, y: usize, options: &Options) -> String {
+    let contents = row_contents(rows, y);
+
+    match y.checked_sub(1) {
+        Some(above) if options.autoindent || options.smartindent => {
+            indent::new_row_indent(row_contents(rows, above), contents, options)
+        }
+        None if options.autoindent || options.smartindent => indent::indent_of(contents).into(),
+        _ => String::new(),
+    }
+}
+
+/// The rows from every cursor up to `extend` rows away from it.
+fn extended_ranges(rows: &RowBuffer, selections: &[Selection], extend: isize) -> Vec<LineRange> {
+    let last = rows.rows_count().saturating_sub(1);
+
+    selections
+        .iter()
+        .map(|it| {
+            let other = it.head.y.saturating_add_signed(extend);
+            LineRange {
+                start: it.head.y.min(other),
+                end: it.head.y.max(other).min(last),
+            }
+        })
+        .collect()
+}
+
+/// The rows every selection is on.
+fn selected_ranges(selections: &[Selection]) -> Vec<LineRange> {
+    selections
+        .iter()
+        .map(|it| {
+            let (start, end) = it.ordered();
+            LineRange {
+                start: start.y,
+                end: end.y,
+            }
+        })
+        .collect()
+}
+
+/// Adds `delta` to the first number in the selected part of every row of every selection, as a
+/// single undo step. Every cursor moves to the start of its selection. Numbers too large to add
+/// to are left alone, and fail once every other number has been added to.
+fn increment_selections(
+    rows: &mut RowBuffer,
+    selections: &mut [Selection],
+    origin: (usize, usize),
+    delta: i64,
+    progressive: bool,
+    options: &Options,
+) -> Result<(), &'static str> {
+    let mut failed = None;
+    rows.begin_group(origin);
+
+    for selection in selections.iter_mut() {
+        let (start, end) = selection.ordered();
+        let (start, end) = (start.clone(), end.clone());
+        let mut step = 0;
+
+        for y in start.y..=end.y.min(rows.rows_count().saturating_sub(1)) {
+            let contents = row_contents(rows, y);
+            let from = if y == start.y { start.x } else { 0 };
+            let to = match y == end.y {
+                true => next_char(rows, &end).unwrap_or(end.x + 1),
+                false => contents.len(),
+            };
+
+            // only a number that starts within the selection counts
+            let Some(number) =
+                increment::find(contents, from, to, options).filter(|it| it.start >= from)
+            else {
+                continue;
+            };
+
+            step += 1;
+
+            // dates change by days, as if the cursor was on the day
+            let added = match progressive {
+                true => delta
+                    .checked_mul(step)
+                    .ok_or(increment::TOO_LARGE)
+                    .and_then(|delta| number.add(delta, number.end)),
+                false => number.add(delta, number.end),
+            };
+            let added = match added {
+                Ok(added) => added,
+                Err(error) => {
+                    failed = Some(error);
+                    continue;
+                }
+            };
+            let range = ((number.start, y), (number.end, y));
+
+            rows.begin_change(origin);
+            rows.remove_text(range.0, range.1);
+            rows.insert_text(range.0 .0, y, &added);
+        }
+
+        selection.head = start;
+    }
+
+    rows.end_group();
+
+    match failed {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// Toggles the comments of every range on its own, as a single undo step. Fails if the filetype
+/// has no comments.
+fn toggle_comments(
+    rows: &mut RowBuffer,
+    ranges: &[LineRange],
+    origin: (usize, usize),
+    options: &Options,
+) -> Result<(), &'static str> {
+    let markers = comment::markers(options).ok_or("No commentstring for this filetype")?;
+
+    rows.begin_group(origin);
+
+    for range in ranges {
+        if range.start >= rows.rows_count() {
+            continue;
+        }
+
+        let changed = comment::toggle(rows, *range, markers);
+
+        if !changed.is_empty() {
+            rows.begin_change(origin);
+        }
+
+        for (y, contents) in changed {
+            rows.set_row(y, &contents);
+        }
+    }
+
+    rows.end_group();
+    Ok(())
+}
+
+/// Joins the rows of every range, as a single undo step. Ranges that overlap are joined
+/// together. Every cursor moves to the last join of its range.
+fn join(
+    rows: &mut RowBuffer,
+    selections: &mut [Selection],
+    ranges: &[LineRange],
+    origin: (usize, usize),
+    normalize: bool,
+) {
+    let blocks = rows::merge(ranges, false);
+    // where the last join of every block is, and how many rows joining it removed
+    let mut joins = vec![(None, 0); blocks.len()];
+
+    rows.begin_group(origin);
+
+    // the blocks further down go first, so the rows of the others stay where they are
+    for (block, (joined, removed)) in blocks.iter().zip(&mut joins).rev() {
+        if block.start + 1 >= rows.rows_count() {
+            continue;
+        }
+
+        let count = rows.rows_count();
+
+        rows.begin_change(origin);
+        *joined = rows::join(rows, block.start, block.end - block.start + 1, normalize);
+        *removed = count - rows.rows_count();
+    }
+
+    rows.end_group();
+
+    for selection in selections.iter_mut() {
+        let y = selection.ordered().0.y;
+        let Some(index) = blocks.iter().position(|it| it.contains(y)) else {
+            continue;
+        };
+
+        // every block above was joined into a single row as well
+        let removed: usize = joins[..index].iter().map(|(_, removed)| removed).sum();
+
+        selection.head = CursorPosition {
+            x: joins[index].0.unwrap_or(selection.head.x),
+            y: blocks[index].start - removed,
+        };
+    }
+}
+
+/// Reflows every range to `textwidth`, as a single undo step. Every cursor moves to the first
+/// non-blank character of the last row of its range.
+fn format(
+    rows: &mut RowBuffer,
+    selections: &mut [Selection],
+    ranges: &[LineRange],
+    origin: (usize, usize),
+    options: &Options,
+) {
+    rows.begin_group(origin);
+
+    // ranges further down the buffer go first, so the rows of the others stay where they are
+    let mut order: Vec<usize> = (0..ranges.len()).collect();
+    order.sort_by_key(|index| std::cmp::Reverse(ranges[*index].start));
+
+    let mut ends: Vec<usize> = ranges.iter().map(|it| it.start).collect();
+    let mut below = usize::MAX;
+
+    for index in order {
+        let range = ranges[index];
+
+        // rows that were already reflowed for another cursor aren't reflowed again
+        if range.start >= rows.rows_count() || range.end >= below {
+            continue;
+        }
+
+        below = range.start;
+
+        let range = LineRange {
+            start: range.start,
+            end: range.end.min(rows.rows_count() - 1),
+        };
+
+        let reflowed = format::reflow(rows, range, options);
+        let unchanged = reflowed.len() == range.end - range.start + 1
+            && range
+                .rows()
+                .zip(&reflowed)
+                .all(|(y, it)| rows.get_row_at(y).contents() == it);
+
+        ends[index] = range.start + reflowed.len().saturating_sub(1);
+
+        if unchanged {
+            continue;
+        }
+
+        rows.begin_change(origin);
+        replace_rows(rows, range, &reflowed);
+
+        // the rows below have moved, and so have the ranges that were already reflowed
+        let moved = reflowed.len() as isize - (range.end - range.start + 1) as isize;
+
+        for end in ends.iter_mut().filter(|it| **it > range.end) {
+            *end = end.saturating_add_signed(moved);
+        }
+    }
+
+    rows.end_group();
+
+    for (selection, end) in selections.iter_mut().zip(ends) {
+        let contents = row_contents(rows, end);
+        selection.head = CursorPosition {
+            x: indent::indent_of(contents).len(),
+            y: end,
+        };
+    }
+}
+
+/// Replaces the rows of `range` with `replacement`, which can have more or fewer rows.
+fn replace_rows(rows: &mut RowBuffer, range: LineRange, replacement: &[String]) {
+    let count = range.end - range.start + 1;
+
+    for (y, contents) in range.rows().zip(replacement) {
+        rows.set_row(y, contents);
+    }
+
+    for (offset, contents) in replacement.iter().enumerate().skip(count) {
+        rows.insert_row(range.start + offset, contents);
+    }
+
+    for _ in replacement.len()..count {
+        rows.remove_row(range.start + replacement.len());
+    }
+}
+
+/// Applies `operator` to the rows of every range, as a single undo step. Every cursor moves to
+/// where the operator leaves it in its range.
+pub fn operate(
+    rows: &mut RowBuffer,
+    selections: &mut [Selection],
+    operator: Operator,
+    ranges: &[LineRange],
+    origin: (usize, usize),
+    options: &Options,
+) -> Result<(), &'static str> {
+    match operator {
+        Operator::Reindent => reindent(rows, selections, ranges, origin, options),
+        Operator::Comment => {
+            toggle_comments(rows, ranges, origin, options)?;
+
+            for (selection, range) in selections.iter_mut().zip(ranges) {
+                selection.head.y = range.start;
+            }
+        }
+        Operator::Format => format(rows, selections, ranges, origin, options),
+    }
+
+    Ok(())
+}
+
+/// Reindents every range, as a single undo step. Every cursor moves to the first non-blank
+/// character of its range.
+fn reindent(
+    rows: &mut RowBuffer,
+    selections: &mut [Selection],
+    ranges: &[LineRange],
+    origin: (usize, usize),
+    options: &Options,
+) {
+    rows.begin_group(origin);
+
+    for range in ranges {
+        if range.start >= rows.rows_count() {
+            continue;
+        }
+
+        let changed = indent::reindent(rows, *range, options);
+
+        if !changed.is_empty() {
+            rows.begin_change(origin);
+        }
+
+        for (y, contents) in changed {
+            rows.set_row(y, &contents);
+        }
+    }
+
+    rows.end_group();
+
+    for (selection, range) in selections.iter_mut().zip(ranges) {
+        let contents = row_contents(rows, range.start);
+        selection.head = CursorPosition {
+            x: indent::indent_of(contents).len(),
+            y: range.start,
+        };
+    }
+}
+
+/// The contents of row `y`, or nothing for the row right below the last one.
+fn row_contents(rows: &RowBuffer, y: usize) -> &str {
+    match y < rows.rows_count() {
+        true => rows.get_row_at(y).contents(),
+        false => "",
+    }
+}
+
+fn insert(rows: &mut RowBuffer, selections: &mut [Selection], origin: (usize, usize), text: &str) {
+    apply_each(rows, selections, origin, |_, selection| {
+        Some(Edit::Insert {
+            at: (selection.head.x, selection.head.y),
+            text: text.to_string(),
+        })
+    });
+}
+
+fn move_for_insert(rows: &RowBuffer, head: &mut CursorPosition, at: InsertPosition) {
+    if head.y >= rows.rows_count() {
+        return;
+    }
+
+    let contents = rows.get_row_at(head.y).contents();
+
+    head.x = match at {
+        InsertPosition::Before => head.x,
+        InsertPosition::After => next_char(rows, head).unwrap_or(head.x),
+        InsertPosition::RowStart => contents.len() - contents.trim_start().len(),
+        InsertPosition::RowEnd => contents.len(),
+    };
+}
+
+/// The byte offset of the character before `position` on its row.
+fn previous_char(rows: &RowBuffer, position: &CursorPosition) -> Option<usize> {
+    if position.y >= rows.rows_count() {
+        return None;
+    }
+
+    let contents = rows.get_row_at(position.y).contents();
+
+    contents[..position.x.min(contents.len())]
+        .char_indices()
+        .next_back()
+        .map(|(index, _)| index)
+}
+
+/// The byte offset right after the character at `position`, `None` at the end of the row.
+fn next_char(rows: &RowBuffer, position: &CursorPosition) -> Option<usize> {
+    if position.y >= rows.rows_count() {
+        return None;
+    }
+
+    let contents = rows.get_row_at(position.y).contents();
+
+    contents
+        .get(position.x..)?
+        .chars()
+        .next()
+        .map(|char| position.x + char.len_utf8())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(rows: &[&str]) -> RowBuffer {
+        let mut buffer = RowBuffer::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        buffer
+    }
+
+    /// Adds `delta` to every row of `rows` selected from top to bottom, like `Ctrl-A` in visual
+    /// mode, or `g Ctrl-A` when `progressive`.
+    fn incremented(
+        rows: &[&str],
+        delta: i64,
+        progressive: bool,
+    ) -> (Vec<String>, Result<(), &'static str>) {
+        let mut buffer = buffer(rows);
+        let options = Options {
+            nrformats: String::from("date"),
+            ..Options::default()
+        };
+        let mut selections = [Selection {
+            anchor: CursorPosition { x: 0, y: 0 },
+            head: CursorPosition {
+                x: 0,
+                y: rows.len() - 1,
+            },
+        }];
+
+        let result = increment_selections(
+            &mut buffer,
+            &mut selections,
+            (0, 0),
+            delta,
+            progressive,
+            &options,
+        );
+        let rows = (0..buffer.rows_count())
+            .map(|y| row_contents(&buffer, y).to_string())
+            .collect();
+
+        (rows, result)
+    }
+
+    #[test]
+    fn adds_more_to_every_row_below_when_progressive() {
+        assert_eq!(
+            incremented(&["1", "1", "1"], 2, true),
+            (
+                vec![String::from("3"), String::from("5"), String::from("7")],
+                Ok(())
+            )
+        );
+        assert_eq!(
+            incremented(&["1", "1"], 2, false),
+            (vec![String::from("3"), String::from("3")], Ok(()))
+        );
+    }
+
+    #[test]
+    fn leaves_dates_too_far_out_alone() {
+        let (rows, result) = incremented(&["2024-01-31", "2024-01-31", "1"], i64::MAX, true);
+
+        assert_eq!(rows, ["2024-01-31", "2024-01-31", "1"]);
+        assert_eq!(result, Err(increment::TOO_LARGE));
+
+        let (rows, result) = incremented(&["1", "2024-01-31"], i64::MAX / 2 + 1, true);
+
+        assert_eq!(rows[1], "2024-01-31");
+        assert_eq!(result, Err(increment::TOO_LARGE));
+    }
+}
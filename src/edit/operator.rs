@@ -0,0 +1,322 @@
+use crate::{command::parser::LineRange, input::data::RowBuffer};
+
+/// The commands that work on the rows a motion moves over, typed in front of the motion like
+/// `=j`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operator {
+    /// `=`
+    Reindent,
+    /// `gc`
+    Comment,
+    /// `gq`
+    Format,
+}
+
+impl Operator {
+    const ALL: [Operator; 3] = [Operator::Reindent, Operator::Comment, Operator::Format];
+
+    /// The keys the operator is typed with.
+    pub fn keys(&self) -> &'static str {
+        match self {
+            Operator::Reindent => "=",
+            Operator::Comment => "gc",
+            Operator::Format => "gq",
+        }
+    }
+
+    /// The operator `keys` start with, along with the keys typed after it.
+    pub fn split(keys: &str) -> Option<(Self, &str)> {
+        Self::ALL
+            .into_iter()
+            .find_map(|it| keys.strip_prefix(it.keys()).map(|rest| (it, rest)))
+    }
+}
+
+/// The motions an operator can be followed by, every one of them covers whole rows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Motion {
+    /// The operator typed twice like `==`, or followed by its last key like `gcc`, which is the
+    /// row of the cursor and the rows below it for a count.
+    Line,
+    /// `j`
+    Down,
+    /// `k`
+    Up,
+    /// `G`, up to the last row, or up to the row of the count.
+    LastRow,
+    /// `gg`, up to the first row, or up to the row of the count.
+    FirstRow,
+    /// `}`, up to the end of the paragraph.
+    ParagraphEnd,
+    /// `{`, up to the blank row above the paragraph.
+    ParagraphStart,
+    /// `ip`, the paragraph, or the blank rows, the cursor is in.
+    InnerParagraph,
+    /// `ap`, the paragraph along with the blank rows after it.
+    AroundParagraph,
+}
+
+/// How far along the keys typed after an operator are.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Keys {
+    /// A complete motion, along with the count of the whole command. A count typed in front of
+    /// the operator and one in front of the motion are multiplied, like `2gc3j` covers 7 rows.
+    Motion(Motion, usize),
+    /// The keys are the start of a motion, like `g` or `i`.
+    Incomplete,
+    /// The keys don't make up a motion, which cancels the operator.
+    Invalid,
+}
+
+impl Motion {
+    /// The motion of the `keys` typed after `operator`, `count` is the count typed in front of
+    /// the operator, or 0 without one.
+    pub fn parse(operator: Operator, keys: &str, count: usize) -> Keys {
+        let last = &operator.keys()[operator.keys().len() - 1..];
+
+        let digits = keys.len()
+            - keys
+                .trim_start_matches(|it: char| it.is_ascii_digit())
+                .len();
+        let (own, keys) = keys.split_at(digits);
+
+        let count = match (count, own.parse::<usize>()) {
+            _ if own.starts_with('0') => return Keys::Invalid,
+            (count, Ok(own)) => count.max(1).saturating_mul(own),
+            (count, Err(_)) => count,
+        };
+
+        let motion = match keys {
+            "" | "g" | "i" | "a" => return Keys::Incomplete,
+            _ if keys == last || keys == operator.keys() => Motion::Line,
+            "j" => Motion::Down,
+            "k" => Motion::Up,
+            "G" => Motion::LastRow,
+            "gg" => Motion::FirstRow,
+            "}" => Motion::ParagraphEnd,
+            "{" => Motion::ParagraphStart,
+            "ip" => Motion::InnerParagraph,
+            "ap" => Motion::AroundParagraph,
+            _ => return Keys::Invalid,
+        };
+
+        Keys::Motion(motion, count)
+    }
+}
+
+/// The rows `motion` covers from row `y`, `count` is the count of the command, or 0 without one.
+/// `None` in an empty buffer.
+pub fn range(rows: &RowBuffer, y: usize, motion: Motion, count: usize) -> Option<LineRange> {
+    let last = rows.rows_count().checked_sub(1)?;
+    let y = y.min(last);
+    let repeat = count.max(1);
+    let blank = |y: usize| rows.get_row_at(y).contents().trim().is_empty();
+
+    let (start, end) = match motion {
+        Motion::Line => (y, y.saturating_add(repeat - 1)),
+        Motion::Down => (y, y.saturating_add(repeat)),
+        Motion::Up => (y.saturating_sub(repeat), y),
+        Motion::LastRow | Motion::FirstRow => {
+            let to = match (count, motion) {
+                (0, Motion::LastRow) => last,
+                (0, _) => 0,
+                (count, _) => count - 1,
+            };
+
+            (y.min(to), y.max(to))
+        }
+        // like vim, the blank row `}` stops at is left out, since the motion ends right before it
+        Motion::ParagraphEnd => {
+            let mut end = y;
+
+            // a count larger than the number of paragraphs stops at the last row
+            for _ in 0..repeat.min(last + 1) {
+                end = (end + 1..=last)
+                    .find(|it| blank(*it) && !blank(it - 1))
+                    .unwrap_or(last + 1);
+            }
+
+            (y, end.saturating_sub(1).max(y))
+        }
+        Motion::ParagraphStart => {
+            let mut start = y;
+
+            for _ in 0..repeat.min(last + 1) {
+                start = (0..start)
+                    .rev()
+                    .find(|it| blank(*it) && !blank(it + 1))
+                    .unwrap_or(0);
+            }
+
+            (start, y)
+        }
+        Motion::InnerParagraph | Motion::AroundParagraph => {
+            let same = |it: &usize| blank(*it) == blank(y);
+            let start = (0..y).rev().find(|it| !same(it)).map_or(0, |it| it + 1);
+            let end = (y + 1..=last)
+                .find(|it| !same(it))
+                .map_or(last, |it| it - 1);
+
+            match motion {
+                Motion::AroundParagraph => around(rows, start, end),
+                _ => (start, end),
+            }
+        }
+    };
+
+    Some(LineRange {
+        start,
+        end: end.min(last),
+    })
+}
+
+/// Grows the rows of a paragraph from `start` to `end` by the blank rows after it, or by the
+/// ones before it when there are none after it. A run of blank rows grows by the paragraph after
+/// it instead.
+fn around(rows: &RowBuffer, start: usize, end: usize) -> (usize, usize) {
+    let last = rows.rows_count() - 1;
+    let blank = |y: usize| rows.get_row_at(y).contents().trim().is_empty();
+    let kind = blank(start);
+
+    let after = (end + 1..=last)
+        .find(|it| blank(*it) == kind)
+        .map_or(last, |it| it - 1);
+
+    if after > end {
+        return (start, after);
+    }
+
+    let before = (0..start)
+        .rev()
+        .find(|it| blank(*it) == kind)
+        .map_or(0, |it| it + 1);
+
+    (before, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(rows: &[&str]) -> RowBuffer {
+        let mut buffer = RowBuffer::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        buffer
+    }
+
+    fn rows(start: usize, end: usize) -> Option<LineRange> {
+        Some(LineRange { start, end })
+    }
+
+    const TEXT: [&str; 7] = ["a", "b", "", "c", "d", "e", ""];
+
+    #[test]
+    fn parses_motions_after_an_operator() {
+        let parse = |keys| Motion::parse(Operator::Reindent, keys, 0);
+
+        assert_eq!(parse(""), Keys::Incomplete);
+        assert_eq!(parse("="), Keys::Motion(Motion::Line, 0));
+        assert_eq!(parse("j"), Keys::Motion(Motion::Down, 0));
+        assert_eq!(parse("g"), Keys::Incomplete);
+        assert_eq!(parse("gg"), Keys::Motion(Motion::FirstRow, 0));
+        assert_eq!(parse("i"), Keys::Incomplete);
+        assert_eq!(parse("ap"), Keys::Motion(Motion::AroundParagraph, 0));
+        assert_eq!(parse("x"), Keys::Invalid);
+        assert_eq!(parse("0"), Keys::Invalid);
+    }
+
+    #[test]
+    fn the_last_key_of_an_operator_is_its_line_motion() {
+        let parse = |keys| Motion::parse(Operator::Comment, keys, 0);
+
+        assert_eq!(parse("c"), Keys::Motion(Motion::Line, 0));
+        assert_eq!(parse("gc"), Keys::Motion(Motion::Line, 0));
+        assert_eq!(parse("G"), Keys::Motion(Motion::LastRow, 0));
+        assert_eq!(parse("="), Keys::Invalid);
+    }
+
+    #[test]
+    fn multiplies_the_counts() {
+        assert_eq!(
+            Motion::parse(Operator::Reindent, "3j", 2),
+            Keys::Motion(Motion::Down, 6)
+        );
+        assert_eq!(Motion::parse(Operator::Reindent, "12", 0), Keys::Incomplete);
+        assert_eq!(
+            Motion::parse(Operator::Reindent, "4=", 0),
+            Keys::Motion(Motion::Line, 4)
+        );
+    }
+
+    #[test]
+    fn splits_the_operator_off() {
+        assert_eq!(Operator::split("=ip"), Some((Operator::Reindent, "ip")));
+        assert_eq!(Operator::split("gc}"), Some((Operator::Comment, "}")));
+        assert_eq!(Operator::split("gqap"), Some((Operator::Format, "ap")));
+        assert_eq!(Operator::split("gt"), None);
+        assert_eq!(Operator::split("zf"), None);
+    }
+
+    #[test]
+    fn line_motions() {
+        let text = buffer(&TEXT);
+
+        assert_eq!(range(&text, 1, Motion::Line, 0), rows(1, 1));
+        assert_eq!(range(&text, 1, Motion::Line, 3), rows(1, 3));
+        assert_eq!(range(&text, 1, Motion::Down, 2), rows(1, 3));
+        assert_eq!(range(&text, 1, Motion::Up, 5), rows(0, 1));
+        assert_eq!(range(&text, 5, Motion::Down, 9), rows(5, 6));
+        assert_eq!(range(&buffer(&[]), 0, Motion::Line, 0), None);
+    }
+
+    #[test]
+    fn huge_counts_stop_at_the_last_row() {
+        let text = buffer(&TEXT);
+
+        assert_eq!(range(&text, 1, Motion::Line, usize::MAX), rows(1, 6));
+        assert_eq!(range(&text, 1, Motion::Down, usize::MAX), rows(1, 6));
+        assert_eq!(range(&text, 1, Motion::Up, usize::MAX), rows(0, 1));
+        assert_eq!(
+            range(&text, 1, Motion::ParagraphEnd, usize::MAX),
+            rows(1, 6)
+        );
+        assert_eq!(
+            range(&text, 4, Motion::ParagraphStart, usize::MAX),
+            rows(0, 4)
+        );
+    }
+
+    #[test]
+    fn first_and_last_row() {
+        let text = buffer(&TEXT);
+
+        assert_eq!(range(&text, 3, Motion::LastRow, 0), rows(3, 6));
+        assert_eq!(range(&text, 3, Motion::FirstRow, 0), rows(0, 3));
+        assert_eq!(range(&text, 3, Motion::LastRow, 2), rows(1, 3));
+    }
+
+    #[test]
+    fn paragraph_motions() {
+        let text = buffer(&TEXT);
+
+        assert_eq!(range(&text, 0, Motion::ParagraphEnd, 0), rows(0, 1));
+        assert_eq!(range(&text, 0, Motion::ParagraphEnd, 2), rows(0, 5));
+        assert_eq!(range(&text, 4, Motion::ParagraphStart, 0), rows(2, 4));
+        assert_eq!(range(&text, 1, Motion::ParagraphStart, 0), rows(0, 1));
+    }
+
+    #[test]
+    fn paragraph_objects() {
+        let text = buffer(&TEXT);
+
+        assert_eq!(range(&text, 4, Motion::InnerParagraph, 0), rows(3, 5));
+        assert_eq!(range(&text, 2, Motion::InnerParagraph, 0), rows(2, 2));
+        assert_eq!(range(&text, 0, Motion::AroundParagraph, 0), rows(0, 2));
+        assert_eq!(range(&text, 4, Motion::AroundParagraph, 0), rows(3, 6));
+        assert_eq!(range(&text, 2, Motion::AroundParagraph, 0), rows(2, 5));
+    }
+}
@@ -0,0 +1,194 @@
+use super::{change::Edit, indent};
+use crate::options::Options;
+
+/// The pairs of the `autopairs` option, every two characters make up a pair, e.g. `()[]""`.
+pub fn pairs(options: &Options) -> Vec<(char, char)> {
+    let chars: Vec<char> = options.autopairs.chars().collect();
+
+    chars
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .collect()
+}
+
+/// Typing `char` at byte `x` of row `y`, which contains `contents`. Typing a closing character
+/// right in front of the same character moves over it, and typing an opening character inserts
+/// its closing character as well, unless it's typed `in_text` like a string or a comment. `None`
+/// if it's typed like any other character.
+pub fn type_char(
+    contents: &str,
+    (x, y): (usize, usize),
+    char: char,
+    in_text: bool,
+    options: &Options,
+) -> Option<Edit> {
+    let pairs = pairs(options);
+    let previous = contents.get(..x)?.chars().next_back();
+    let next = contents.get(x..)?.chars().next();
+    let is_closing = |it: char| pairs.iter().any(|(_, close)| *close == it);
+
+    if next == Some(char) && is_closing(char) {
+        return Some(Edit::Replace {
+            start: (x, y),
+            end: (x + char.len_utf8(), y),
+            text: char.to_string(),
+        });
+    }
+
+    if in_text {
+        return None;
+    }
+
+    let &(open, close) = pairs.iter().find(|(open, _)| *open == char)?;
+
+    // a pair is only completed in front of whitespace or another closing character, and quotes
+    // aren't completed right after a word, like the one in `don't`.
+    let completes = next.is_none_or(|it| it.is_whitespace() || is_closing(it))
+        && (open != close || previous.is_none_or(|it| !it.is_alphanumeric() && it != open));
+
+    completes.then(|| Edit::InsertPair {
+        at: (x, y),
+        before: open.to_string(),
+        after: close.to_string(),
+    })
+}
+
+/// Backspace right in between an empty pair removes both of its characters.
+pub fn backspace(contents: &str, (x, y): (usize, usize), options: &Options) -> Option<Edit> {
+    let previous = contents.get(..x)?.chars().next_back()?;
+    let next = contents.get(x..)?.chars().next()?;
+
+    pairs(options)
+        .contains(&(previous, next))
+        .then(|| Edit::Remove {
+            start: (x - previous.len_utf8(), y),
+            end: (x + next.len_utf8(), y),
+        })
+}
+
+/// Enter right in between a pair that indents, like `{}`, opens an indented block in between.
+pub fn enter(contents: &str, (x, y): (usize, usize), options: &Options) -> Option<Edit> {
+    let previous = contents.get(..x)?.chars().next_back()?;
+    let next = contents.get(x..)?.chars().next()?;
+
+    if !pairs(options).contains(&(previous, next)) || !options.indentopen.contains(previous) {
+        return None;
+    }
+
+    let inner = indent::indent_for(&contents[..x], "", true, options);
+
+    Some(Edit::InsertPair {
+        at: (x, y),
+        before: format!("\n{inner}"),
+        after: format!("\n{}", indent::indent_of(contents)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cursor::multi::Selection, edit::change::apply_each, input::data::RowBuffer};
+
+    /// The rows and the cursor after making `edit` in a row containing `contents` with the
+    /// cursor at byte `x`, `None` without an edit.
+    fn applied(
+        contents: &str,
+        x: usize,
+        edit: Option<Edit>,
+    ) -> Option<(Vec<String>, (usize, usize))> {
+        let mut rows = RowBuffer::new();
+        let mut selections = [Selection::at(x, 0)];
+        let mut edit = Some(edit?);
+
+        rows.insert_row(0, contents);
+        apply_each(&mut rows, &mut selections, (x, 0), |_, _| edit.take());
+
+        let contents = (0..rows.rows_count())
+            .map(|y| rows.get_row_at(y).contents().to_string())
+            .collect();
+        let head = &selections[0].head;
+
+        Some((contents, (head.x, head.y)))
+    }
+
+    fn typed(contents: &str, x: usize, char: char, in_text: bool) -> Option<(String, usize)> {
+        let edit = type_char(contents, (x, 0), char, in_text, &Options::default());
+        let (rows, (x, _)) = applied(contents, x, edit)?;
+
+        Some((rows.concat(), x))
+    }
+
+    fn row(contents: &str, x: usize) -> Option<(String, usize)> {
+        Some((contents.to_string(), x))
+    }
+
+    #[test]
+    fn completes_pairs_in_front_of_whitespace_and_closing_characters() {
+        assert_eq!(typed("", 0, '(', false), row("()", 1));
+        assert_eq!(typed("a ", 1, '[', false), row("a[] ", 2));
+        assert_eq!(typed("()", 1, '{', false), row("({})", 2));
+        assert_eq!(typed("ab", 0, '(', false), None);
+        assert_eq!(typed("", 0, 'a', false), None);
+    }
+
+    #[test]
+    fn quotes_are_not_completed_after_a_word() {
+        assert_eq!(typed("don", 3, '\'', false), None);
+        assert_eq!(typed("x = ", 4, '"', false), row("x = \"\"", 5));
+    }
+
+    #[test]
+    fn typing_a_closing_character_moves_over_it() {
+        assert_eq!(typed("()", 1, ')', false), row("()", 2));
+        assert_eq!(typed("\"\"", 1, '"', false), row("\"\"", 2));
+        assert_eq!(typed("\"\"", 1, '"', true), row("\"\"", 2));
+    }
+
+    #[test]
+    fn nothing_is_completed_in_strings_and_comments() {
+        assert_eq!(typed("\"\"", 1, '(', true), None);
+    }
+
+    #[test]
+    fn backspace_removes_an_empty_pair() {
+        let backspaced = |contents, x| {
+            applied(
+                contents,
+                x,
+                backspace(contents, (x, 0), &Options::default()),
+            )
+        };
+
+        assert_eq!(
+            backspaced("a()", 2),
+            Some((vec![String::from("a")], (1, 0)))
+        );
+        assert_eq!(backspaced("(a)", 2), None);
+        assert_eq!(backspaced("()", 0), None);
+        assert_eq!(backspaced("()", 2), None);
+    }
+
+    #[test]
+    fn enter_in_between_braces_opens_an_indented_block() {
+        let options = Options {
+            expandtab: true,
+            shiftwidth: 4,
+            ..Options::default()
+        };
+        let entered = |contents, x| applied(contents, x, enter(contents, (x, 0), &options));
+
+        assert_eq!(
+            entered("  f {}", 5),
+            Some((
+                vec![
+                    String::from("  f {"),
+                    String::from("      "),
+                    String::from("  }")
+                ],
+                (6, 1)
+            ))
+        );
+        assert_eq!(entered("\"\"", 1), None);
+        assert_eq!(entered("{ }", 1), None);
+    }
+}
@@ -0,0 +1,224 @@
+use super::indent;
+use crate::{command::parser::LineRange, input::data::RowBuffer};
+
+/// Joins `count` rows starting at row `y` into a single row, at least two. With `normalize`, like
+/// `J`, the indentation of the joined rows is replaced with a single space, which is left out
+/// after trailing whitespace, around an empty row and in front of a `)`. Without it, like `gJ`,
+/// the rows are joined as-is. Returns the byte offset of the last join, `None` if there's no row
+/// to join.
+pub fn join(rows: &mut RowBuffer, y: usize, count: usize, normalize: bool) -> Option<usize> {
+    let last = (y + count.max(2) - 1).min(rows.rows_count().saturating_sub(1));
+    let mut joined = None;
+
+    for _ in y..last {
+        let contents = rows.get_row_at(y).contents();
+        let below = rows.get_row_at(y + 1).contents();
+
+        let (separator, skip) = match normalize {
+            true => {
+                let skip = indent::indent_of(below).len();
+                let text = &below[skip..];
+                let bare = contents.is_empty()
+                    || contents.ends_with(char::is_whitespace)
+                    || text.is_empty()
+                    || text.starts_with(')');
+
+                (if bare { "" } else { " " }, skip)
+            }
+            false => ("", 0),
+        };
+
+        joined = Some(rows.join_row(y, separator, skip));
+    }
+
+    joined
+}
+
+/// Merges ranges that overlap into one, as well as ranges right next to each other with
+/// `adjacent`. The merged ranges are ordered from the top of the buffer down.
+pub fn merge(ranges: &[LineRange], adjacent: bool) -> Vec<LineRange> {
+    let mut sorted = ranges.to_vec();
+    sorted.sort_by_key(|it| it.start);
+
+    let mut merged: Vec<LineRange> = Vec::new();
+
+    for range in sorted {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end + usize::from(adjacent) => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+
+    merged
+}
+
+/// Whether every block of rows can be moved a row down or up, which none can when any of them
+/// is already at the bottom or top of the buffer.
+pub fn can_move(rows: &RowBuffer, blocks: &[LineRange], down: bool) -> bool {
+    match down {
+        true => blocks
+            .last()
+            .is_some_and(|it| it.end + 1 < rows.rows_count()),
+        false => blocks.first().is_some_and(|it| it.start > 0),
+    }
+}
+
+/// Swaps every block of rows with the row above it, or the one below it when moving `down`. The
+/// blocks have to be merged with [merge], including adjacent ones, and checked with [can_move].
+pub fn move_blocks(rows: &mut RowBuffer, blocks: &[LineRange], down: bool) {
+    for block in blocks {
+        match down {
+            true => rows.move_rows(block.start, block.end, block.start + 1),
+            false => rows.move_rows(block.start, block.end, block.start - 1),
+        }
+    }
+}
+
+/// Copies every block of rows right below itself, or right above itself when not `down`.
+pub fn duplicate_blocks(rows: &mut RowBuffer, blocks: &[LineRange], down: bool) {
+    // the blocks further down go first, so the rows of the others stay where they are
+    for block in blocks.iter().rev() {
+        let copied: Vec<String> = block
+            .rows()
+            .map(|y| rows.get_row_at(y).contents().to_string())
+            .collect();
+
+        let at = match down {
+            true => block.end + 1,
+            false => block.start,
+        };
+
+        for (index, contents) in copied.iter().enumerate() {
+            rows.insert_row(at + index, contents);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(rows: &[&str]) -> RowBuffer {
+        let mut buffer = RowBuffer::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        buffer
+    }
+
+    fn contents(buffer: &RowBuffer) -> Vec<String> {
+        (0..buffer.rows_count())
+            .map(|y| buffer.get_row_at(y).contents().to_string())
+            .collect()
+    }
+
+    fn range(start: usize, end: usize) -> LineRange {
+        LineRange { start, end }
+    }
+
+    #[test]
+    fn joins_rows_with_a_single_space() {
+        let mut rows = buffer(&["if x {", "    a", "\t \tb", "}"]);
+
+        assert_eq!(join(&mut rows, 0, 3, true), Some(8));
+        assert_eq!(contents(&rows), ["if x { a b", "}"]);
+    }
+
+    #[test]
+    fn leaves_out_the_space_where_it_does_not_belong() {
+        let mut rows = buffer(&["call(", "  x", "  )", "end ", "next", "", "after"]);
+
+        join(&mut rows, 0, 3, true);
+        join(&mut rows, 1, 2, true);
+        join(&mut rows, 2, 2, true);
+
+        assert_eq!(contents(&rows), ["call( x)", "end next", "after"]);
+    }
+
+    #[test]
+    fn joins_rows_as_they_are_without_normalizing() {
+        let mut rows = buffer(&["a", "  b", "\tc"]);
+
+        assert_eq!(join(&mut rows, 0, 3, false), Some(4));
+        assert_eq!(contents(&rows), ["a  b\tc"]);
+    }
+
+    #[test]
+    fn joins_at_least_two_rows_and_stops_at_the_last_one() {
+        let mut rows = buffer(&["a", "b", "c"]);
+
+        join(&mut rows, 0, 1, true);
+        assert_eq!(contents(&rows), ["a b", "c"]);
+
+        join(&mut rows, 0, 10, true);
+        assert_eq!(contents(&rows), ["a b c"]);
+
+        assert_eq!(join(&mut rows, 0, 2, true), None);
+        assert_eq!(join(&mut buffer(&[]), 0, 2, true), None);
+    }
+
+    #[test]
+    fn moves_marks_along_with_the_joined_row() {
+        let mut rows = buffer(&["ab", "    cd"]);
+        rows.set_mark('a', 5, 1);
+
+        join(&mut rows, 0, 2, true);
+
+        let mark = rows.get_mark('a').unwrap();
+        assert_eq!((mark.x, mark.y), (4, 0));
+    }
+
+    #[test]
+    fn merges_overlapping_and_adjacent_ranges() {
+        let ranges = [range(5, 6), range(0, 2), range(1, 3), range(4, 4)];
+
+        assert_eq!(
+            merge(&ranges, false),
+            [range(0, 3), range(4, 4), range(5, 6)]
+        );
+        assert_eq!(merge(&ranges, true), [range(0, 6)]);
+        assert_eq!(merge(&[], true), []);
+    }
+
+    #[test]
+    fn cannot_move_past_the_top_or_bottom() {
+        let rows = buffer(&["a", "b", "c"]);
+
+        assert!(!can_move(&rows, &[range(0, 1)], false));
+        assert!(can_move(&rows, &[range(0, 1)], true));
+        assert!(can_move(&rows, &[range(1, 2)], false));
+        assert!(!can_move(&rows, &[range(1, 2)], true));
+        assert!(!can_move(&rows, &[], true));
+    }
+
+    #[test]
+    fn moves_every_block_past_its_neighbour() {
+        let mut rows = buffer(&["a", "b", "c", "d", "e"]);
+        let blocks = [range(0, 0), range(2, 3)];
+
+        move_blocks(&mut rows, &blocks, true);
+        assert_eq!(contents(&rows), ["b", "a", "e", "c", "d"]);
+
+        let blocks = [range(1, 1), range(3, 4)];
+
+        move_blocks(&mut rows, &blocks, false);
+        assert_eq!(contents(&rows), ["a", "b", "c", "d", "e"]);
+    }
+
+    #[test]
+    fn duplicates_every_block_above_or_below_itself() {
+        let mut rows = buffer(&["a", "b", "c"]);
+
+        duplicate_blocks(&mut rows, &[range(0, 0), range(1, 2)], true);
+        assert_eq!(contents(&rows), ["a", "a", "b", "c", "b", "c"]);
+
+        let mut rows = buffer(&["a", "b"]);
+
+        duplicate_blocks(&mut rows, &[range(1, 1)], false);
+        assert_eq!(contents(&rows), ["a", "b", "b"]);
+    }
+}
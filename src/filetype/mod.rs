@@ -0,0 +1,213 @@
+use std::path::Path;
+
+use bevy_ecs::prelude::*;
+
+use crate::{
+    command::{self, CommandError, CommandRegistry, Invocation},
+    input::data::RowBuffer,
+    status::StatusBuffer,
+};
+
+pub fn init(world: &mut World, _schedule: &mut Schedule) {
+    world.insert_resource(FileType::default());
+    world.insert_resource(AutoCommands::default());
+
+    let mut registry = world.resource_mut::<CommandRegistry>();
+    registry.register("setfiletype", 4, set_filetype);
+    registry.register("autocmd", 2, autocmd);
+}
+
+/// A filetype that is known out of the box, along with the options its buffers start out with.
+struct Definition {
+    name: &'static str,
+    /// File extensions, or whole file names for files like `Makefile`.
+    patterns: &'static [&'static str],
+    /// Arguments to `:set`, applied on top of [DEFAULT_SETTINGS].
+    settings: &'static str,
+}
+
+/// Settings every filetype starts out with, so nothing is left over from the previous filetype.
+const DEFAULT_SETTINGS: &str =
+    "shiftwidth=4 expandtab indentopen={([ indentclose=})] autopairs=()[]{}\"\"'' commentstring=";
+
+const DEFINITIONS: &[Definition] = &[
+    Definition {
+        name: "rust",
+        patterns: &["rs"],
+        // a single quote is far more likely to start a lifetime than a character
+        settings: "autopairs=()[]{}\"\" commentstring=//\\ %s",
+    },
+    Definition {
+        name: "c",
+        patterns: &["c", "h", "cc", "cpp", "hpp"],
+        settings: "commentstring=//\\ %s",
+    },
+    Definition {
+        name: "python",
+        patterns: &["py"],
+        settings: "indentopen={([: commentstring=#\\ %s",
+    },
+    Definition {
+        name: "javascript",
+        patterns: &["js", "jsx", "ts", "tsx"],
+        settings: "shiftwidth=2 commentstring=//\\ %s",
+    },
+    Definition {
+        name: "json",
+        patterns: &["json"],
+        settings: "shiftwidth=2",
+    },
+    Definition {
+        name: "toml",
+        patterns: &["toml"],
+        settings: "commentstring=#\\ %s",
+    },
+    Definition {
+        name: "go",
+        patterns: &["go"],
+        settings: "noexpandtab commentstring=//\\ %s",
+    },
+    Definition {
+        name: "make",
+        patterns: &["Makefile", "makefile", "mk"],
+        settings: "noexpandtab indentopen=: indentclose= commentstring=#\\ %s",
+    },
+    Definition {
+        name: "markdown",
+        patterns: &["md"],
+        // markdown only has html comments, which are block comments
+        settings: "indentopen= indentclose= autopairs=()[]\"\"`` commentstring=<!--\\ %s\\ -->",
+    },
+];
+
+/// The filetype of the buffer, which decides the options it uses, e.g. how rows get indented.
+#[derive(Resource)]
+pub struct FileType {
+    name: String,
+}
+
+impl Default for FileType {
+    fn default() -> Self {
+        Self {
+            name: String::from("text"),
+        }
+    }
+}
+
+impl FileType {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Commands registered with `:autocmd FileType`, run whenever a matching filetype is set.
+#[derive(Resource, Default)]
+pub struct AutoCommands {
+    filetype: Vec<(Vec<String>, String)>,
+}
+
+/// The filetype of the file at `path`, `text` if it isn't a known one.
+pub fn detect(path: &Path) -> &'static str {
+    let file_name = path.file_name().and_then(|it| it.to_str());
+    let extension = path.extension().and_then(|it| it.to_str());
+
+    DEFINITIONS
+        .iter()
+        .find(|it| {
+            it.patterns
+                .iter()
+                .any(|pattern| Some(*pattern) == extension || Some(*pattern) == file_name)
+        })
+        .map_or("text", |it| it.name)
+}
+
+/// Sets the filetype of the buffer to the one detected from its path.
+pub fn detect_buffer(world: &mut World) {
+    let name = world
+        .resource::<RowBuffer>()
+        .get_path()
+        .map_or("text", detect);
+
+    if let Err(error) = set(world, name) {
+        world
+            .resource_mut::<StatusBuffer>()
+            .error(error.to_string());
+    }
+}
+
+/// Sets the filetype and applies its options, followed by every `:autocmd FileType` for it.
+pub fn set(world: &mut World, name: &str) -> Result<(), CommandError> {
+    world.resource_mut::<FileType>().name = name.to_string();
+
+    let settings = DEFINITIONS
+        .iter()
+        .find(|it| it.name == name)
+        .map_or("", |it| it.settings);
+
+    command::run(world, &format!("set {DEFAULT_SETTINGS} {settings}"))?;
+
+    let commands: Vec<String> = world
+        .resource::<AutoCommands>()
+        .filetype
+        .iter()
+        .filter(|(patterns, _)| patterns.iter().any(|it| it == "*" || it == name))
+        .map(|(_, command)| command.clone())
+        .collect();
+
+    for command in commands {
+        command::run(world, &command)?;
+    }
+
+    Ok(())
+}
+
+/// `:setfiletype {name}`
+fn set_filetype(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    match invocation.arguments().as_slice() {
+        [name] => set(world, name),
+        [] => Err(CommandError::InvalidArgument(String::from("setfiletype"))),
+        [_, rest @ ..] => Err(CommandError::TrailingCharacters(rest.join(" "))),
+    }
+}
+
+/// `:autocmd FileType {names} {command}` runs `command` whenever the filetype is set to one of
+/// the comma separated `names`, or to any filetype for `*`. It also runs right away if the
+/// current filetype matches. `:autocmd! FileType` removes every command.
+fn autocmd(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let mut parts = invocation.args.trim().splitn(3, char::is_whitespace);
+
+    let (Some(event), names, command) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(CommandError::InvalidArgument(invocation.args.clone()));
+    };
+
+    if !event.eq_ignore_ascii_case("filetype") {
+        return Err(CommandError::InvalidArgument(format!(
+            "No such event: {event}"
+        )));
+    }
+
+    if invocation.bang {
+        world.resource_mut::<AutoCommands>().filetype.clear();
+    }
+
+    let (Some(names), Some(command)) = (names, command.map(str::trim)) else {
+        return match invocation.bang {
+            true => Ok(()),
+            false => Err(CommandError::InvalidArgument(invocation.args.clone())),
+        };
+    };
+
+    let names: Vec<String> = names.split(',').map(String::from).collect();
+    let current = world.resource::<FileType>().name.clone();
+    let matches = names.iter().any(|it| it == "*" || *it == current);
+
+    world
+        .resource_mut::<AutoCommands>()
+        .filetype
+        .push((names, command.to_string()));
+
+    match matches {
+        true => command::run(world, command),
+        false => Ok(()),
+    }
+}
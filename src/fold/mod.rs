@@ -0,0 +1,726 @@
+use std::collections::HashSet;
+
+use bevy_ecs::prelude::*;
+
+use crate::{
+    command::{parser::LineRange, CommandError, CommandRegistry, Invocation},
+    cursor::{update, VisualAnchor},
+    edit::indent,
+    input::{
+        data::{self, Fold, RowBuffer},
+        InputMode,
+    },
+    options::Options,
+    status::StatusBuffer,
+    window::{ActiveView, View},
+    SystemType,
+};
+
+pub fn init(world: &mut World, schedule: &mut Schedule) {
+    world.insert_resource(Events::<FoldEvent>::default());
+
+    let mut registry = world.resource_mut::<CommandRegistry>();
+    registry.register("fold", 2, fold);
+    registry.register("foldopen", 5, fold_open);
+    registry.register("foldclose", 5, fold_close);
+
+    schedule.add_systems(
+        handle_fold
+            .after(SystemType::Input)
+            .before(SystemType::Cursor),
+    );
+
+    // folds have to be up to date before the cursor moves over them, and the view only scrolls
+    // once the cursor is out of any fold it shouldn't be in.
+    schedule.add_systems(
+        (
+            update_folds.before(update::handle_cursor_move),
+            follow_cursor.after(update::handle_cursor_move),
+        )
+            .in_set(SystemType::Cursor),
+    );
+}
+
+/// The `z` commands in normal and visual mode.
+#[derive(Event, Clone, Copy, PartialEq, Eq)]
+pub enum FoldEvent {
+    /// `zf` in visual mode, `zfj` and `zfk` fold the rows from the cursor up to `extend` rows
+    /// away from it.
+    Create { extend: Option<isize> },
+    /// `zo`, opens the fold under the cursor.
+    Open,
+    /// `zc`, closes the fold under the cursor.
+    Close,
+    /// `za`, opens the fold under the cursor when it's closed, and closes it otherwise.
+    Toggle,
+    /// `zR`
+    OpenAll,
+    /// `zM`
+    CloseAll,
+    /// `zd`, removes the fold under the cursor.
+    Delete,
+    /// `zE`, removes every fold.
+    DeleteAll,
+}
+
+#[derive(Debug)]
+enum FoldError {
+    NoFold,
+    WrongMethod(&'static str),
+    Overlapping,
+}
+
+impl From<FoldError> for String {
+    fn from(value: FoldError) -> Self {
+        match value {
+            FoldError::NoFold => String::from("No fold found"),
+            FoldError::WrongMethod(action) => {
+                format!("Cannot {action} fold with current 'foldmethod'")
+            }
+            FoldError::Overlapping => String::from("Folds cannot partially overlap"),
+        }
+    }
+}
+
+impl From<FoldError> for CommandError {
+    fn from(value: FoldError) -> Self {
+        CommandError::InvalidArgument(value.into())
+    }
+}
+
+/// The outermost closed fold `y` is in, the rows of which are shown as a single line.
+pub fn closed_fold(rows: &RowBuffer, y: usize) -> Option<Fold> {
+    rows.folds()
+        .iter()
+        .find(|it| it.closed && it.start <= y && y <= it.end)
+        .copied()
+}
+
+/// The first row of the line `y` is shown on.
+pub fn line_start(rows: &RowBuffer, y: usize) -> usize {
+    closed_fold(rows, y).map_or(y, |it| it.start)
+}
+
+/// The first row of the line below the one `y` is shown on.
+pub fn next_line(rows: &RowBuffer, y: usize) -> usize {
+    closed_fold(rows, y).map_or(y, |it| it.end) + 1
+}
+
+/// The first row of every line from row `from` onward.
+pub fn lines(rows: &RowBuffer, from: usize) -> impl Iterator<Item = usize> + '_ {
+    std::iter::successors(Some(line_start(rows, from)), |y| Some(next_line(rows, *y)))
+        .take_while(|y| *y < rows.rows_count())
+}
+
+/// The amount of lines shown from row `from` up to the line row `to` is shown on.
+pub fn line_count(rows: &RowBuffer, from: usize, to: usize) -> usize {
+    let to = line_start(rows, to);
+
+    lines(rows, from).take_while(|y| *y < to).count()
+}
+
+/// The first row of the line `count` lines above the one `y` is shown on.
+pub fn lines_above(rows: &RowBuffer, y: usize, count: usize) -> usize {
+    (0..count).fold(line_start(rows, y), |y, _| match y {
+        0 => 0,
+        _ => line_start(rows, y - 1),
+    })
+}
+
+/// The line a closed fold is shown as, like `+---  12 lines: fn main() {-----`, with a dash for
+/// every fold it's nested in, filled up to `width` columns.
+pub fn summary(rows: &RowBuffer, fold: &Fold, width: usize) -> String {
+    let count = fold.end - fold.start + 1;
+    let text = data::render_contents(rows.get_row_at(fold.start).contents().trim());
+    let line = format!(
+        "+-{}{count:>3} lines: {text}",
+        "-".repeat(depth(rows, fold) + 1)
+    );
+
+    format!("{line:-<width$}")
+}
+
+/// How many folds `fold` is nested in.
+pub fn depth(rows: &RowBuffer, fold: &Fold) -> usize {
+    rows.folds()
+        .iter()
+        .filter(|it| *it != fold && it.start <= fold.start && fold.end <= it.end)
+        .count()
+}
+
+/// Every fold containing `y`, outermost first.
+fn folds_at(rows: &RowBuffer, y: usize) -> Vec<usize> {
+    rows.folds()
+        .iter()
+        .enumerate()
+        .filter(|(_, it)| it.start <= y && y <= it.end)
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Opens the outermost closed fold at `y`, or every fold at `y` with `all`.
+fn open_at(rows: &mut RowBuffer, y: usize, all: bool) -> Result<(), FoldError> {
+    let at = folds_at(rows, y);
+    let folds = rows.folds_mut();
+
+    match all {
+        true => at.iter().for_each(|index| folds[*index].closed = false),
+        false => {
+            let index = at
+                .into_iter()
+                .find(|index| folds[*index].closed)
+                .ok_or(FoldError::NoFold)?;
+            folds[index].closed = false;
+        }
+    }
+
+    Ok(())
+}
+
+/// Closes the innermost open fold at `y` that isn't hidden inside of a closed one, or every fold
+/// at `y` with `all`.
+fn close_at(rows: &mut RowBuffer, y: usize, all: bool) -> Result<(), FoldError> {
+    if all {
+        let at = folds_at(rows, y);
+        let folds = rows.folds_mut();
+        at.iter().for_each(|index| folds[*index].closed = true);
+        return Ok(());
+    }
+
+    let index = innermost_open(rows, y).ok_or(FoldError::NoFold)?;
+    rows.folds_mut()[index].closed = true;
+
+    Ok(())
+}
+
+/// The innermost open fold at `y` that isn't inside of a closed fold.
+fn innermost_open(rows: &RowBuffer, y: usize) -> Option<usize> {
+    let at = folds_at(rows, y);
+    let visible = at
+        .iter()
+        .position(|index| rows.folds()[*index].closed)
+        .unwrap_or(at.len());
+
+    at[..visible].last().copied()
+}
+
+/// Adds a closed manual fold, which can contain other folds or be contained by them.
+fn create(rows: &mut RowBuffer, range: LineRange, options: &Options) -> Result<(), FoldError> {
+    if options.foldmethod != "manual" {
+        return Err(FoldError::WrongMethod("create"));
+    }
+
+    let overlaps = rows.folds().iter().any(|it| {
+        (it.start < range.start && range.start <= it.end && it.end < range.end)
+            || (range.start < it.start && it.start <= range.end && range.end < it.end)
+    });
+
+    if overlaps {
+        return Err(FoldError::Overlapping);
+    }
+
+    let folds = rows.folds_mut();
+    folds.push(Fold {
+        start: range.start,
+        end: range.end,
+        closed: true,
+    });
+    sort(folds);
+
+    Ok(())
+}
+
+/// Orders folds by their start, outer folds first.
+fn sort(folds: &mut [Fold]) {
+    folds.sort_by_key(|it| (it.start, std::cmp::Reverse(it.end)));
+}
+
+/// The folds of `foldmethod=indent`, every run of rows indented by at least a couple of
+/// `shiftwidth`s is a fold. Blank rows are part of a fold when the rows around them are.
+fn indent_folds(rows: &RowBuffer, options: &Options) -> Vec<Fold> {
+    let width = options.shiftwidth.max(1);
+    let levels: Vec<Option<usize>> = (0..rows.rows_count())
+        .map(|y| rows.get_row_at(y).contents())
+        .map(|it| match it.trim().is_empty() {
+            true => None,
+            false => Some(indent::indent_width(it) / width),
+        })
+        .collect();
+
+    let mut folds = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+    let mut previous = 0;
+
+    for (y, level) in levels.iter().enumerate() {
+        let level = level.unwrap_or_else(|| {
+            let next = levels[y..].iter().flatten().next().copied().unwrap_or(0);
+            previous.min(next)
+        });
+
+        while open.len() > level {
+            let start = open.pop().unwrap_or_default();
+            folds.push(Fold {
+                start,
+                end: y - 1,
+                closed: false,
+            });
+        }
+
+        while open.len() < level {
+            open.push(y);
+        }
+
+        previous = level;
+    }
+
+    while let Some(start) = open.pop() {
+        folds.push(Fold {
+            start,
+            end: rows.rows_count() - 1,
+            closed: false,
+        });
+    }
+
+    folds
+}
+
+/// The folds of `foldmethod=marker`, from a row containing the start of `foldmarker` up to the
+/// row containing its end.
+fn marker_folds(rows: &RowBuffer, options: &Options) -> Vec<Fold> {
+    let Some((open_marker, close_marker)) = options.foldmarker.split_once(',') else {
+        return Vec::new();
+    };
+
+    let mut folds = Vec::new();
+    let mut open = Vec::new();
+
+    for y in 0..rows.rows_count() {
+        let contents = rows.get_row_at(y).contents();
+
+        if !close_marker.is_empty() && contents.contains(close_marker) {
+            if let Some(start) = open.pop() {
+                folds.push(Fold {
+                    start,
+                    end: y,
+                    closed: false,
+                });
+            }
+        } else if !open_marker.is_empty() && contents.contains(open_marker) {
+            open.push(y);
+        }
+    }
+
+    // a fold that's never closed lasts until the end of the buffer
+    while let Some(start) = open.pop() {
+        folds.push(Fold {
+            start,
+            end: rows.rows_count() - 1,
+            closed: false,
+        });
+    }
+
+    folds
+}
+
+/// The depth of every fold, in the same order. The folds have to be sorted.
+fn depths(folds: &[Fold]) -> Vec<usize> {
+    let mut ends: Vec<usize> = Vec::new();
+
+    folds
+        .iter()
+        .map(|fold| {
+            while ends.last().is_some_and(|end| *end < fold.start) {
+                ends.pop();
+            }
+
+            let depth = ends.len();
+            ends.push(fold.end);
+            depth
+        })
+        .collect()
+}
+
+/// Recomputes the folds for `foldmethod=indent` and `foldmethod=marker` from the contents of the
+/// buffer. A fold stays closed if a fold starting at the same row and at the same depth was.
+pub fn update_folds(mut rows: ResMut<RowBuffer>, options: Res<Options>) {
+    // the folds only change along with the buffer or the options, there's no need to scan the
+    // whole buffer again on every frame
+    if !rows.is_changed() && !options.is_changed() {
+        return;
+    }
+
+    let mut folds = match options.foldmethod.as_str() {
+        "indent" => indent_folds(&rows, &options),
+        "marker" => marker_folds(&rows, &options),
+        _ => return,
+    };
+
+    sort(&mut folds);
+
+    let current = rows.folds();
+    let closed: HashSet<(usize, usize)> = current
+        .iter()
+        .zip(depths(current))
+        .filter(|(fold, _)| fold.closed)
+        .map(|(fold, depth)| (fold.start, depth))
+        .collect();
+
+    let depths = depths(&folds);
+
+    for (fold, depth) in folds.iter_mut().zip(depths) {
+        fold.closed = closed.contains(&(fold.start, depth));
+    }
+
+    if rows.folds() != folds {
+        *rows.folds_mut() = folds;
+    }
+}
+
+/// Opens the folds the cursor ends up in, unless it's on the first row of a closed fold in normal
+/// or visual mode, which is where moving onto a fold puts it.
+pub fn follow_cursor(mut rows: ResMut<RowBuffer>, view: ActiveView, input_mode: Res<InputMode>) {
+    let position = &view.get().position;
+
+    let Some(fold) = closed_fold(&rows, position.y) else {
+        return;
+    };
+
+    let allowed =
+        matches!(*input_mode, InputMode::Normal | InputMode::Visual) && fold.start == position.y;
+
+    if !allowed {
+        let _ = open_at(&mut rows, position.y, true);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn handle_fold(
+    mut events: EventReader<FoldEvent>,
+    mut rows: ResMut<RowBuffer>,
+    mut view: ActiveView,
+    anchor: Res<VisualAnchor>,
+    mut input_mode: ResMut<InputMode>,
+    mut status: ResMut<StatusBuffer>,
+    options: Res<Options>,
+) {
+    for event in events.iter() {
+        let mut view = view.get_mut();
+        let View {
+            position, cursors, ..
+        } = &mut *view;
+        let y = position.y;
+
+        let result = match *event {
+            FoldEvent::Create { extend } => {
+                let selections = cursors.collect(&anchor, position);
+                let ranges: Vec<LineRange> = selections
+                    .iter()
+                    .map(|it| match extend {
+                        Some(extend) => {
+                            let other = it
+                                .head
+                                .y
+                                .saturating_add_signed(extend)
+                                .min(rows.rows_count().saturating_sub(1));
+                            LineRange {
+                                start: it.head.y.min(other),
+                                end: it.head.y.max(other),
+                            }
+                        }
+                        None => {
+                            let (start, end) = it.ordered();
+                            LineRange {
+                                start: start.y,
+                                end: end.y,
+                            }
+                        }
+                    })
+                    .collect();
+
+                *input_mode = InputMode::Normal;
+
+                // the cursor goes to the first row of the new fold, which is the one shown
+                position.y = ranges.first().map_or(y, |it| it.start);
+
+                ranges
+                    .into_iter()
+                    .try_for_each(|range| create(&mut rows, range, &options))
+            }
+            FoldEvent::Open => open_at(&mut rows, y, false),
+            FoldEvent::Close => close_at(&mut rows, y, false),
+            FoldEvent::Toggle => match closed_fold(&rows, y) {
+                Some(_) => open_at(&mut rows, y, false),
+                None => close_at(&mut rows, y, false),
+            },
+            FoldEvent::OpenAll => {
+                rows.folds_mut().iter_mut().for_each(|it| it.closed = false);
+                Ok(())
+            }
+            FoldEvent::CloseAll => {
+                rows.folds_mut().iter_mut().for_each(|it| it.closed = true);
+                Ok(())
+            }
+            FoldEvent::Delete | FoldEvent::DeleteAll if options.foldmethod != "manual" => {
+                Err(FoldError::WrongMethod("delete"))
+            }
+            FoldEvent::Delete => {
+                let at = folds_at(&rows, y);
+
+                // the closed fold that's shown, otherwise the innermost fold
+                let index = at
+                    .iter()
+                    .find(|index| rows.folds()[**index].closed)
+                    .or(at.last())
+                    .copied();
+
+                match index {
+                    Some(index) => {
+                        rows.folds_mut().remove(index);
+                        Ok(())
+                    }
+                    None => Err(FoldError::NoFold),
+                }
+            }
+            FoldEvent::DeleteAll => {
+                rows.folds_mut().clear();
+                Ok(())
+            }
+        };
+
+        if let Err(error) = result {
+            status.error(String::from(error));
+        }
+
+        // the cursor is moved onto the line of the fold it's in
+        position.y = line_start(&rows, position.y);
+        update::clamp_to_row(position, &rows);
+    }
+}
+
+/// `:[range]fo[ld]` creates a closed fold over the rows of the range.
+fn fold(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let range = invocation.range_or_current(world);
+
+    world.resource_scope(|world, mut rows: Mut<RowBuffer>| {
+        create(&mut rows, range, world.resource::<Options>())
+    })?;
+
+    Ok(())
+}
+
+/// `:[range]foldo[pen][!]` opens the outermost closed folds in the range, or every fold with a
+/// bang.
+fn fold_open(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let range = invocation.range_or_current(world);
+    let mut rows = world.resource_mut::<RowBuffer>();
+
+    for y in range.rows() {
+        let _ = open_at(&mut rows, y, invocation.bang);
+    }
+
+    Ok(())
+}
+
+/// `:[range]foldc[lose][!]` closes the innermost open folds in the range, or every fold with a
+/// bang.
+fn fold_close(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let range = invocation.range_or_current(world);
+    let mut rows = world.resource_mut::<RowBuffer>();
+
+    if invocation.bang {
+        for y in range.rows() {
+            let _ = close_at(&mut rows, y, true);
+        }
+
+        return Ok(());
+    }
+
+    // closing a fold on one row shouldn't make the next row close the fold around it
+    let innermost: Vec<usize> = range
+        .rows()
+        .filter_map(|y| innermost_open(&rows, y))
+        .collect();
+
+    for index in innermost {
+        rows.folds_mut()[index].closed = true;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(rows: &[&str]) -> RowBuffer {
+        let mut buffer = RowBuffer::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        buffer
+    }
+
+    fn options(foldmethod: &str) -> Options {
+        Options {
+            foldmethod: foldmethod.to_string(),
+            shiftwidth: 2,
+            ..Options::default()
+        }
+    }
+
+    /// The start and end of every fold, sorted.
+    fn ranges(mut folds: Vec<Fold>) -> Vec<(usize, usize)> {
+        sort(&mut folds);
+        folds.into_iter().map(|it| (it.start, it.end)).collect()
+    }
+
+    fn fold(start: usize, end: usize, closed: bool) -> Fold {
+        Fold { start, end, closed }
+    }
+
+    #[test]
+    fn indent_folds_nest_by_level() {
+        let rows = buffer(&["a", "  b", "    c", "  d", "e"]);
+
+        assert_eq!(
+            ranges(indent_folds(&rows, &options("indent"))),
+            [(1, 3), (2, 2)]
+        );
+    }
+
+    #[test]
+    fn indent_folds_take_in_blank_rows_and_reach_the_last_row() {
+        let rows = buffer(&["a", "  b", "", "  c", "", "d", "  e"]);
+
+        assert_eq!(
+            ranges(indent_folds(&rows, &options("indent"))),
+            [(1, 3), (6, 6)]
+        );
+        assert!(indent_folds(&buffer(&[]), &options("indent")).is_empty());
+    }
+
+    #[test]
+    fn indent_folds_measure_mixed_tabs_and_spaces_in_columns() {
+        let options = Options {
+            shiftwidth: 4,
+            ..options("indent")
+        };
+        let rows = buffer(&["a", "\tb", "    c", "  \td", "e"]);
+
+        assert_eq!(ranges(indent_folds(&rows, &options)), [(1, 3)]);
+    }
+
+    #[test]
+    fn marker_folds_nest_and_last_until_the_end() {
+        let rows = buffer(&["a {{{", "b {{{", "c }}}", "d }}}", "e {{{", "f"]);
+
+        assert_eq!(
+            ranges(marker_folds(&rows, &options("marker"))),
+            [(0, 3), (1, 2), (4, 5)]
+        );
+        assert!(marker_folds(&buffer(&[]), &options("marker")).is_empty());
+        assert!(marker_folds(
+            &rows,
+            &Options {
+                foldmarker: String::from("{{{"),
+                ..options("marker")
+            }
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn depths_count_the_folds_around() {
+        let folds = [
+            fold(0, 5, false),
+            fold(1, 2, false),
+            fold(3, 4, false),
+            fold(6, 7, false),
+        ];
+
+        assert_eq!(depths(&folds), [0, 1, 1, 0]);
+    }
+
+    #[test]
+    fn folds_move_along_with_inserted_and_removed_rows() {
+        let mut rows = buffer(&["a", "b", "c", "d", "e"]);
+        *rows.folds_mut() = vec![fold(1, 2, true), fold(4, 4, true)];
+
+        rows.insert_row(2, "x");
+        assert_eq!(rows.folds(), [fold(1, 3, true), fold(5, 5, true)]);
+
+        rows.insert_row(0, "y");
+        assert_eq!(rows.folds(), [fold(2, 4, true), fold(6, 6, true)]);
+
+        rows.remove_row(6);
+        rows.remove_row(3);
+        assert_eq!(rows.folds(), [fold(2, 3, true)]);
+    }
+
+    #[test]
+    fn closed_folds_are_shown_as_a_single_line() {
+        let mut rows = buffer(&["a", "b", "c", "d", "e"]);
+        *rows.folds_mut() = vec![fold(1, 3, true), fold(2, 3, false)];
+
+        assert_eq!(closed_fold(&rows, 2), Some(fold(1, 3, true)));
+        assert_eq!(closed_fold(&rows, 4), None);
+        assert_eq!(lines(&rows, 0).collect::<Vec<_>>(), [0, 1, 4]);
+        assert_eq!(lines(&rows, 2).collect::<Vec<_>>(), [1, 4]);
+        assert_eq!(line_count(&rows, 0, 4), 2);
+        assert_eq!(lines_above(&rows, 4, 1), 1);
+        assert_eq!(lines_above(&rows, 4, 9), 0);
+    }
+
+    #[test]
+    fn summaries_show_the_row_count_and_the_depth() {
+        let mut rows = buffer(&["  fn a() {", "b", "c"]);
+        *rows.folds_mut() = vec![fold(0, 2, true), fold(1, 2, true)];
+
+        assert_eq!(
+            summary(&rows, &fold(0, 2, true), 24),
+            "+--  3 lines: fn a() {--"
+        );
+        assert_eq!(summary(&rows, &fold(1, 2, true), 0), "+---  2 lines: b");
+    }
+
+    #[test]
+    fn manual_folds_cannot_partially_overlap() {
+        let mut rows = buffer(&["a", "b", "c", "d"]);
+        let range = |start, end| LineRange { start, end };
+
+        create(&mut rows, range(1, 2), &options("manual")).unwrap();
+        create(&mut rows, range(0, 3), &options("manual")).unwrap();
+
+        assert!(matches!(
+            create(&mut rows, range(2, 3), &options("manual")),
+            Err(FoldError::Overlapping)
+        ));
+        assert!(matches!(
+            create(&mut rows, range(0, 0), &options("indent")),
+            Err(FoldError::WrongMethod(_))
+        ));
+        assert_eq!(rows.folds(), [fold(0, 3, true), fold(1, 2, true)]);
+    }
+
+    #[test]
+    fn opening_and_closing_follow_the_nesting() {
+        let mut rows = buffer(&["a", "b", "c", "d"]);
+        *rows.folds_mut() = vec![fold(0, 3, true), fold(1, 2, true)];
+
+        open_at(&mut rows, 1, false).unwrap();
+        assert_eq!(rows.folds(), [fold(0, 3, false), fold(1, 2, true)]);
+
+        open_at(&mut rows, 1, true).unwrap();
+        close_at(&mut rows, 1, false).unwrap();
+        assert_eq!(rows.folds(), [fold(0, 3, false), fold(1, 2, true)]);
+
+        close_at(&mut rows, 0, false).unwrap();
+        assert_eq!(rows.folds(), [fold(0, 3, true), fold(1, 2, true)]);
+        assert!(matches!(
+            open_at(&mut buffer(&["a"]), 0, false),
+            Err(FoldError::NoFold)
+        ));
+    }
+}
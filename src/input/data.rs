@@ -1,7 +1,13 @@
-use std::{fs, path::Path};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
 use bevy_ecs::system::Resource;
 
+use super::undo::{State, Step, UndoHistory};
+
 pub const TAB_SIZE: usize = 4;
 
 #[derive(Clone)]
@@ -15,25 +21,69 @@ impl Row {
         Self { contents, render }
     }
 
+    fn from_contents(contents: &str) -> Self {
+        let mut row = Row::new(contents.into(), String::new());
+
+        RowBuffer::render_row(&mut row);
+        row
+    }
+
     pub fn get_len_with_offset(&self, offset: usize) -> usize {
-        if offset > self.contents.len() {
-            return self.contents.len();
-        }
+        render_column(&self.contents, offset)
+    }
 
-        self.contents[..offset].chars().fold(0, |acc, current| {
-            acc + if current == '\t' {
-                (TAB_SIZE - 1) - (acc % TAB_SIZE) + 1
-            } else {
-                1
-            }
-        })
+    pub fn contents(&self) -> &str {
+        &self.contents
     }
 }
 
+/// A position inside of a [RowBuffer], remembered under a single character name.
+///
+/// `x` is a byte offset into the row's contents, `y` is the row index.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Mark {
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+}
+
+/// Rows `start` up to and including `end` folded into a single row while `closed`, see the
+/// `fold` module. Folds can be nested, but never partially overlap.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Fold {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) closed: bool,
+}
+
 #[derive(Clone, Resource)]
 pub struct RowBuffer {
     rows: Vec<Row>,
     name: String,
+    path: Option<PathBuf>,
+    marks: HashMap<char, Mark>,
+    modified: bool,
+    history: UndoHistory,
+    /// Rows followed by [RowBuffer::track_rows], kept up to date as rows are inserted and removed.
+    tracked: Vec<Option<usize>>,
+    /// The cursor the current group of changes started at, see [RowBuffer::begin_group].
+    group: Option<UndoGroup>,
+    /// Ordered by their start, outer folds before the folds inside of them.
+    folds: Vec<Fold>,
+}
+
+/// Changes made while a group is open are undone as a single step.
+#[derive(Copy, Clone)]
+struct UndoGroup {
+    cursor: (usize, usize),
+    changed: bool,
+    /// How many groups are open, only the outermost one is closed by [RowBuffer::end_group].
+    depth: usize,
+}
+
+impl Default for RowBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RowBuffer {
@@ -41,6 +91,25 @@ impl RowBuffer {
         Self {
             rows: Vec::new(),
             name: String::from("Empty Buffer"),
+            path: None,
+            marks: HashMap::new(),
+            modified: false,
+            history: UndoHistory::default(),
+            tracked: Vec::new(),
+            group: None,
+            folds: Vec::new(),
+        }
+    }
+
+    /// An empty buffer for the file at `path`, which doesn't have to exist until the buffer is
+    /// written.
+    pub fn new_file(path: &Path) -> Self {
+        let name = path.file_name().map_or(path.as_os_str(), |it| it);
+
+        Self {
+            name: name.to_string_lossy().to_string(),
+            path: Some(path.to_path_buf()),
+            ..Self::new()
         }
     }
 
@@ -53,40 +122,19 @@ impl RowBuffer {
     }
 
     pub fn get_buffer_name(&self) -> &str {
-        return &self.name;
+        &self.name
+    }
+
+    pub fn get_path(&self) -> Option<&Path> {
+        self.path.as_deref()
     }
 
     pub fn render_row(row: &mut Row) {
-        // Calculate the capacity needed for the `render` String
-        let capacity = row
-            .contents
-            .chars()
-            .map(|c| if c == '\t' { TAB_SIZE } else { 1 })
-            .sum();
-
-        // Create the `render` String with the calculated capacity
-        row.render = String::with_capacity(capacity);
-
-        // Iterate over the characters in `contents`
-        for current in row.contents.chars() {
-            if current == '\t' {
-                // Replace tabs with spaces
-                row.render.push(' ');
-
-                // Calculate the number of spaces needed to reach the next tab stop
-                let next_tab_diff = TAB_SIZE - (row.render.len() % TAB_SIZE);
-
-                // Append the required number of spaces
-                (0..next_tab_diff).for_each(|_| row.render.push(' '));
-            } else {
-                // Copy other characters as-is
-                row.render.push(current);
-            }
-        }
+        row.render = render_contents(&row.contents);
     }
 
     pub fn get_char_count(&self) -> usize {
-        return self.rows.iter().map(|row| row.contents.len()).sum();
+        self.rows.iter().map(|row| row.contents.len()).sum()
     }
 
     pub fn get_render(&self, y: usize) -> &String {
@@ -100,6 +148,402 @@ impl RowBuffer {
     pub fn rows_count(&self) -> usize {
         self.rows.len()
     }
+
+    pub fn set_mark(&mut self, name: char, x: usize, y: usize) {
+        self.marks.insert(name, Mark { x, y });
+    }
+
+    pub fn get_mark(&self, name: char) -> Option<Mark> {
+        self.marks.get(&name).copied()
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// Replaces the contents of the row at `y`.
+    pub fn set_row(&mut self, y: usize, contents: &str) {
+        self.rows[y] = Row::from_contents(contents);
+        self.modified = true;
+    }
+
+    /// Inserts a new row at `y`, moving every row from `y` onward down by one.
+    pub fn insert_row(&mut self, y: usize, contents: &str) {
+        self.rows.insert(y, Row::from_contents(contents));
+        self.modified = true;
+
+        self.marks
+            .values_mut()
+            .filter(|mark| mark.y >= y)
+            .for_each(|mark| mark.y += 1);
+
+        self.tracked
+            .iter_mut()
+            .flatten()
+            .filter(|row| **row >= y)
+            .for_each(|row| *row += 1);
+
+        // a row inserted inside of a fold makes it grow
+        for fold in &mut self.folds {
+            if fold.start >= y {
+                fold.start += 1;
+                fold.end += 1;
+            } else if fold.end >= y {
+                fold.end += 1;
+            }
+        }
+    }
+
+    /// Removes the row at `y`, returning its contents. Marks on the removed row are kept on the
+    /// row that takes its place.
+    pub fn remove_row(&mut self, y: usize) -> Box<str> {
+        let row = self.rows.remove(y);
+        self.modified = true;
+
+        self.marks
+            .values_mut()
+            .filter(|mark| mark.y > y)
+            .for_each(|mark| mark.y -= 1);
+
+        for tracked in &mut self.tracked {
+            *tracked = match *tracked {
+                Some(row) if row == y => None,
+                Some(row) if row > y => Some(row - 1),
+                row => row,
+            };
+        }
+
+        // a fold shrinks along with its rows, and is gone once its last row is
+        self.folds
+            .retain(|fold| !(fold.start == y && fold.end == y));
+
+        for fold in &mut self.folds {
+            if fold.start > y {
+                fold.start -= 1;
+                fold.end -= 1;
+            } else if fold.end >= y {
+                fold.end -= 1;
+            }
+        }
+
+        row.contents
+    }
+
+    /// Inserts `text` at byte `x` of row `y`, splitting the row on every newline. Inserting right
+    /// below the last row adds a new row. Returns the position right after the inserted text.
+    pub fn insert_text(&mut self, x: usize, y: usize, text: &str) -> (usize, usize) {
+        if y == self.rows.len() {
+            self.insert_row(y, "");
+        }
+
+        let contents = self.rows[y].contents.to_string();
+        let (before, after) = contents.split_at(x);
+        let mut lines = text.split('\n');
+
+        let first = lines.next().unwrap_or_default();
+        let rest: Vec<&str> = lines.collect();
+
+        let Some((last, middle)) = rest.split_last() else {
+            self.set_row(y, &format!("{before}{first}{after}"));
+            return (x + first.len(), y);
+        };
+
+        self.set_row(y, &format!("{before}{first}"));
+
+        for (index, line) in middle.iter().enumerate() {
+            self.insert_row(y + index + 1, line);
+        }
+
+        let end_y = y + rest.len();
+        self.insert_row(end_y, &format!("{last}{after}"));
+
+        (last.len(), end_y)
+    }
+
+    /// The text from `start` up to (but not including) `end`, with rows joined by newlines. Both
+    /// are `(x, y)` positions.
+    pub fn text(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        let ((start_x, start_y), (end_x, end_y)) = (start, end);
+
+        if (start_y, start_x) >= (end_y, end_x) {
+            return String::new();
+        }
+
+        if start_y == end_y {
+            return self.rows[start_y].contents[start_x..end_x].to_string();
+        }
+
+        let mut text = self.rows[start_y].contents[start_x..].to_string();
+
+        for y in start_y + 1..end_y {
+            text.push('\n');
+            text.push_str(&self.rows[y].contents);
+        }
+
+        text.push('\n');
+        text.push_str(&self.rows[end_y].contents[..end_x]);
+        text
+    }
+
+    /// Removes the text from `start` up to (but not including) `end`, joining the rows in between.
+    /// Returns the text that was removed.
+    pub fn remove_text(&mut self, start: (usize, usize), end: (usize, usize)) -> String {
+        let removed = self.text(start, end);
+
+        if removed.is_empty() {
+            return removed;
+        }
+
+        let ((start_x, start_y), (end_x, end_y)) = (start, end);
+        let joined = format!(
+            "{}{}",
+            &self.rows[start_y].contents[..start_x],
+            &self.rows[end_y].contents[end_x..]
+        );
+
+        for _ in start_y..end_y {
+            self.remove_row(start_y + 1);
+        }
+
+        self.set_row(start_y, &joined);
+        removed
+    }
+
+    /// Joins the row below `y` onto the end of row `y` with `separator` in between, leaving out
+    /// the first `skip` bytes of the joined row. Marks on the joined row move along with its
+    /// text. Returns the byte offset the separator starts at.
+    pub fn join_row(&mut self, y: usize, separator: &str, skip: usize) -> usize {
+        let x = self.rows[y].contents.len();
+        let joined = self.remove_row(y + 1);
+
+        // removing the row left its marks on the row that took its place
+        for mark in self.marks.values_mut().filter(|mark| mark.y == y + 1) {
+            mark.y = y;
+            mark.x = x + separator.len() + mark.x.saturating_sub(skip);
+        }
+
+        let contents = format!("{}{separator}{}", self.rows[y].contents, &joined[skip..]);
+        self.set_row(y, &contents);
+        x
+    }
+
+    /// Moves rows `start` up to and including `end`, so the first of them ends up at row `to`.
+    /// Marks, tracked rows and folds move along with the rows they're on.
+    pub fn move_rows(&mut self, start: usize, end: usize, to: usize) {
+        let count = end - start + 1;
+        let moved: Vec<Row> = self.rows.drain(start..=end).collect();
+        self.rows.splice(to..to, moved);
+        self.modified = true;
+
+        let map = |y: usize| match (start..=end).contains(&y) {
+            true => y - start + to,
+            false => {
+                let y = if y > end { y - count } else { y };
+                if y >= to {
+                    y + count
+                } else {
+                    y
+                }
+            }
+        };
+
+        self.marks
+            .values_mut()
+            .for_each(|mark| mark.y = map(mark.y));
+        self.tracked
+            .iter_mut()
+            .flatten()
+            .for_each(|row| *row = map(*row));
+
+        // a fold that only partially moved can end up inside out, which makes it meaningless
+        for fold in &mut self.folds {
+            (fold.start, fold.end) = (map(fold.start), map(fold.end));
+        }
+
+        self.folds.retain(|fold| fold.start <= fold.end);
+        self.folds
+            .sort_by_key(|fold| (fold.start, std::cmp::Reverse(fold.end)));
+    }
+
+    fn state(&self, cursor: (usize, usize)) -> State {
+        State {
+            folds: self.folds.clone(),
+            marks: self.marks.clone(),
+            cursor,
+            modified: self.modified,
+        }
+    }
+
+    /// Puts back the rows a step replaced, along with the rest of its state.
+    fn restore(&mut self, step: Step) -> (usize, usize) {
+        let range = step.start..step.start + step.replaced;
+        let rows = step.rows.iter().map(|it| Row::from_contents(it));
+        self.rows.splice(range, rows);
+
+        self.folds = step.state.folds;
+        self.marks = step.state.marks;
+        self.modified = step.state.modified;
+        step.state.cursor
+    }
+
+    /// Remembers the current state of the buffer as a single undo step, has to be called before
+    /// making a change. `cursor` is where the cursor goes back to when undoing the change.
+    pub fn begin_change(&mut self, cursor: (usize, usize)) {
+        let cursor = match &mut self.group {
+            Some(group) if group.changed => return,
+            Some(group) => {
+                group.changed = true;
+                group.cursor
+            }
+            None => cursor,
+        };
+
+        let state = self.state(cursor);
+        self.history.begin(&self.rows, state);
+    }
+
+    /// Starts grouping changes, every change until [RowBuffer::end_group] is undone as a single
+    /// step that puts the cursor back at `cursor`. Nothing is remembered if nothing changes.
+    ///
+    /// Groups nest, a group begun while another one is open becomes part of it, e.g. every row
+    /// `:g` reindents is undone along with the rest of the `:g`.
+    pub fn begin_group(&mut self, cursor: (usize, usize)) {
+        match &mut self.group {
+            Some(group) => group.depth += 1,
+            None => {
+                self.group = Some(UndoGroup {
+                    cursor,
+                    changed: false,
+                    depth: 1,
+                })
+            }
+        }
+    }
+
+    pub fn end_group(&mut self) {
+        if let Some(group) = &mut self.group {
+            group.depth -= 1;
+
+            if group.depth == 0 {
+                self.group = None;
+            }
+        }
+    }
+
+    /// Starts following `rows`, their indices are adjusted as rows are inserted or removed. A
+    /// removed row isn't followed anymore, see [RowBuffer::tracked_row].
+    pub fn track_rows(&mut self, rows: Vec<usize>) {
+        self.tracked = rows.into_iter().map(Some).collect();
+    }
+
+    /// The current index of the `index`th tracked row, `None` if it has been removed since.
+    pub fn tracked_row(&self, index: usize) -> Option<usize> {
+        self.tracked.get(index).copied().flatten()
+    }
+
+    pub fn clear_tracked(&mut self) {
+        self.tracked.clear();
+    }
+
+    /// Undoes the last change, returning the position the cursor was at before the change.
+    pub fn undo(&mut self, cursor: (usize, usize)) -> Option<(usize, usize)> {
+        let current = self.state(cursor);
+        let previous = self.history.undo(&self.rows, current)?;
+
+        Some(self.restore(previous))
+    }
+
+    /// Redoes the last undone change, returning the position the cursor was at when undoing it.
+    pub fn redo(&mut self, cursor: (usize, usize)) -> Option<(usize, usize)> {
+        let current = self.state(cursor);
+        let next = self.history.redo(&self.rows, current)?;
+
+        Some(self.restore(next))
+    }
+
+    pub fn folds(&self) -> &[Fold] {
+        &self.folds
+    }
+
+    pub fn folds_mut(&mut self) -> &mut Vec<Fold> {
+        &mut self.folds
+    }
+
+    pub fn undo_count(&self) -> usize {
+        self.history.undo_count()
+    }
+
+    /// Writes the contents of the buffer to `path`, joining every row with a newline.
+    pub fn write_to(&mut self, path: &Path) -> std::io::Result<usize> {
+        let contents = self
+            .rows
+            .iter()
+            .fold(String::new(), |acc, row| acc + &row.contents + "\n");
+
+        fs::write(path, &contents)?;
+
+        // writing to another file doesn't make the buffer's own file any less out of date.
+        if self.path.as_deref().is_none_or(|it| it == path) {
+            self.modified = false;
+        }
+
+        Ok(contents.len())
+    }
+}
+
+/// Renders the contents of a row the way they're shown on screen, with tabs expanded to spaces.
+pub fn render_contents(contents: &str) -> String {
+    // Calculate the capacity needed for the `render` String
+    let capacity = contents
+        .chars()
+        .map(|c| if c == '\t' { TAB_SIZE } else { 1 })
+        .sum();
+
+    // Create the `render` String with the calculated capacity
+    let mut render = String::with_capacity(capacity);
+
+    // Iterate over the characters in `contents`
+    for current in contents.chars() {
+        if current == '\t' {
+            // Replace tabs with spaces
+            render.push(' ');
+
+            // Calculate the number of spaces needed to reach the next tab stop
+            let next_tab_diff = TAB_SIZE - (render.chars().count() % TAB_SIZE);
+
+            // Append the required number of spaces
+            (0..next_tab_diff % TAB_SIZE).for_each(|_| render.push(' '));
+        } else {
+            // Copy other characters as-is
+            render.push(current);
+        }
+    }
+
+    render
+}
+
+/// The column the byte `offset` of `contents` is rendered at, after expanding tabs.
+pub fn render_column(contents: &str, offset: usize) -> usize {
+    if offset > contents.len() {
+        return contents.len();
+    }
+
+    contents[..offset].chars().fold(0, |acc, current| {
+        acc + if current == '\t' {
+            (TAB_SIZE - 1) - (acc % TAB_SIZE) + 1
+        } else {
+            1
+        }
+    })
+}
+
+/// The byte offset of the character of `contents` rendered at `column`, a tab covers all of the
+/// columns it's expanded to. The length of `contents` for columns past its end.
+pub fn column_offset(contents: &str, column: usize) -> usize {
+    contents
+        .char_indices()
+        .find(|(index, char)| render_column(contents, index + char.len_utf8()) > column)
+        .map_or(contents.len(), |(index, _)| index)
 }
 
 impl TryFrom<&Path> for RowBuffer {
@@ -109,20 +553,121 @@ impl TryFrom<&Path> for RowBuffer {
         let contents = fs::read_to_string(value)?;
 
         Ok(Self {
-            name: value
-                .file_name()
-                .expect("Could not get file name from path")
-                .to_string_lossy()
-                .to_string(),
             rows: contents
                 .lines()
                 .map(|it| {
                     let mut row = Row::new(it.into(), it.to_string());
 
                     Self::render_row(&mut row);
-                    return row;
+                    row
                 })
                 .collect(),
+            ..Self::new_file(value)
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(rows: &[&str]) -> RowBuffer {
+        let mut buffer = RowBuffer::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        buffer
+    }
+
+    fn contents(buffer: &RowBuffer) -> Vec<&str> {
+        buffer.rows.iter().map(|it| it.contents()).collect()
+    }
+
+    #[test]
+    fn undo_and_redo_put_back_the_changed_rows() {
+        let mut rows = buffer(&["one", "two", "three"]);
+
+        rows.begin_change((0, 1));
+        rows.set_row(1, "TWO");
+        rows.begin_change((0, 2));
+        rows.insert_row(3, "four");
+        rows.remove_row(0);
+
+        assert_eq!(rows.undo((0, 0)), Some((0, 2)));
+        assert_eq!(contents(&rows), ["one", "TWO", "three"]);
+        assert_eq!(rows.undo((0, 0)), Some((0, 1)));
+        assert_eq!(contents(&rows), ["one", "two", "three"]);
+        assert_eq!(rows.undo((0, 0)), None);
+
+        assert_eq!(rows.redo((1, 1)), Some((0, 0)));
+        assert_eq!(contents(&rows), ["one", "TWO", "three"]);
+        assert_eq!(rows.redo((1, 1)), Some((0, 0)));
+        assert_eq!(contents(&rows), ["TWO", "three", "four"]);
+        assert_eq!(rows.redo((0, 0)), None);
+    }
+
+    #[test]
+    fn a_new_change_drops_the_undone_ones() {
+        let mut rows = buffer(&["one", "two"]);
+
+        rows.begin_change((0, 0));
+        rows.set_row(0, "ONE");
+        rows.undo((0, 0));
+
+        rows.begin_change((0, 1));
+        rows.set_row(1, "TWO");
+
+        assert_eq!(rows.redo((0, 0)), None);
+        assert_eq!(rows.undo((0, 0)), Some((0, 1)));
+        assert_eq!(contents(&rows), ["one", "two"]);
+    }
+
+    #[test]
+    fn a_group_is_undone_as_a_single_step() {
+        let mut rows = buffer(&["a", "b", "c"]);
+
+        rows.begin_group((2, 0));
+        for y in 0..3 {
+            rows.begin_change((0, y));
+            rows.set_row(y, "x");
+        }
+        rows.end_group();
+
+        assert_eq!(rows.undo_count(), 1);
+        assert_eq!(rows.undo((0, 0)), Some((2, 0)));
+        assert_eq!(contents(&rows), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn a_nested_group_is_part_of_the_outer_one() {
+        let mut rows = buffer(&["a", "b", "c"]);
+
+        rows.begin_group((2, 0));
+        for y in 0..3 {
+            rows.begin_group((0, y));
+            rows.begin_change((0, y));
+            rows.set_row(y, "x");
+            rows.end_group();
+        }
+        rows.end_group();
+
+        assert_eq!(rows.undo_count(), 1);
+        assert_eq!(rows.undo((0, 0)), Some((2, 0)));
+        assert_eq!(contents(&rows), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn undo_puts_marks_back() {
+        let mut rows = buffer(&["a", "b", "c"]);
+        rows.set_mark('m', 0, 2);
+
+        rows.begin_change((0, 0));
+        rows.move_rows(2, 2, 0);
+        assert_eq!(rows.get_mark('m'), Some(Mark { x: 0, y: 0 }));
+
+        rows.undo((0, 0));
+        assert_eq!(rows.get_mark('m'), Some(Mark { x: 0, y: 2 }));
+    }
+}
@@ -1,65 +1,465 @@
 use std::fmt::Display;
 
 use bevy_ecs::{
-    event::{Event, EventReader, EventWriter},
-    system::{ResMut, Resource},
+    event::{Event, EventWriter},
+    system::{Local, Res, ResMut, Resource, SystemParam},
 };
 use crossterm::event::{KeyCode, KeyModifiers};
 
-use crate::cursor::CursorMoveEvent;
+use crate::{
+    command::{
+        line::{CommandKind, CommandLine, LineAction},
+        CommandEvent,
+    },
+    complete::CompletionEvent,
+    cursor::{
+        multi::MultiCursorEvent, update::clamp_to_row, CursorMoveEvent, CursorPosition,
+        VisualAnchor,
+    },
+    edit::{
+        operator::{Keys, Motion, Operator},
+        EditEvent, InsertPosition,
+    },
+    fold::FoldEvent,
+    output::OutputSize,
+    search::SearchEvent,
+    status::StatusBuffer,
+    substitute::confirm::ConfirmEvent,
+    window::{ActiveView, View, WindowEvent},
+};
 
-use self::reader::InputReader;
+use self::{
+    data::RowBuffer,
+    reader::{InputEvent, InputReader},
+};
 
 pub mod data;
 pub mod reader;
+pub mod undo;
 
-#[derive(Copy, Clone, Resource)]
+#[derive(Copy, Clone, PartialEq, Eq, Resource)]
 pub enum InputMode {
     Insert,
     Visual,
     Normal,
+    Command,
+    /// A substitution is asking for confirmation of a replacement.
+    Confirm,
 }
 
 #[derive(Event, Default)]
 pub struct QuitWriter;
 
+/// The keys typed so far of a command that takes more than one, like `gc` waiting for its motion.
+#[derive(Resource, Default)]
+pub struct PendingKeys {
+    pub(crate) keys: String,
+}
+
+impl PendingKeys {
+    /// Whether the keys are an operator waiting for its motion, like `gc` or `=`, rather than
+    /// the start of a command like `gt` or `Ctrl-W`.
+    pub fn is_operator(&self) -> bool {
+        Operator::split(&self.keys).is_some() || self.keys == "zf"
+    }
+}
+
+/// Every event [process_input] sends, bundled together to keep the amount of system parameters
+/// in check.
+#[derive(SystemParam)]
+pub struct InputWriters<'w> {
+    quit: EventWriter<'w, QuitWriter>,
+    cursor_move: EventWriter<'w, CursorMoveEvent>,
+    command: EventWriter<'w, CommandEvent>,
+    search: EventWriter<'w, SearchEvent>,
+    confirm: EventWriter<'w, ConfirmEvent>,
+    edit: EventWriter<'w, EditEvent>,
+    multi_cursor: EventWriter<'w, MultiCursorEvent>,
+    completion: EventWriter<'w, CompletionEvent>,
+    fold: EventWriter<'w, FoldEvent>,
+    window: EventWriter<'w, WindowEvent>,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn process_input(
     reader: ResMut<InputReader>,
-    mut quit_writer: EventWriter<QuitWriter>,
-    mut cursor_move_writer: EventWriter<CursorMoveEvent>,
+    mut writers: InputWriters,
+    mut pending: ResMut<PendingKeys>,
+    mut count: Local<usize>,
     mut input_mode: ResMut<InputMode>,
+    mut command_line: ResMut<CommandLine>,
+    mut status: ResMut<StatusBuffer>,
+    mut rows: ResMut<RowBuffer>,
+    mut visual_anchor: ResMut<VisualAnchor>,
+    mut view: ActiveView,
+    mut size: ResMut<OutputSize>,
 ) {
-    let event = reader.read_key().expect("Could not read input!");
+    let event = match reader.read_event().expect("Could not read input!") {
+        InputEvent::Key(event) => event,
+        // the next frame is drawn at the new size, with the view scrolled to the cursor again
+        InputEvent::Resize(resized) => {
+            *size = resized;
+            return;
+        }
+    };
+
+    let mut view = view.get_mut();
+    let View {
+        position, cursors, ..
+    } = &mut *view;
+
+    // messages only stay around until the next key is pressed
+    status.clear_message();
+
+    // a count typed in front of a command, only commands that take one look at it
+    let counted = std::mem::take(&mut *count);
+
+    if !pending.keys.is_empty() {
+        let mut keys = std::mem::take(&mut pending.keys);
+
+        if let ("g", KeyCode::Char(key @ ('a' | 'x')), KeyModifiers::CONTROL, InputMode::Visual) =
+            (keys.as_str(), event.code, event.modifiers, *input_mode)
+        {
+            set_visual_marks(&mut rows, &visual_anchor, position);
+            writers.edit.send(EditEvent::IncrementSelection {
+                delta: increment_delta(key, counted),
+                progressive: true,
+            });
+        } else if let (KeyCode::Char(key), InputMode::Normal | InputMode::Visual) =
+            (event.code, *input_mode)
+        {
+            keys.push(key);
+
+            match (keys.as_str(), *input_mode) {
+                ("gc", InputMode::Visual) => {
+                    set_visual_marks(&mut rows, &visual_anchor, position);
+                    writers.edit.send(EditEvent::ToggleCommentSelection);
+                }
+                ("gJ", InputMode::Visual) => {
+                    set_visual_marks(&mut rows, &visual_anchor, position);
+                    writers
+                        .edit
+                        .send(EditEvent::JoinSelection { normalize: false });
+                }
+                ("gq", InputMode::Visual) => {
+                    set_visual_marks(&mut rows, &visual_anchor, position);
+                    writers.edit.send(EditEvent::FormatSelection);
+                }
+                ("gj" | "gk", InputMode::Visual) => {
+                    writers
+                        .cursor_move
+                        .send(CursorMoveEvent::screen_line(keys == "gj"));
+                }
+                ("zf", InputMode::Visual) => {
+                    writers.fold.send(FoldEvent::Create { extend: None });
+                }
+                (keys, InputMode::Normal) if pending_key(keys, counted, &mut writers) => {
+                    pending.keys = keys.to_string();
+                }
+                _ => {}
+            }
+        }
+
+        return;
+    }
 
     match (event.code, event.modifiers, *input_mode) {
+        (_, _, InputMode::Command) => match command_line.handle_key(event) {
+            Some(LineAction::Submit(CommandKind::Ex, line)) => {
+                *input_mode = command_line.origin();
+                writers.command.send(CommandEvent { line });
+            }
+            Some(LineAction::Submit(CommandKind::Search { backward }, line)) => {
+                *input_mode = command_line.origin();
+                writers.search.send(SearchEvent::Submit { line, backward });
+            }
+            Some(LineAction::Cancel) => *input_mode = command_line.origin(),
+            None => {}
+        },
+        (_, _, InputMode::Confirm) => writers.confirm.send(ConfirmEvent { key: event }),
+        (
+            KeyCode::Char(key @ ('n' | 'p' | 'y' | 'e')),
+            KeyModifiers::CONTROL,
+            InputMode::Insert,
+        ) => writers.completion.send(match key {
+            'n' => CompletionEvent::Next,
+            'p' => CompletionEvent::Previous,
+            'y' => CompletionEvent::Accept,
+            _ => CompletionEvent::Cancel,
+        }),
+        (_, _, InputMode::Insert) => writers.edit.send(EditEvent::Key(event)),
         (KeyCode::Char('q'), KeyModifiers::CONTROL, InputMode::Normal) => {
-            quit_writer.send_default();
-            return;
+            writers.quit.send_default();
+        }
+        (KeyCode::Char('u'), KeyModifiers::NONE, InputMode::Normal) => {
+            match rows.undo((position.x, position.y)) {
+                Some(cursor) => (position.x, position.y) = cursor,
+                None => status.error("Already at oldest change"),
+            }
+
+            clamp_to_row(position, &rows);
+        }
+        (KeyCode::Char('r'), KeyModifiers::CONTROL, InputMode::Normal) => {
+            match rows.redo((position.x, position.y)) {
+                Some(cursor) => (position.x, position.y) = cursor,
+                None => status.error("Already at newest change"),
+            }
+
+            clamp_to_row(position, &rows);
+        }
+        (
+            KeyCode::Char(key @ ('i' | 'a' | 'I' | 'A')),
+            KeyModifiers::NONE | KeyModifiers::SHIFT,
+            InputMode::Normal,
+        ) => writers.edit.send(EditEvent::StartInsert(match key {
+            'i' => InsertPosition::Before,
+            'a' => InsertPosition::After,
+            'I' => InsertPosition::RowStart,
+            _ => InsertPosition::RowEnd,
+        })),
+        (KeyCode::Char('x'), KeyModifiers::NONE, InputMode::Normal) => {
+            writers.edit.send(EditEvent::RemoveChar)
+        }
+        (
+            KeyCode::Char(key @ ('o' | 'O')),
+            KeyModifiers::NONE | KeyModifiers::SHIFT,
+            InputMode::Normal,
+        ) => writers.edit.send(EditEvent::OpenRow { above: key == 'O' }),
+        // the first key of a command that takes more keys, e.g. `==`, `gcc`, `zo` or `rx`
+        (KeyCode::Char(key @ ('=' | 'g' | 'z' | 'r')), KeyModifiers::NONE, InputMode::Normal) => {
+            pending.keys.push(key);
+            *count = counted;
+        }
+        (KeyCode::Char('w'), KeyModifiers::CONTROL, InputMode::Normal) => {
+            pending.keys.push(WINDOW_PREFIX);
+            *count = counted;
+        }
+        (KeyCode::Char(key @ ('g' | 'z')), KeyModifiers::NONE, InputMode::Visual) => {
+            pending.keys.push(key);
+            *count = counted;
+        }
+        (
+            KeyCode::Char(digit @ '0'..='9'),
+            KeyModifiers::NONE,
+            InputMode::Normal | InputMode::Visual,
+        ) if digit != '0' || counted > 0 => {
+            *count = counted
+                .saturating_mul(10)
+                .saturating_add(digit.to_digit(10).unwrap_or(0) as usize);
+        }
+        (KeyCode::Char(key @ ('a' | 'x')), KeyModifiers::CONTROL, InputMode::Normal) => {
+            writers.edit.send(EditEvent::Increment {
+                delta: increment_delta(key, counted),
+            })
+        }
+        (KeyCode::Char(key @ ('a' | 'x')), KeyModifiers::CONTROL, InputMode::Visual) => {
+            set_visual_marks(&mut rows, &visual_anchor, position);
+            writers.edit.send(EditEvent::IncrementSelection {
+                delta: increment_delta(key, counted),
+                progressive: false,
+            });
+        }
+        (KeyCode::Char('J'), KeyModifiers::NONE | KeyModifiers::SHIFT, InputMode::Normal) => {
+            writers.edit.send(EditEvent::Join {
+                count: counted,
+                normalize: true,
+            })
         }
-        (KeyCode::Char('i'), KeyModifiers::NONE, InputMode::Normal) => {
-            *input_mode = InputMode::Insert;
+        (KeyCode::Char('J'), KeyModifiers::NONE | KeyModifiers::SHIFT, InputMode::Visual) => {
+            set_visual_marks(&mut rows, &visual_anchor, position);
+            writers
+                .edit
+                .send(EditEvent::JoinSelection { normalize: true });
         }
-        (KeyCode::Esc, KeyModifiers::NONE, _) => {
+        // `Alt-Shift-j` is reported as either `J` or `j` with shift, depending on the terminal
+        (
+            KeyCode::Char(key @ ('j' | 'k' | 'J' | 'K')),
+            modifiers,
+            InputMode::Normal | InputMode::Visual,
+        ) if modifiers.contains(KeyModifiers::ALT) => {
+            let down = key.eq_ignore_ascii_case(&'j');
+
+            match key.is_ascii_uppercase() || modifiers.contains(KeyModifiers::SHIFT) {
+                true => writers.edit.send(EditEvent::DuplicateRows { down }),
+                false => writers.edit.send(EditEvent::MoveRows { down }),
+            }
+        }
+        (KeyCode::Char('='), KeyModifiers::NONE, InputMode::Visual) => {
+            set_visual_marks(&mut rows, &visual_anchor, position);
+            writers.edit.send(EditEvent::ReindentSelection);
+        }
+        (KeyCode::Char(key @ ('d' | 'x' | 'c')), KeyModifiers::NONE, InputMode::Visual) => {
+            set_visual_marks(&mut rows, &visual_anchor, position);
+            writers
+                .edit
+                .send(EditEvent::RemoveSelection { insert: key == 'c' });
+        }
+        (KeyCode::Char('v'), KeyModifiers::NONE, InputMode::Normal) => {
+            *visual_anchor = VisualAnchor {
+                x: position.x,
+                y: position.y,
+            };
+
+            for selection in cursors.secondary_mut() {
+                selection.anchor = selection.head.clone();
+            }
+
+            *input_mode = InputMode::Visual;
+        }
+        (KeyCode::Down, KeyModifiers::CONTROL, InputMode::Normal) => {
+            writers.multi_cursor.send(MultiCursorEvent::Below)
+        }
+        (KeyCode::Up, KeyModifiers::CONTROL, InputMode::Normal) => {
+            writers.multi_cursor.send(MultiCursorEvent::Above)
+        }
+        (KeyCode::Char('n'), KeyModifiers::CONTROL, InputMode::Normal | InputMode::Visual) => {
+            writers.multi_cursor.send(MultiCursorEvent::NextMatch)
+        }
+        (KeyCode::Char('S'), KeyModifiers::NONE | KeyModifiers::SHIFT, InputMode::Visual) => {
+            writers.multi_cursor.send(MultiCursorEvent::SplitSelection)
+        }
+        (KeyCode::Char(':'), KeyModifiers::NONE | KeyModifiers::SHIFT, InputMode::Normal) => {
+            command_line.open(CommandKind::Ex, "", InputMode::Normal);
+            *input_mode = InputMode::Command;
+        }
+        // a command started from visual mode operates on the selected lines
+        (KeyCode::Char(':'), KeyModifiers::NONE | KeyModifiers::SHIFT, InputMode::Visual) => {
+            set_visual_marks(&mut rows, &visual_anchor, position);
+            command_line.open(CommandKind::Ex, "'<,'>", InputMode::Normal);
+            *input_mode = InputMode::Command;
+        }
+        (
+            KeyCode::Char(prompt @ ('/' | '?')),
+            KeyModifiers::NONE | KeyModifiers::SHIFT,
+            InputMode::Normal | InputMode::Visual,
+        ) => {
+            let backward = prompt == '?';
+
+            command_line.open(CommandKind::Search { backward }, "", *input_mode);
+            *input_mode = InputMode::Command;
+        }
+        (
+            KeyCode::Char(key @ ('n' | 'N')),
+            KeyModifiers::NONE | KeyModifiers::SHIFT,
+            InputMode::Normal | InputMode::Visual,
+        ) => writers.search.send(SearchEvent::Repeat {
+            reverse: key == 'N',
+        }),
+        (
+            KeyCode::Char(key @ ('*' | '#')),
+            KeyModifiers::NONE | KeyModifiers::SHIFT,
+            InputMode::Normal | InputMode::Visual,
+        ) => writers.search.send(SearchEvent::WordUnderCursor {
+            backward: key == '#',
+        }),
+        (KeyCode::Esc, KeyModifiers::NONE, InputMode::Visual) => {
+            set_visual_marks(&mut rows, &visual_anchor, position);
             *input_mode = InputMode::Normal;
         }
+        // escape in normal mode drops every cursor except for the primary one
+        (KeyCode::Esc, KeyModifiers::NONE, InputMode::Normal) => cursors.clear(),
         (
             KeyCode::Char(val @ ('h' | 'j' | 'k' | 'l')),
             KeyModifiers::NONE,
             // only move when in either normal or input mode
             InputMode::Visual | InputMode::Normal,
-        ) => cursor_move_writer.send(CursorMoveEvent::from(val)),
-        _ => {
-            println!("{:?}, {:?}", event.code, event.modifiers);
+        ) => writers.cursor_move.send(CursorMoveEvent::from(val)),
+        _ => {}
+    }
+}
+
+/// Stands in for `Ctrl-W` in the pending keys, the key typed after it picks a window command.
+const WINDOW_PREFIX: char = '\u{17}';
+
+/// Handles the keys of a command like `==`, `gcc` or `zo` typed so far in normal mode, `count` is
+/// the count typed in front of it. Returns whether the command needs more keys, any key that
+/// doesn't continue a command cancels it.
+fn pending_key(keys: &str, count: usize, writers: &mut InputWriters) -> bool {
+    if let Some(rest) = keys.strip_prefix(WINDOW_PREFIX) {
+        let event = rest
+            .chars()
+            .next()
+            .and_then(|key| WindowEvent::from_key(key, count));
+
+        if let Some(event) = event {
+            writers.window.send(event);
         }
+
+        return false;
     }
+
+    if let Some((operator, rest)) = Operator::split(keys) {
+        match Motion::parse(operator, rest, count) {
+            Keys::Motion(motion, count) => writers.edit.send(EditEvent::Operator {
+                operator,
+                motion,
+                count,
+            }),
+            Keys::Incomplete => return true,
+            Keys::Invalid => {}
+        }
+
+        return false;
+    }
+
+    if let Some(char) = keys.strip_prefix('r').and_then(|it| it.chars().next()) {
+        writers.edit.send(EditEvent::ReplaceChar { char, count });
+        return false;
+    }
+
+    match keys {
+        "r" => return true,
+        "gJ" => writers.edit.send(EditEvent::Join {
+            count,
+            normalize: false,
+        }),
+        "gt" => writers.window.send(WindowEvent::NextTab { count }),
+        "gT" => writers.window.send(WindowEvent::PreviousTab { count }),
+        "gj" | "gk" => writers
+            .cursor_move
+            .send(CursorMoveEvent::screen_line(keys == "gj")),
+        "zf" => return true,
+        "zfj" => writers.fold.send(FoldEvent::Create { extend: Some(1) }),
+        "zfk" => writers.fold.send(FoldEvent::Create { extend: Some(-1) }),
+        "zo" => writers.fold.send(FoldEvent::Open),
+        "zc" => writers.fold.send(FoldEvent::Close),
+        "za" => writers.fold.send(FoldEvent::Toggle),
+        "zR" => writers.fold.send(FoldEvent::OpenAll),
+        "zM" => writers.fold.send(FoldEvent::CloseAll),
+        "zd" => writers.fold.send(FoldEvent::Delete),
+        "zE" => writers.fold.send(FoldEvent::DeleteAll),
+        _ => {}
+    }
+
+    false
 }
 
-pub fn quit(mut events: EventReader<QuitWriter>) {
-    for _ in events.iter() {
-        panic!();
+/// What `Ctrl-A` (`a`) or `Ctrl-X` (`x`) with a `count` adds to a number.
+fn increment_delta(key: char, count: usize) -> i64 {
+    let count = i64::try_from(count.max(1)).unwrap_or(i64::MAX);
+
+    match key {
+        'a' => count,
+        _ => -count,
     }
 }
 
+/// Remembers the start and end of the visual selection in the `<` and `>` marks.
+fn set_visual_marks(rows: &mut RowBuffer, anchor: &VisualAnchor, position: &CursorPosition) {
+    let (start, end) = match (anchor.y, anchor.x) <= (position.y, position.x) {
+        true => ((anchor.x, anchor.y), (position.x, position.y)),
+        false => ((position.x, position.y), (anchor.x, anchor.y)),
+    };
+
+    rows.set_mark('<', start.0, start.1);
+    rows.set_mark('>', end.0, end.1);
+}
+
+/// A run condition for systems that should only run while a command line is being typed.
+pub fn in_command_mode(input_mode: Res<InputMode>) -> bool {
+    *input_mode == InputMode::Command
+}
+
 impl Display for InputMode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -69,6 +469,8 @@ impl Display for InputMode {
                 InputMode::Normal => "[normal]",
                 InputMode::Insert => "[insert]",
                 InputMode::Visual => "[visual]",
+                InputMode::Command => "[command]",
+                InputMode::Confirm => "[confirm]",
             }
         )
     }
@@ -3,15 +3,31 @@ use std::time::Duration;
 use bevy_ecs::system::Resource;
 use crossterm::event::{self, Event, KeyEvent};
 
+use crate::output::OutputSize;
+
 #[derive(Resource)]
 pub struct InputReader;
 
+/// The events of the terminal the editor reacts to.
+pub enum InputEvent {
+    Key(KeyEvent),
+    /// The terminal was resized to the new size.
+    Resize(OutputSize),
+}
+
 impl InputReader {
-    pub fn read_key(&self) -> Result<KeyEvent, std::io::Error> {
+    pub fn read_event(&self) -> Result<InputEvent, std::io::Error> {
         loop {
             if event::poll(Duration::from_millis(150))? {
-                if let Event::Key(event) = event::read()? {
-                    return Ok(event);
+                match event::read()? {
+                    Event::Key(event) => return Ok(InputEvent::Key(event)),
+                    Event::Resize(columns, rows) => {
+                        return Ok(InputEvent::Resize(OutputSize {
+                            columns: columns as usize,
+                            rows: rows as usize,
+                        }))
+                    }
+                    _ => {}
                 }
             }
         }
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use super::data::{Fold, Mark, Row};
+
+const UNDO_LEVELS: usize = 1000;
+
+/// Everything about a buffer besides its rows that goes back along with a change.
+#[derive(Clone)]
+pub struct State {
+    pub(crate) folds: Vec<Fold>,
+    /// Marks move along with rows that are joined or moved, so they go back along with them.
+    pub(crate) marks: HashMap<char, Mark>,
+    pub(crate) cursor: (usize, usize),
+    pub(crate) modified: bool,
+}
+
+/// A change to a buffer as the rows it replaced, rather than a copy of the whole buffer.
+#[derive(Clone)]
+pub struct Step {
+    /// The first row the change touched.
+    pub(crate) start: usize,
+    /// The contents of the rows from `start` on before the change.
+    pub(crate) rows: Vec<Box<str>>,
+    /// How many rows the change replaced them with.
+    pub(crate) replaced: usize,
+    pub(crate) state: State,
+}
+
+/// The steps to go back (and forth) through the changes of a buffer. Every change that should be
+/// undone as a single step records one step, no matter how many rows it touches.
+///
+/// Only the rows a change touched are kept, they're found by comparing the rows after the change
+/// with the `base`, the contents of the rows as of the last step.
+#[derive(Clone, Default)]
+pub struct UndoHistory {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+    base: Vec<Box<str>>,
+    /// The state before the change that's being made, which becomes a step once the next change
+    /// starts or the change is undone.
+    pending: Option<State>,
+}
+
+impl UndoHistory {
+    /// Starts a step for a change that's about to be made to `rows`, with `state` as the state
+    /// to go back to.
+    pub fn begin(&mut self, rows: &[Row], state: State) {
+        self.record(rows);
+        self.pending = Some(state);
+    }
+
+    /// Goes back a step, `current` is kept around to redo the step later on. The rows of the
+    /// returned step replace the rows of the buffer it lists.
+    pub fn undo(&mut self, rows: &[Row], current: State) -> Option<Step> {
+        self.record(rows);
+
+        let step = self.undo.pop()?;
+        let inverse = self.apply(&step, current);
+        self.redo.push(inverse);
+        Some(step)
+    }
+
+    pub fn redo(&mut self, rows: &[Row], current: State) -> Option<Step> {
+        self.record(rows);
+
+        let step = self.redo.pop()?;
+        let inverse = self.apply(&step, current);
+        self.undo.push(inverse);
+        Some(step)
+    }
+
+    pub fn undo_count(&self) -> usize {
+        self.undo.len() + usize::from(self.pending.is_some())
+    }
+
+    /// Turns the pending change into a step, and brings the base up to date with `rows`.
+    fn record(&mut self, rows: &[Row]) {
+        let (start, base_end, rows_end) = changed_range(&self.base, rows);
+        let replacement = rows[start..rows_end].iter().map(|it| it.contents().into());
+        let old: Vec<Box<str>> = self.base.splice(start..base_end, replacement).collect();
+
+        let Some(state) = self.pending.take() else {
+            return;
+        };
+
+        self.redo.clear();
+        self.undo.push(Step {
+            start,
+            rows: old,
+            replaced: rows_end - start,
+            state,
+        });
+
+        if self.undo.len() > UNDO_LEVELS {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Applies `step` to the base, returning the step that goes back to `current`.
+    fn apply(&mut self, step: &Step, current: State) -> Step {
+        let range = step.start..step.start + step.replaced;
+        let replaced: Vec<Box<str>> = self.base.splice(range, step.rows.clone()).collect();
+
+        Step {
+            start: step.start,
+            replaced: step.rows.len(),
+            rows: replaced,
+            state: current,
+        }
+    }
+}
+
+/// The rows that differ between `base` and `rows`, as the index of the first one and the end of
+/// the rows that differ in `base` and in `rows`.
+fn changed_range(base: &[Box<str>], rows: &[Row]) -> (usize, usize, usize) {
+    let start = base
+        .iter()
+        .zip(rows)
+        .take_while(|(base, row)| &***base == row.contents())
+        .count();
+
+    let end = base[start..]
+        .iter()
+        .rev()
+        .zip(rows[start..].iter().rev())
+        .take_while(|(base, row)| &***base == row.contents())
+        .count();
+
+    (start, base.len() - end, rows.len() - end)
+}
@@ -1,22 +1,33 @@
 use bevy_ecs::{
     event::Events,
-    schedule::{IntoSystemConfigs, Schedule, SystemSet},
+    schedule::{common_conditions::not, IntoSystemConfigs, Schedule, SystemSet},
     world::World,
 };
 
-use crossterm::terminal;
-use input::{data::RowBuffer, reader::InputReader, InputMode, QuitWriter};
+use crossterm::{cursor::SetCursorStyle, execute, terminal};
+use input::{data::RowBuffer, reader::InputReader, InputMode, PendingKeys, QuitWriter};
 use output::{buffer::OutputBuffer, OutputSize};
 use std::{
-    io::Result,
+    io::{stdout, Result},
     panic::{self},
     path::Path,
 };
 
+pub mod command;
+pub mod complete;
 pub mod cursor;
+pub mod edit;
+pub mod filetype;
+pub mod fold;
 pub mod input;
+pub mod options;
 pub mod output;
+pub mod search;
 pub mod status;
+pub mod substitute;
+pub mod syntax;
+pub mod theme;
+pub mod window;
 
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 pub enum SystemType {
@@ -41,7 +52,9 @@ fn main() -> Result<()> {
             output::draw_rows,
             // because the order of systems is not the same as register order, we want to make sure
             // to always run this after draw_rows is called.
-            status::draw::draw_status_bar.after(output::draw_rows),
+            status::draw::draw_status_bar
+                .after(output::draw_rows)
+                .run_if(not(input::in_command_mode)),
         )
             .in_set(SystemType::Output),
     );
@@ -53,16 +66,28 @@ fn main() -> Result<()> {
     );
 
     cursor::init(&mut world, &mut schedule);
+    edit::init(&mut world, &mut schedule);
+    complete::init(&mut world, &mut schedule);
     status::init(&mut world, &mut schedule);
+    command::init(&mut world, &mut schedule);
+    fold::init(&mut world, &mut schedule);
+    options::init(&mut world, &mut schedule);
+    filetype::init(&mut world, &mut schedule);
+    search::init(&mut world, &mut schedule);
+    substitute::init(&mut world, &mut schedule);
+    syntax::init(&mut world, &mut schedule);
+    window::init(&mut world, &mut schedule);
+    theme::init(&mut world, &mut schedule);
 
     // input-systems
     schedule.add_systems(
-        (input::process_input, input::quit)
-            .after(SystemType::Output)
+        input::process_input
+            .after(SystemType::Flush)
             .in_set(SystemType::Input),
     );
 
     world.insert_resource(InputMode::Normal);
+    world.insert_resource(PendingKeys::default());
     world.insert_resource(InputReader);
 
     // insert output resources
@@ -78,11 +103,26 @@ fn main() -> Result<()> {
     );
     world.insert_resource(Events::<QuitWriter>::default());
 
-    panic::set_hook(Box::new(|_| {
-        terminal::disable_raw_mode().expect("Could not disable raw mode");
+    filetype::detect_buffer(&mut world);
+
+    // a crash still leaves the terminal usable, before it's reported like any other panic
+    let report = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = restore_terminal();
+        report(info);
     }));
 
-    loop {
+    // the frame a quit is asked for in is finished before the editor quits
+    while world.resource::<Events<QuitWriter>>().is_empty() {
         schedule.run(&mut world);
     }
+
+    restore_terminal()
+}
+
+/// Leaves the terminal the way the editor found it, the cursor gets back the shape the user set
+/// up.
+fn restore_terminal() -> Result<()> {
+    execute!(stdout(), SetCursorStyle::DefaultUserShape)?;
+    terminal::disable_raw_mode()
 }
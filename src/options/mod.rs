@@ -0,0 +1,185 @@
+use bevy_ecs::prelude::*;
+
+use crate::{
+    command::{CommandError, CommandRegistry, Invocation},
+    status::StatusBuffer,
+};
+
+pub fn init(world: &mut World, _schedule: &mut Schedule) {
+    world.insert_resource(Options::default());
+    world
+        .resource_mut::<CommandRegistry>()
+        .register("set", 2, set);
+}
+
+pub enum OptionValue {
+    Bool(bool),
+    Number(usize),
+    Text(String),
+}
+
+/// Declares the [Options] resource, every option has a full name, a short name and a default.
+macro_rules! options {
+    ($($name:ident, $short:literal: $kind:ident = $default:expr,)*) => {
+        /// Editor-wide settings, changed at runtime with `:set`.
+        #[derive(Resource, Clone)]
+        pub struct Options {
+            $(pub(crate) $name: options!(@type $kind),)*
+        }
+
+        impl Default for Options {
+            fn default() -> Self {
+                Self {
+                    $($name: $default.into(),)*
+                }
+            }
+        }
+
+        impl Options {
+            /// Resolves a full or short option name to its full name.
+            fn full_name(name: &str) -> Option<&'static str> {
+                match name {
+                    $(stringify!($name) | $short => Some(stringify!($name)),)*
+                    _ => None,
+                }
+            }
+
+            pub fn get(&self, name: &str) -> Option<OptionValue> {
+                match Self::full_name(name)? {
+                    $(stringify!($name) => Some(options!(@get $kind, self.$name)),)*
+                    _ => None,
+                }
+            }
+
+            pub fn set(&mut self, name: &str, value: OptionValue) -> Result<(), CommandError> {
+                match (Self::full_name(name), value) {
+                    $((Some(stringify!($name)), options!(@pattern $kind, value)) => {
+                        self.$name = value;
+                        Ok(())
+                    })*
+                    (Some(_), _) => Err(CommandError::InvalidArgument(name.to_string())),
+                    (None, _) => Err(CommandError::InvalidArgument(format!("Unknown option: {name}"))),
+                }
+            }
+        }
+    };
+    (@type bool) => { bool };
+    (@type number) => { usize };
+    (@type text) => { String };
+    (@get bool, $value:expr) => { OptionValue::Bool($value) };
+    (@get number, $value:expr) => { OptionValue::Number($value) };
+    (@get text, $value:expr) => { OptionValue::Text($value.clone()) };
+    (@pattern bool, $value:ident) => { OptionValue::Bool($value) };
+    (@pattern number, $value:ident) => { OptionValue::Number($value) };
+    (@pattern text, $value:ident) => { OptionValue::Text($value) };
+}
+
+options! {
+    ignorecase, "ic": bool = false,
+    smartcase, "scs": bool = false,
+    hlsearch, "hls": bool = true,
+    incsearch, "is": bool = true,
+    wrapscan, "ws": bool = true,
+    inccommand, "icm": bool = true,
+    autoindent, "ai": bool = true,
+    smartindent, "si": bool = true,
+    shiftwidth, "sw": number = 4usize,
+    expandtab, "et": bool = true,
+    indentopen, "iop": text = "{([",
+    indentclose, "icl": text = "})]",
+    autopairs, "ap": text = "()[]{}\"\"''",
+    commentstring, "cms": text = "",
+    dictionary, "dict": text = "",
+    nrformats, "nf": text = "bin,hex",
+    foldmethod, "fdm": text = "manual",
+    foldmarker, "fmr": text = "{{{,}}}",
+    number, "nu": bool = false,
+    relativenumber, "rnu": bool = false,
+    textwidth, "tw": number = 0usize,
+    formatoptions, "fo": text = "tc",
+    wrap, "wr": bool = false,
+    linebreak, "lbr": bool = false,
+    breakindent, "bri": bool = false,
+    showbreak, "sbr": text = "",
+    syntax, "syn": bool = true,
+    syntaxdir, "sdir": text = "",
+    showtabline, "stal": number = 1usize,
+    themedir, "thdir": text = "",
+    colordepth, "cdep": text = "auto",
+    statusline, "stl": text = crate::status::format::DEFAULT,
+    guicursor, "gcr": text = crate::cursor::shape::DEFAULT,
+}
+
+/// `:set {option}`, `:set no{option}`, `:set inv{option}`, `:set {option}!`, `:set {option}?`
+/// and `:set {option}={value}`, any amount of these can be combined in a single command.
+fn set(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let mut shown = Vec::new();
+
+    world.resource_scope(|_, mut options: Mut<Options>| {
+        for argument in invocation.arguments() {
+            if let Some((name, value)) = argument.split_once('=') {
+                let value = match options.get(name) {
+                    Some(OptionValue::Number(_)) => OptionValue::Number(
+                        value
+                            .parse()
+                            .map_err(|_| CommandError::InvalidArgument(argument.clone()))?,
+                    ),
+                    Some(OptionValue::Text(_)) => OptionValue::Text(value.to_string()),
+                    _ => return Err(CommandError::InvalidArgument(argument.clone())),
+                };
+
+                options.set(name, value)?;
+                continue;
+            }
+
+            if let Some(name) = argument.strip_suffix('?') {
+                shown.push(describe(&options, name)?);
+                continue;
+            }
+
+            let (name, toggle) = match argument.strip_suffix('!') {
+                Some(name) => (name, true),
+                None => match argument.strip_prefix("inv") {
+                    Some(name) if Options::full_name(name).is_some() => (name, true),
+                    _ => (argument.as_str(), false),
+                },
+            };
+
+            match options.get(name) {
+                Some(OptionValue::Bool(current)) => {
+                    options.set(name, OptionValue::Bool(!toggle || !current))?
+                }
+                // non-boolean options are shown when set without a value
+                Some(_) if !toggle => shown.push(describe(&options, name)?),
+                Some(_) => return Err(CommandError::InvalidArgument(argument.clone())),
+                None => match name.strip_prefix("no") {
+                    Some(name) if matches!(options.get(name), Some(OptionValue::Bool(_))) => {
+                        options.set(name, OptionValue::Bool(false))?
+                    }
+                    _ => options.set(name, OptionValue::Bool(true))?,
+                },
+            }
+        }
+
+        Ok(())
+    })?;
+
+    if !shown.is_empty() {
+        world.resource_mut::<StatusBuffer>().info(shown.join("  "));
+    }
+
+    Ok(())
+}
+
+fn describe(options: &Options, name: &str) -> Result<String, CommandError> {
+    let full_name = Options::full_name(name)
+        .ok_or_else(|| CommandError::InvalidArgument(format!("Unknown option: {name}")))?;
+
+    Ok(match options.get(name) {
+        Some(OptionValue::Bool(true)) => format!("  {full_name}"),
+        Some(OptionValue::Bool(false)) => format!("no{full_name}"),
+        Some(OptionValue::Number(value)) => format!("{full_name}={value}"),
+        Some(OptionValue::Text(value)) => format!("{full_name}={value}"),
+        None => unreachable!(),
+    })
+}
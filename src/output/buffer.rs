@@ -1,43 +1,466 @@
-use std::io::{self, stdout};
+use std::io::{self, stdout, Write};
 
 use bevy_ecs::system::Resource;
+use crossterm::{
+    cursor, queue,
+    style::{
+        Attribute, ContentStyle, Print, SetAttribute, SetAttributes, SetBackgroundColor,
+        SetForegroundColor,
+    },
+    terminal::{self, ClearType},
+};
 
+use crate::{
+    cursor::shape::CursorShape,
+    theme::color::{self, ColorDepth},
+};
+
+/// A single character on the screen, along with its style.
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct Cell {
+    char: char,
+    style: ContentStyle,
+    /// The amount of columns the character takes up. A wide character is followed by a cell of
+    /// width 0, which stands for the column it covers and is never drawn on its own.
+    width: u8,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            char: ' ',
+            style: ContentStyle::default(),
+            width: 1,
+        }
+    }
+}
+
+impl Cell {
+    fn blank(style: ContentStyle) -> Self {
+        Self {
+            style,
+            ..Self::default()
+        }
+    }
+}
+
+/// The characters terminals show two columns wide, the wide and fullwidth characters of East
+/// Asian scripts along with emoji.
+const WIDE: [(u32, u32); 13] = [
+    (0x1100, 0x115f),
+    (0x2e80, 0x303e),
+    (0x3041, 0x33ff),
+    (0x3400, 0x4dbf),
+    (0x4e00, 0x9fff),
+    (0xa000, 0xa4cf),
+    (0xac00, 0xd7a3),
+    (0xf900, 0xfaff),
+    (0xfe30, 0xfe4f),
+    (0xff00, 0xff60),
+    (0xffe0, 0xffe6),
+    (0x1f300, 0x1faff),
+    (0x20000, 0x3fffd),
+];
+
+/// The amount of columns `char` takes up in the terminal.
+pub fn char_width(char: char) -> usize {
+    let code = char as u32;
+
+    match WIDE
+        .iter()
+        .any(|(start, end)| (*start..=*end).contains(&code))
+    {
+        true => 2,
+        false => 1,
+    }
+}
+
+/// The screen as a grid of cells. Everything is drawn into the back grid every frame, and
+/// [OutputBuffer::flush] only sends the cells that differ from the front grid, which holds what
+/// the terminal is currently showing.
 #[derive(Resource, Clone)]
 pub struct OutputBuffer {
-    content: String,
+    columns: usize,
+    rows: usize,
+    back: Vec<Cell>,
+    /// `None` when the terminal's contents are unknown, like before the first frame, in which
+    /// case the whole screen is drawn again.
+    front: Option<Vec<Cell>>,
+    /// Where the next text is drawn, text past the end of a row is cut off.
+    position: (usize, usize),
+    /// Where the terminal's cursor is shown once the frame is done, hidden when `None`.
+    cursor: Option<(usize, usize)>,
+    /// The shape the cursor is shown with.
+    shape: CursorShape,
+    /// The shape the terminal's cursor has, `None` when it's unknown.
+    shown_shape: Option<CursorShape>,
+    /// How many colors the terminal shows, colors it doesn't have are sent as the closest one
+    /// it does.
+    depth: ColorDepth,
+    /// Whether frames are wrapped in a synchronized update, see [supports_synchronized_updates].
+    synchronized: bool,
+}
+
+impl Default for OutputBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl OutputBuffer {
     pub fn new() -> Self {
         Self {
-            content: String::new(),
+            columns: 0,
+            rows: 0,
+            back: Vec::new(),
+            front: None,
+            position: (0, 0),
+            cursor: None,
+            shape: CursorShape::Default,
+            shown_shape: None,
+            depth: ColorDepth::TrueColor,
+            synchronized: supports_synchronized_updates(
+                &std::env::var("TERM").unwrap_or_default(),
+                &std::env::var("TERM_PROGRAM").unwrap_or_default(),
+            ),
+        }
+    }
+
+    /// Starts a new frame of `columns` by `rows` cells, with every cell blank and the cursor
+    /// hidden. A different size than the last frame draws the whole screen again.
+    pub fn clear(&mut self, columns: usize, rows: usize) {
+        if (columns, rows) != (self.columns, self.rows) {
+            (self.columns, self.rows) = (columns, rows);
+            self.front = None;
         }
+
+        self.back.clear();
+        self.back.resize(columns * rows, Cell::default());
+        self.position = (0, 0);
+        self.cursor = None;
+    }
+
+    /// Forgets what the terminal is showing, so the next frame draws every cell again.
+    pub fn invalidate(&mut self) {
+        self.front = None;
+    }
+
+    /// Changing the depth draws the whole screen again, in the colors of the new depth.
+    pub fn set_color_depth(&mut self, depth: ColorDepth) {
+        if depth != self.depth {
+            self.depth = depth;
+            self.front = None;
+        }
+    }
+
+    pub fn move_to(&mut self, x: usize, y: usize) {
+        self.position = (x, y);
+    }
+
+    pub fn show_cursor(&mut self, x: usize, y: usize) {
+        self.cursor = Some((x, y));
+    }
+
+    /// The shape is only sent to the terminal once it differs from the one the cursor has.
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) {
+        self.shape = shape;
+    }
+
+    pub fn hide_cursor(&mut self) {
+        self.cursor = None;
     }
 
     pub fn push(&mut self, char: char) {
-        self.content.push(char);
+        self.push_char(char, ContentStyle::default());
     }
 
     pub fn push_str(&mut self, data: &str) {
-        self.content.push_str(data);
+        self.push_styled(data, ContentStyle::default());
     }
-}
 
-impl io::Write for OutputBuffer {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        match std::str::from_utf8(buf) {
-            Ok(s) => {
-                self.content.push_str(s);
-                Ok(s.len())
+    /// Draws `data` with `style`, a `\r` goes back to the start of the row and a `\n` down a row.
+    pub fn push_styled(&mut self, data: &str, style: ContentStyle) {
+        for char in data.chars() {
+            self.push_char(char, style);
+        }
+    }
+
+    fn push_char(&mut self, char: char, style: ContentStyle) {
+        let (x, y) = self.position;
+
+        match char {
+            '\r' => self.position.0 = 0,
+            '\n' => self.position.1 += 1,
+            _ => {
+                let width = char_width(char);
+
+                // a wide character that doesn't fit on the row anymore is left out
+                match x + width <= self.columns {
+                    true => self.set(
+                        x,
+                        y,
+                        Cell {
+                            char,
+                            style,
+                            width: width as u8,
+                        },
+                    ),
+                    false => self.set(x, y, Cell::blank(style)),
+                }
+
+                if width == 2 && x + 1 < self.columns {
+                    let covered = Cell {
+                        width: 0,
+                        ..Cell::blank(style)
+                    };
+                    self.set(x + 1, y, covered);
+                }
+
+                self.position.0 += width;
             }
-            Err(_) => Err(io::ErrorKind::WriteZero.into()),
         }
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        let out = write!(stdout(), "{}", self.content);
-        stdout().flush()?;
-        self.content.clear();
-        out
+    /// Puts `cell` at `x` and `y`. A wide character that's partly overwritten is replaced by a
+    /// blank, so no half of it is left behind.
+    fn set(&mut self, x: usize, y: usize, cell: Cell) {
+        if x >= self.columns || y >= self.rows {
+            return;
+        }
+
+        let index = y * self.columns + x;
+        let replaced = self.back[index];
+
+        if replaced.width == 0 && cell.width != 0 && x > 0 {
+            self.back[index - 1] = Cell::blank(self.back[index - 1].style);
+        }
+
+        if replaced.width == 2 && cell.width != 2 && x + 1 < self.columns {
+            self.back[index + 1] = Cell::blank(self.back[index + 1].style);
+        }
+
+        self.back[index] = cell;
+    }
+
+    /// The escape sequences that turn the front grid into the back grid. The cursor only moves
+    /// when a changed cell isn't right after the last one written, and the style only changes
+    /// when it differs from the last cell written.
+    fn render(&self) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+
+        if self.synchronized {
+            queue!(out, terminal::BeginSynchronizedUpdate)?;
+        }
+
+        queue!(out, cursor::Hide)?;
+
+        if self.front.is_none() {
+            queue!(
+                out,
+                SetAttribute(Attribute::Reset),
+                terminal::Clear(ClearType::All)
+            )?;
+        }
+
+        // a cleared screen is blank
+        let blank;
+        let front = match &self.front {
+            Some(front) => front,
+            None => {
+                blank = vec![Cell::default(); self.back.len()];
+                &blank
+            }
+        };
+
+        let mut written: Option<(usize, usize)> = None;
+        let mut style = ContentStyle::default();
+
+        for (index, (cell, shown)) in self.back.iter().zip(front).enumerate() {
+            let (x, y) = (index % self.columns, index / self.columns);
+
+            // the column a wide character covers is drawn along with it
+            if cell == shown || cell.width == 0 {
+                continue;
+            }
+
+            match written {
+                Some((last_x, last_y)) if (last_x + 1, last_y) == (x, y) => {}
+                Some((_, last_y)) if last_y == y => queue!(out, cursor::MoveToColumn(x as u16))?,
+                _ => queue!(out, cursor::MoveTo(x as u16, y as u16))?,
+            }
+
+            if cell.style != style {
+                queue!(out, SetAttribute(Attribute::Reset))?;
+
+                if let Some(color) = cell.style.foreground_color {
+                    queue!(out, SetForegroundColor(color::reduce(color, self.depth)))?;
+                }
+
+                if let Some(color) = cell.style.background_color {
+                    queue!(out, SetBackgroundColor(color::reduce(color, self.depth)))?;
+                }
+
+                if !cell.style.attributes.is_empty() {
+                    queue!(out, SetAttributes(cell.style.attributes))?;
+                }
+
+                style = cell.style;
+            }
+
+            queue!(out, Print(cell.char))?;
+
+            // terminals don't agree on the width of every character, so the cursor is moved
+            // explicitly after a wide one
+            written = match cell.width {
+                1 => Some((x, y)),
+                _ => None,
+            };
+        }
+
+        queue!(out, SetAttribute(Attribute::Reset))?;
+
+        if let Some((x, y)) = self.cursor {
+            if self.shown_shape != Some(self.shape) {
+                queue!(out, self.shape.command())?;
+            }
+
+            queue!(out, cursor::MoveTo(x as u16, y as u16), cursor::Show)?;
+        }
+
+        if self.synchronized {
+            queue!(out, terminal::EndSynchronizedUpdate)?;
+        }
+
+        Ok(out)
+    }
+
+    /// The characters drawn on row `y` so far, for tests to check what ends up on the screen.
+    #[cfg(test)]
+    pub fn row_text(&self, y: usize) -> String {
+        self.back[y * self.columns..(y + 1) * self.columns]
+            .iter()
+            .filter(|it| it.width != 0)
+            .map(|it| it.char)
+            .collect()
+    }
+
+    /// Sends the changes of this frame to the terminal.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let out = self.render()?;
+
+        let mut stdout = stdout();
+        stdout.write_all(&out)?;
+        stdout.flush()?;
+
+        self.front = Some(self.back.clone());
+
+        if self.cursor.is_some() {
+            self.shown_shape = Some(self.shape);
+        }
+
+        Ok(())
+    }
+}
+
+/// Guesses from `TERM` and `TERM_PROGRAM` whether the terminal draws a frame wrapped in a
+/// synchronized update all at once. Terminals that don't know about them are supposed to ignore
+/// them, but some print them instead, so only terminals known to support them get them.
+fn supports_synchronized_updates(term: &str, program: &str) -> bool {
+    const TERMS: [&str; 5] = ["kitty", "foot", "alacritty", "contour", "wezterm"];
+    const PROGRAMS: [&str; 3] = ["iTerm.app", "WezTerm", "ghostty"];
+
+    TERMS.iter().any(|it| term.contains(it)) || PROGRAMS.contains(&program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(buffer: &OutputBuffer, y: usize) -> String {
+        buffer.row_text(y)
+    }
+
+    /// A buffer that shows what's been drawn so far, so only what changes after is rendered.
+    fn shown(columns: usize, rows: usize) -> OutputBuffer {
+        let mut buffer = OutputBuffer::new();
+        buffer.clear(columns, rows);
+        buffer.front = Some(buffer.back.clone());
+        buffer
+    }
+
+    #[test]
+    fn wide_characters_take_two_columns() {
+        let mut buffer = shown(6, 1);
+        buffer.push_str("a漢b");
+
+        assert_eq!(row(&buffer, 0), "a漢b  ");
+        assert_eq!(buffer.back[2].width, 0);
+        assert_eq!(buffer.back[3].char, 'b');
+    }
+
+    #[test]
+    fn a_wide_character_that_does_not_fit_is_left_out() {
+        let mut buffer = shown(3, 1);
+        buffer.push_str("ab漢");
+
+        assert_eq!(row(&buffer, 0), "ab ");
+    }
+
+    #[test]
+    fn overwriting_half_of_a_wide_character_blanks_the_other_half() {
+        let mut buffer = shown(4, 1);
+        buffer.push_str("漢漢");
+        buffer.move_to(1, 0);
+        buffer.push('x');
+
+        assert_eq!(row(&buffer, 0), " x漢");
+
+        buffer.move_to(2, 0);
+        buffer.push('y');
+
+        assert_eq!(row(&buffer, 0), " xy ");
+    }
+
+    #[test]
+    fn the_cursor_moves_explicitly_after_a_wide_character() {
+        let mut buffer = shown(4, 1);
+        buffer.push_str("漢b");
+
+        let out = String::from_utf8(buffer.render().unwrap()).unwrap();
+
+        assert!(out.contains("漢\x1b[1;3Hb"), "{out:?}");
+    }
+
+    #[test]
+    fn only_changed_cells_are_rendered() {
+        let mut buffer = shown(4, 2);
+        buffer.move_to(1, 1);
+        buffer.push_str("ab");
+
+        let out = String::from_utf8(buffer.render().unwrap()).unwrap();
+
+        assert!(!out.contains("\x1b[2J"));
+        assert!(out.contains("\x1b[2;2Hab"), "{out:?}");
+    }
+
+    #[test]
+    fn only_known_terminals_get_synchronized_updates() {
+        assert!(supports_synchronized_updates("xterm-kitty", ""));
+        assert!(supports_synchronized_updates("xterm-256color", "WezTerm"));
+        assert!(!supports_synchronized_updates("xterm-256color", ""));
+        assert!(!supports_synchronized_updates("linux", "Apple_Terminal"));
+
+        let mut buffer = shown(2, 1);
+        let render = |buffer: &OutputBuffer| String::from_utf8(buffer.render().unwrap()).unwrap();
+
+        buffer.synchronized = false;
+        assert!(!render(&buffer).contains("\x1b[?2026h"));
+
+        buffer.synchronized = true;
+        let out = render(&buffer);
+        assert!(
+            out.starts_with("\x1b[?2026h") && out.ends_with("\x1b[?2026l"),
+            "{out:?}"
+        );
     }
 }
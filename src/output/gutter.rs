@@ -0,0 +1,164 @@
+use super::buffer::OutputBuffer;
+use crate::{fold, input::data::RowBuffer, options::Options, theme::Theme};
+
+/// The narrowest the numbers in the gutter get, like vim's default `numberwidth`.
+const MIN_DIGITS: usize = 3;
+
+/// The width of the line number gutter, with room for the highest row number and a space to
+/// separate it from the text. Nothing without `number` or `relativenumber`.
+pub fn width(rows: &RowBuffer, options: &Options) -> usize {
+    if !options.number && !options.relativenumber {
+        return 0;
+    }
+
+    rows.rows_count().to_string().len().max(MIN_DIGITS) + 1
+}
+
+/// Draws the gutter for the line starting at row `y`, `current` is the row of the cursor.
+pub fn draw(
+    buffer: &mut OutputBuffer,
+    rows: &RowBuffer,
+    y: usize,
+    current: usize,
+    options: &Options,
+    theme: &Theme,
+) {
+    let Some(label) = label(rows, y, current, options) else {
+        return;
+    };
+
+    let group = match y == fold::line_start(rows, current) {
+        true => "CursorLineNr",
+        false => "LineNr",
+    };
+
+    buffer.push_styled(&label, theme.style(group));
+}
+
+/// The gutter of the line starting at row `y`, `None` without one. With `relativenumber`, every
+/// line shows how many lines it's away from the cursor's, and the cursor's own line shows `0`.
+/// With `number` as well, the cursor's line shows its own number instead, aligned to the left.
+fn label(rows: &RowBuffer, y: usize, current: usize, options: &Options) -> Option<String> {
+    let width = width(rows, options);
+
+    if width == 0 {
+        return None;
+    }
+
+    let digits = width - 1;
+    let current = fold::line_start(rows, current);
+
+    let label = match (options.number, options.relativenumber) {
+        (true, true) if y == current => format!("{:<digits$} ", y + 1),
+        (_, true) => {
+            let distance = match y < current {
+                true => fold::line_count(rows, y, current),
+                false => fold::line_count(rows, current, y),
+            };
+
+            format!("{distance:>digits$} ")
+        }
+        _ => format!("{:>digits$} ", y + 1),
+    };
+
+    Some(label)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::data::Fold;
+
+    fn buffer(count: usize) -> RowBuffer {
+        let mut buffer = RowBuffer::new();
+
+        for y in 0..count {
+            buffer.insert_row(y, "");
+        }
+
+        buffer
+    }
+
+    fn options(number: bool, relativenumber: bool) -> Options {
+        Options {
+            number,
+            relativenumber,
+            ..Options::default()
+        }
+    }
+
+    /// The gutter of every line in `rows`, with the cursor on row `current`.
+    fn labels(rows: &RowBuffer, current: usize, options: &Options) -> Vec<String> {
+        fold::lines(rows, 0)
+            .filter_map(|y| label(rows, y, current, options))
+            .collect()
+    }
+
+    #[test]
+    fn no_gutter_without_numbers() {
+        let rows = buffer(3);
+
+        assert_eq!(width(&rows, &options(false, false)), 0);
+        assert!(labels(&rows, 0, &options(false, false)).is_empty());
+    }
+
+    #[test]
+    fn the_gutter_grows_with_the_row_count() {
+        assert_eq!(width(&buffer(0), &options(true, false)), 4);
+        assert_eq!(width(&buffer(999), &options(false, true)), 4);
+        assert_eq!(width(&buffer(1000), &options(true, false)), 5);
+    }
+
+    #[test]
+    fn shows_absolute_numbers() {
+        let rows = buffer(3);
+
+        assert_eq!(
+            labels(&rows, 1, &options(true, false)),
+            ["  1 ", "  2 ", "  3 "]
+        );
+    }
+
+    #[test]
+    fn shows_the_distance_from_the_cursor() {
+        let rows = buffer(4);
+
+        assert_eq!(
+            labels(&rows, 1, &options(false, true)),
+            ["  1 ", "  0 ", "  1 ", "  2 "]
+        );
+        assert_eq!(
+            labels(&rows, 3, &options(false, true)),
+            ["  3 ", "  2 ", "  1 ", "  0 "]
+        );
+    }
+
+    #[test]
+    fn shows_the_cursor_row_number_on_the_left_with_both() {
+        let rows = buffer(3);
+
+        assert_eq!(
+            labels(&rows, 2, &options(true, true)),
+            ["  2 ", "  1 ", "3   "]
+        );
+    }
+
+    #[test]
+    fn a_closed_fold_counts_as_a_single_line() {
+        let mut rows = buffer(5);
+        rows.folds_mut().push(Fold {
+            start: 1,
+            end: 3,
+            closed: true,
+        });
+
+        assert_eq!(
+            labels(&rows, 4, &options(false, true)),
+            ["  2 ", "  1 ", "  0 "]
+        );
+        assert_eq!(
+            labels(&rows, 2, &options(true, true)),
+            ["  1 ", "2   ", "  1 "]
+        );
+    }
+}
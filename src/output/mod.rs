@@ -1,15 +1,26 @@
-use bevy_ecs::system::{Res, ResMut, Resource};
-use crossterm::terminal::ClearType;
-use crossterm::{execute, queue, terminal};
+use bevy_ecs::system::{Query, Res, ResMut, Resource};
+use crossterm::terminal;
+use regex::Regex;
 
-use std::io::{stdout, Write};
-
-use crate::cursor::CursorOffset;
-use crate::input::data::RowBuffer;
+use crate::cursor::multi::Selection;
+use crate::cursor::VisualAnchor;
+use crate::fold;
+use crate::input::data::{self, Row, RowBuffer};
+use crate::input::InputMode;
+use crate::options::Options;
+use crate::search::{incremental::SearchPreview, SearchMatch, SearchState};
+use crate::substitute::{confirm::Confirmation, preview::SubstitutePreview};
+use crate::syntax::Highlighter;
+use crate::theme::{self, Theme};
+use crate::window::{layout::Area, View, Windows};
 
 use self::buffer::OutputBuffer;
+use self::style::Highlight;
 
 pub mod buffer;
+pub mod gutter;
+pub mod style;
+pub mod wrap;
 
 #[derive(Copy, Clone, Resource)]
 pub struct OutputSize {
@@ -17,6 +28,13 @@ pub struct OutputSize {
     pub(crate) rows: usize,
 }
 
+impl OutputSize {
+    /// The amount of rows available for text, the last row is taken up by the status bar.
+    pub fn text_rows(&self) -> usize {
+        self.rows.saturating_sub(1)
+    }
+}
+
 impl Default for OutputSize {
     fn default() -> Self {
         terminal::size()
@@ -28,47 +46,418 @@ impl Default for OutputSize {
     }
 }
 
+/// What every window draws its rows with.
+struct Frame<'a> {
+    rows: &'a RowBuffer,
+    options: &'a Options,
+    highlighter: &'a Highlighter,
+    theme: &'a Theme,
+    substitute_preview: &'a SubstitutePreview,
+    search: Option<&'a Regex>,
+    current_match: Option<SearchMatch>,
+    visual: bool,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn draw_rows(
-    size: Res<OutputSize>,
-    rows: ResMut<RowBuffer>,
-    cursor_offset: Res<CursorOffset>,
+    rows: Res<RowBuffer>,
+    search: Res<SearchState>,
+    preview: Res<SearchPreview>,
+    substitute_preview: Res<SubstitutePreview>,
+    confirmation: Res<Confirmation>,
+    options: Res<Options>,
+    anchor: Res<VisualAnchor>,
+    input_mode: Res<InputMode>,
+    highlighter: Res<Highlighter>,
+    theme: Res<Theme>,
+    windows: Res<Windows>,
+    views: Query<&View>,
     mut buffer: ResMut<OutputBuffer>,
 ) {
-    let OutputSize {
-        columns,
-        rows: row_size,
-    } = *size;
+    // while a search is being typed, its matches are shown instead of the last search's
+    let (search, current_match) = match (preview.is_active(), confirmation.current()) {
+        (true, _) => (preview.regex(), preview.current()),
+        (false, Some(current)) => (search.highlighted(), Some(current)),
+        (false, None) => (search.highlighted().filter(|_| options.hlsearch), None),
+    };
+
+    let frame = Frame {
+        rows: &rows,
+        options: &options,
+        highlighter: &highlighter,
+        theme: &theme,
+        substitute_preview: &substitute_preview,
+        search,
+        current_match,
+        visual: *input_mode == InputMode::Visual,
+    };
+
+    for (window, area) in windows.areas() {
+        let Ok(view) = views.get(*window) else {
+            continue;
+        };
+
+        draw_window(
+            &mut buffer,
+            &frame,
+            area,
+            view,
+            &view.cursors.collect(&anchor, &view.position),
+            *window == windows.active(),
+        );
+    }
+}
+
+/// Draws the rows a window shows in `area`, starting at the offset of its `view`. Only the
+/// `active` window shows the visual selection, the current search match and the preview of a
+/// substitution.
+fn draw_window(
+    buffer: &mut OutputBuffer,
+    frame: &Frame,
+    area: &Area,
+    view: &View,
+    selections: &[Selection],
+    active: bool,
+) {
+    let Frame { rows, options, .. } = *frame;
+    let (offset, current) = (&view.offset, view.position.y);
+    let gutter = gutter::width(rows, options);
+    let columns = area.columns.saturating_sub(gutter);
+    let current_match = frame.current_match.filter(|_| active);
+
+    // a closed fold takes up a single line, so the rows drawn aren't always consecutive
+    let mut lines = fold::lines(rows, offset.row);
+    let mut drawn = 0;
+
+    while drawn < area.rows {
+        let row = lines.next().unwrap_or(rows.rows_count());
+        let line = Area {
+            y: area.y + drawn,
+            rows: area.rows - drawn,
+            ..*area
+        };
+
+        buffer.move_to(line.x, line.y);
+
+        if row < rows.rows_count() {
+            gutter::draw(buffer, rows, row, current, options, frame.theme);
+        }
 
-    for current in 0..row_size {
-        let row = current + cursor_offset.row;
+        let previewed = frame.substitute_preview.row(row).filter(|_| active);
 
-        if rows.rows_count() == 0 || row >= rows.rows_count() {
+        drawn += if row >= rows.rows_count() {
             buffer.push('~');
+            1
+        } else if let Some(closed) = fold::closed_fold(rows, row) {
+            let summary = fold::summary(rows, &closed, columns + offset.column);
+
+            style::push_highlighted(
+                buffer,
+                &summary,
+                offset.column,
+                columns,
+                &[Highlight::new(
+                    0..summary.chars().count(),
+                    frame.theme.style("Folded"),
+                )],
+            );
+            1
+        } else if let Some(previewed) = previewed {
+            let highlights: Vec<Highlight> = previewed
+                .replaced
+                .iter()
+                .map(|replaced| {
+                    Highlight::new(
+                        data::render_column(&previewed.contents, replaced.start)
+                            ..data::render_column(&previewed.contents, replaced.end),
+                        frame.theme.style("Substitute"),
+                    )
+                })
+                .collect();
+
+            push_wrapped(
+                buffer,
+                &data::render_contents(&previewed.contents),
+                offset.column,
+                line,
+                gutter,
+                &highlights,
+                frame,
+            )
         } else {
-            let row = rows.get_render(row);
+            // search matches and selections are drawn over the syntax highlighting
+            let mut highlights = syntax_highlights(frame, rows.get_row_at(row), row);
+
+            highlights.extend(match frame.search {
+                Some(regex) => search_highlights(
+                    frame.theme,
+                    regex,
+                    rows.get_row_at(row),
+                    row,
+                    current_match,
+                    options.hlsearch,
+                ),
+                None => Vec::new(),
+            });
 
-            let CursorOffset {
-                column: column_offset,
-                ..
-            } = *cursor_offset;
+            highlights.extend(selection_highlights(
+                frame.theme,
+                selections,
+                rows.get_row_at(row),
+                row,
+                frame.visual && active,
+            ));
 
-            let len = row.len().min(columns);
-            let start = column_offset.max(0);
+            // selections and cursors can go past the end of the row, which needs some cells
+            // to be drawn on.
+            let render = rows.get_render(row);
+            let width = highlights
+                .iter()
+                .map(|it| it.columns.end)
+                .max()
+                .unwrap_or(0);
+
+            push_wrapped(
+                buffer,
+                &format!("{render:width$}"),
+                offset.column,
+                line,
+                gutter,
+                &highlights,
+                frame,
+            )
+        };
+    }
+}
+
+/// Draws a row in the lines of `area`, the first one of which is right after the gutter. With
+/// `wrap`, the row goes on as many lines as it's split over and fit in `area`, otherwise it's
+/// drawn starting at column `start` on a single line. Every line after the first starts with a
+/// blank gutter, followed by the `breakindent` and `showbreak` prefix. Returns the amount of
+/// lines drawn.
+fn push_wrapped(
+    buffer: &mut OutputBuffer,
+    render: &str,
+    start: usize,
+    area: Area,
+    gutter: usize,
+    highlights: &[Highlight],
+    frame: &Frame,
+) -> usize {
+    let options = frame.options;
+    let columns = area.columns.saturating_sub(gutter);
+
+    if !options.wrap {
+        style::push_highlighted(buffer, render, start, columns, highlights);
+        return 1;
+    }
 
-            buffer.push_str(&row[start..start + len])
+    let segments = wrap::segments(render, columns, options);
+    let prefix = wrap::prefix(render, columns, options);
+
+    for (line, segment) in segments.iter().enumerate().take(area.rows) {
+        if line > 0 {
+            buffer.move_to(area.x + gutter, area.y + line);
+            buffer.push_styled(
+                &wrap::prefix_text(prefix, options),
+                frame.theme.style("NonText"),
+            );
         }
 
-        queue!(buffer, terminal::Clear(ClearType::UntilNewLine)).unwrap();
+        // a line broken at a blank ends before the edge, so the row is cut off at its end
+        let end = render
+            .char_indices()
+            .nth(segment.end)
+            .map_or(render.len(), |(index, _)| index);
+        let width = match line {
+            0 => columns,
+            _ => columns.saturating_sub(prefix),
+        };
 
-        buffer.push_str("\r\n");
+        style::push_highlighted(buffer, &render[..end], segment.start, width, highlights);
     }
+
+    segments.len().min(area.rows)
+}
+
+/// Colors a single row by the scopes of its syntax.
+fn syntax_highlights(frame: &Frame, row: &Row, y: usize) -> Vec<Highlight> {
+    let Some(grammar) = frame.highlighter.grammar() else {
+        return Vec::new();
+    };
+
+    frame
+        .highlighter
+        .spans(y)
+        .iter()
+        .map(|span| {
+            Highlight::new(
+                row.get_len_with_offset(span.range.start)..row.get_len_with_offset(span.range.end),
+                frame
+                    .theme
+                    .style(&theme::scope_group(grammar.scope_name(span.scope))),
+            )
+        })
+        .collect()
+}
+
+/// Highlights the matches of a search on a single row. The `current` match is highlighted
+/// differently, and is the only match highlighted when `all` is not set.
+fn search_highlights(
+    theme: &Theme,
+    regex: &Regex,
+    row: &Row,
+    y: usize,
+    current: Option<SearchMatch>,
+    all: bool,
+) -> Vec<Highlight> {
+    regex
+        .find_iter(row.contents())
+        .filter(|found| !found.is_empty())
+        .filter_map(|found| {
+            let is_current = current.is_some_and(|it| it.y == y && it.start == found.start());
+
+            let group = match (is_current, all) {
+                (true, _) => "IncSearch",
+                (false, true) => "Search",
+                (false, false) => return None,
+            };
+
+            Some(Highlight::new(
+                row.get_len_with_offset(found.start())..row.get_len_with_offset(found.end()),
+                theme.style(group),
+            ))
+        })
+        .collect()
+}
+
+/// Highlights the visual selections on a single row, and every cursor besides the primary one,
+/// which is drawn by the terminal itself.
+fn selection_highlights(
+    theme: &Theme,
+    selections: &[Selection],
+    row: &Row,
+    y: usize,
+    visual: bool,
+) -> Vec<Highlight> {
+    let column_after = |x: usize| {
+        let next = row.contents()[x.min(row.contents().len())..]
+            .chars()
+            .next()
+            .map_or(1, char::len_utf8);
+
+        row.get_len_with_offset(x + next)
+            .max(row.get_len_with_offset(x) + 1)
+    };
+
+    let mut highlights = Vec::new();
+
+    if visual {
+        for selection in selections {
+            let (start, end) = selection.ordered();
+
+            if !(start.y..=end.y).contains(&y) {
+                continue;
+            }
+
+            let from = match start.y == y {
+                true => row.get_len_with_offset(start.x),
+                false => 0,
+            };
+
+            // the end of every row but the last one is selected as well
+            let to = match end.y == y {
+                true => column_after(end.x),
+                false => row.get_len_with_offset(row.contents().len()) + 1,
+            };
+
+            highlights.push(Highlight::new(from..to, theme.style("Visual")));
+        }
+    }
+
+    for selection in selections.iter().skip(1).filter(|it| it.head.y == y) {
+        highlights.push(Highlight::new(
+            row.get_len_with_offset(selection.head.x)..column_after(selection.head.x),
+            theme.style("Cursor"),
+        ));
+    }
+
+    highlights
 }
 
 pub fn flush_buffer(mut buffer: ResMut<OutputBuffer>) {
     buffer.flush().expect("Could not flush buffer!");
-    stdout().flush().expect("Could not flush stdout!");
 }
 
-pub fn clear() {
-    execute!(stdout(), terminal::Clear(ClearType::All)).expect("Could not clear stdout!");
+/// Starts drawing a new frame on a blank screen, only what changed since the last frame is sent
+/// to the terminal once it's flushed.
+pub fn clear(mut buffer: ResMut<OutputBuffer>, size: Res<OutputSize>) {
+    buffer.clear(size.columns, size.rows);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::prelude::*;
+
+    use super::*;
+    use crate::{command::CommandRegistry, window};
+
+    fn world(rows: &[&str], columns: usize, options: Options) -> World {
+        let mut world = World::new();
+        let mut buffer = RowBuffer::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        world.insert_resource(buffer);
+        world.insert_resource(options);
+        world.insert_resource(OutputSize { columns, rows: 4 });
+        world.insert_resource(OutputBuffer::new());
+        world.insert_resource(CommandRegistry::default());
+        world.insert_resource(SearchState::default());
+        world.insert_resource(SearchPreview::default());
+        world.insert_resource(SubstitutePreview::default());
+        world.insert_resource(Confirmation::default());
+        world.insert_resource(VisualAnchor::default());
+        world.insert_resource(InputMode::Normal);
+        world.insert_resource(Highlighter::default());
+        world.insert_resource(Theme::default());
+        window::init(&mut world, &mut Schedule::default());
+
+        world
+    }
+
+    /// Draws a frame and returns the text of every row of the screen.
+    fn draw(world: &mut World) -> Vec<String> {
+        let mut schedule = Schedule::default();
+        schedule.add_systems((clear, window::update_layout, draw_rows).chain());
+        schedule.run(world);
+
+        let buffer = world.resource::<OutputBuffer>();
+        let rows = world.resource::<OutputSize>().rows;
+
+        (0..rows).map(|y| buffer.row_text(y)).collect()
+    }
+
+    #[test]
+    fn wrapped_lines_of_wide_characters_fit_in_the_window() {
+        let options = Options {
+            wrap: true,
+            ..Options::default()
+        };
+        let mut world = world(&["漢字漢", "ab"], 5, options);
+
+        assert_eq!(draw(&mut world)[..3], ["漢字 ", "漢   ", "ab   "]);
+    }
+
+    #[test]
+    fn wide_characters_stay_inside_their_window_in_a_vertical_split() {
+        let mut world = world(&["a漢字漢", "abcdefgh"], 9, Options::default());
+        world.send_event(window::WindowEvent::Split { vertical: true });
+        window::handle_window(&mut world);
+
+        // the character that would straddle the edge of the left window is blanked
+        assert_eq!(draw(&mut world)[..2], ["a漢  a漢 ", "abcd abcd"]);
+    }
 }
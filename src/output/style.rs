@@ -0,0 +1,105 @@
+use std::ops::Range;
+
+use crossterm::style::ContentStyle;
+
+use super::buffer::{char_width, OutputBuffer};
+
+/// A style applied to a range of render columns of a row.
+#[derive(Clone)]
+pub struct Highlight {
+    pub(crate) columns: Range<usize>,
+    pub(crate) style: ContentStyle,
+}
+
+impl Highlight {
+    pub fn new(columns: Range<usize>, style: ContentStyle) -> Self {
+        Self { columns, style }
+    }
+}
+
+/// Pushes `render` to the buffer starting at column `start`, as much of it as fits in `width`
+/// cells, styling every column covered by a highlight. Later highlights take precedence over
+/// earlier ones. A wide character that only fits halfway is left out for a blank.
+pub fn push_highlighted(
+    buffer: &mut OutputBuffer,
+    render: &str,
+    start: usize,
+    width: usize,
+    highlights: &[Highlight],
+) {
+    let mut current = None;
+    let mut run = String::new();
+    let mut cells = 0;
+
+    for (column, char) in render.chars().enumerate().skip(start) {
+        let style = highlights
+            .iter()
+            .rev()
+            .find(|it| it.columns.contains(&column))
+            .map(|it| it.style);
+
+        if style != current {
+            push_run(buffer, &mut run, current);
+            current = style;
+        }
+
+        let char_width = char_width(char);
+
+        if cells + char_width > width {
+            // the half of a wide character that still fits is blanked instead
+            if cells < width {
+                run.push(' ');
+            }
+
+            break;
+        }
+
+        cells += char_width;
+        run.push(char);
+    }
+
+    push_run(buffer, &mut run, current);
+}
+
+fn push_run(buffer: &mut OutputBuffer, run: &mut String, style: Option<ContentStyle>) {
+    buffer.push_styled(run, style.unwrap_or_default());
+    run.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The row `render` ends up as when it's pushed from column `start` into `width` cells, on a
+    /// row of 6 cells that was filled with `x` before.
+    fn pushed(render: &str, start: usize, width: usize) -> String {
+        let mut buffer = OutputBuffer::new();
+        buffer.clear(6, 1);
+        buffer.push_str("xxxxxx");
+        buffer.move_to(0, 0);
+
+        push_highlighted(&mut buffer, render, start, width, &[]);
+        buffer.row_text(0)
+    }
+
+    #[test]
+    fn only_pushes_what_fits_in_the_width() {
+        assert_eq!(pushed("abcdef", 0, 3), "abcxxx");
+        assert_eq!(pushed("abcdef", 2, 3), "cdexxx");
+        assert_eq!(pushed("ab", 0, 3), "abxxxx");
+        assert_eq!(pushed("", 0, 3), "xxxxxx");
+    }
+
+    #[test]
+    fn wide_characters_are_clipped_by_their_cells() {
+        assert_eq!(pushed("漢字", 0, 4), "漢字xx");
+        assert_eq!(pushed("a漢字", 1, 4), "漢字xx");
+        assert_eq!(pushed("漢字漢", 0, 2), "漢xxxx");
+    }
+
+    #[test]
+    fn a_wide_character_on_the_edge_leaves_a_blank() {
+        assert_eq!(pushed("漢字漢", 0, 3), "漢 xxx");
+        assert_eq!(pushed("a漢", 0, 2), "a xxxx");
+    }
+}
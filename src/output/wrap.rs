@@ -0,0 +1,353 @@
+use std::ops::Range;
+
+use super::{buffer::char_width, gutter};
+use crate::{
+    cursor::CursorOffset, fold, input::data::RowBuffer, options::Options, window::layout::Area,
+};
+
+/// The least amount of columns left for text on the lines a row continues on, the indentation
+/// and `showbreak` in front of them get cut off otherwise.
+const MIN_TEXT_WIDTH: usize = 20;
+
+/// The amount of columns the text of a row is shown in, next to the gutter of a window in `area`.
+pub fn text_width(rows: &RowBuffer, area: &Area, options: &Options) -> usize {
+    area.columns.saturating_sub(gutter::width(rows, options))
+}
+
+/// The columns in front of every line a row continues on, with `breakindent` the indentation of
+/// the row followed by `showbreak`.
+pub fn prefix(render: &str, width: usize, options: &Options) -> usize {
+    let indent = match options.breakindent {
+        true => render.chars().take_while(|it| it.is_whitespace()).count(),
+        false => 0,
+    };
+
+    (indent + options.showbreak.chars().count()).min(width.saturating_sub(MIN_TEXT_WIDTH))
+}
+
+/// The text of the prefix of [prefix] columns.
+pub fn prefix_text(prefix: usize, options: &Options) -> String {
+    let indent = prefix.saturating_sub(options.showbreak.chars().count());
+
+    format!("{}{}", " ".repeat(indent), options.showbreak)
+        .chars()
+        .take(prefix)
+        .collect()
+}
+
+/// The columns of a row's `render` shown on each screen line, `width` cells wide. A row only
+/// takes up a single line without `wrap`, and with `linebreak` lines are broken after the last
+/// blank that fits instead of right at the edge.
+pub fn segments(render: &str, width: usize, options: &Options) -> Vec<Range<usize>> {
+    let chars: Vec<char> = render.chars().collect();
+    let cells: usize = chars.iter().map(|it| char_width(*it)).sum();
+
+    if !options.wrap || cells <= width || width == 0 {
+        return std::iter::once(0..chars.len()).collect();
+    }
+
+    let prefix = prefix(render, width, options);
+    let mut segments: Vec<Range<usize>> = Vec::new();
+    let mut start = 0;
+
+    loop {
+        let available = match segments.is_empty() {
+            true => width,
+            false => width - prefix,
+        };
+
+        // a line holds at least a single character, even one wider than the line
+        let mut end = start + 1;
+        let mut used = char_width(chars[start]);
+
+        while end < chars.len() && used + char_width(chars[end]) <= available {
+            used += char_width(chars[end]);
+            end += 1;
+        }
+
+        if end == chars.len() {
+            segments.push(start..end);
+            return segments;
+        }
+
+        if options.linebreak {
+            if let Some(blank) = (start + 1..=end)
+                .rev()
+                .find(|it| chars[it - 1].is_whitespace())
+            {
+                end = blank;
+            }
+        }
+
+        segments.push(start..end);
+        start = end;
+    }
+}
+
+/// The screen line of a row and the column on it that render `column` is shown at, counting the
+/// prefix in front of continued lines.
+pub fn locate(segments: &[Range<usize>], prefix: usize, column: usize) -> (usize, usize) {
+    // the column right after the end of the row is on the last line
+    let line = segments
+        .iter()
+        .rposition(|it| it.start <= column)
+        .unwrap_or(0);
+
+    let indent = match line {
+        0 => 0,
+        _ => prefix,
+    };
+
+    (line, indent + column - segments[line].start)
+}
+
+/// The render column shown at `column` of screen `line` of a row, the last column of the line
+/// when `column` goes past its end.
+pub fn column_at(segments: &[Range<usize>], prefix: usize, line: usize, column: usize) -> usize {
+    let segment = &segments[line.min(segments.len() - 1)];
+
+    let indent = match line {
+        0 => 0,
+        _ => prefix,
+    };
+
+    (segment.start + column.saturating_sub(indent))
+        .min(segment.end.saturating_sub(1).max(segment.start))
+}
+
+/// The amount of screen lines the line starting at row `y` takes up, a closed fold only takes up
+/// a single one.
+pub fn height(rows: &RowBuffer, y: usize, width: usize, options: &Options) -> usize {
+    if !options.wrap || y >= rows.rows_count() || fold::closed_fold(rows, y).is_some() {
+        return 1;
+    }
+
+    segments(rows.get_render(y), width, options).len()
+}
+
+/// The screen line of row `y` that its byte `x` is shown on, and the column on that line.
+pub fn line_of(
+    rows: &RowBuffer,
+    (x, y): (usize, usize),
+    width: usize,
+    options: &Options,
+) -> (usize, usize) {
+    if y >= rows.rows_count() {
+        return (0, 0);
+    }
+
+    let column = rows.get_row_at(y).get_len_with_offset(x);
+
+    if fold::closed_fold(rows, y).is_some() {
+        return (0, column);
+    }
+
+    let render = rows.get_render(y);
+
+    locate(
+        &segments(render, width, options),
+        prefix(render, width, options),
+        column,
+    )
+}
+
+/// Where on screen the byte `x` of row `y` is shown, by a window in `area` with view `offset`.
+pub fn screen_position(
+    rows: &RowBuffer,
+    (x, y): (usize, usize),
+    offset: &CursorOffset,
+    area: &Area,
+    options: &Options,
+) -> (usize, usize) {
+    let width = text_width(rows, area, options);
+    let start = fold::line_start(rows, y);
+
+    let above: usize = fold::lines(rows, offset.row)
+        .take_while(|it| *it < start)
+        .map(|it| height(rows, it, width, options))
+        .sum();
+
+    let (line, column) = line_of(rows, (x, y), width, options);
+
+    // a line can't show the cursor past its last column
+    let column = match options.wrap {
+        true => column.min(width.saturating_sub(1)),
+        false => column.saturating_sub(offset.column),
+    };
+
+    (
+        area.x + column + gutter::width(rows, options),
+        area.y + above + line,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::data::Fold;
+
+    fn buffer(rows: &[&str]) -> RowBuffer {
+        let mut buffer = RowBuffer::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        buffer
+    }
+
+    fn wrapping() -> Options {
+        Options {
+            wrap: true,
+            ..Options::default()
+        }
+    }
+
+    /// The start and end of every screen line of `render`.
+    fn lines(render: &str, width: usize, options: &Options) -> Vec<(usize, usize)> {
+        segments(render, width, options)
+            .into_iter()
+            .map(|it| (it.start, it.end))
+            .collect()
+    }
+
+    #[test]
+    fn a_row_takes_a_single_line_without_wrap() {
+        assert_eq!(lines("abcdefgh", 3, &Options::default()), [(0, 8)]);
+        assert_eq!(lines("", 3, &wrapping()), [(0, 0)]);
+        assert_eq!(lines("abc", 3, &wrapping()), [(0, 3)]);
+    }
+
+    #[test]
+    fn wraps_rows_at_the_edge() {
+        assert_eq!(lines("abcdefgh", 3, &wrapping()), [(0, 3), (3, 6), (6, 8)]);
+    }
+
+    #[test]
+    fn wraps_wide_characters_by_their_cells() {
+        assert_eq!(lines("漢字漢字", 8, &wrapping()), [(0, 4)]);
+        assert_eq!(lines("漢字漢字", 5, &wrapping()), [(0, 2), (2, 4)]);
+        assert_eq!(lines("a漢字", 4, &wrapping()), [(0, 2), (2, 3)]);
+        // a character wider than the line still takes up a line of its own
+        assert_eq!(lines("漢a", 1, &wrapping()), [(0, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn breaks_after_the_last_blank_with_linebreak() {
+        let options = Options {
+            linebreak: true,
+            ..wrapping()
+        };
+
+        assert_eq!(lines("aaa bbb ccc", 5, &options), [(0, 4), (4, 8), (8, 11)]);
+        // a word that doesn't fit on a line of its own is still broken at the edge
+        assert_eq!(
+            lines("abcdefgh ij", 3, &options),
+            [(0, 3), (3, 6), (6, 9), (9, 11)]
+        );
+    }
+
+    #[test]
+    fn continued_lines_get_the_indentation_and_showbreak() {
+        let options = Options {
+            breakindent: true,
+            showbreak: String::from("> "),
+            ..wrapping()
+        };
+
+        // the tab and the spaces after it are both part of the indentation
+        let rows = buffer(&[&format!("\t  {}", "x".repeat(30))]);
+        let render = rows.get_render(0);
+
+        assert_eq!(prefix(render, 30, &options), 8);
+        assert_eq!(prefix_text(8, &options), "      > ");
+        assert_eq!(lines(render, 30, &options), [(0, 30), (30, 36)]);
+    }
+
+    #[test]
+    fn the_prefix_leaves_room_for_text() {
+        let options = Options {
+            breakindent: true,
+            showbreak: String::from("> "),
+            ..wrapping()
+        };
+        let render = format!("        {}", "x".repeat(30));
+
+        assert_eq!(prefix(&render, 24, &options), 4);
+        assert_eq!(prefix_text(4, &options), "  > ");
+        assert_eq!(prefix(&render, 10, &options), 0);
+    }
+
+    #[test]
+    fn locates_columns_on_screen_lines() {
+        let segments = [0..3, 3..6, 6..8];
+
+        assert_eq!(locate(&segments, 2, 0), (0, 0));
+        assert_eq!(locate(&segments, 2, 4), (1, 3));
+        // right after the end of the row is still on its last line
+        assert_eq!(locate(&segments, 2, 8), (2, 4));
+    }
+
+    #[test]
+    fn finds_the_column_shown_on_a_screen_line() {
+        let segments = [0..3, 3..6, 6..8];
+
+        assert_eq!(column_at(&segments, 2, 1, 0), 3);
+        assert_eq!(column_at(&segments, 2, 1, 3), 4);
+        assert_eq!(column_at(&segments, 2, 1, 10), 5);
+        assert_eq!(column_at(&segments, 2, 5, 3), 7);
+        let empty: Vec<_> = std::iter::once(0..0).collect();
+        assert_eq!(column_at(&empty, 0, 0, 5), 0);
+    }
+
+    #[test]
+    fn measures_the_height_of_a_line() {
+        let mut rows = buffer(&["abcdefgh", "", "abcdefgh", "abcdefgh"]);
+        rows.folds_mut().push(Fold {
+            start: 2,
+            end: 3,
+            closed: true,
+        });
+
+        assert_eq!(height(&rows, 0, 3, &wrapping()), 3);
+        assert_eq!(height(&rows, 0, 3, &Options::default()), 1);
+        assert_eq!(height(&rows, 1, 3, &wrapping()), 1);
+        assert_eq!(height(&rows, 2, 3, &wrapping()), 1);
+        assert_eq!(height(&rows, 4, 3, &wrapping()), 1);
+        assert_eq!(height(&buffer(&[]), 0, 3, &wrapping()), 1);
+    }
+
+    #[test]
+    fn finds_the_screen_line_of_a_byte() {
+        let rows = buffer(&["\tabcdef"]);
+
+        assert_eq!(line_of(&rows, (2, 0), 3, &wrapping()), (1, 2));
+        assert_eq!(line_of(&rows, (7, 0), 3, &wrapping()), (3, 1));
+        assert_eq!(line_of(&rows, (0, 1), 3, &wrapping()), (0, 0));
+    }
+
+    #[test]
+    fn counts_the_wrapped_lines_above_on_screen() {
+        let rows = buffer(&["abcdefgh", "xy"]);
+        let area = Area {
+            x: 1,
+            y: 2,
+            columns: 3,
+            rows: 10,
+        };
+        let offset = CursorOffset::default();
+
+        assert_eq!(
+            screen_position(&rows, (3, 0), &offset, &area, &wrapping()),
+            (1, 3)
+        );
+        assert_eq!(
+            screen_position(&rows, (1, 1), &offset, &area, &wrapping()),
+            (2, 5)
+        );
+        assert_eq!(
+            screen_position(&rows, (1, 1), &offset, &area, &Options::default()),
+            (2, 3)
+        );
+    }
+}
@@ -0,0 +1,203 @@
+use bevy_ecs::prelude::*;
+use regex::Regex;
+
+use super::{compile, find_match, SearchMatch};
+use crate::{
+    command::{
+        line::{CommandKind, CommandLine},
+        parser,
+    },
+    cursor::{update::clamp_to_row, CursorOffset, CursorPosition},
+    input::{data::RowBuffer, InputMode},
+    options::Options,
+    window::ActiveView,
+};
+
+/// The state of a search that is still being typed. The cursor is moved to the first match while
+/// typing, and put back where it came from once the search is submitted or cancelled.
+#[derive(Resource, Default)]
+pub struct SearchPreview {
+    origin: Option<(CursorPosition, CursorOffset)>,
+    /// The last line the preview was updated for, so unchanged lines aren't searched again.
+    line: String,
+    regex: Option<Regex>,
+    current: Option<SearchMatch>,
+}
+
+impl SearchPreview {
+    pub fn is_active(&self) -> bool {
+        self.origin.is_some()
+    }
+
+    /// The regex of the pattern typed so far, `None` if nothing (valid) has been typed yet.
+    pub fn regex(&self) -> Option<&Regex> {
+        self.regex.as_ref()
+    }
+
+    /// The match the cursor was moved to.
+    pub fn current(&self) -> Option<SearchMatch> {
+        self.current
+    }
+}
+
+pub fn preview(
+    mut preview: ResMut<SearchPreview>,
+    mut view: ActiveView,
+    command_line: Res<CommandLine>,
+    input_mode: Res<InputMode>,
+    rows: Res<RowBuffer>,
+    options: Res<Options>,
+) {
+    let backward = match (*input_mode, command_line.kind()) {
+        (InputMode::Command, CommandKind::Search { backward }) if options.incsearch => backward,
+        _ => {
+            // the search was either submitted or cancelled, submitted searches start from the
+            // original position as well, as if the cursor was never moved.
+            if let Some((origin_position, origin_offset)) = preview.origin.take() {
+                let mut view = view.get_mut();
+                view.position = origin_position;
+                view.offset = origin_offset;
+                *preview = SearchPreview::default();
+            }
+
+            return;
+        }
+    };
+
+    if preview.origin.is_none() {
+        let view = view.get();
+        preview.origin = Some((view.position.clone(), view.offset.clone()));
+    } else if preview.line == command_line.content() {
+        return;
+    }
+
+    preview.line = command_line.content().to_string();
+
+    let delimiter = if backward { '?' } else { '/' };
+    let (pattern, _) = parser::split_pattern(command_line.content(), delimiter);
+
+    let Some((origin, _)) = &preview.origin else {
+        return;
+    };
+
+    let origin = (origin.x, origin.y);
+    let mut view = view.get_mut();
+    let position = &mut view.position;
+
+    if pattern.is_empty() {
+        (position.x, position.y) = origin;
+        preview.regex = None;
+        preview.current = None;
+        return;
+    }
+
+    // a partial pattern is often invalid, e.g. `foo(` while typing `foo(bar)`. errors are only
+    // shown once the search is submitted, until then the last valid preview is kept around.
+    let Ok(regex) = compile(&pattern, &options) else {
+        return;
+    };
+
+    preview.current =
+        find_match(&rows, &regex, origin, backward, options.wrapscan).map(|(found, _)| found);
+    preview.regex = Some(regex);
+
+    (position.x, position.y) = match preview.current {
+        Some(found) => (found.start, found.y),
+        None => origin,
+    };
+
+    clamp_to_row(position, &rows);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        command::CommandRegistry,
+        window::{self, View},
+    };
+
+    fn world(rows: &[&str]) -> World {
+        let mut world = World::new();
+        let mut buffer = RowBuffer::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        world.insert_resource(buffer);
+        world.insert_resource(CommandRegistry::default());
+        world.insert_resource(CommandLine::default());
+        world.insert_resource(SearchPreview::default());
+        world.insert_resource(Options::default());
+        world.insert_resource(InputMode::Normal);
+        window::init(&mut world, &mut Schedule::default());
+
+        world
+    }
+
+    /// Runs the preview with `line` typed after `/`, or after `?` when `backward`, and returns
+    /// where the cursor ends up.
+    fn typed(world: &mut World, line: &str, backward: bool) -> (usize, usize) {
+        world.resource_mut::<CommandLine>().open(
+            CommandKind::Search { backward },
+            line,
+            InputMode::Normal,
+        );
+        *world.resource_mut::<InputMode>() = InputMode::Command;
+
+        run(world)
+    }
+
+    fn run(world: &mut World) -> (usize, usize) {
+        let mut schedule = Schedule::default();
+        schedule.add_systems(preview);
+        schedule.run(world);
+
+        let position = &View::active(world).position;
+        (position.x, position.y)
+    }
+
+    #[test]
+    fn moves_the_cursor_to_the_first_match_while_typing() {
+        let mut world = world(&["foo", "bar foo", "baz"]);
+
+        assert_eq!(typed(&mut world, "f", false), (4, 1));
+        assert_eq!(typed(&mut world, "ba", false), (0, 1));
+        assert_eq!(typed(&mut world, "baz", false), (0, 2));
+        assert_eq!(typed(&mut world, "", false), (0, 0));
+        assert_eq!(typed(&mut world, "foo", true), (4, 1));
+    }
+
+    #[test]
+    fn keeps_the_last_valid_preview_for_a_partial_pattern() {
+        let mut world = world(&["foo", "bar(1)"]);
+
+        assert_eq!(typed(&mut world, "bar", false), (0, 1));
+        assert_eq!(typed(&mut world, "bar(", false), (0, 1));
+        assert!(world.resource::<SearchPreview>().regex().is_some());
+
+        assert_eq!(typed(&mut world, "nothing", false), (0, 0));
+        assert!(world.resource::<SearchPreview>().current().is_none());
+    }
+
+    #[test]
+    fn puts_the_cursor_back_once_the_search_is_done() {
+        let mut world = world(&["foo", "bar"]);
+
+        assert_eq!(typed(&mut world, "bar", false), (0, 1));
+
+        *world.resource_mut::<InputMode>() = InputMode::Normal;
+        assert_eq!(run(&mut world), (0, 0));
+        assert!(!world.resource::<SearchPreview>().is_active());
+    }
+
+    #[test]
+    fn does_nothing_without_incsearch() {
+        let mut world = world(&["foo", "bar"]);
+        world.resource_mut::<Options>().incsearch = false;
+
+        assert_eq!(typed(&mut world, "bar", false), (0, 0));
+        assert!(!world.resource::<SearchPreview>().is_active());
+    }
+}
@@ -0,0 +1,545 @@
+use bevy_ecs::prelude::*;
+use regex::{Regex, RegexBuilder};
+
+use crate::{
+    command::{parser, CommandError, CommandRegistry, Invocation},
+    cursor::{update::clamp_to_row, CursorPosition},
+    input::data::RowBuffer,
+    options::Options,
+    status::StatusBuffer,
+    window::ActiveView,
+    SystemType,
+};
+
+pub mod incremental;
+
+pub fn init(world: &mut World, schedule: &mut Schedule) {
+    world.insert_resource(SearchState::default());
+    world.insert_resource(incremental::SearchPreview::default());
+    world.insert_resource(Events::<SearchEvent>::default());
+
+    world
+        .resource_mut::<CommandRegistry>()
+        .register("nohlsearch", 3, nohlsearch);
+
+    // the preview has to put the cursor back before a submitted search is handled.
+    schedule.add_systems(
+        (
+            incremental::preview,
+            handle_search.after(incremental::preview),
+        )
+            .after(SystemType::Input)
+            .before(SystemType::Cursor),
+    );
+}
+
+#[derive(Event)]
+pub enum SearchEvent {
+    /// A line typed after `/` or `?`, consisting of a pattern and an optional offset.
+    Submit { line: String, backward: bool },
+    /// `n` and `N`, `reverse` searches in the opposite direction of the last search.
+    Repeat { reverse: bool },
+    /// `*` and `#`
+    WordUnderCursor { backward: bool },
+}
+
+/// Where the cursor ends up relative to a match, e.g. `/foo/e+1`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SearchOffset {
+    #[default]
+    None,
+    /// `/foo/+2`, lines below (or above) the match, at the start of the line.
+    Line(isize),
+    /// `/foo/s+1` or `/foo/b+1`, characters from the start of the match.
+    Start(isize),
+    /// `/foo/e-1`, characters from the last character of the match.
+    End(isize),
+}
+
+#[derive(Resource, Default)]
+pub struct SearchState {
+    pattern: Option<String>,
+    regex: Option<Regex>,
+    backward: bool,
+    offset: SearchOffset,
+    /// Cleared by `:nohlsearch`, until the next search is done.
+    highlight: bool,
+}
+
+impl SearchState {
+    pub fn pattern(&self) -> Option<&str> {
+        self.pattern.as_deref()
+    }
+
+    /// The regex matches should be highlighted with, if any.
+    pub fn highlighted(&self) -> Option<&Regex> {
+        self.regex.as_ref().filter(|_| self.highlight)
+    }
+
+    pub fn set_pattern(&mut self, pattern: &str, options: &Options) -> Result<(), CommandError> {
+        self.regex = Some(compile(pattern, options)?);
+        self.pattern = Some(pattern.to_string());
+        self.highlight = true;
+        Ok(())
+    }
+}
+
+/// A match of a search, `start` and `end` are byte offsets into row `y`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub(crate) y: usize,
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+}
+
+/// Compiles a search pattern, respecting `ignorecase` and `smartcase`. Like vim, `\c` anywhere in
+/// the pattern forces ignoring case, and `\C` forces matching case.
+pub fn compile(pattern: &str, options: &Options) -> Result<Regex, CommandError> {
+    let mut ignore_case =
+        options.ignorecase && !(options.smartcase && pattern.chars().any(char::is_uppercase));
+
+    let mut cleaned = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+
+    while let Some(current) = chars.next() {
+        match (current, chars.clone().next()) {
+            ('\\', Some('c')) => {
+                chars.next();
+                ignore_case = true;
+            }
+            ('\\', Some('C')) => {
+                chars.next();
+                ignore_case = false;
+            }
+            ('\\', Some(escaped)) => {
+                chars.next();
+                cleaned.push('\\');
+                cleaned.push(escaped);
+            }
+            (current, _) => cleaned.push(current),
+        }
+    }
+
+    RegexBuilder::new(&cleaned)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|_| CommandError::InvalidPattern(pattern.to_string()))
+}
+
+/// Finds the first match after (or before) the position `x`, `y`, wrapping around the buffer if
+/// `wrap` is set. Returns the match and whether the search wrapped around.
+pub fn find_match(
+    rows: &RowBuffer,
+    regex: &Regex,
+    (x, y): (usize, usize),
+    backward: bool,
+    wrap: bool,
+) -> Option<(SearchMatch, bool)> {
+    let count = rows.rows_count();
+
+    if y >= count {
+        return None;
+    }
+
+    // the row the search starts on is visited twice, once for the part after the cursor and once
+    // for the part before it, after wrapping around.
+    let (order, unwrapped): (Vec<usize>, usize) = match backward {
+        true => ((0..=y).rev().chain((y..count).rev()).collect(), y + 1),
+        false => ((y..count).chain(0..=y).collect(), count - y),
+    };
+
+    order
+        .into_iter()
+        .enumerate()
+        .take(if wrap { count + 1 } else { unwrapped })
+        .find_map(|(index, row)| {
+            let mut matches = regex
+                .find_iter(rows.get_row_at(row).contents())
+                .map(|found| SearchMatch {
+                    y: row,
+                    start: found.start(),
+                    end: found.end(),
+                })
+                .filter(|found| match (index, backward) {
+                    (0, false) => found.start > x,
+                    (0, true) => found.start < x,
+                    (_, false) if index == count => found.start <= x,
+                    (_, true) if index == count => found.start >= x,
+                    _ => true,
+                });
+
+            let found = match backward {
+                true => matches.last(),
+                false => matches.next(),
+            };
+
+            found.map(|found| (found, index >= unwrapped))
+        })
+}
+
+/// The cursor position a match leads to, after applying `offset`.
+pub fn apply_offset(rows: &RowBuffer, found: SearchMatch, offset: SearchOffset) -> (usize, usize) {
+    let contents = rows.get_row_at(found.y).contents();
+
+    // moves `amount` characters away from `from`, staying inside of the row.
+    let move_chars = |from: usize, amount: isize| -> usize {
+        match amount >= 0 {
+            true => contents[from..]
+                .char_indices()
+                .map(|(index, _)| from + index)
+                .nth(amount as usize)
+                .unwrap_or(contents.len()),
+            false => contents[..from]
+                .char_indices()
+                .rev()
+                .map(|(index, _)| index)
+                .nth(amount.unsigned_abs() - 1)
+                .unwrap_or(0),
+        }
+    };
+
+    match offset {
+        SearchOffset::None => (found.start, found.y),
+        SearchOffset::Line(lines) => (
+            0,
+            (found.y as isize + lines).clamp(0, rows.rows_count() as isize - 1) as usize,
+        ),
+        SearchOffset::Start(amount) => (move_chars(found.start, amount), found.y),
+        SearchOffset::End(amount) => {
+            // the end of a match is exclusive, while the offset is relative to its last character
+            let last = move_chars(found.end, -1).max(found.start);
+            (move_chars(last, amount), found.y)
+        }
+    }
+}
+
+/// Parses a search offset like `e+1`, `s-2`, `b`, `+3` or `-`.
+pub fn parse_offset(offset: &str) -> Result<SearchOffset, CommandError> {
+    let invalid = || CommandError::InvalidArgument(format!("Invalid search offset: {offset}"));
+
+    let amount = |value: &str| -> Result<isize, CommandError> {
+        match value {
+            "" => Ok(0),
+            "+" => Ok(1),
+            "-" => Ok(-1),
+            value => value
+                .strip_prefix('+')
+                .unwrap_or(value)
+                .parse()
+                .map_err(|_| invalid()),
+        }
+    };
+
+    match offset.chars().next() {
+        None => Ok(SearchOffset::None),
+        Some('e') => Ok(SearchOffset::End(amount(&offset[1..])?)),
+        Some('s' | 'b') => Ok(SearchOffset::Start(amount(&offset[1..])?)),
+        Some('+' | '-' | '0'..='9') => Ok(SearchOffset::Line(amount(offset)?)),
+        Some(_) => Err(invalid()),
+    }
+}
+
+/// The keyword under (or after) the cursor, used by `*` and `#`.
+pub fn word_under_cursor(rows: &RowBuffer, x: usize, y: usize) -> Option<&str> {
+    if y >= rows.rows_count() {
+        return None;
+    }
+
+    let contents = rows.get_row_at(y).contents();
+    let x = x.min(contents.len());
+    let is_word = |char: char| char.is_alphanumeric() || char == '_';
+
+    let start = match contents[x..].chars().next().is_some_and(is_word) {
+        // walk back to the start of the keyword the cursor is on
+        true => contents[..x]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, char)| is_word(char))
+            .last()
+            .map(|(index, _)| index)
+            .unwrap_or(x),
+        // like vim, the first keyword after the cursor is used when the cursor isn't on one.
+        false => contents[x..]
+            .char_indices()
+            .find(|&(_, char)| is_word(char))
+            .map(|(index, _)| x + index)?,
+    };
+
+    let end = contents[start..]
+        .char_indices()
+        .find(|&(_, char)| !is_word(char))
+        .map(|(index, _)| start + index)
+        .unwrap_or(contents.len());
+
+    Some(&contents[start..end])
+}
+
+pub fn handle_search(
+    mut events: EventReader<SearchEvent>,
+    mut state: ResMut<SearchState>,
+    mut view: ActiveView,
+    mut status: ResMut<StatusBuffer>,
+    rows: Res<RowBuffer>,
+    options: Res<Options>,
+) {
+    for event in events.iter() {
+        let backward = match event {
+            SearchEvent::Submit { line, backward } => {
+                let delimiter = if *backward { '?' } else { '/' };
+                let (pattern, offset) = parser::split_pattern(line, delimiter);
+
+                let offset = match parse_offset(offset) {
+                    Ok(offset) => offset,
+                    Err(error) => {
+                        status.error(error.to_string());
+                        continue;
+                    }
+                };
+
+                // an empty pattern searches for the last pattern again
+                let pattern = match (pattern.is_empty(), state.pattern.clone()) {
+                    (true, Some(last)) => last,
+                    (true, None) => {
+                        status.error(CommandError::NoPreviousPattern.to_string());
+                        continue;
+                    }
+                    (false, _) => pattern,
+                };
+
+                if let Err(error) = state.set_pattern(&pattern, &options) {
+                    status.error(error.to_string());
+                    continue;
+                }
+
+                state.backward = *backward;
+                state.offset = offset;
+                state.backward
+            }
+            SearchEvent::Repeat { reverse } => {
+                if state.regex.is_none() {
+                    status.error(CommandError::NoPreviousPattern.to_string());
+                    continue;
+                }
+
+                // the highlighting comes back after `:nohlsearch` when searching again
+                state.highlight = true;
+                state.backward ^ reverse
+            }
+            SearchEvent::WordUnderCursor { backward } => {
+                let position = &view.get().position;
+
+                let Some(word) = word_under_cursor(&rows, position.x, position.y) else {
+                    status.error("No string under cursor");
+                    continue;
+                };
+
+                let pattern = format!(r"\b{}\b", regex::escape(word));
+
+                // smartcase doesn't apply to `*` and `#`, only ignorecase does.
+                let options = Options {
+                    smartcase: false,
+                    ..options.clone()
+                };
+
+                if let Err(error) = state.set_pattern(&pattern, &options) {
+                    status.error(error.to_string());
+                    continue;
+                }
+
+                state.backward = *backward;
+                state.offset = SearchOffset::None;
+                state.backward
+            }
+        };
+
+        search(
+            &state,
+            backward,
+            &rows,
+            &mut view.get_mut().position,
+            &mut status,
+            &options,
+        );
+    }
+}
+
+/// Moves the cursor to the next match of the current search, in the given direction.
+fn search(
+    state: &SearchState,
+    backward: bool,
+    rows: &RowBuffer,
+    position: &mut CursorPosition,
+    status: &mut StatusBuffer,
+    options: &Options,
+) {
+    let (Some(regex), Some(pattern)) = (&state.regex, &state.pattern) else {
+        return;
+    };
+
+    let current = (position.x, position.y);
+    let mut result = find_match(rows, regex, current, backward, options.wrapscan);
+
+    // a match the cursor is already positioned at (after the offset) is skipped, so repeating a
+    // search with an offset doesn't get stuck on the same match.
+    if let Some((found, wrapped)) = result {
+        if apply_offset(rows, found, state.offset) == current {
+            let origin = (found.start, found.y);
+
+            if let Some((next, next_wrapped)) =
+                find_match(rows, regex, origin, backward, options.wrapscan)
+            {
+                result = Some((next, wrapped || next_wrapped));
+            }
+        }
+    }
+
+    let Some((found, wrapped)) = result else {
+        status.error(match options.wrapscan {
+            true => CommandError::PatternNotFound(pattern.clone()).to_string(),
+            false => match backward {
+                true => format!("search hit TOP without match for: {pattern}"),
+                false => format!("search hit BOTTOM without match for: {pattern}"),
+            },
+        });
+
+        return;
+    };
+
+    (position.x, position.y) = apply_offset(rows, found, state.offset);
+    clamp_to_row(position, rows);
+
+    match (wrapped, backward) {
+        (true, false) => status.error("search hit BOTTOM, continuing at TOP"),
+        (true, true) => status.error("search hit TOP, continuing at BOTTOM"),
+        (false, true) => status.info(format!("?{pattern}")),
+        (false, false) => status.info(format!("/{pattern}")),
+    }
+}
+
+fn nohlsearch(world: &mut World, _: &Invocation) -> Result<(), CommandError> {
+    world.resource_mut::<SearchState>().highlight = false;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(rows: &[&str]) -> RowBuffer {
+        let mut buffer = RowBuffer::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        buffer
+    }
+
+    fn at(y: usize, start: usize, end: usize) -> SearchMatch {
+        SearchMatch { y, start, end }
+    }
+
+    const TEXT: [&str; 3] = ["foo bar foo", "baz", "a foo"];
+
+    #[test]
+    fn finds_matches_forward() {
+        let rows = buffer(&TEXT);
+        let regex = Regex::new("foo").unwrap();
+
+        assert_eq!(
+            find_match(&rows, &regex, (0, 0), false, true),
+            Some((at(0, 8, 11), false))
+        );
+        assert_eq!(
+            find_match(&rows, &regex, (8, 0), false, true),
+            Some((at(2, 2, 5), false))
+        );
+        assert_eq!(
+            find_match(&rows, &regex, (2, 2), false, true),
+            Some((at(0, 0, 3), true))
+        );
+        assert_eq!(find_match(&rows, &regex, (2, 2), false, false), None);
+    }
+
+    #[test]
+    fn finds_matches_backward() {
+        let rows = buffer(&TEXT);
+        let regex = Regex::new("foo").unwrap();
+
+        assert_eq!(
+            find_match(&rows, &regex, (2, 2), true, true),
+            Some((at(0, 8, 11), false))
+        );
+        assert_eq!(
+            find_match(&rows, &regex, (0, 0), true, true),
+            Some((at(2, 2, 5), true))
+        );
+        assert_eq!(find_match(&rows, &regex, (0, 0), true, false), None);
+    }
+
+    #[test]
+    fn wraps_around_to_the_start_row() {
+        let rows = buffer(&["foo x", "y"]);
+        let regex = Regex::new("foo").unwrap();
+
+        assert_eq!(
+            find_match(&rows, &regex, (0, 0), false, true),
+            Some((at(0, 0, 3), true))
+        );
+        assert_eq!(find_match(&rows, &regex, (0, 5), false, true), None);
+    }
+
+    #[test]
+    fn parses_offsets() {
+        assert_eq!(parse_offset("").ok(), Some(SearchOffset::None));
+        assert_eq!(parse_offset("+").ok(), Some(SearchOffset::Line(1)));
+        assert_eq!(parse_offset("-2").ok(), Some(SearchOffset::Line(-2)));
+        assert_eq!(parse_offset("3").ok(), Some(SearchOffset::Line(3)));
+        assert_eq!(parse_offset("e").ok(), Some(SearchOffset::End(0)));
+        assert_eq!(parse_offset("e-1").ok(), Some(SearchOffset::End(-1)));
+        assert_eq!(parse_offset("s+2").ok(), Some(SearchOffset::Start(2)));
+        assert_eq!(parse_offset("b-").ok(), Some(SearchOffset::Start(-1)));
+        assert!(parse_offset("x").is_err());
+        assert!(parse_offset("e+x").is_err());
+    }
+
+    #[test]
+    fn applies_offsets() {
+        let rows = buffer(&["aä bcd", "x", "y"]);
+        let found = at(0, 0, 3);
+
+        assert_eq!(apply_offset(&rows, found, SearchOffset::None), (0, 0));
+        assert_eq!(apply_offset(&rows, found, SearchOffset::Start(2)), (3, 0));
+        assert_eq!(apply_offset(&rows, found, SearchOffset::Start(-1)), (0, 0));
+        assert_eq!(apply_offset(&rows, found, SearchOffset::End(0)), (1, 0));
+        assert_eq!(apply_offset(&rows, found, SearchOffset::End(2)), (4, 0));
+        assert_eq!(apply_offset(&rows, found, SearchOffset::End(20)), (7, 0));
+        assert_eq!(apply_offset(&rows, found, SearchOffset::Line(1)), (0, 1));
+        assert_eq!(apply_offset(&rows, found, SearchOffset::Line(5)), (0, 2));
+    }
+
+    #[test]
+    fn compiles_with_smartcase() {
+        let options = Options {
+            ignorecase: true,
+            smartcase: true,
+            ..Options::default()
+        };
+
+        assert!(compile("foo", &options).unwrap().is_match("FOO"));
+        assert!(!compile("Foo", &options).unwrap().is_match("FOO"));
+        assert!(compile(r"Foo\c", &options).unwrap().is_match("FOO"));
+        assert!(!compile(r"foo\C", &options).unwrap().is_match("FOO"));
+        assert!(compile("(", &options).is_err());
+    }
+
+    #[test]
+    fn finds_the_word_under_the_cursor() {
+        let rows = buffer(&["  foo_bar(baz)"]);
+
+        assert_eq!(word_under_cursor(&rows, 5, 0), Some("foo_bar"));
+        assert_eq!(word_under_cursor(&rows, 0, 0), Some("foo_bar"));
+        assert_eq!(word_under_cursor(&rows, 9, 0), Some("baz"));
+        assert_eq!(word_under_cursor(&rows, 13, 0), None);
+    }
+}
@@ -1,8 +1,36 @@
 use bevy_ecs::system::{Res, ResMut};
 
-use super::StatusBuffer;
-use crate::output::buffer::OutputBuffer;
+use super::{MessageKind, StatusBuffer};
+use crate::{
+    output::{buffer::OutputBuffer, OutputSize},
+    theme::Theme,
+};
 
-pub fn draw_status_bar(status: Res<StatusBuffer>, mut buffer: ResMut<OutputBuffer>) {
-    buffer.push_str(&status.value);
+pub fn draw_status_bar(
+    status: Res<StatusBuffer>,
+    size: Res<OutputSize>,
+    theme: Res<Theme>,
+    mut buffer: ResMut<OutputBuffer>,
+) {
+    buffer.move_to(0, size.text_rows());
+
+    match &status.message {
+        Some(message) => {
+            let text: String = message.text.chars().take(size.columns).collect();
+            let padding = size.columns.saturating_sub(text.chars().count());
+            let line = text + &" ".repeat(padding);
+
+            let group = match message.kind {
+                MessageKind::Info => "StatusLine",
+                MessageKind::Error => "ErrorMsg",
+            };
+
+            buffer.push_styled(&line, theme.style(group));
+        }
+        None => {
+            for part in &status.value {
+                buffer.push_styled(part.content(), *part.style());
+            }
+        }
+    }
 }
@@ -1,23 +1,22 @@
 use bevy_ecs::prelude::*;
-use crossterm::style::{Attribute, Color};
-use crossterm::style::{StyledContent, Stylize};
-use lazy_static::lazy_static;
+use crossterm::style::{ContentStyle, StyledContent};
 
+use crate::cursor::{CursorPosition, VisualAnchor};
+use crate::filetype::FileType;
 use crate::input::data::RowBuffer;
+use crate::options::Options;
+use crate::theme::Theme;
+use crate::window::ActiveView;
 use crate::{input::InputMode, output::OutputSize};
 
+use super::format::{self, Item, Segment};
 use super::StatusBuffer;
 
-pub const GREEN_COLOR: Color = color_rgb(0x98, 0xbe, 0x65);
-pub const RED_COLOR: Color = color_rgb(0xec, 0x5f, 0x67);
-pub const PINK_COLOR: Color = color_rgb(0xc6, 0x78, 0xdd);
-pub const BAR_FOREGROUND: Color = color_rgb(0xbb, 0xc2, 0xcf);
-pub const BAR_BACKGROUND: Color = color_rgb(0x20, 0x23, 0x28);
-
-pub const BAR_PADDING: usize = 2;
-
-lazy_static! {
-    static ref EMPTY: StyledContent<String> = String::from(" ").stylize();
+/// A `statusline` format along with its items, it's only parsed again once it changes.
+#[derive(Default)]
+pub struct ParsedFormat {
+    source: String,
+    items: Vec<Item>,
 }
 
 /// Fills the status bar with content based on the provided parameters. Usually called from Bevy's ECS.
@@ -31,108 +30,241 @@ lazy_static! {
 /// * `row_buffer` - A reference to the `RowBuffer` struct containing row-related information.
 /// * `size` - A reference to the `OutputSize` struct representing the terminal output size.
 /// * `input_mode` - A reference to the `InputMode` enum representing the current input mode.
-
+/// * `theme` - A reference to the `Theme` the parts of the bar are styled with.
+/// * `options` - A reference to the `Options`, whose `statusline` lays out the bar.
+/// * `format` - The parsed `statusline`, kept around in between calls.
+#[allow(clippy::too_many_arguments)]
 pub fn fill_bar(
     mut status_bar: ResMut<StatusBuffer>,
     row_buffer: Res<RowBuffer>,
     size: Res<OutputSize>,
     input_mode: Res<InputMode>,
+    theme: Res<Theme>,
+    options: Res<Options>,
+    filetype: Res<FileType>,
+    view: ActiveView,
+    anchor: Res<VisualAnchor>,
+    mut format: Local<ParsedFormat>,
 ) {
-    let mut bar_value = String::new();
-
-    let (right_size, bar_right_value) = build_bar_part(vec![
-        String::from("[UTF-8] ").with(GREEN_COLOR),
-        String::from("UNIX ").with(GREEN_COLOR),
-    ]);
-
-    let (left_size, bar_left_value) = build_bar_part(vec![
-        match input_mode.clone() {
-            InputMode::Insert => String::from("i").with(BAR_FOREGROUND),
-            InputMode::Normal => String::from("n").with(RED_COLOR),
-            InputMode::Visual => String::from("v").with(GREEN_COLOR),
+    // a broken format is reported once, the default one is shown until it's fixed
+    if format.source != options.statusline {
+        format.source = options.statusline.clone();
+        format.items = format::parse(&options.statusline).unwrap_or_else(|error| {
+            status_bar.error(error);
+            format::parse(format::DEFAULT).expect("The default status line is invalid")
+        });
+    }
+
+    let bar = theme.style("StatusLine");
+    let mut sections = vec![Vec::new()];
+    let mut group = None;
+
+    for item in &format.items {
+        let (content, style) = match item {
+            Item::Text(text) => (text.clone(), group.unwrap_or(bar)),
+            Item::Segment(segment) => {
+                let own = match segment {
+                    Segment::Mode => mode_group(*input_mode),
+                    _ => segment.group(),
+                };
+
+                let content = segment_content(
+                    *segment,
+                    &row_buffer,
+                    *input_mode,
+                    &filetype,
+                    &view.get().position,
+                    &anchor,
+                );
+
+                (content, group.unwrap_or_else(|| theme.style(own)))
+            }
+            Item::Group(name) => {
+                group = name.as_deref().map(|it| theme.style(it));
+                continue;
+            }
+            Item::Separator => {
+                sections.push(Vec::new());
+                continue;
+            }
+        };
+
+        if let Some(section) = sections.last_mut() {
+            section.push(StyledContent::new(style, content));
+        }
+    }
+
+    status_bar.value = wrap_colored(layout(sections, size.columns), bar);
+}
+
+/// The group the mode segment is drawn with, which differs for every mode.
+fn mode_group(input_mode: InputMode) -> &'static str {
+    match input_mode {
+        InputMode::Insert => "StatusInsert",
+        InputMode::Normal => "StatusNormal",
+        InputMode::Visual => "StatusVisual",
+        InputMode::Command | InputMode::Confirm => "StatusCommand",
+    }
+}
+
+fn segment_content(
+    segment: Segment,
+    rows: &RowBuffer,
+    input_mode: InputMode,
+    filetype: &FileType,
+    position: &CursorPosition,
+    anchor: &VisualAnchor,
+) -> String {
+    match segment {
+        Segment::Mode => String::from(match input_mode {
+            InputMode::Insert => "i",
+            InputMode::Normal => "n",
+            InputMode::Visual => "v",
+            InputMode::Command | InputMode::Confirm => "c",
+        }),
+        Segment::Chars => rows.get_char_count().to_string(),
+        Segment::File => rows.get_buffer_name().to_string(),
+        Segment::Modified => match rows.is_modified() {
+            true => String::from("[+]"),
+            false => String::new(),
         },
-        EMPTY.clone(),
-        row_buffer.get_char_count().to_string().with(BAR_FOREGROUND),
-        EMPTY.clone(),
-        row_buffer.get_buffer_name().to_string().with(PINK_COLOR),
-    ]);
+        Segment::FileType => filetype.name().to_string(),
+        Segment::Encoding => String::from("UTF-8"),
+        Segment::FileFormat => String::from("UNIX"),
+        Segment::Position => format!("{}:{}", position.y + 1, position.x + 1),
+        Segment::Percent => {
+            let count = rows.rows_count().max(1);
+            format!("{}%", (position.y + 1).min(count) * 100 / count)
+        }
+        Segment::Selection if input_mode != InputMode::Visual => String::new(),
+        Segment::Selection if anchor.y != position.y => {
+            (anchor.y.abs_diff(position.y) + 1).to_string()
+        }
+        Segment::Selection => {
+            let contents = match position.y < rows.rows_count() {
+                true => rows.get_row_at(position.y).contents(),
+                false => "",
+            };
+            let (start, end) = (anchor.x.min(position.x), anchor.x.max(position.x));
+
+            // the character under the end of the selection is selected too
+            let end = contents
+                .get(end..)
+                .and_then(|it| it.chars().next())
+                .map_or(end, |it| end + it.len_utf8());
+
+            contents
+                .get(start..end.min(contents.len()))
+                .map_or(1, |it| it.chars().count().max(1))
+                .to_string()
+        }
+    }
+}
 
-    let attribute_len = Attribute::Reset.to_string().len();
+/// Lays the sections out over `columns`, the first one to the left, the last one to the right
+/// and the one in between centered. When they don't all fit, the middle section is cut off first
+/// and the left one last.
+fn layout(sections: Vec<Vec<StyledContent<String>>>, columns: usize) -> Vec<StyledContent<String>> {
+    let width = |section: &[StyledContent<String>]| {
+        section
+            .iter()
+            .map(|it| it.content().chars().count())
+            .sum::<usize>()
+    };
 
-    (0..BAR_PADDING).for_each(|_| bar_value.push(' '));
-    bar_value.push_str(&bar_left_value);
+    let mut sections = sections.into_iter();
+    let left = sections.next().unwrap_or_default();
+    let right = sections.next_back().unwrap_or_default();
+    let center = sections.next().unwrap_or_default();
 
-    // Calculate the adjusted length, considering the ANSI escape codes
-    let adjusted_length = size.columns
-        - attribute_len // remove the attribute's text (Attribute::Reset) 
-        - left_size
-        - right_size;
+    let left = truncate(left, columns);
+    let left_width = width(&left);
+    let right = truncate(right, columns - left_width);
+    let right_width = width(&right);
+    let center = truncate(center, columns - left_width - right_width);
+    let center_width = width(&center);
 
-    (0..adjusted_length).for_each(|_| bar_value.push(' '));
+    // the middle section is centered on the screen, as far as the other sections leave room
+    let center_start = (columns.saturating_sub(center_width) / 2)
+        .clamp(left_width, columns - right_width - center_width);
 
-    bar_value.push_str(&bar_right_value);
-    (0..BAR_PADDING).for_each(|_| bar_value.push(' '));
+    let mut value = left;
+    value.push(gap(center_start - left_width));
+    value.extend(center);
+    value.push(gap(columns - right_width - center_start - center_width));
+    value.extend(right);
 
-    // reverse the contents of the bar value
-    status_bar.value = wrap_colored(bar_value);
+    value
 }
 
-/// Builds a part of the status bar content based on the provided content elements.
-///
-/// # Arguments
-///
-/// * `content` - A vector of elements that implement the `Into<StyledContent<String>>` trait.
-///
-/// # Returns
-///
-/// A tuple containing the total size of the unstyled content, and the concatenated string of styled content.
-///
-/// # Example
-///
-/// ```
-/// let content = vec![
-///     String::from("i").with(BAR_FOREGROUND),
-///     EMPTY.clone(),
-///     row_buffer.get_char_count().to_string().with(BAR_FOREGROUND),
-///     EMPTY.clone(),
-///     row_buffer.get_buffer_name().to_string().with(PINK_COLOR),
-/// ];
-/// let (size, value) = build_bar_part(content);
-/// ```
-fn build_bar_part(content: Vec<impl Into<StyledContent<String>>>) -> (usize, String) {
-    return content
+/// Cuts the end off a section that's wider than `columns`.
+fn truncate(section: Vec<StyledContent<String>>, columns: usize) -> Vec<StyledContent<String>> {
+    let mut left = columns;
+
+    section
         .into_iter()
-        .fold((0, String::new()), |(size_acc, string_acc), content| {
-            let styled_content = content.into();
+        .map(|it| {
+            let content: String = it.content().chars().take(left).collect();
+            left -= content.chars().count();
 
-            let content_len = styled_content.content().len();
-            let content_str = styled_content.to_string();
+            StyledContent::new(*it.style(), content)
+        })
+        .collect()
+}
 
-            (size_acc + content_len, string_acc + &content_str)
-        });
+fn gap(columns: usize) -> StyledContent<String> {
+    StyledContent::new(ContentStyle::default(), " ".repeat(columns))
 }
 
-fn wrap_colored(value: String) -> String {
-    return value.on(BAR_BACKGROUND).to_string() + &Attribute::Reset.to_string();
+/// Gives every part of the bar without a background of its own the bar's background.
+fn wrap_colored(
+    value: Vec<StyledContent<String>>,
+    bar: ContentStyle,
+) -> Vec<StyledContent<String>> {
+    value
+        .into_iter()
+        .map(|it| {
+            let mut style = *it.style();
+            style.background_color = style.background_color.or(bar.background_color);
+
+            StyledContent::new(style, it.content().clone())
+        })
+        .collect()
 }
 
-/// A const function that creates a `Color` enum representing an RGB color.
-///
-/// # Arguments
-///
-/// * `r` - The red component of the RGB color (0-255).
-/// * `g` - The green component of the RGB color (0-255).
-/// * `b` - The blue component of the RGB color (0-255).
-///
-/// # Returns
-///
-/// A `Color::Rgb` enum variant representing the specified RGB color.
-///
-/// # Example
-///
-/// ```
-/// const MY_COLOR: Color = color_rgb(255, 0, 0); // Red color
-/// ```
-pub const fn color_rgb(r: u8, g: u8, b: u8) -> Color {
-    Color::Rgb { r, g, b }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(parts: &[&str]) -> Vec<StyledContent<String>> {
+        parts
+            .iter()
+            .map(|it| StyledContent::new(ContentStyle::default(), it.to_string()))
+            .collect()
+    }
+
+    fn laid_out(sections: &[&[&str]], columns: usize) -> String {
+        let sections = sections.iter().map(|it| section(it)).collect();
+
+        layout(sections, columns)
+            .iter()
+            .map(|it| it.content().as_str())
+            .collect()
+    }
+
+    #[test]
+    fn lays_out_the_sections_left_centered_and_right() {
+        assert_eq!(laid_out(&[&["ab"]], 6), "ab    ");
+        assert_eq!(laid_out(&[&["a"], &["b"]], 6), "a    b");
+        assert_eq!(laid_out(&[&["a"], &["mid"], &["b"]], 9), "a  mid  b");
+        assert_eq!(laid_out(&[&["long"], &["mid"], &["b"]], 10), "longmid  b");
+    }
+
+    #[test]
+    fn cuts_off_the_middle_section_first_and_the_left_one_last() {
+        assert_eq!(laid_out(&[&["ab"], &["mid"], &["cd"]], 6), "abmicd");
+        assert_eq!(laid_out(&[&["ab", "cd"], &["ef"]], 5), "abcde");
+        assert_eq!(laid_out(&[&["abcdef"], &["gh"]], 4), "abcd");
+        assert_eq!(laid_out(&[], 3), "   ");
+    }
 }
@@ -0,0 +1,173 @@
+/// The status bar the editor starts out with, the mode, the amount of characters and the file's
+/// name on the left, and the encoding and line endings on the right.
+pub const DEFAULT: &str =
+    "  %{mode} %{chars} %{file} %{modified}%=%#StatusFileInfo#[%{encoding}] %{fileformat}%*   ";
+
+/// A piece of information about the buffer or the editor shown in the status bar.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Segment {
+    /// A letter for the input mode, `n`, `i`, `v` or `c`.
+    Mode,
+    /// The amount of characters in the buffer.
+    Chars,
+    File,
+    /// `[+]` when the buffer is modified.
+    Modified,
+    FileType,
+    Encoding,
+    /// The line endings the file is written with.
+    FileFormat,
+    /// The cursor's line and column, as `line:column`.
+    Position,
+    /// How far into the buffer the cursor's line is.
+    Percent,
+    /// The amount of characters selected in visual mode, or lines once more than one is selected.
+    Selection,
+}
+
+impl Segment {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "mode" => Segment::Mode,
+            "chars" => Segment::Chars,
+            "file" => Segment::File,
+            "modified" => Segment::Modified,
+            "filetype" => Segment::FileType,
+            "encoding" => Segment::Encoding,
+            "fileformat" => Segment::FileFormat,
+            "position" => Segment::Position,
+            "percent" => Segment::Percent,
+            "selection" => Segment::Selection,
+            _ => return None,
+        })
+    }
+
+    /// The highlight group the segment is drawn with, unless the format picks one itself.
+    pub fn group(&self) -> &'static str {
+        match self {
+            Segment::File => "StatusFileName",
+            Segment::Encoding | Segment::FileFormat => "StatusFileInfo",
+            _ => "StatusLine",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Item {
+    Text(String),
+    Segment(Segment),
+    /// `%#{Group}#` draws what follows with `Group`, `%*` goes back to every segment's own group.
+    Group(Option<String>),
+    /// `%=` ends a section of the bar, the sections are aligned to the left, center and right.
+    Separator,
+}
+
+/// The most sections a status bar is split into.
+const MAX_SECTIONS: usize = 3;
+
+/// Parses a `statusline` format. Text is shown as is, `%{name}` is replaced by a [Segment],
+/// `%#Group#` and `%*` pick the highlight group and `%=` separates the sections. `%%` is a
+/// literal `%`.
+pub fn parse(format: &str) -> Result<Vec<Item>, String> {
+    let mut items = Vec::new();
+    let mut text = String::new();
+    let mut chars = format.chars();
+    let mut separators = 0;
+
+    while let Some(char) = chars.next() {
+        if char != '%' {
+            text.push(char);
+            continue;
+        }
+
+        let item = match chars.next() {
+            Some('%') => {
+                text.push('%');
+                continue;
+            }
+            Some('{') => {
+                let (name, rest) = chars
+                    .as_str()
+                    .split_once('}')
+                    .ok_or_else(|| String::from("Missing } in the status line"))?;
+                let segment = Segment::from_name(name)
+                    .ok_or_else(|| format!("Unknown status line segment: {name}"))?;
+
+                chars = rest.chars();
+                Item::Segment(segment)
+            }
+            Some('#') => {
+                let (group, rest) = chars
+                    .as_str()
+                    .split_once('#')
+                    .ok_or_else(|| String::from("Missing # in the status line"))?;
+
+                chars = rest.chars();
+                Item::Group(Some(group.to_string()))
+            }
+            Some('*') => Item::Group(None),
+            Some('=') => {
+                separators += 1;
+
+                if separators >= MAX_SECTIONS {
+                    return Err(String::from("Too many %= in the status line"));
+                }
+
+                Item::Separator
+            }
+            Some(other) => return Err(format!("Unknown status line item: %{other}")),
+            None => return Err(String::from("Status line ends in %")),
+        };
+
+        if !text.is_empty() {
+            items.push(Item::Text(std::mem::take(&mut text)));
+        }
+
+        items.push(item);
+    }
+
+    if !text.is_empty() {
+        items.push(Item::Text(text));
+    }
+
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> Item {
+        Item::Text(text.to_string())
+    }
+
+    #[test]
+    fn parses_text_segments_and_groups() {
+        assert_eq!(
+            parse("a %{file}%#Error#b%*%=c"),
+            Ok(vec![
+                text("a "),
+                Item::Segment(Segment::File),
+                Item::Group(Some(String::from("Error"))),
+                text("b"),
+                Item::Group(None),
+                Item::Separator,
+                text("c"),
+            ])
+        );
+        assert_eq!(parse("100%%"), Ok(vec![text("100%")]));
+        assert_eq!(parse(""), Ok(vec![]));
+        assert!(parse(DEFAULT).is_ok());
+    }
+
+    #[test]
+    fn rejects_broken_formats() {
+        assert!(parse("%{file").is_err());
+        assert!(parse("%{nothing}").is_err());
+        assert!(parse("%#Error").is_err());
+        assert!(parse("%x").is_err());
+        assert!(parse("a%").is_err());
+        assert!(parse("%=%=").is_ok());
+        assert!(parse("%=%=%=").is_err());
+    }
+}
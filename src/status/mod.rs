@@ -1,24 +1,63 @@
 use bevy_ecs::prelude::*;
+use crossterm::style::StyledContent;
 
 pub mod draw;
 pub mod fill;
+pub mod format;
 
 pub fn init(world: &mut World, schedule: &mut Schedule) {
     world.insert_resource(StatusBuffer::new());
 
-    // we don't care much about the order of fill_bar, so we'll call it here.
-    schedule.add_systems(fill::fill_bar);
+    // the bar only has to be filled before it's drawn.
+    schedule.add_systems(fill::fill_bar.before(draw::draw_status_bar));
 }
 
-#[derive(Clone, Resource)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum MessageKind {
+    Info,
+    Error,
+}
+
+/// A one-off message shown in place of the status bar, until the next key is pressed.
+#[derive(Clone)]
+pub struct StatusMessage {
+    pub(crate) text: String,
+    pub(crate) kind: MessageKind,
+}
+
+#[derive(Clone, Resource, Default)]
 pub struct StatusBuffer {
-    value: String,
+    value: Vec<StyledContent<String>>,
+    message: Option<StatusMessage>,
 }
 
 impl StatusBuffer {
     pub fn new() -> Self {
         Self {
-            value: String::new(),
+            value: Vec::new(),
+            message: None,
         }
     }
+
+    pub fn info(&mut self, text: impl Into<String>) {
+        self.message = Some(StatusMessage {
+            text: text.into(),
+            kind: MessageKind::Info,
+        });
+    }
+
+    pub fn error(&mut self, text: impl Into<String>) {
+        self.message = Some(StatusMessage {
+            text: text.into(),
+            kind: MessageKind::Error,
+        });
+    }
+
+    pub fn clear_message(&mut self) {
+        self.message = None;
+    }
+
+    pub fn get_message(&self) -> Option<&StatusMessage> {
+        self.message.as_ref()
+    }
 }
@@ -0,0 +1,276 @@
+use bevy_ecs::prelude::*;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use regex::Regex;
+
+use super::{replacement::Replacement, report, set_split_row};
+use crate::{
+    command::{self, parser::LineRange},
+    cursor::{update::clamp_to_row, CursorPosition},
+    input::{data::RowBuffer, InputMode},
+    search::SearchMatch,
+    status::StatusBuffer,
+    window::View,
+};
+
+/// A key pressed while a substitution with the `c` flag is asking for confirmation.
+#[derive(Event)]
+pub struct ConfirmEvent {
+    pub(crate) key: KeyEvent,
+}
+
+/// A substitution with the `c` flag that is waiting for a key for its current match.
+pub struct Session {
+    regex: Regex,
+    replacement: Replacement,
+    /// The replacement as it was typed, shown in the prompt.
+    typed: String,
+    global: bool,
+    /// The row and byte offset the next match is searched from.
+    y: usize,
+    x: usize,
+    /// The last row of the range, moves down as replacements split rows.
+    end: usize,
+    /// Where the last non-empty match ended, an empty match right there isn't a match of its own.
+    after_match: Option<(usize, usize)>,
+    current: Option<SearchMatch>,
+    origin: CursorPosition,
+    substitutions: usize,
+    lines: usize,
+    last_line: Option<usize>,
+}
+
+impl Session {
+    pub fn new(
+        regex: Regex,
+        typed: &str,
+        global: bool,
+        range: LineRange,
+        origin: CursorPosition,
+    ) -> Self {
+        Self {
+            regex,
+            replacement: Replacement::parse(typed),
+            typed: typed.to_string(),
+            global,
+            y: range.start,
+            x: 0,
+            end: range.end,
+            after_match: None,
+            current: None,
+            origin,
+            substitutions: 0,
+            lines: 0,
+            last_line: None,
+        }
+    }
+
+    /// Finds the next match from the current position, returns `false` once there are none left.
+    fn advance(&mut self, rows: &RowBuffer) -> bool {
+        while self.y <= self.end && self.y < rows.rows_count() {
+            let contents = rows.get_row_at(self.y).contents();
+
+            if let Some(found) = contents
+                .get(self.x..)
+                .and_then(|_| self.regex.find_at(contents, self.x))
+            {
+                if found.is_empty() && self.after_match == Some((self.y, found.start())) {
+                    self.continue_at(rows, found.start(), true);
+                    continue;
+                }
+
+                self.current = Some(SearchMatch {
+                    y: self.y,
+                    start: found.start(),
+                    end: found.end(),
+                });
+
+                return true;
+            }
+
+            self.y += 1;
+            self.x = 0;
+        }
+
+        self.current = None;
+        false
+    }
+
+    /// Continues searching at byte `x` of the current row, or on the next row if only the first
+    /// match of every row is replaced. Empty matches skip a character, so they aren't found again.
+    fn continue_at(&mut self, rows: &RowBuffer, x: usize, empty: bool) {
+        if !self.global {
+            self.y += 1;
+            self.x = 0;
+            return;
+        }
+
+        let contents = rows.get_row_at(self.y).contents();
+
+        self.after_match = (!empty).then_some((self.y, x));
+        self.x = match (empty, contents[x..].chars().next()) {
+            (true, Some(next)) => x + next.len_utf8(),
+            // moving past the end of the row makes `advance` go to the next row
+            (true, None) => contents.len() + 1,
+            (false, _) => x,
+        };
+    }
+
+    fn skip(&mut self, rows: &RowBuffer) {
+        if let Some(found) = self.current {
+            self.continue_at(rows, found.end, found.start == found.end);
+        }
+    }
+
+    fn replace(&mut self, rows: &mut RowBuffer) {
+        let Some(found) = self.current else {
+            return;
+        };
+
+        let contents = rows.get_row_at(found.y).contents().to_string();
+
+        let Some(captures) = self.regex.captures_at(&contents, found.start) else {
+            return;
+        };
+
+        let expanded = self.replacement.expand(&captures);
+
+        // all of the confirmed replacements are undone as a single step
+        if self.last_line.is_none() {
+            rows.begin_change((self.origin.x, self.origin.y));
+        }
+
+        if self.last_line != Some(found.y) {
+            self.lines += 1;
+        }
+
+        let replaced = format!(
+            "{}{}{}",
+            &contents[..found.start],
+            expanded,
+            &contents[found.end..]
+        );
+
+        let added = set_split_row(rows, found.y, &replaced);
+
+        // the search continues right after the replacement, which is on the last row it added
+        let x = match expanded.rfind('\n') {
+            Some(newline) => expanded.len() - newline - 1,
+            None => found.start + expanded.len(),
+        };
+
+        self.substitutions += 1;
+        self.y += added;
+        self.end += added;
+        self.last_line = Some(self.y);
+
+        self.continue_at(rows, x, found.start == found.end);
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Confirmation {
+    session: Option<Session>,
+}
+
+impl Confirmation {
+    /// Starts asking for confirmation, returns `false` if there is nothing to confirm at all.
+    pub fn start(&mut self, mut session: Session, rows: &RowBuffer) -> bool {
+        if !session.advance(rows) {
+            return false;
+        }
+
+        self.session = Some(session);
+        true
+    }
+
+    /// The match that is currently waiting for confirmation.
+    pub fn current(&self) -> Option<SearchMatch> {
+        self.session.as_ref().and_then(|it| it.current)
+    }
+}
+
+/// Moves the cursor to the current match, and asks what should be done with it.
+pub fn show(world: &mut World) {
+    let confirmation = world.resource::<Confirmation>();
+
+    let (Some(found), Some(session)) = (confirmation.current(), &confirmation.session) else {
+        return;
+    };
+
+    let prompt = format!("replace with {} (y/n/a/q/l)?", session.typed);
+
+    let mut position = CursorPosition {
+        x: found.start,
+        y: found.y,
+    };
+    clamp_to_row(&mut position, world.resource::<RowBuffer>());
+    View::active_mut(world).position = position;
+
+    world.resource_mut::<StatusBuffer>().info(prompt);
+}
+
+pub fn handle_confirm(world: &mut World) {
+    let events: Vec<ConfirmEvent> = world
+        .resource_mut::<Events<ConfirmEvent>>()
+        .drain()
+        .collect();
+
+    for ConfirmEvent { key } in events {
+        let Some(mut session) = world.resource_mut::<Confirmation>().session.take() else {
+            continue;
+        };
+
+        let mut rows = world.resource_mut::<RowBuffer>();
+
+        let finished = match (key.code, key.modifiers) {
+            (KeyCode::Char('y'), _) => {
+                session.replace(&mut rows);
+                !session.advance(&rows)
+            }
+            (KeyCode::Char('n'), _) => {
+                session.skip(&rows);
+                !session.advance(&rows)
+            }
+            (KeyCode::Char('a'), _) => {
+                while session.current.is_some() {
+                    session.replace(&mut rows);
+                    session.advance(&rows);
+                }
+
+                true
+            }
+            (KeyCode::Char('l'), _) => {
+                session.replace(&mut rows);
+                true
+            }
+            (KeyCode::Char('q'), _)
+            | (KeyCode::Esc, _)
+            | (KeyCode::Char('c'), KeyModifiers::CONTROL) => true,
+            _ => false,
+        };
+
+        if !finished {
+            world.resource_mut::<Confirmation>().session = Some(session);
+            show(world);
+            continue;
+        }
+
+        finish(world, session);
+    }
+}
+
+fn finish(world: &mut World, session: Session) {
+    *world.resource_mut::<InputMode>() = InputMode::Normal;
+
+    match session.last_line {
+        Some(last_line) => command::jump_to_row(world, last_line),
+        None => View::active_mut(world).position = session.origin,
+    }
+
+    report(
+        &mut world.resource_mut::<StatusBuffer>(),
+        session.substitutions,
+        session.lines,
+        false,
+    );
+}
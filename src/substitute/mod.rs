@@ -0,0 +1,481 @@
+use bevy_ecs::prelude::*;
+use regex::Regex;
+
+use crate::{
+    command::{self, parser, parser::LineRange, CommandError, CommandRegistry, Invocation},
+    input::{data::RowBuffer, InputMode},
+    options::Options,
+    search::{self, SearchState},
+    status::StatusBuffer,
+    window::View,
+    SystemType,
+};
+
+use self::replacement::Replacement;
+
+pub mod confirm;
+pub mod preview;
+pub mod replacement;
+
+pub fn init(world: &mut World, schedule: &mut Schedule) {
+    world.insert_resource(SubstituteState::default());
+    world.insert_resource(confirm::Confirmation::default());
+    world.insert_resource(preview::SubstitutePreview::default());
+    world.insert_resource(Events::<confirm::ConfirmEvent>::default());
+
+    let mut registry = world.resource_mut::<CommandRegistry>();
+    registry.register("substitute", 1, substitute);
+    registry.register("&", 1, repeat);
+
+    schedule.add_systems(
+        (confirm::handle_confirm, preview::preview)
+            .after(SystemType::Input)
+            .before(SystemType::Cursor),
+    );
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Flags {
+    /// `g`, replaces every match in a row instead of only the first one.
+    pub(crate) global: bool,
+    /// `c`, asks for confirmation before every replacement.
+    pub(crate) confirm: bool,
+    /// `n`, only counts the matches without replacing anything.
+    pub(crate) count_only: bool,
+    /// `e`, doesn't treat not finding the pattern as an error.
+    pub(crate) no_error: bool,
+    /// `i` and `I`, overriding the `ignorecase` option.
+    pub(crate) ignore_case: Option<bool>,
+}
+
+/// The arguments of a substitution, e.g. `/foo/bar/g 3`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubstituteArgs {
+    pub(crate) pattern: String,
+    pub(crate) replacement: String,
+    pub(crate) flags: Flags,
+    pub(crate) count: Option<usize>,
+}
+
+/// The last substitution, repeated by `:s` and `:&` without a pattern.
+#[derive(Resource, Default)]
+pub struct SubstituteState {
+    last: Option<SubstituteArgs>,
+}
+
+/// Whether `name` refers to the `:substitute` command.
+pub fn is_substitute(name: &str) -> bool {
+    !name.is_empty() && "substitute".starts_with(name)
+}
+
+/// Parses the arguments of `:s`. Without a delimiter (e.g. `:s`, `:s g` or `:&&`), the last
+/// substitution is repeated with the given flags, where `&` keeps the last flags.
+pub fn parse_args(
+    args: &str,
+    last: Option<&SubstituteArgs>,
+) -> Result<SubstituteArgs, CommandError> {
+    let delimiter = args
+        .chars()
+        .next()
+        .filter(|it| !it.is_alphanumeric() && !it.is_whitespace() && !"\\\"|&".contains(*it));
+
+    let (pattern, replacement, rest) = match delimiter {
+        Some(delimiter) => {
+            let (pattern, rest) = parser::split_pattern(&args[delimiter.len_utf8()..], delimiter);
+            let (replacement, rest) = split_replacement(rest, delimiter);
+
+            (pattern, replacement, rest)
+        }
+        None => {
+            let last = last.ok_or(CommandError::NoPreviousSubstitute)?;
+            (last.pattern.clone(), last.replacement.clone(), args)
+        }
+    };
+
+    let mut flags = Flags::default();
+    let mut rest = rest.trim_start();
+
+    if let Some(keep) = rest.strip_prefix('&') {
+        flags = last.map(|it| it.flags).unwrap_or_default();
+        rest = keep;
+    }
+
+    let flag_count = rest.chars().take_while(|it| "gcneiI".contains(*it)).count();
+
+    for flag in rest[..flag_count].chars() {
+        match flag {
+            'g' => flags.global = !flags.global,
+            'c' => flags.confirm = true,
+            'n' => flags.count_only = true,
+            'e' => flags.no_error = true,
+            'i' => flags.ignore_case = Some(true),
+            _ => flags.ignore_case = Some(false),
+        }
+    }
+
+    let rest = rest[flag_count..].trim();
+
+    let count = match rest.is_empty() {
+        true => None,
+        false => Some(
+            rest.parse()
+                .ok()
+                .filter(|it| *it > 0)
+                .ok_or_else(|| CommandError::TrailingCharacters(rest.to_string()))?,
+        ),
+    };
+
+    Ok(SubstituteArgs {
+        pattern,
+        replacement,
+        flags,
+        count,
+    })
+}
+
+/// Reads the replacement up until an unescaped `delimiter`. Unlike patterns, every escape is kept
+/// as-is, as they're interpreted by [Replacement::parse] later on.
+fn split_replacement(input: &str, delimiter: char) -> (String, &str) {
+    let mut replacement = String::new();
+    let mut chars = input.char_indices();
+
+    while let Some((index, current)) = chars.next() {
+        match current {
+            '\\' => match chars.next() {
+                Some((_, escaped)) if escaped == delimiter => replacement.push(delimiter),
+                Some((_, escaped)) => {
+                    replacement.push('\\');
+                    replacement.push(escaped);
+                }
+                None => replacement.push('\\'),
+            },
+            current if current == delimiter => {
+                return (replacement, &input[index + delimiter.len_utf8()..]);
+            }
+            current => replacement.push(current),
+        }
+    }
+
+    (replacement, "")
+}
+
+/// Compiles the pattern of a substitution, an empty pattern uses the last search pattern.
+pub fn compile(
+    args: &SubstituteArgs,
+    last_pattern: Option<&str>,
+    options: &Options,
+) -> Result<(String, Regex), CommandError> {
+    let pattern = match (args.pattern.is_empty(), last_pattern) {
+        (true, Some(last)) => last.to_string(),
+        (true, None) => return Err(CommandError::NoPreviousPattern),
+        (false, _) => args.pattern.clone(),
+    };
+
+    let options = match args.flags.ignore_case {
+        Some(ignore_case) => Options {
+            ignorecase: ignore_case,
+            smartcase: false,
+            ..options.clone()
+        },
+        None => options.clone(),
+    };
+
+    let regex = search::compile(&pattern, &options)?;
+    Ok((pattern, regex))
+}
+
+/// The range a substitution applies to, a count starts at the last row of the range.
+pub fn effective_range(range: LineRange, count: Option<usize>, rows: usize) -> LineRange {
+    match count {
+        Some(count) => range.with_count(count, rows),
+        None => range,
+    }
+}
+
+/// Replaces the matches on a single row, returns the new contents and the amount of matches that
+/// were replaced, or `None` if nothing matched.
+pub fn replace_row(
+    contents: &str,
+    regex: &Regex,
+    replacement: &Replacement,
+    global: bool,
+) -> Option<(String, usize)> {
+    let mut replaced = String::new();
+    let mut last = 0;
+    let mut count = 0;
+
+    for captures in regex.captures_iter(contents) {
+        let found = captures.get(0).unwrap();
+
+        replaced.push_str(&contents[last..found.start()]);
+        replaced.push_str(&replacement.expand(&captures));
+
+        last = found.end();
+        count += 1;
+
+        if !global {
+            break;
+        }
+    }
+
+    if count == 0 {
+        return None;
+    }
+
+    replaced.push_str(&contents[last..]);
+    Some((replaced, count))
+}
+
+/// Sets a row to `contents`, splitting it into multiple rows on every newline. Returns the amount
+/// of rows that were added.
+pub fn set_split_row(rows: &mut RowBuffer, y: usize, contents: &str) -> usize {
+    let mut lines = contents.split('\n');
+
+    rows.set_row(y, lines.next().unwrap_or_default());
+
+    lines.enumerate().fold(0, |added, (index, line)| {
+        rows.insert_row(y + index + 1, line);
+        added + 1
+    })
+}
+
+/// Shows how many substitutions were made, if it's more than a couple. Counting matches is
+/// always reported.
+pub fn report(status: &mut StatusBuffer, substitutions: usize, lines: usize, count_only: bool) {
+    let lines = match lines {
+        1 => String::from("1 line"),
+        lines => format!("{lines} lines"),
+    };
+
+    match (count_only, substitutions) {
+        (true, 1) => status.info(format!("1 match on {lines}")),
+        (true, count) => status.info(format!("{count} matches on {lines}")),
+        (false, count) if count > 2 => status.info(format!("{count} substitutions on {lines}")),
+        (false, _) => {}
+    }
+}
+
+fn substitute(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let args = parse_args(
+        &invocation.args,
+        world.resource::<SubstituteState>().last.as_ref(),
+    )?;
+
+    run(world, invocation, args)
+}
+
+/// `:&` repeats the last substitution, `:&&` keeps its flags.
+fn repeat(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let last = world
+        .resource::<SubstituteState>()
+        .last
+        .clone()
+        .ok_or(CommandError::NoPreviousSubstitute)?;
+
+    // the arguments never contain a pattern, so they're always parsed as flags
+    let args = parse_args(invocation.args.trim_start(), Some(&last))?;
+
+    run(world, invocation, args)
+}
+
+fn run(
+    world: &mut World,
+    invocation: &Invocation,
+    args: SubstituteArgs,
+) -> Result<(), CommandError> {
+    let (pattern, regex) = compile(
+        &args,
+        world.resource::<SearchState>().pattern(),
+        world.resource::<Options>(),
+    )?;
+
+    // like vim, the pattern of a substitution becomes the last search pattern as well.
+    world.resource_scope(|world, mut search: Mut<SearchState>| {
+        search.set_pattern(&pattern, world.resource::<Options>())
+    })?;
+
+    world.resource_mut::<SubstituteState>().last = Some(args.clone());
+
+    let range = effective_range(
+        invocation.range_or_current(world),
+        args.count,
+        world.resource::<RowBuffer>().rows_count(),
+    );
+
+    if args.flags.confirm && !args.flags.count_only {
+        let cursor = View::active(world).position.clone();
+        let session =
+            confirm::Session::new(regex, &args.replacement, args.flags.global, range, cursor);
+
+        let started =
+            world.resource_scope(|world, mut confirmation: Mut<confirm::Confirmation>| {
+                confirmation.start(session, world.resource::<RowBuffer>())
+            });
+
+        if !started {
+            return not_found(&args, &pattern);
+        }
+
+        *world.resource_mut::<InputMode>() = InputMode::Confirm;
+        confirm::show(world);
+
+        return Ok(());
+    }
+
+    let replacement = Replacement::parse(&args.replacement);
+    let cursor = View::active(world).position.clone();
+    let mut rows = world.resource_mut::<RowBuffer>();
+
+    let mut substitutions = 0;
+    let mut lines = 0;
+    let mut last_line = None;
+    let mut y = range.start;
+    let mut end = range.end;
+
+    while y <= end && y < rows.rows_count() {
+        let Some((replaced, count)) = replace_row(
+            rows.get_row_at(y).contents(),
+            &regex,
+            &replacement,
+            args.flags.global,
+        ) else {
+            y += 1;
+            continue;
+        };
+
+        substitutions += count;
+        lines += 1;
+
+        if args.flags.count_only {
+            y += 1;
+            continue;
+        }
+
+        // the whole substitution is undone as a single step
+        if last_line.is_none() {
+            rows.begin_change((cursor.x, cursor.y));
+        }
+
+        let added = set_split_row(&mut rows, y, &replaced);
+
+        last_line = Some(y + added);
+        y += added + 1;
+        end += added;
+    }
+
+    if substitutions == 0 {
+        return not_found(&args, &pattern);
+    }
+
+    if let Some(last_line) = last_line {
+        command::jump_to_row(world, last_line);
+    }
+
+    report(
+        &mut world.resource_mut::<StatusBuffer>(),
+        substitutions,
+        lines,
+        args.flags.count_only,
+    );
+
+    Ok(())
+}
+
+fn not_found(args: &SubstituteArgs, pattern: &str) -> Result<(), CommandError> {
+    match args.flags.no_error {
+        true => Ok(()),
+        false => Err(CommandError::PatternNotFound(pattern.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(
+        pattern: &str,
+        replacement: &str,
+        flags: Flags,
+        count: Option<usize>,
+    ) -> SubstituteArgs {
+        SubstituteArgs {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            flags,
+            count,
+        }
+    }
+
+    #[test]
+    fn parses_pattern_replacement_flags_and_count() {
+        let global = Flags {
+            global: true,
+            ..Flags::default()
+        };
+
+        assert_eq!(
+            parse_args("/foo/bar/g 3", None).ok(),
+            Some(args("foo", "bar", global, Some(3)))
+        );
+        assert_eq!(
+            parse_args(r"#a\#b#c\#\1#", None).ok(),
+            Some(args("a#b", r"c#\1", Flags::default(), None))
+        );
+        assert_eq!(
+            parse_args("/foo", None).ok(),
+            Some(args("foo", "", Flags::default(), None))
+        );
+        assert_eq!(
+            parse_args("/a/b/cnI", None).map(|it| it.flags).ok(),
+            Some(Flags {
+                confirm: true,
+                count_only: true,
+                ignore_case: Some(false),
+                ..Flags::default()
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_trailing_characters() {
+        assert!(matches!(
+            parse_args("/a/b/g x", None),
+            Err(CommandError::TrailingCharacters(_))
+        ));
+        assert!(matches!(
+            parse_args("/a/b/ 0", None),
+            Err(CommandError::TrailingCharacters(_))
+        ));
+    }
+
+    #[test]
+    fn repeats_the_last_substitution() {
+        let last = args(
+            "a",
+            "b",
+            Flags {
+                global: true,
+                confirm: true,
+                ..Flags::default()
+            },
+            None,
+        );
+
+        assert!(matches!(
+            parse_args("", None),
+            Err(CommandError::NoPreviousSubstitute)
+        ));
+        assert_eq!(
+            parse_args("", Some(&last)).ok(),
+            Some(args("a", "b", Flags::default(), None))
+        );
+        assert_eq!(
+            parse_args("& 2", Some(&last)).ok(),
+            Some(args("a", "b", last.flags, Some(2)))
+        );
+        assert_eq!(
+            parse_args("&g", Some(&last)).map(|it| it.flags.global).ok(),
+            Some(false)
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use std::{collections::HashMap, ops::Range};
+
+use bevy_ecs::prelude::*;
+
+use super::{
+    compile, effective_range, is_substitute, parse_args, replacement::Replacement, SubstituteState,
+};
+use crate::{
+    command::{
+        line::{CommandKind, CommandLine},
+        parser::{self, AddressContext, LineRange},
+    },
+    fold,
+    input::{data::RowBuffer, InputMode},
+    options::Options,
+    search::SearchState,
+    window::{ActiveView, Windows},
+};
+
+/// A row as it would look after a substitution that is still being typed.
+pub struct PreviewRow {
+    pub(crate) contents: String,
+    /// The byte ranges of `contents` that were replaced.
+    pub(crate) replaced: Vec<Range<usize>>,
+}
+
+/// The visible rows a substitution that is still being typed would change, shown in place of the
+/// actual rows until the command line is submitted or cancelled.
+#[derive(Resource, Default)]
+pub struct SubstitutePreview {
+    rows: HashMap<usize, PreviewRow>,
+    /// The last line the preview was built for, so unchanged lines aren't previewed again.
+    line: String,
+}
+
+impl SubstitutePreview {
+    pub fn row(&self, y: usize) -> Option<&PreviewRow> {
+        self.rows.get(&y)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn preview(
+    mut preview: ResMut<SubstitutePreview>,
+    input_mode: Res<InputMode>,
+    command_line: Res<CommandLine>,
+    rows: Res<RowBuffer>,
+    view: ActiveView,
+    windows: Res<Windows>,
+    search: Res<SearchState>,
+    state: Res<SubstituteState>,
+    options: Res<Options>,
+) {
+    let active = *input_mode == InputMode::Command
+        && command_line.kind() == CommandKind::Ex
+        && options.inccommand;
+
+    if !active {
+        if !preview.line.is_empty() {
+            *preview = SubstitutePreview::default();
+        }
+
+        return;
+    }
+
+    if preview.line == command_line.content() {
+        return;
+    }
+
+    preview.line = command_line.content().to_string();
+    preview.rows.clear();
+
+    // anything that isn't a (valid) substitution yet simply isn't previewed
+    let Ok(parsed) = parser::parse(command_line.content()) else {
+        return;
+    };
+
+    if !is_substitute(&parsed.name) {
+        return;
+    }
+
+    let Ok(args) = parse_args(&parsed.args, state.last.as_ref()) else {
+        return;
+    };
+
+    let Ok((_, regex)) = compile(&args, search.pattern(), &options) else {
+        return;
+    };
+
+    let range = match &parsed.range {
+        Some(range) => range.resolve(&AddressContext {
+            rows: &rows,
+            cursor: view.get().position.y,
+            last_pattern: search.pattern(),
+            options: &options,
+        }),
+        None => Ok(LineRange::single(view.get().position.y)),
+    };
+
+    let Ok(range) = range else {
+        return;
+    };
+
+    let range = effective_range(range, args.count, rows.rows_count());
+    let replacement = Replacement::parse(&args.replacement);
+
+    // only the rows that are visible in the window are previewed, no matter how large the range is
+    let visible: Vec<usize> = fold::lines(&rows, view.get().offset.row)
+        .take(windows.area().rows)
+        .collect();
+
+    for y in range.rows().filter(|y| visible.contains(y)) {
+        let contents = rows.get_row_at(y).contents();
+
+        let mut previewed = String::new();
+        let mut replaced = Vec::new();
+        let mut last = 0;
+
+        for captures in regex.captures_iter(contents) {
+            let found = captures.get(0).unwrap();
+
+            // counting matches doesn't change anything, so the matches themselves are shown
+            let expanded = match args.flags.count_only {
+                true => found.as_str().to_string(),
+                false => replacement.expand(&captures).replace('\n', "^M"),
+            };
+
+            previewed.push_str(&contents[last..found.start()]);
+            replaced.push(previewed.len()..previewed.len() + expanded.len());
+            previewed.push_str(&expanded);
+            last = found.end();
+
+            if !args.flags.global {
+                break;
+            }
+        }
+
+        if replaced.is_empty() {
+            continue;
+        }
+
+        previewed.push_str(&contents[last..]);
+        preview.rows.insert(
+            y,
+            PreviewRow {
+                contents: previewed,
+                replaced,
+            },
+        );
+    }
+}
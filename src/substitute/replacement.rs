@@ -0,0 +1,202 @@
+use regex::Captures;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CaseChange {
+    /// `\u`, uppercases the next character.
+    NextUpper,
+    /// `\l`, lowercases the next character.
+    NextLower,
+    /// `\U`, uppercases everything up until `\E`.
+    Upper,
+    /// `\L`, lowercases everything up until `\E`.
+    Lower,
+    /// `\E` or `\e`
+    End,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Literal(String),
+    /// `&`, `\0` to `\9`, `$0` to `$9` or `${10}`.
+    Group(usize),
+    /// `${name}`
+    Named(String),
+    /// `\r` or `\n`, splits the row in two.
+    Newline,
+    Case(CaseChange),
+}
+
+/// The replacement of a substitution, e.g. `\u$1-\2` in `:s/(\w+) (\w+)/\u$1-\2/`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Replacement {
+    parts: Vec<Part>,
+}
+
+impl Replacement {
+    pub fn parse(input: &str) -> Self {
+        let mut parts = Vec::new();
+        let mut chars = input.chars().peekable();
+
+        let push_literal = |parts: &mut Vec<Part>, char: char| match parts.last_mut() {
+            Some(Part::Literal(literal)) => literal.push(char),
+            _ => parts.push(Part::Literal(char.to_string())),
+        };
+
+        while let Some(current) = chars.next() {
+            match current {
+                '\\' => match chars.next() {
+                    Some(digit @ '0'..='9') => {
+                        parts.push(Part::Group(digit.to_digit(10).unwrap() as usize))
+                    }
+                    Some('n' | 'r') => parts.push(Part::Newline),
+                    Some('t') => push_literal(&mut parts, '\t'),
+                    Some('u') => parts.push(Part::Case(CaseChange::NextUpper)),
+                    Some('l') => parts.push(Part::Case(CaseChange::NextLower)),
+                    Some('U') => parts.push(Part::Case(CaseChange::Upper)),
+                    Some('L') => parts.push(Part::Case(CaseChange::Lower)),
+                    Some('E' | 'e') => parts.push(Part::Case(CaseChange::End)),
+                    // any other escaped character is taken literally, including `\&` and `\\`
+                    Some(escaped) => push_literal(&mut parts, escaped),
+                    None => push_literal(&mut parts, '\\'),
+                },
+                '&' => parts.push(Part::Group(0)),
+                '$' => match chars.peek() {
+                    Some('$') => {
+                        chars.next();
+                        push_literal(&mut parts, '$');
+                    }
+                    Some(digit @ '0'..='9') => {
+                        parts.push(Part::Group(digit.to_digit(10).unwrap() as usize));
+                        chars.next();
+                    }
+                    Some('{') => {
+                        chars.next();
+
+                        let name: String = chars.by_ref().take_while(|it| *it != '}').collect();
+
+                        parts.push(match name.parse() {
+                            Ok(group) => Part::Group(group),
+                            Err(_) => Part::Named(name),
+                        });
+                    }
+                    _ => push_literal(&mut parts, '$'),
+                },
+                current => push_literal(&mut parts, current),
+            }
+        }
+
+        Self { parts }
+    }
+
+    /// Expands the replacement for a single match.
+    pub fn expand(&self, captures: &Captures) -> String {
+        let mut expanded = String::new();
+        let mut next: Option<CaseChange> = None;
+        let mut ongoing: Option<CaseChange> = None;
+
+        let mut push = |text: &str, next: &mut Option<CaseChange>, ongoing: Option<CaseChange>| {
+            for char in text.chars() {
+                match next.take().or(ongoing) {
+                    Some(CaseChange::NextUpper | CaseChange::Upper) => {
+                        expanded.extend(char.to_uppercase())
+                    }
+                    Some(CaseChange::NextLower | CaseChange::Lower) => {
+                        expanded.extend(char.to_lowercase())
+                    }
+                    _ => expanded.push(char),
+                }
+            }
+        };
+
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => push(literal, &mut next, ongoing),
+                Part::Group(group) => push(
+                    captures.get(*group).map_or("", |it| it.as_str()),
+                    &mut next,
+                    ongoing,
+                ),
+                Part::Named(name) => push(
+                    captures.name(name).map_or("", |it| it.as_str()),
+                    &mut next,
+                    ongoing,
+                ),
+                Part::Newline => push("\n", &mut None, None),
+                Part::Case(change @ (CaseChange::NextUpper | CaseChange::NextLower)) => {
+                    next = Some(*change)
+                }
+                Part::Case(change @ (CaseChange::Upper | CaseChange::Lower)) => {
+                    ongoing = Some(*change)
+                }
+                Part::Case(CaseChange::End) => ongoing = None,
+            }
+        }
+
+        expanded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::*;
+
+    fn expand(pattern: &str, replacement: &str, text: &str) -> String {
+        let regex = Regex::new(pattern).unwrap();
+        Replacement::parse(replacement).expand(&regex.captures(text).unwrap())
+    }
+
+    #[test]
+    fn parses_groups_and_literals() {
+        assert_eq!(
+            Replacement::parse(r"a&\1$2${3}${name}\&$$\\x\t").parts,
+            [
+                Part::Literal(String::from("a")),
+                Part::Group(0),
+                Part::Group(1),
+                Part::Group(2),
+                Part::Group(3),
+                Part::Named(String::from("name")),
+                Part::Literal(String::from("&$\\x\t")),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_newlines_and_case_changes() {
+        assert_eq!(
+            Replacement::parse(r"\r\n\u\l\U\L\E\e$").parts,
+            [
+                Part::Newline,
+                Part::Newline,
+                Part::Case(CaseChange::NextUpper),
+                Part::Case(CaseChange::NextLower),
+                Part::Case(CaseChange::Upper),
+                Part::Case(CaseChange::Lower),
+                Part::Case(CaseChange::End),
+                Part::Case(CaseChange::End),
+                Part::Literal(String::from("$")),
+            ]
+        );
+    }
+
+    #[test]
+    fn expands_groups() {
+        assert_eq!(
+            expand(r"(\w+) (\w+)", r"$2 \1 [&]", "foo bar"),
+            "bar foo [foo bar]"
+        );
+        assert_eq!(expand(r"(?P<word>\w+)", "<${word}>", "foo"), "<foo>");
+        assert_eq!(expand(r"(a)|(b)", r"[\2]", "a"), "[]");
+        assert_eq!(expand("a", r"x\ny", "a"), "x\ny");
+    }
+
+    #[test]
+    fn expands_case_changes() {
+        assert_eq!(expand(r"(\w+) (\w+)", r"\u$1-\2", "foo bar"), "Foo-bar");
+        assert_eq!(expand(r"(\w+) (\w+)", r"\U$1\E $2", "foo bar"), "FOO bar");
+        assert_eq!(expand(r"(\w+)", r"\L\u$1", "FOO"), "Foo");
+        assert_eq!(expand(r"(\w+)", r"\l$1", "FOO"), "fOO");
+    }
+}
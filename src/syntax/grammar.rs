@@ -0,0 +1,291 @@
+//! Grammars are read from a line based format, every line is a directive made up of fields
+//! separated by whitespace, so patterns match a space with `\s` or `\x20`. Empty lines and lines
+//! starting with `#` are skipped.
+//!
+//! - `context {name}` starts the context the following rules are added to, rules before the first
+//!   `context` belong to `main`, which is the context rows start out in.
+//! - `keyword {scope} {words...}` highlights any of the whole `words`.
+//! - `match {scope} {pattern}` highlights the matches of `pattern`, or only its first group when
+//!   it has one.
+//! - `region {scope} {start} {end} [context]` highlights everything from a match of `start` up to
+//!   and including a match of `end`, which can be on a later row. The rules of `context` apply in
+//!   between, which can start regions of their own.
+
+use std::ops::Range;
+
+use regex::Regex;
+
+/// The regions a position is nested in, innermost last. Rows start out in the state the row
+/// above them ended in.
+pub type State = Vec<usize>;
+
+/// A highlighted range of bytes of a row.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub(crate) range: Range<usize>,
+    pub(crate) scope: usize,
+}
+
+enum RuleKind {
+    Match,
+    /// Starts the region at this index of [Grammar::regions].
+    Region(usize),
+}
+
+struct Rule {
+    scope: usize,
+    regex: Regex,
+    kind: RuleKind,
+}
+
+struct Region {
+    scope: usize,
+    end: Regex,
+    /// Index into [Grammar::contexts], `None` for regions nothing is highlighted in.
+    context: Option<usize>,
+}
+
+pub struct Grammar {
+    /// The rules of every context, the first one is `main`.
+    contexts: Vec<Vec<Rule>>,
+    regions: Vec<Region>,
+    scopes: Vec<String>,
+}
+
+/// A rule, or the end of the innermost region, matched somewhere in a row.
+enum Token {
+    Rule(usize),
+    End,
+}
+
+impl Grammar {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut context_names = vec![String::from("main")];
+        let mut grammar = Grammar {
+            contexts: vec![Vec::new()],
+            regions: Vec::new(),
+            scopes: Vec::new(),
+        };
+
+        // regions can refer to contexts defined further down, they're resolved at the end
+        let mut region_contexts = Vec::new();
+        let mut current = 0;
+
+        for (index, line) in source.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let error = |message: String| format!("line {}: {message}", index + 1);
+            let regex = |pattern: &str| {
+                Regex::new(pattern).map_err(|_| error(format!("invalid pattern {pattern}")))
+            };
+
+            let (scope, kind) = match fields.as_slice() {
+                [] => continue,
+                [first, ..] if first.starts_with('#') => continue,
+                ["context", name] => {
+                    current = match context_names.iter().position(|it| it == name) {
+                        Some(known) => known,
+                        None => {
+                            context_names.push(name.to_string());
+                            grammar.contexts.push(Vec::new());
+                            context_names.len() - 1
+                        }
+                    };
+                    continue;
+                }
+                ["keyword", scope, words @ ..] if !words.is_empty() => {
+                    let words: Vec<String> = words.iter().map(|it| regex::escape(it)).collect();
+                    let pattern = format!(r"\b(?:{})\b", words.join("|"));
+
+                    (scope, (regex(&pattern)?, RuleKind::Match))
+                }
+                ["match", scope, pattern] => (scope, (regex(pattern)?, RuleKind::Match)),
+                ["region", scope, start, end, context @ ..] if context.len() <= 1 => {
+                    let scope_index = grammar.scope(scope);
+
+                    grammar.regions.push(Region {
+                        scope: scope_index,
+                        end: regex(end)?,
+                        context: None,
+                    });
+                    region_contexts.push((index, context.first().map(|it| it.to_string())));
+
+                    let region = grammar.regions.len() - 1;
+                    (scope, (regex(start)?, RuleKind::Region(region)))
+                }
+                _ => return Err(error(format!("invalid directive {line}"))),
+            };
+
+            let (regex, kind) = kind;
+            let scope = grammar.scope(scope);
+            grammar.contexts[current].push(Rule { scope, regex, kind });
+        }
+
+        for (region, (line, name)) in region_contexts.into_iter().enumerate() {
+            let Some(name) = name else {
+                continue;
+            };
+
+            let context = context_names
+                .iter()
+                .position(|it| *it == name)
+                .ok_or_else(|| format!("line {}: unknown context {name}", line + 1))?;
+
+            grammar.regions[region].context = Some(context);
+        }
+
+        Ok(grammar)
+    }
+
+    /// The index of the scope called `name`, which is added if it isn't known yet.
+    fn scope(&mut self, name: &str) -> usize {
+        match self.scopes.iter().position(|it| it == name) {
+            Some(index) => index,
+            None => {
+                self.scopes.push(name.to_string());
+                self.scopes.len() - 1
+            }
+        }
+    }
+
+    pub fn scope_name(&self, scope: usize) -> &str {
+        &self.scopes[scope]
+    }
+
+    /// The scope of the region at this index of a [State].
+    pub fn region_scope(&self, region: usize) -> usize {
+        self.regions[region].scope
+    }
+
+    /// Highlights a row that starts out in `state`, along with the state the row ends in.
+    pub fn highlight(&self, line: &str, state: &State) -> (Vec<Span>, State) {
+        self.run(line, state, usize::MAX)
+    }
+
+    /// The state at byte `x` of a row that starts out in `state`. A region is only left once the
+    /// end of it starts before `x`, so a position right in front of a closing quote is still in
+    /// the string.
+    pub fn state_at(&self, line: &str, state: &State, x: usize) -> State {
+        self.run(line, state, x).1
+    }
+
+    /// Highlights a row up to the first token starting at or after byte `limit`.
+    fn run(&self, line: &str, state: &State, limit: usize) -> (Vec<Span>, State) {
+        let mut state = state.clone();
+        let mut spans = Vec::new();
+        let mut position = 0;
+
+        // everything in a region that isn't highlighted by one of its rules gets its scope
+        let fill = |spans: &mut Vec<Span>, state: &State, range: Range<usize>| {
+            if let Some(&region) = state.last() {
+                push_span(spans, range, self.regions[region].scope);
+            }
+        };
+
+        loop {
+            let rules = match state.last() {
+                Some(&region) => self.regions[region]
+                    .context
+                    .map_or(&[][..], |it| self.contexts[it].as_slice()),
+                None => self.contexts[0].as_slice(),
+            };
+
+            let Some((token, found)) = self.next_token(line, position, &state, rules) else {
+                fill(&mut spans, &state, position..line.len().min(limit));
+                break;
+            };
+
+            if found.start >= limit {
+                fill(&mut spans, &state, position..limit);
+                break;
+            }
+
+            fill(&mut spans, &state, position..found.start);
+
+            match token {
+                Token::End => {
+                    let region = state.pop().expect("only regions have an end");
+                    push_span(&mut spans, found.clone(), self.regions[region].scope);
+                }
+                Token::Rule(index) => {
+                    let rule = &rules[index];
+
+                    match rule.kind {
+                        RuleKind::Match => {
+                            let styled = rule
+                                .regex
+                                .captures_at(line, found.start)
+                                .and_then(|it| it.get(1))
+                                .map_or(found.clone(), |it| it.range());
+
+                            // the text around the group is still part of the region it's in
+                            fill(&mut spans, &state, found.start..styled.start);
+                            push_span(&mut spans, styled.clone(), rule.scope);
+                            fill(&mut spans, &state, styled.end..found.end);
+                        }
+                        RuleKind::Region(region) => {
+                            push_span(&mut spans, found.clone(), rule.scope);
+                            state.push(region);
+                        }
+                    }
+                }
+            }
+
+            position = found.end;
+        }
+
+        (spans, state)
+    }
+
+    /// The first match at or after `position`, either the end of the innermost region or one of
+    /// `rules`. The end wins over a rule starting at the same byte, and earlier rules win over
+    /// later ones. Only the end of a region can be empty, like `$`.
+    fn next_token(
+        &self,
+        line: &str,
+        position: usize,
+        state: &State,
+        rules: &[Rule],
+    ) -> Option<(Token, Range<usize>)> {
+        let end = state.last().and_then(|&region| {
+            self.regions[region]
+                .end
+                .find_at(line, position)
+                .map(|it| (Token::End, it.range()))
+        });
+
+        let rules = rules.iter().enumerate().filter_map(|(index, rule)| {
+            first_match(&rule.regex, line, position).map(|it| (Token::Rule(index), it))
+        });
+
+        end.into_iter()
+            .chain(rules)
+            .min_by_key(|(_, range)| range.start)
+    }
+}
+
+/// The first match of `regex` at or after `position` that isn't empty.
+fn first_match(regex: &Regex, line: &str, mut position: usize) -> Option<Range<usize>> {
+    while let Some(found) = regex.find_at(line, position) {
+        if !found.is_empty() {
+            return Some(found.range());
+        }
+
+        position = found.start() + line[found.start()..].chars().next()?.len_utf8();
+    }
+
+    None
+}
+
+/// Adds a span, merged into the previous one when it continues it with the same scope.
+fn push_span(spans: &mut Vec<Span>, range: Range<usize>, scope: usize) {
+    if range.is_empty() {
+        return;
+    }
+
+    match spans.last_mut() {
+        Some(last) if last.scope == scope && last.range.end == range.start => {
+            last.range.end = range.end
+        }
+        _ => spans.push(Span { range, scope }),
+    }
+}
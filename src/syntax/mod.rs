@@ -0,0 +1,368 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    path::Path,
+};
+
+use bevy_ecs::prelude::*;
+
+use crate::{
+    command::{CommandError, CommandRegistry, Invocation},
+    filetype::FileType,
+    fold,
+    input::data::RowBuffer,
+    options::Options,
+    output,
+    status::StatusBuffer,
+    window::{View, Windows},
+    SystemType,
+};
+
+use self::grammar::{Grammar, Span, State};
+
+pub mod grammar;
+
+pub fn init(world: &mut World, schedule: &mut Schedule) {
+    world.insert_resource(Highlighter::default());
+    world
+        .resource_mut::<CommandRegistry>()
+        .register("syntax", 2, syntax);
+
+    schedule.add_systems(
+        (load_grammar, highlight_rows)
+            .chain()
+            .before(output::draw_rows)
+            .in_set(SystemType::Output),
+    );
+}
+
+/// The grammars that come with the editor, by filetype.
+const BUILTIN: &[(&str, &str)] = &[("rust", include_str!("rust.syntax"))];
+
+/// Highlights the rows of the buffer with the grammar of its filetype. The spans of every row are
+/// kept along with the state it starts and ends in, so after an edit only the rows from the
+/// changed one onward are highlighted again, and only as far down as they're shown.
+#[derive(Resource, Default)]
+pub struct Highlighter {
+    grammar: Option<Grammar>,
+    /// The filetype and `syntaxdir` the grammar was loaded for, it's loaded again once either
+    /// of them changes.
+    source: Option<(String, String)>,
+    /// The highlighted rows, `None` for a row that was inserted since and isn't highlighted yet.
+    lines: Vec<Option<Line>>,
+    /// How many rows the buffer had when it was last highlighted.
+    rows: usize,
+}
+
+/// A highlighted row.
+struct Line {
+    hash: u64,
+    start: State,
+    end: State,
+    spans: Vec<Span>,
+}
+
+impl Highlighter {
+    fn set_grammar(&mut self, grammar: Option<Grammar>) {
+        self.grammar = grammar;
+        self.lines.clear();
+    }
+
+    pub fn grammar(&self) -> Option<&Grammar> {
+        self.grammar.as_ref()
+    }
+
+    fn line(&self, y: usize) -> Option<&Line> {
+        self.lines.get(y).and_then(Option::as_ref)
+    }
+
+    /// The spans of row `y`, empty when it hasn't been highlighted.
+    pub fn spans(&self, y: usize) -> &[Span] {
+        self.line(y).map_or(&[], |it| it.spans.as_slice())
+    }
+
+    /// Highlights every row up to and including row `last`, reusing the rows that still have the
+    /// same contents and start out in the same state as when they were last highlighted. Returns
+    /// how many rows had to be highlighted again.
+    ///
+    /// Rows that were inserted or removed since are taken to be at the first row that changed,
+    /// the highlighted rows below it move along with them so they can still be reused.
+    fn update(&mut self, rows: &RowBuffer, last: usize) -> usize {
+        let Some(grammar) = &self.grammar else {
+            return 0;
+        };
+
+        let count = rows.rows_count();
+        let mut shift = count as isize - self.rows as isize;
+        self.rows = count;
+
+        let mut state = State::new();
+        let mut highlighted = 0;
+
+        for y in 0..count.min(last.saturating_add(1)) {
+            let contents = rows.get_row_at(y).contents();
+            let hash = hash(contents);
+
+            if shift != 0 && self.line(y).map(|it| it.hash) != Some(hash) {
+                shift_lines(&mut self.lines, y, shift);
+                shift = 0;
+            }
+
+            match self.line(y) {
+                Some(line) if line.hash == hash && line.start == state => {}
+                _ => {
+                    let (spans, end) = grammar.highlight(contents, &state);
+                    let line = Some(Line {
+                        hash,
+                        start: state,
+                        end,
+                        spans,
+                    });
+
+                    highlighted += 1;
+
+                    match y < self.lines.len() {
+                        true => self.lines[y] = line,
+                        false => self.lines.push(line),
+                    }
+                }
+            }
+
+            state = self.line(y).map_or_else(State::new, |it| it.end.clone());
+        }
+
+        // the rows below the shown ones can't be lined up with the buffer anymore when rows were
+        // inserted or removed further down
+        match shift {
+            0 => self.lines.truncate(count),
+            _ => self.lines.truncate(last.saturating_add(1).min(count)),
+        }
+
+        highlighted
+    }
+
+    /// Whether every row up to and including row `last` has been highlighted.
+    fn is_highlighted(&self, last: usize) -> bool {
+        self.lines.len() > last && self.lines[..=last].iter().all(Option::is_some)
+    }
+
+    /// Whether byte `x` of row `y` is in a string or a comment, where pairs aren't completed.
+    pub fn in_text(&self, rows: &RowBuffer, x: usize, y: usize) -> bool {
+        let (Some(grammar), Some(line)) = (&self.grammar, self.line(y)) else {
+            return false;
+        };
+
+        let contents = rows.get_row_at(y).contents();
+
+        grammar
+            .state_at(contents, &line.start, x)
+            .iter()
+            .any(|&region| {
+                matches!(
+                    grammar.scope_name(grammar.region_scope(region)),
+                    "string" | "comment"
+                )
+            })
+    }
+}
+
+/// Moves the highlighted rows from row `y` on down by `shift` rows, or up when it's negative,
+/// dropping the ones in between.
+fn shift_lines(lines: &mut Vec<Option<Line>>, y: usize, shift: isize) {
+    if y >= lines.len() {
+        return;
+    }
+
+    match shift > 0 {
+        true => {
+            let inserted = std::iter::repeat_with(|| None).take(shift as usize);
+            lines.splice(y..y, inserted);
+        }
+        false => {
+            let end = y.saturating_add(shift.unsigned_abs()).min(lines.len());
+            lines.drain(y..end);
+        }
+    }
+}
+
+fn hash(contents: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The grammar for `filetype`, from the `syntaxdir` directory when it has a `{filetype}.syntax`
+/// file and otherwise a built-in one.
+fn find_grammar(filetype: &str, directory: &str) -> Result<Option<Grammar>, String> {
+    if !directory.is_empty() {
+        let path = Path::new(directory).join(format!("{filetype}.syntax"));
+
+        if path.is_file() {
+            return read_grammar(&path).map(Some);
+        }
+    }
+
+    BUILTIN
+        .iter()
+        .find(|(name, _)| *name == filetype)
+        .map(|(_, source)| Grammar::parse(source))
+        .transpose()
+}
+
+fn read_grammar(path: &Path) -> Result<Grammar, String> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|_| format!("Can't read grammar {}", path.display()))?;
+
+    Grammar::parse(&source).map_err(|error| format!("{}: {error}", path.display()))
+}
+
+/// Loads the grammar for the filetype once it, or `syntaxdir`, changes.
+pub fn load_grammar(
+    mut highlighter: ResMut<Highlighter>,
+    filetype: Res<FileType>,
+    options: Res<Options>,
+    mut status: ResMut<StatusBuffer>,
+) {
+    let source = (filetype.name().to_string(), options.syntaxdir.clone());
+
+    if highlighter.source.as_ref() == Some(&source) {
+        return;
+    }
+
+    match find_grammar(&source.0, &source.1) {
+        Ok(grammar) => highlighter.set_grammar(grammar),
+        Err(error) => {
+            highlighter.set_grammar(None);
+            status.error(error);
+        }
+    }
+
+    highlighter.source = Some(source);
+}
+
+/// Highlights the rows that are about to be drawn in any of the windows, along with every row
+/// above them, since those decide which state the shown ones start out in.
+pub fn highlight_rows(
+    mut highlighter: ResMut<Highlighter>,
+    rows: Res<RowBuffer>,
+    windows: Res<Windows>,
+    views: Query<&View>,
+    options: Res<Options>,
+) {
+    if !options.syntax {
+        highlighter.lines.clear();
+        return;
+    }
+
+    let last = windows
+        .areas()
+        .iter()
+        .filter_map(|(window, area)| {
+            let top = views.get(*window).map_or(0, |it| it.offset.row);
+
+            fold::lines(&rows, top).take(area.rows).last()
+        })
+        .max();
+
+    let Some(last) = last else {
+        return;
+    };
+
+    // nothing to do while the buffer is left alone and the shown rows are highlighted already
+    if rows.is_changed() || !highlighter.is_highlighted(last) {
+        highlighter.update(&rows, last);
+    }
+}
+
+/// `:syntax {file}` highlights the buffer with the grammar in `file`, until the filetype changes.
+fn syntax(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let path = match invocation.arguments().as_slice() {
+        [path] => path.clone(),
+        [] => return Err(CommandError::InvalidArgument(String::from("syntax"))),
+        [_, rest @ ..] => return Err(CommandError::TrailingCharacters(rest.join(" "))),
+    };
+
+    let grammar = read_grammar(Path::new(&path)).map_err(CommandError::InvalidArgument)?;
+    world
+        .resource_mut::<Highlighter>()
+        .set_grammar(Some(grammar));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(rows: &[&str]) -> RowBuffer {
+        let mut buffer = RowBuffer::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        buffer
+    }
+
+    fn highlighter() -> Highlighter {
+        let mut highlighter = Highlighter::default();
+        highlighter.set_grammar(find_grammar("rust", "").unwrap());
+        highlighter
+    }
+
+    const TEXT: [&str; 5] = ["fn a() {}", "let b = 1;", "/* c", "d */", "let e = \"f\";"];
+
+    #[test]
+    fn only_highlights_the_changed_rows() {
+        let mut rows = buffer(&TEXT);
+        let mut highlighter = highlighter();
+
+        assert_eq!(highlighter.update(&rows, 4), 5);
+        assert_eq!(highlighter.update(&rows, 4), 0);
+
+        rows.set_row(1, "let b = 2;");
+        assert_eq!(highlighter.update(&rows, 4), 1);
+    }
+
+    #[test]
+    fn highlighted_rows_move_along_with_inserted_and_removed_rows() {
+        let mut rows = buffer(&TEXT);
+        let mut highlighter = highlighter();
+        highlighter.update(&rows, 4);
+
+        let comment = highlighter.spans(3).to_vec();
+
+        rows.insert_row(1, "let x = 0;");
+        rows.insert_row(1, "let y = 0;");
+        assert_eq!(highlighter.update(&rows, 6), 2);
+        assert_eq!(highlighter.spans(5), comment);
+
+        rows.remove_row(0);
+        assert_eq!(highlighter.update(&rows, 5), 0);
+        assert_eq!(highlighter.spans(4), comment);
+    }
+
+    #[test]
+    fn rows_after_a_changed_state_are_highlighted_again() {
+        let mut rows = buffer(&TEXT);
+        let mut highlighter = highlighter();
+        highlighter.update(&rows, 4);
+
+        // opening a comment changes the state every row below it starts out in
+        rows.set_row(0, "/* fn a() {}");
+        assert_eq!(highlighter.update(&rows, 4), 5);
+
+        assert_eq!(highlighter.update(&buffer(&[]), 0), 0);
+        assert!(highlighter.spans(0).is_empty());
+    }
+
+    #[test]
+    fn only_the_shown_rows_count_as_highlighted() {
+        let rows = buffer(&TEXT);
+        let mut highlighter = highlighter();
+
+        highlighter.update(&rows, 1);
+        assert!(highlighter.is_highlighted(1));
+        assert!(!highlighter.is_highlighted(4));
+    }
+}
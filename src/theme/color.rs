@@ -0,0 +1,282 @@
+use crossterm::style::Color;
+
+/// How many colors the terminal can show.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl ColorDepth {
+    /// Guesses the depth from `COLORTERM` and `TERM`, a terminal that says nothing about itself
+    /// is trusted with every color.
+    pub fn detect() -> Self {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+
+        Self::from_env(&colorterm, &term)
+    }
+
+    fn from_env(colorterm: &str, term: &str) -> Self {
+        match (colorterm, term) {
+            ("truecolor" | "24bit", _) => ColorDepth::TrueColor,
+            (_, "") => ColorDepth::TrueColor,
+            (_, term) if term.contains("truecolor") || term.contains("direct") => {
+                ColorDepth::TrueColor
+            }
+            (_, term) if term.contains("256color") => ColorDepth::Ansi256,
+            _ => ColorDepth::Ansi16,
+        }
+    }
+
+    /// The depth of a `colordepth` value, `None` for `auto` and anything that isn't a depth.
+    pub fn from_option(value: &str) -> Option<Self> {
+        match value {
+            "truecolor" | "24bit" => Some(ColorDepth::TrueColor),
+            "256" => Some(ColorDepth::Ansi256),
+            "16" => Some(ColorDepth::Ansi16),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a color of a theme file, which is either `#rrggbb`, an index into the 256 color
+/// palette, or the name of one of the 16 colors like `dark_red`. `none` is no color at all.
+pub fn parse(value: &str) -> Result<Option<Color>, String> {
+    if value.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+
+    if let Some(hex) = value.strip_prefix('#') {
+        let channel = |index: usize| {
+            hex.get(index..index + 2)
+                .and_then(|it| u8::from_str_radix(it, 16).ok())
+        };
+
+        return match (hex.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(Some(Color::Rgb { r, g, b })),
+            _ => Err(format!("Invalid color: {value}")),
+        };
+    }
+
+    if let Ok(index) = value.parse::<u8>() {
+        return Ok(Some(Color::AnsiValue(index)));
+    }
+
+    Color::try_from(value)
+        .map(Some)
+        .map_err(|_| format!("Invalid color: {value}"))
+}
+
+/// The 16 colors along with how xterm shows them by default.
+const ANSI: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0x00, 0x00, 0x00)),
+    (Color::DarkRed, (0xcd, 0x00, 0x00)),
+    (Color::DarkGreen, (0x00, 0xcd, 0x00)),
+    (Color::DarkYellow, (0xcd, 0xcd, 0x00)),
+    (Color::DarkBlue, (0x00, 0x00, 0xee)),
+    (Color::DarkMagenta, (0xcd, 0x00, 0xcd)),
+    (Color::DarkCyan, (0x00, 0xcd, 0xcd)),
+    (Color::Grey, (0xe5, 0xe5, 0xe5)),
+    (Color::DarkGrey, (0x7f, 0x7f, 0x7f)),
+    (Color::Red, (0xff, 0x00, 0x00)),
+    (Color::Green, (0x00, 0xff, 0x00)),
+    (Color::Yellow, (0xff, 0xff, 0x00)),
+    (Color::Blue, (0x5c, 0x5c, 0xff)),
+    (Color::Magenta, (0xff, 0x00, 0xff)),
+    (Color::Cyan, (0x00, 0xff, 0xff)),
+    (Color::White, (0xff, 0xff, 0xff)),
+];
+
+/// The least difference between the brightest and darkest channel for a color to not be a gray.
+const GRAY_CHROMA: u8 = 0x30;
+
+/// The brightest a channel gets in a color that isn't one of the bright colors.
+const BRIGHT: u8 = 0xc0;
+
+/// The levels of every channel in the 6x6x6 color cube of the 256 color palette.
+const CUBE: [u8; 6] = [0x00, 0x5f, 0x87, 0xaf, 0xd7, 0xff];
+
+/// Turns `color` into the closest color the terminal can show at `depth`.
+pub fn reduce(color: Color, depth: ColorDepth) -> Color {
+    match (depth, color) {
+        (ColorDepth::TrueColor, _) => color,
+        (ColorDepth::Ansi256, Color::Rgb { r, g, b }) => Color::AnsiValue(closest_256((r, g, b))),
+        (ColorDepth::Ansi16, Color::Rgb { r, g, b }) => closest_16((r, g, b)),
+        (ColorDepth::Ansi16, Color::AnsiValue(index)) => match ANSI.get(index as usize) {
+            Some((color, _)) => *color,
+            None => closest_16(palette(index)),
+        },
+        _ => color,
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let channel = |a: u8, b: u8| (a as i32 - b as i32).unsigned_abs().pow(2);
+    channel(a.0, b.0) + channel(a.1, b.1) + channel(a.2, b.2)
+}
+
+/// The closest of the 16 colors by hue, which keeps muted colors apart better than the distance
+/// does. Colors without much of a hue are one of the grays.
+fn closest_16((r, g, b): (u8, u8, u8)) -> Color {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+
+    if max - min < GRAY_CHROMA {
+        return match (r as u32 + g as u32 + b as u32) / 3 {
+            0x00..=0x3f => Color::Black,
+            0x40..=0x8f => Color::DarkGrey,
+            0x90..=0xcf => Color::Grey,
+            _ => Color::White,
+        };
+    }
+
+    // the channels above the middle of the brightest and darkest make up the hue
+    let middle = ((max as u32 + min as u32) / 2) as u8;
+    let hue = usize::from(r > middle) | usize::from(g > middle) << 1 | usize::from(b > middle) << 2;
+    let bright = match max > BRIGHT {
+        true => 8,
+        false => 0,
+    };
+
+    ANSI[hue + bright].0
+}
+
+/// The closest of the color cube and the grayscale ramp of the 256 color palette, the 16 colors
+/// at its start are left out since terminals tend to change them.
+fn closest_256(rgb: (u8, u8, u8)) -> u8 {
+    let level = |channel: u8| {
+        (0..CUBE.len())
+            .min_by_key(|it| (CUBE[*it] as i32 - channel as i32).abs())
+            .unwrap_or(0) as u8
+    };
+
+    let cube = 16 + 36 * level(rgb.0) + 6 * level(rgb.1) + level(rgb.2);
+
+    let average = (rgb.0 as u32 + rgb.1 as u32 + rgb.2 as u32) / 3;
+    let gray = 232 + (average.saturating_sub(3) / 10).min(23) as u8;
+
+    match distance(palette(gray), rgb) < distance(palette(cube), rgb) {
+        true => gray,
+        false => cube,
+    }
+}
+
+/// How a color of the 256 color palette is shown by default.
+fn palette(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI[index as usize].1,
+        16..=231 => {
+            let index = index - 16;
+            (
+                CUBE[(index / 36) as usize],
+                CUBE[(index / 6 % 6) as usize],
+                CUBE[(index % 6) as usize],
+            )
+        }
+        _ => {
+            let level = 8 + (index - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_the_depth_from_the_environment() {
+        assert_eq!(
+            ColorDepth::from_env("truecolor", "xterm"),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(ColorDepth::from_env("24bit", ""), ColorDepth::TrueColor);
+        assert_eq!(ColorDepth::from_env("", ""), ColorDepth::TrueColor);
+        assert_eq!(
+            ColorDepth::from_env("", "xterm-direct"),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(
+            ColorDepth::from_env("", "xterm-256color"),
+            ColorDepth::Ansi256
+        );
+        assert_eq!(ColorDepth::from_env("", "xterm"), ColorDepth::Ansi16);
+        assert_eq!(ColorDepth::from_env("", "linux"), ColorDepth::Ansi16);
+    }
+
+    #[test]
+    fn reads_the_depth_of_the_option() {
+        assert_eq!(
+            ColorDepth::from_option("24bit"),
+            Some(ColorDepth::TrueColor)
+        );
+        assert_eq!(ColorDepth::from_option("256"), Some(ColorDepth::Ansi256));
+        assert_eq!(ColorDepth::from_option("16"), Some(ColorDepth::Ansi16));
+        assert_eq!(ColorDepth::from_option("auto"), None);
+    }
+
+    #[test]
+    fn parses_every_kind_of_color() {
+        let orange = Color::Rgb {
+            r: 0xff,
+            g: 0x80,
+            b: 0x00,
+        };
+
+        assert_eq!(parse("#ff8000"), Ok(Some(orange)));
+        assert_eq!(parse("208"), Ok(Some(Color::AnsiValue(208))));
+        assert_eq!(parse("dark_red"), Ok(Some(Color::DarkRed)));
+        assert_eq!(parse("NONE"), Ok(None));
+        assert!(parse("#ff80").is_err());
+        assert!(parse("#gg8000").is_err());
+        assert!(parse("orange").is_err());
+    }
+
+    #[test]
+    fn keeps_every_color_with_truecolor() {
+        let color = Color::Rgb {
+            r: 0x12,
+            g: 0x34,
+            b: 0x56,
+        };
+
+        assert_eq!(reduce(color, ColorDepth::TrueColor), color);
+    }
+
+    #[test]
+    fn reduces_truecolor_to_the_256_color_palette() {
+        let rgb = |r, g, b| reduce(Color::Rgb { r, g, b }, ColorDepth::Ansi256);
+
+        assert_eq!(rgb(0xff, 0x00, 0x00), Color::AnsiValue(196));
+        assert_eq!(rgb(0xff, 0x87, 0x00), Color::AnsiValue(208));
+        assert_eq!(rgb(0xf0, 0x8a, 0x10), Color::AnsiValue(208));
+        // grays are closer to the grayscale ramp than to the cube
+        assert_eq!(rgb(0x80, 0x80, 0x80), Color::AnsiValue(244));
+        assert_eq!(reduce(Color::Red, ColorDepth::Ansi256), Color::Red);
+    }
+
+    #[test]
+    fn reduces_truecolor_and_the_256_colors_to_the_16_colors() {
+        let rgb = |r, g, b| reduce(Color::Rgb { r, g, b }, ColorDepth::Ansi16);
+
+        assert_eq!(rgb(0xff, 0x00, 0x00), Color::Red);
+        assert_eq!(rgb(0xa0, 0x00, 0x00), Color::DarkRed);
+        assert_eq!(rgb(0x40, 0x80, 0x40), Color::DarkGreen);
+        assert_eq!(rgb(0x20, 0x20, 0x20), Color::Black);
+        assert_eq!(rgb(0x80, 0x80, 0x80), Color::DarkGrey);
+        assert_eq!(rgb(0xf0, 0xf0, 0xf0), Color::White);
+
+        assert_eq!(reduce(Color::AnsiValue(9), ColorDepth::Ansi16), Color::Red);
+        assert_eq!(
+            reduce(Color::AnsiValue(196), ColorDepth::Ansi16),
+            Color::Red
+        );
+        assert_eq!(
+            reduce(Color::AnsiValue(244), ColorDepth::Ansi16),
+            Color::DarkGrey
+        );
+        assert_eq!(reduce(Color::Blue, ColorDepth::Ansi16), Color::Blue);
+    }
+}
@@ -0,0 +1,190 @@
+use std::{collections::HashMap, path::Path};
+
+use bevy_ecs::prelude::*;
+use crossterm::style::{Attribute, ContentStyle};
+
+use crate::{
+    command::{CommandError, CommandRegistry, Invocation},
+    options::Options,
+    output::buffer::OutputBuffer,
+    status::StatusBuffer,
+    SystemType,
+};
+
+use self::color::ColorDepth;
+
+pub mod color;
+
+pub fn init(world: &mut World, schedule: &mut Schedule) {
+    world.insert_resource(Theme::default());
+    world
+        .resource_mut::<CommandRegistry>()
+        .register("colorscheme", 4, colorscheme);
+
+    schedule.add_systems(set_color_depth.in_set(SystemType::Output));
+}
+
+/// The theme the editor starts out with.
+const DEFAULT: &str = include_str!("default.theme");
+
+/// The name of the theme the editor starts out with.
+const DEFAULT_NAME: &str = "default";
+
+/// The most links followed to find the style of a group, links going around in a circle end up
+/// without a style.
+const MAX_LINKS: usize = 16;
+
+/// The style of a highlight group, or the group it's styled like.
+#[derive(Clone)]
+enum Group {
+    Style(ContentStyle),
+    Link(String),
+}
+
+/// The styles of the named highlight groups everything on screen is drawn with, like `Search`
+/// or `StatusLine`. Text in a syntax scope is drawn with the group of the same name, starting
+/// with an uppercase letter.
+#[derive(Resource)]
+pub struct Theme {
+    name: String,
+    groups: HashMap<String, Group>,
+    /// The color depth of the terminal, as far as it tells, which is used unless `colordepth`
+    /// says otherwise.
+    detected: ColorDepth,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            name: String::from(DEFAULT_NAME),
+            groups: parse(DEFAULT).expect("The default theme is invalid"),
+            detected: ColorDepth::detect(),
+        }
+    }
+}
+
+impl Theme {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The style of `group`, a group the theme doesn't know has the default style.
+    pub fn style(&self, group: &str) -> ContentStyle {
+        let mut group = group;
+
+        for _ in 0..MAX_LINKS {
+            match self.groups.get(group) {
+                Some(Group::Style(style)) => return *style,
+                Some(Group::Link(other)) => group = other,
+                None => break,
+            }
+        }
+
+        ContentStyle::default()
+    }
+
+    /// Switches to the theme called `name`, which is read from `{themedir}/{name}.theme`. Its
+    /// groups are applied on top of the default theme, so the groups it leaves out keep their
+    /// default style.
+    fn load(&mut self, name: &str, themedir: &str) -> Result<(), String> {
+        let path = Path::new(themedir).join(format!("{name}.theme"));
+
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) if !themedir.is_empty() => source,
+            _ if name == DEFAULT_NAME => String::new(),
+            _ => return Err(format!("Can't find theme {name}")),
+        };
+
+        let mut groups = parse(DEFAULT).expect("The default theme is invalid");
+        groups.extend(parse(&source).map_err(|error| format!("{}: {error}", path.display()))?);
+
+        self.groups = groups;
+        self.name = name.to_string();
+        Ok(())
+    }
+}
+
+/// The highlight group text in `scope` is drawn with, `string` is drawn with `String`.
+pub fn scope_group(scope: &str) -> String {
+    let mut chars = scope.chars();
+
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Parses the groups of a theme file. Every line styles a group with `{Group} fg={color}
+/// bg={color}`, along with any of `bold`, `italic`, `underline` and `reverse`, or styles it like
+/// another group with `link {Group} {Other}`. Lines starting with `#` are comments.
+fn parse(source: &str) -> Result<HashMap<String, Group>, String> {
+    let mut groups = HashMap::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let error = |message: String| format!("line {}: {message}", index + 1);
+
+        let (name, group) = match fields.as_slice() {
+            [] => continue,
+            [first, ..] if first.starts_with('#') => continue,
+            ["link", name, other] => (name, Group::Link(other.to_string())),
+            ["link", ..] => return Err(error(String::from("link needs two groups"))),
+            [name, attributes @ ..] => {
+                let mut style = ContentStyle::default();
+
+                for attribute in attributes {
+                    match attribute.split_once('=') {
+                        Some(("fg", value)) => {
+                            style.foreground_color = color::parse(value).map_err(error)?
+                        }
+                        Some(("bg", value)) => {
+                            style.background_color = color::parse(value).map_err(error)?
+                        }
+                        None => style.attributes.set(match *attribute {
+                            "bold" => Attribute::Bold,
+                            "italic" => Attribute::Italic,
+                            "underline" => Attribute::Underlined,
+                            "reverse" => Attribute::Reverse,
+                            _ => return Err(error(format!("unknown attribute {attribute}"))),
+                        }),
+                        Some(_) => return Err(error(format!("unknown attribute {attribute}"))),
+                    }
+                }
+
+                (name, Group::Style(style))
+            }
+        };
+
+        groups.insert(name.to_string(), group);
+    }
+
+    Ok(groups)
+}
+
+/// Tells the buffer how many colors the terminal shows, the colors of the theme are turned into
+/// the closest ones it has once they're sent to the terminal.
+pub fn set_color_depth(theme: Res<Theme>, options: Res<Options>, mut buffer: ResMut<OutputBuffer>) {
+    let depth = ColorDepth::from_option(&options.colordepth).unwrap_or(theme.detected);
+    buffer.set_color_depth(depth);
+}
+
+/// `:colorscheme {name}` switches to another theme, without a name the current theme's name is
+/// shown.
+fn colorscheme(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let name = match invocation.arguments().as_slice() {
+        [name] => name.clone(),
+        [] => {
+            let name = world.resource::<Theme>().name().to_string();
+            world.resource_mut::<StatusBuffer>().info(name);
+
+            return Ok(());
+        }
+        [_, rest @ ..] => return Err(CommandError::TrailingCharacters(rest.join(" "))),
+    };
+
+    let themedir = world.resource::<Options>().themedir.clone();
+    world
+        .resource_mut::<Theme>()
+        .load(&name, &themedir)
+        .map_err(CommandError::InvalidArgument)
+}
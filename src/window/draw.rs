@@ -0,0 +1,118 @@
+use bevy_ecs::prelude::*;
+
+use super::{View, Windows};
+use crate::{
+    input::data::RowBuffer,
+    output::{buffer::OutputBuffer, OutputSize},
+    theme::Theme,
+};
+
+/// Draws the columns in between windows next to each other, and the status line of every
+/// window once the screen is split. The status line shows the buffer's name and where the
+/// window's cursor is, and stands out for the active window.
+pub fn draw_windows(
+    windows: Res<Windows>,
+    views: Query<&View>,
+    rows: Res<RowBuffer>,
+    theme: Res<Theme>,
+    mut buffer: ResMut<OutputBuffer>,
+) {
+    for separator in windows.separators() {
+        for y in separator.y..separator.y + separator.rows {
+            buffer.move_to(separator.x, y);
+            buffer.push_styled("│", theme.style("VertSplit"));
+        }
+    }
+
+    if !windows.is_split() {
+        return;
+    }
+
+    for (window, area) in windows.areas() {
+        let active = *window == windows.active();
+        let cursor = views
+            .get(*window)
+            .map(|it| it.position.clone())
+            .unwrap_or_default();
+
+        let name = match rows.is_modified() {
+            true => format!(" {} [+]", rows.get_buffer_name()),
+            false => format!(" {}", rows.get_buffer_name()),
+        };
+        let location = format!("{},{} ", cursor.y + 1, cursor.x + 1);
+        let gap = area
+            .columns
+            .saturating_sub(name.chars().count() + location.chars().count());
+
+        let line: String = format!("{name}{}{location}", " ".repeat(gap))
+            .chars()
+            .take(area.columns)
+            .collect();
+
+        let group = match active {
+            true => "StatusLine",
+            false => "StatusLineNC",
+        };
+
+        buffer.move_to(area.x, area.y + area.rows);
+        buffer.push_styled(&line, theme.style(group));
+    }
+}
+
+/// Draws the tab line at the top of the screen, with a label for every tab page. A label shows
+/// the amount of windows in a tab page when there's more than one, whether its buffer is
+/// modified and its name. When the labels don't fit, the first ones are left out for the shown
+/// tab page's label to fit.
+pub fn draw_tabline(
+    windows: Res<Windows>,
+    rows: Res<RowBuffer>,
+    size: Res<OutputSize>,
+    theme: Res<Theme>,
+    mut buffer: ResMut<OutputBuffer>,
+) {
+    if !windows.has_tabline() {
+        return;
+    }
+
+    let labels: Vec<String> = windows
+        .tabs()
+        .into_iter()
+        .enumerate()
+        .map(|(index, count)| {
+            let rows = windows.buffer(index, &rows);
+            let modified = match rows.is_modified() {
+                true => "+",
+                false => "",
+            };
+
+            match count {
+                1 if modified.is_empty() => format!(" {} ", rows.get_buffer_name()),
+                1 => format!(" {modified} {} ", rows.get_buffer_name()),
+                count => format!(" {count}{modified} {} ", rows.get_buffer_name()),
+            }
+        })
+        .collect();
+
+    let width = |labels: &[String]| labels.iter().map(|it| it.chars().count()).sum::<usize>();
+    let current = windows.tab();
+    let first = (0..current)
+        .find(|first| width(&labels[*first..=current]) <= size.columns)
+        .unwrap_or(current);
+
+    buffer.move_to(0, 0);
+    let mut left = size.columns;
+
+    for (index, label) in labels.iter().enumerate().skip(first) {
+        let label: String = label.chars().take(left).collect();
+        left -= label.chars().count();
+
+        let group = match index == current {
+            true => "TabLineSel",
+            false => "TabLine",
+        };
+
+        buffer.push_styled(&label, theme.style(group));
+    }
+
+    buffer.push_styled(&" ".repeat(left), theme.style("TabLineFill"));
+}
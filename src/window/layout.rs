@@ -0,0 +1,498 @@
+use bevy_ecs::entity::Entity;
+
+/// A part of the screen, `rows` only counts the rows text is drawn on, the status line of a
+/// window goes right below them.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Area {
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+    pub(crate) columns: usize,
+    pub(crate) rows: usize,
+}
+
+impl Area {
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x..self.x + self.columns).contains(&x) && (self.y..self.y + self.rows).contains(&y)
+    }
+}
+
+/// How the screen is split into windows. A split lays its children out next to each other when
+/// it's `vertical`, and above each other otherwise. Every child has a size, which is the amount
+/// of cells it took up the last time the layout was computed, and is scaled along with the
+/// screen.
+pub enum Node {
+    Window(Entity),
+    Split {
+        vertical: bool,
+        children: Vec<(Node, usize)>,
+    },
+}
+
+/// The fewest rows a window is squeezed down to, a row of text and its status line.
+const MIN_ROWS: usize = 2;
+/// The fewest columns a window is squeezed down to.
+const MIN_COLUMNS: usize = 1;
+
+impl Node {
+    /// Every window, from the top left to the bottom right.
+    pub fn windows(&self) -> Vec<Entity> {
+        match self {
+            Node::Window(window) => vec![*window],
+            Node::Split { children, .. } => children
+                .iter()
+                .flat_map(|(child, _)| child.windows())
+                .collect(),
+        }
+    }
+
+    fn contains(&self, window: Entity) -> bool {
+        match self {
+            Node::Window(it) => *it == window,
+            Node::Split { children, .. } => {
+                children.iter().any(|(child, _)| child.contains(window))
+            }
+        }
+    }
+
+    /// Splits `window`, the `new` window goes above it, or to the left of it when `vertical`,
+    /// and gets half of its space.
+    pub fn split(&mut self, window: Entity, new: Entity, vertical: bool) {
+        if let Node::Window(it) = self {
+            if *it == window {
+                *self = Node::Split {
+                    vertical,
+                    children: vec![(Node::Window(new), 1), (Node::Window(window), 1)],
+                };
+            }
+
+            return;
+        }
+
+        let Node::Split {
+            vertical: direction,
+            children,
+        } = self
+        else {
+            return;
+        };
+
+        let found = children
+            .iter()
+            .position(|(child, _)| matches!(child, Node::Window(it) if *it == window));
+
+        match found {
+            Some(index) if *direction == vertical => {
+                let size = children[index].1;
+                children[index].1 = (size - size / 2).max(1);
+                children.insert(index, (Node::Window(new), (size / 2).max(1)));
+            }
+            _ => children
+                .iter_mut()
+                .for_each(|(child, _)| child.split(window, new, vertical)),
+        }
+    }
+
+    /// Removes `window`, its space goes to the window before it, or the one after it when it's
+    /// the first. A split left with a single child is replaced by it.
+    pub fn remove(&mut self, window: Entity) {
+        let Node::Split { children, .. } = self else {
+            return;
+        };
+
+        if let Some(index) = children
+            .iter()
+            .position(|(child, _)| matches!(child, Node::Window(it) if *it == window))
+        {
+            let (_, size) = children.remove(index);
+
+            if let Some((_, neighbour)) = children.get_mut(index.saturating_sub(1)) {
+                *neighbour += size;
+            }
+        } else {
+            children
+                .iter_mut()
+                .for_each(|(child, _)| child.remove(window));
+        }
+
+        if children.len() == 1 {
+            let (child, _) = children.remove(0);
+            *self = child;
+        }
+    }
+
+    /// Gives every child of every split the same size.
+    pub fn equalize(&mut self) {
+        if let Node::Split { children, .. } = self {
+            for (child, size) in children {
+                *size = 1;
+                child.equalize();
+            }
+        }
+    }
+
+    /// Sets the height of `window`, or its width when `vertical`, to `size` cells. The space is
+    /// taken from or given to the window after it, or the one before it when it's the last.
+    pub fn resize(&mut self, window: Entity, vertical: bool, size: usize) {
+        let Node::Split {
+            vertical: direction,
+            children,
+        } = self
+        else {
+            return;
+        };
+
+        let Some(index) = children
+            .iter()
+            .position(|(child, _)| child.contains(window))
+        else {
+            return;
+        };
+
+        // the closest split in the right direction is the one that's resized
+        let nested = children[index].0.closest_split(window, vertical);
+
+        if *direction != vertical || nested {
+            children[index].0.resize(window, vertical, size);
+            return;
+        }
+
+        if children.len() < 2 {
+            return;
+        }
+
+        let neighbour = match index + 1 < children.len() {
+            true => index + 1,
+            false => index - 1,
+        };
+
+        let least = match vertical {
+            true => MIN_COLUMNS,
+            false => MIN_ROWS,
+        };
+
+        let total = children[index].1 + children[neighbour].1;
+        let size = size.clamp(least, total.saturating_sub(least).max(least));
+
+        children[index].1 = size;
+        children[neighbour].1 = total.saturating_sub(size);
+    }
+
+    /// The fewest cells this node fits in, in columns when `vertical` and in rows otherwise.
+    fn least(&self, vertical: bool) -> usize {
+        match self {
+            Node::Window(_) if vertical => MIN_COLUMNS,
+            Node::Window(_) => MIN_ROWS,
+            Node::Split {
+                vertical: direction,
+                children,
+            } => {
+                let least = children.iter().map(|(child, _)| child.least(vertical));
+
+                match *direction == vertical {
+                    // windows next to each other have a column in between them
+                    true if vertical => least.sum::<usize>() + children.len() - 1,
+                    true => least.sum(),
+                    false => least.max().unwrap_or(0),
+                }
+            }
+        }
+    }
+
+    /// Whether a split below this node in direction `vertical` contains `window`.
+    fn closest_split(&self, window: Entity, vertical: bool) -> bool {
+        match self {
+            Node::Window(_) => false,
+            Node::Split {
+                vertical: direction,
+                children,
+            } => {
+                (*direction == vertical && self.contains(window))
+                    || children
+                        .iter()
+                        .any(|(child, _)| child.closest_split(window, vertical))
+            }
+        }
+    }
+
+    /// Lays out every window in `area`, along with the columns separating windows next to each
+    /// other. When there's more than a single window, every window gets a status line. The size
+    /// of every child is updated to the cells it ends up with.
+    pub fn layout(&mut self, area: Area, separators: &mut Vec<Area>) -> Vec<(Entity, Area)> {
+        let single = matches!(self, Node::Window(_));
+        let mut areas = Vec::new();
+
+        self.place(area, separators, &mut areas);
+
+        if !single {
+            for (_, area) in &mut areas {
+                area.rows = area.rows.saturating_sub(1);
+            }
+        }
+
+        areas
+    }
+
+    fn place(&mut self, area: Area, separators: &mut Vec<Area>, areas: &mut Vec<(Entity, Area)>) {
+        let (vertical, children) = match self {
+            Node::Window(window) => {
+                areas.push((*window, area));
+                return;
+            }
+            Node::Split { vertical, children } => (*vertical, children),
+        };
+
+        // windows next to each other have a column in between them
+        let count = children.len();
+        let total = match vertical {
+            true => area.columns.saturating_sub(count - 1),
+            false => area.rows,
+        };
+
+        let weights = children.iter().map(|(_, size)| *size).sum::<usize>().max(1);
+        let mut start = 0;
+        let mut cells = Vec::with_capacity(count);
+
+        for (index, (_, size)) in children.iter().enumerate() {
+            // the last child takes up whatever rounding left over
+            cells.push(match index + 1 == count {
+                true => total.saturating_sub(start),
+                false => (total * *size / weights).min(total.saturating_sub(start)),
+            });
+            start += cells[index];
+        }
+
+        let least: Vec<usize> = children
+            .iter()
+            .map(|(child, _)| child.least(vertical))
+            .collect();
+        keep_least(&mut cells, &least);
+        start = 0;
+
+        for (index, (child, size)) in children.iter_mut().enumerate() {
+            let cells = cells[index];
+
+            let placed = match vertical {
+                true => Area {
+                    x: area.x + start + index,
+                    columns: cells,
+                    ..area
+                },
+                false => Area {
+                    y: area.y + start,
+                    rows: cells,
+                    ..area
+                },
+            };
+
+            if vertical && index + 1 < count {
+                separators.push(Area {
+                    x: placed.x + cells,
+                    columns: 1,
+                    ..area
+                });
+            }
+
+            child.place(placed, separators, areas);
+
+            // a child squeezed down to nothing still gets some of the space once there's more
+            *size = cells.max(1);
+            start += cells;
+        }
+    }
+}
+
+/// Grows the children with fewer `cells` than their `least` by taking cells from the children
+/// with the most to spare. A screen too small for every child's least amount is left alone, the
+/// windows are squeezed down instead.
+fn keep_least(cells: &mut [usize], least: &[usize]) {
+    if cells.iter().sum::<usize>() < least.iter().sum() {
+        return;
+    }
+
+    for index in 0..cells.len() {
+        while cells[index] < least[index] {
+            let Some(spare) = (0..cells.len())
+                .filter(|it| cells[*it] > least[*it])
+                .max_by_key(|it| cells[*it] - least[*it])
+            else {
+                return;
+            };
+
+            cells[spare] -= 1;
+            cells[index] += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: Entity = Entity::from_raw(0);
+    const B: Entity = Entity::from_raw(1);
+    const C: Entity = Entity::from_raw(2);
+
+    fn area(x: usize, y: usize, columns: usize, rows: usize) -> Area {
+        Area {
+            x,
+            y,
+            columns,
+            rows,
+        }
+    }
+
+    fn laid_out(node: &mut Node, columns: usize, rows: usize) -> (Vec<(Entity, Area)>, Vec<Area>) {
+        let mut separators = Vec::new();
+        let areas = node.layout(area(0, 0, columns, rows), &mut separators);
+
+        (areas, separators)
+    }
+
+    #[test]
+    fn a_single_window_takes_up_everything() {
+        let mut node = Node::Window(A);
+
+        assert_eq!(
+            laid_out(&mut node, 80, 24),
+            (vec![(A, area(0, 0, 80, 24))], vec![])
+        );
+    }
+
+    #[test]
+    fn split_windows_share_the_space_and_get_a_status_line() {
+        let mut node = Node::Window(A);
+        node.split(A, B, false);
+
+        assert_eq!(node.windows(), [B, A]);
+        assert_eq!(
+            laid_out(&mut node, 80, 24).0,
+            [(B, area(0, 0, 80, 11)), (A, area(0, 12, 80, 11))]
+        );
+    }
+
+    #[test]
+    fn windows_next_to_each_other_have_a_separator_in_between() {
+        let mut node = Node::Window(A);
+        node.split(A, B, true);
+
+        let (areas, separators) = laid_out(&mut node, 80, 24);
+
+        assert_eq!(areas, [(B, area(0, 0, 39, 23)), (A, area(40, 0, 40, 23))]);
+        assert_eq!(separators, [area(39, 0, 1, 24)]);
+    }
+
+    #[test]
+    fn splitting_the_other_way_nests_a_split() {
+        let mut node = Node::Window(A);
+        node.split(A, B, true);
+        node.split(A, C, false);
+
+        assert_eq!(node.windows(), [B, C, A]);
+        assert_eq!(
+            laid_out(&mut node, 81, 24).0,
+            [
+                (B, area(0, 0, 40, 23)),
+                (C, area(41, 0, 40, 11)),
+                (A, area(41, 12, 40, 11))
+            ]
+        );
+    }
+
+    #[test]
+    fn removing_a_window_gives_its_space_away() {
+        let mut node = Node::Window(A);
+        node.split(A, B, false);
+        node.split(A, C, false);
+        laid_out(&mut node, 80, 24);
+
+        // the window before it gets the space, not the one after it
+        node.remove(C);
+        assert_eq!(
+            laid_out(&mut node, 80, 24).0,
+            [(B, area(0, 0, 80, 15)), (A, area(0, 16, 80, 7))]
+        );
+
+        node.remove(B);
+        assert!(matches!(node, Node::Window(A)));
+    }
+
+    #[test]
+    fn resizing_takes_space_from_the_neighbour() {
+        let mut node = Node::Window(A);
+        node.split(A, B, false);
+        laid_out(&mut node, 80, 24);
+
+        node.resize(A, false, 20);
+        assert_eq!(
+            laid_out(&mut node, 80, 24).0,
+            [(B, area(0, 0, 80, 3)), (A, area(0, 4, 80, 19))]
+        );
+
+        // a window is never squeezed below a row of text and its status line
+        node.resize(A, false, 100);
+        assert_eq!(laid_out(&mut node, 80, 24).0[0], (B, area(0, 0, 80, 1)));
+
+        node.equalize();
+        assert_eq!(laid_out(&mut node, 80, 24).0[0], (B, area(0, 0, 80, 11)));
+    }
+
+    #[test]
+    fn resizing_finds_the_split_in_the_right_direction() {
+        let mut node = Node::Window(A);
+        node.split(A, B, true);
+        node.split(A, C, false);
+        laid_out(&mut node, 81, 24);
+
+        node.resize(A, true, 60);
+        assert_eq!(
+            laid_out(&mut node, 81, 24).0,
+            [
+                (B, area(0, 0, 20, 23)),
+                (C, area(21, 0, 60, 11)),
+                (A, area(21, 12, 60, 11))
+            ]
+        );
+    }
+
+    #[test]
+    fn shrinking_the_screen_keeps_windows_at_their_least_size() {
+        let mut node = Node::Window(A);
+        node.split(A, B, false);
+        laid_out(&mut node, 80, 24);
+        node.resize(A, false, 20);
+        laid_out(&mut node, 80, 24);
+
+        assert_eq!(
+            laid_out(&mut node, 80, 8).0,
+            [(B, area(0, 0, 80, 1)), (A, area(0, 2, 80, 5))]
+        );
+
+        // a split inside of a window's place needs room for every window in it
+        node.split(B, C, true);
+
+        assert_eq!(
+            laid_out(&mut node, 10, 8).0,
+            [
+                (C, area(0, 0, 4, 1)),
+                (B, area(5, 0, 5, 1)),
+                (A, area(0, 2, 10, 5))
+            ]
+        );
+    }
+
+    #[test]
+    fn a_tiny_screen_squeezes_windows_down() {
+        let mut node = Node::Window(A);
+        node.split(A, B, false);
+        node.split(A, C, false);
+
+        let (areas, _) = laid_out(&mut node, 10, 2);
+
+        assert_eq!(
+            areas.iter().map(|(_, it)| it.rows).collect::<Vec<_>>(),
+            [0, 0, 1]
+        );
+        assert!(area(2, 3, 4, 5).contains(5, 7));
+        assert!(!area(2, 3, 4, 5).contains(6, 7));
+    }
+}
@@ -0,0 +1,760 @@
+use bevy_ecs::{prelude::*, system::SystemParam};
+
+use crate::{
+    command::{self, CommandError, CommandRegistry, Invocation},
+    cursor::{
+        multi::Cursors,
+        update::{self, clamp_to_row},
+        CursorOffset, CursorPosition,
+    },
+    input::data::RowBuffer,
+    options::Options,
+    output::{self, wrap, OutputSize},
+    status::StatusBuffer,
+    SystemType,
+};
+
+use self::layout::{Area, Node};
+
+pub mod draw;
+pub mod layout;
+pub mod tab;
+
+pub fn init(world: &mut World, schedule: &mut Schedule) {
+    let window = world.spawn(View::default()).id();
+
+    world.insert_resource(Windows::new(window));
+    world.insert_resource(Events::<WindowEvent>::default());
+
+    let mut registry = world.resource_mut::<CommandRegistry>();
+    registry.register("split", 2, split);
+    registry.register("vsplit", 2, vsplit);
+    registry.register("close", 3, close);
+    registry.register("only", 2, only);
+    registry.register("resize", 3, resize);
+    registry.register("vertical", 4, vertical);
+    registry.register("wincmd", 4, wincmd);
+    tab::register(&mut registry);
+
+    schedule.add_systems(
+        handle_window
+            .after(SystemType::Input)
+            .after(command::execute)
+            .before(SystemType::Cursor),
+    );
+
+    // the windows are laid out again before the cursor moves, since the terminal might've been
+    // resized, and before drawing the very first frame.
+    schedule.add_systems(
+        update_layout
+            .before(update::handle_cursor_move)
+            .in_set(SystemType::Cursor),
+    );
+    schedule.add_systems(
+        update_layout
+            .before(output::draw_rows)
+            .in_set(SystemType::Output),
+    );
+    schedule.add_systems(
+        (draw::draw_windows, draw::draw_tabline)
+            .after(output::draw_rows)
+            .in_set(SystemType::Output),
+    );
+}
+
+/// The view a window has into the buffer, which is where its cursors are and how far it's
+/// scrolled. Everything that works with the cursor works with the view of the active window,
+/// through [ActiveView] in systems or [View::active] with the world at hand.
+#[derive(Component, Clone, Default)]
+pub struct View {
+    pub(crate) position: CursorPosition,
+    pub(crate) offset: CursorOffset,
+    pub(crate) cursors: Cursors,
+}
+
+impl View {
+    /// The view of the active window.
+    pub fn active(world: &World) -> &View {
+        let active = world.resource::<Windows>().active();
+        world
+            .get::<View>(active)
+            .expect("The active window has no view")
+    }
+
+    pub fn active_mut(world: &mut World) -> Mut<'_, View> {
+        let active = world.resource::<Windows>().active();
+        world
+            .get_mut::<View>(active)
+            .expect("The active window has no view")
+    }
+}
+
+/// The view of the active window, for systems that work with the cursor.
+#[derive(SystemParam)]
+pub struct ActiveView<'w, 's> {
+    windows: Res<'w, Windows>,
+    views: Query<'w, 's, &'static mut View>,
+}
+
+impl ActiveView<'_, '_> {
+    pub fn get(&self) -> &View {
+        self.views
+            .get(self.windows.active())
+            .expect("The active window has no view")
+    }
+
+    pub fn get_mut(&mut self) -> Mut<'_, View> {
+        self.views
+            .get_mut(self.windows.active())
+            .expect("The active window has no view")
+    }
+}
+
+/// A tab page, which has a layout of windows of its own.
+struct Page {
+    layout: Node,
+    active: Entity,
+    /// The buffer every window of the tab page shows, an index into [Windows::buffers].
+    buffer: usize,
+}
+
+impl Page {
+    /// The window that becomes active once the active window is closed, which is the window
+    /// before it, or the one after it when it's the first. There's none for the last window.
+    fn successor(&self) -> Option<Entity> {
+        let all = self.layout.windows();
+        let index = all.iter().position(|it| *it == self.active).unwrap_or(0);
+
+        match index {
+            _ if all.len() == 1 => None,
+            0 => Some(all[1]),
+            _ => Some(all[index - 1]),
+        }
+    }
+}
+
+/// Every tab page, along with the one that's shown and the window the cursor is in.
+#[derive(Resource)]
+pub struct Windows {
+    pages: Vec<Page>,
+    /// The tab page that's shown on the screen.
+    page: usize,
+    /// Where every window of the shown tab page is shown, as of the last time they were laid
+    /// out.
+    areas: Vec<(Entity, Area)>,
+    separators: Vec<Area>,
+    tabline: bool,
+    /// The buffers shown by tab pages other than the shown one, the shown tab page's buffer is
+    /// the [RowBuffer] resource instead. A buffer is dropped once no tab page shows it.
+    buffers: Vec<Option<RowBuffer>>,
+}
+
+impl Windows {
+    fn new(window: Entity) -> Self {
+        Self {
+            pages: vec![Page {
+                layout: Node::Window(window),
+                active: window,
+                buffer: 0,
+            }],
+            page: 0,
+            areas: Vec::new(),
+            separators: Vec::new(),
+            tabline: false,
+            buffers: vec![None],
+        }
+    }
+
+    fn current(&self) -> &Page {
+        &self.pages[self.page]
+    }
+
+    fn current_mut(&mut self) -> &mut Page {
+        &mut self.pages[self.page]
+    }
+
+    pub fn active(&self) -> Entity {
+        self.current().active
+    }
+
+    /// Where the active window is shown.
+    pub fn area(&self) -> Area {
+        self.area_of(self.active())
+    }
+
+    pub fn area_of(&self, window: Entity) -> Area {
+        self.areas
+            .iter()
+            .find(|(it, _)| *it == window)
+            .map(|(_, area)| *area)
+            .unwrap_or_default()
+    }
+
+    pub fn areas(&self) -> &[(Entity, Area)] {
+        &self.areas
+    }
+
+    pub fn separators(&self) -> &[Area] {
+        &self.separators
+    }
+
+    /// Whether the shown tab page is split, in which case every window has a status line.
+    pub fn is_split(&self) -> bool {
+        !matches!(self.current().layout, Node::Window(_))
+    }
+
+    /// The amount of windows in every tab page together.
+    pub fn count(&self) -> usize {
+        self.pages.iter().map(|it| it.layout.windows().len()).sum()
+    }
+
+    /// The amount of windows in every tab page, from the first to the last.
+    pub fn tabs(&self) -> Vec<usize> {
+        self.pages
+            .iter()
+            .map(|it| it.layout.windows().len())
+            .collect()
+    }
+
+    /// The buffer the tab page at `index` shows, where `shown` is the [RowBuffer] resource.
+    pub fn buffer<'a>(&'a self, index: usize, shown: &'a RowBuffer) -> &'a RowBuffer {
+        let buffer = self.pages[index].buffer;
+
+        match buffer == self.current().buffer {
+            true => shown,
+            false => self.buffers[buffer]
+                .as_ref()
+                .expect("A tab page shows a dropped buffer"),
+        }
+    }
+
+    /// The index of the tab page that's shown.
+    pub fn tab(&self) -> usize {
+        self.page
+    }
+
+    /// Whether the tab line is shown at the top of the screen, as of the last layout.
+    pub fn has_tabline(&self) -> bool {
+        self.tabline
+    }
+
+    /// Lays the windows of the shown tab page out on the screen, below the tab line and above
+    /// the status bar.
+    fn layout(&mut self, size: &OutputSize, options: &Options) {
+        self.tabline = match options.showtabline {
+            0 => false,
+            1 => self.pages.len() > 1,
+            _ => true,
+        };
+
+        let top = usize::from(self.tabline);
+        let area = Area {
+            x: 0,
+            y: top,
+            columns: size.columns,
+            rows: size.text_rows().saturating_sub(top),
+        };
+
+        self.separators.clear();
+        self.areas = self.pages[self.page]
+            .layout
+            .layout(area, &mut self.separators);
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Down,
+    Up,
+    Right,
+}
+
+/// The `Ctrl-W` commands in normal mode, along with the commands going to another tab page.
+#[derive(Event, Clone, Copy, PartialEq, Eq)]
+pub enum WindowEvent {
+    /// `Ctrl-W s` and `Ctrl-W v`
+    Split { vertical: bool },
+    /// `Ctrl-W c` and `Ctrl-W q`
+    Close,
+    /// `Ctrl-W o`
+    Only,
+    /// `Ctrl-W w` and `Ctrl-W W` go to the next and previous window.
+    Cycle { forward: bool },
+    /// `Ctrl-W h`, `Ctrl-W j`, `Ctrl-W k` and `Ctrl-W l`
+    Focus(Direction),
+    /// `Ctrl-W +` and `Ctrl-W -` change the height, `Ctrl-W <` and `Ctrl-W >` the width.
+    Resize { vertical: bool, delta: isize },
+    /// `Ctrl-W _` and `Ctrl-W |` set the height or width, as large as it gets without a size.
+    SetSize { vertical: bool, size: Option<usize> },
+    /// `Ctrl-W =`
+    Equalize,
+    /// `gt` goes to the next tab page, or to tab page `count` when it's given.
+    NextTab { count: usize },
+    /// `gT` goes `count` tab pages back.
+    PreviousTab { count: usize },
+    /// `Ctrl-W T` moves the active window to a tab page of its own.
+    MoveToTab,
+}
+
+impl WindowEvent {
+    /// The command of the key typed after `Ctrl-W`, `count` is the count typed in front of it.
+    pub fn from_key(key: char, count: usize) -> Option<Self> {
+        let delta = count.max(1) as isize;
+
+        Some(match key {
+            's' | 'S' => WindowEvent::Split { vertical: false },
+            'v' => WindowEvent::Split { vertical: true },
+            'c' | 'q' => WindowEvent::Close,
+            'o' => WindowEvent::Only,
+            'w' => WindowEvent::Cycle { forward: true },
+            'W' => WindowEvent::Cycle { forward: false },
+            'h' => WindowEvent::Focus(Direction::Left),
+            'j' => WindowEvent::Focus(Direction::Down),
+            'k' => WindowEvent::Focus(Direction::Up),
+            'l' => WindowEvent::Focus(Direction::Right),
+            '+' => WindowEvent::Resize {
+                vertical: false,
+                delta,
+            },
+            '-' => WindowEvent::Resize {
+                vertical: false,
+                delta: -delta,
+            },
+            '>' => WindowEvent::Resize {
+                vertical: true,
+                delta,
+            },
+            '<' => WindowEvent::Resize {
+                vertical: true,
+                delta: -delta,
+            },
+            '_' | '|' => WindowEvent::SetSize {
+                vertical: key == '|',
+                size: (count > 0).then_some(count),
+            },
+            '=' => WindowEvent::Equalize,
+            'T' => WindowEvent::MoveToTab,
+            _ => return None,
+        })
+    }
+}
+
+pub fn update_layout(mut windows: ResMut<Windows>, size: Res<OutputSize>, options: Res<Options>) {
+    windows.layout(&size, &options);
+}
+
+/// Runs every `Ctrl-W` command sent since the last run, errors are shown in the status bar.
+pub fn handle_window(world: &mut World) {
+    let events: Vec<WindowEvent> = world
+        .resource_mut::<Events<WindowEvent>>()
+        .drain()
+        .collect();
+
+    for event in events {
+        if let Err(error) = apply(world, event) {
+            world
+                .resource_mut::<StatusBuffer>()
+                .error(error.to_string());
+        }
+    }
+}
+
+fn apply(world: &mut World, event: WindowEvent) -> Result<(), CommandError> {
+    match event {
+        WindowEvent::Split { vertical } => split_window(world, vertical),
+        WindowEvent::Close => return close_window(world, false),
+        WindowEvent::Only => only_window(world),
+        WindowEvent::Cycle { forward } => {
+            let windows = world.resource::<Windows>();
+            let all = windows.current().layout.windows();
+            let index = all
+                .iter()
+                .position(|it| *it == windows.active())
+                .unwrap_or(0);
+
+            let next = match forward {
+                true => (index + 1) % all.len(),
+                false => (index + all.len() - 1) % all.len(),
+            };
+
+            focus(world, all[next]);
+        }
+        WindowEvent::Focus(direction) => {
+            if let Some(window) = neighbour(world, direction) {
+                focus(world, window);
+            }
+        }
+        WindowEvent::Resize { vertical, delta } => {
+            let current = current_size(world, vertical);
+            set_size(world, vertical, current.saturating_add_signed(delta));
+        }
+        WindowEvent::SetSize { vertical, size } => {
+            set_size(world, vertical, size.unwrap_or(usize::MAX))
+        }
+        WindowEvent::Equalize => world
+            .resource_mut::<Windows>()
+            .current_mut()
+            .layout
+            .equalize(),
+        WindowEvent::NextTab { count } => {
+            let windows = world.resource::<Windows>();
+            let pages = windows.pages.len();
+
+            let next = match count {
+                0 => (windows.page + 1) % pages,
+                count => (count - 1).min(pages - 1),
+            };
+
+            tab::switch(world, next);
+        }
+        WindowEvent::PreviousTab { count } => {
+            let windows = world.resource::<Windows>();
+            let pages = windows.pages.len();
+
+            tab::switch(world, (windows.page + pages - count.max(1) % pages) % pages);
+        }
+        WindowEvent::MoveToTab => tab::move_window(world),
+    }
+
+    Ok(())
+}
+
+/// Makes `window` the active one, the cursor moves to where it was left in it.
+fn focus(world: &mut World, window: Entity) {
+    world.resource_mut::<Windows>().current_mut().active = window;
+    clamp_view(world);
+}
+
+/// Keeps the cursor of the active window inside of the buffer, which might have been edited
+/// through another window in the meantime. The other cursors are kept inside of it by
+/// [clamp_cursors](crate::cursor::multi::clamp_cursors).
+fn clamp_view(world: &mut World) {
+    let mut position = View::active(world).position.clone();
+    clamp_to_row(&mut position, world.resource::<RowBuffer>());
+
+    if position != View::active(world).position {
+        View::active_mut(world).position = position;
+    }
+}
+
+/// Splits the active window in two, the new window shows the same view and becomes the active
+/// one.
+fn split_window(world: &mut World, vertical: bool) {
+    let active = world.resource::<Windows>().active();
+    let new = world.spawn(View::active(world).clone()).id();
+
+    let mut windows = world.resource_mut::<Windows>();
+    let page = windows.current_mut();
+    page.layout.split(active, new, vertical);
+    page.active = new;
+}
+
+/// Closes the active window, the window that gets its space becomes the active one. Closing the
+/// last window of a tab page closes the tab page, see [tab::close] for `force`.
+pub fn close_window(world: &mut World, force: bool) -> Result<(), CommandError> {
+    let windows = world.resource::<Windows>();
+    let active = windows.active();
+
+    let Some(next) = windows.current().successor() else {
+        return match windows.pages.len() {
+            1 => Err(CommandError::LastWindow),
+            _ => tab::close(world, force),
+        };
+    };
+
+    world
+        .resource_mut::<Windows>()
+        .current_mut()
+        .layout
+        .remove(active);
+    focus(world, next);
+    world.despawn(active);
+
+    Ok(())
+}
+
+/// Closes every window but the active one.
+fn only_window(world: &mut World) {
+    let mut windows = world.resource_mut::<Windows>();
+    let page = windows.current_mut();
+    let active = page.active;
+    let others: Vec<Entity> = page
+        .layout
+        .windows()
+        .into_iter()
+        .filter(|it| *it != active)
+        .collect();
+
+    page.layout = Node::Window(active);
+
+    for window in others {
+        world.despawn(window);
+    }
+}
+
+/// The height of the active window's text, or its width when `vertical`.
+fn current_size(world: &World, vertical: bool) -> usize {
+    let area = world.resource::<Windows>().area();
+
+    match vertical {
+        true => area.columns,
+        false => area.rows,
+    }
+}
+
+/// Sets the height of the active window's text to `size` rows, or its width to `size` columns
+/// when `vertical`, as far as there's room for it.
+fn set_size(world: &mut World, vertical: bool, size: usize) {
+    let mut windows = world.resource_mut::<Windows>();
+    let active = windows.active();
+
+    // the size of a window in the layout includes its status line
+    let cells = match vertical {
+        true => size,
+        false => size.saturating_add(1),
+    };
+
+    windows.current_mut().layout.resize(active, vertical, cells);
+}
+
+/// The window next to the active one in `direction`. Out of several windows, the one next to the
+/// cursor is picked.
+fn neighbour(world: &World, direction: Direction) -> Option<Entity> {
+    let windows = world.resource::<Windows>();
+    let active = windows.area();
+
+    let view = View::active(world);
+
+    let (x, y) = wrap::screen_position(
+        world.resource::<RowBuffer>(),
+        (view.position.x, view.position.y),
+        &view.offset,
+        &active,
+        world.resource::<Options>(),
+    );
+
+    // the rows or columns a window spans across the direction, the status line below a window
+    // is part of it too
+    let across = |area: &Area| match direction {
+        Direction::Left | Direction::Right => (area.y..area.y + area.rows + 1, y),
+        Direction::Up | Direction::Down => (area.x..area.x + area.columns, x),
+    };
+
+    let adjacent = |area: &Area| match direction {
+        Direction::Left => area.x + area.columns + 1 == active.x,
+        Direction::Right => active.x + active.columns + 1 == area.x,
+        Direction::Up => area.y + area.rows + 1 == active.y,
+        Direction::Down => active.y + active.rows + 1 == area.y,
+    };
+
+    let (own, _) = across(&active);
+    let candidates: Vec<(Entity, Area)> = windows
+        .areas
+        .iter()
+        .filter(|(window, area)| {
+            let (range, _) = across(area);
+
+            *window != windows.active()
+                && adjacent(area)
+                && range.start < own.end
+                && own.start < range.end
+        })
+        .copied()
+        .collect();
+
+    candidates
+        .iter()
+        .find(|(_, area)| {
+            let (range, at) = across(area);
+            range.contains(&at)
+        })
+        .or(candidates.first())
+        .map(|(window, _)| *window)
+}
+
+/// `:split`
+fn split(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    no_arguments(invocation)?;
+    split_window(world, false);
+
+    Ok(())
+}
+
+/// `:vsplit`
+fn vsplit(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    no_arguments(invocation)?;
+    split_window(world, true);
+
+    Ok(())
+}
+
+/// `:close`
+fn close(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    no_arguments(invocation)?;
+    close_window(world, invocation.bang)
+}
+
+/// `:only`
+fn only(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    no_arguments(invocation)?;
+    only_window(world);
+
+    Ok(())
+}
+
+/// `:resize {size}` sets the height of the window, `:resize +{n}` and `:resize -{n}` change it by
+/// `n` rows. Without a size the window gets as high as it can.
+fn resize(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    resize_window(world, invocation, false)
+}
+
+fn resize_window(
+    world: &mut World,
+    invocation: &Invocation,
+    vertical: bool,
+) -> Result<(), CommandError> {
+    let size = match invocation.arguments().as_slice() {
+        [] => usize::MAX,
+        [size] => {
+            let invalid = || CommandError::InvalidArgument(size.clone());
+            let current = current_size(world, vertical);
+
+            match size.chars().next() {
+                Some('+') => current.saturating_add(size[1..].parse().map_err(|_| invalid())?),
+                Some('-') => current.saturating_sub(size[1..].parse().map_err(|_| invalid())?),
+                _ => size.parse().map_err(|_| invalid())?,
+            }
+        }
+        [_, rest @ ..] => return Err(CommandError::TrailingCharacters(rest.join(" "))),
+    };
+
+    set_size(world, vertical, size);
+    Ok(())
+}
+
+/// `:vertical resize` sets the width of the window instead of its height, and `:vertical split`
+/// splits it like `:vsplit`.
+fn vertical(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let args = invocation.args.trim();
+    let (name, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+
+    let nested = Invocation {
+        args: rest.to_string(),
+        ..invocation.clone()
+    };
+
+    match name {
+        _ if !name.is_empty() && "resize".starts_with(name) && name.len() >= 3 => {
+            resize_window(world, &nested, true)
+        }
+        _ if !name.is_empty() && "split".starts_with(name) && name.len() >= 2 => {
+            no_arguments(&nested)?;
+            split_window(world, true);
+            Ok(())
+        }
+        _ => Err(CommandError::InvalidArgument(args.to_string())),
+    }
+}
+
+/// `:wincmd {key}` runs the `Ctrl-W` command of `key`, like `:wincmd j`.
+fn wincmd(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let event = match invocation
+        .args
+        .trim()
+        .chars()
+        .collect::<Vec<char>>()
+        .as_slice()
+    {
+        [key] => WindowEvent::from_key(*key, 0),
+        _ => None,
+    };
+
+    let event = event.ok_or_else(|| CommandError::InvalidArgument(invocation.args.clone()))?;
+    apply(world, event)
+}
+
+fn no_arguments(invocation: &Invocation) -> Result<(), CommandError> {
+    match invocation.arguments().as_slice() {
+        [] => Ok(()),
+        rest => Err(CommandError::TrailingCharacters(rest.join(" "))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::scroll;
+
+    fn world(rows: usize, columns: usize) -> World {
+        let mut world = World::new();
+        let mut buffer = RowBuffer::new();
+
+        for y in 0..40 {
+            buffer.insert_row(y, &format!("{y:>30}"));
+        }
+
+        world.insert_resource(buffer);
+        world.insert_resource(CommandRegistry::default());
+        world.insert_resource(Options {
+            wrap: false,
+            ..Options::default()
+        });
+        world.insert_resource(OutputSize { columns, rows });
+        init(&mut world, &mut Schedule::default());
+
+        world
+    }
+
+    /// Lays out the windows at `rows` and `columns` and scrolls the active window to the cursor,
+    /// the way the next frame does after the terminal is resized.
+    fn resize_to(world: &mut World, rows: usize, columns: usize) {
+        *world.resource_mut::<OutputSize>() = OutputSize { columns, rows };
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems((update_layout, scroll::scroll).chain());
+        schedule.run(world);
+    }
+
+    #[test]
+    fn shrinking_the_terminal_keeps_the_cursor_inside_of_the_window() {
+        let mut world = world(25, 40);
+        command::run(&mut world, "split").unwrap();
+        resize_to(&mut world, 25, 40);
+
+        View::active_mut(&mut world).position = CursorPosition { x: 29, y: 10 };
+        resize_to(&mut world, 25, 40);
+        assert_eq!(View::active(&world).offset.row, 0);
+        assert_eq!(View::active(&world).offset.column, 0);
+
+        resize_to(&mut world, 9, 20);
+        let area = world.resource::<Windows>().area();
+        let View {
+            position, offset, ..
+        } = View::active(&world);
+
+        assert_eq!(area.rows, 3);
+        assert_eq!(area.columns, 20);
+        assert!((offset.row..offset.row + area.rows).contains(&position.y));
+        assert!((offset.column..offset.column + area.columns).contains(&29));
+
+        // growing it again leaves the view where it was, it still shows the cursor
+        resize_to(&mut world, 25, 40);
+        assert_eq!(View::active(&world).offset.row, 8);
+        assert_eq!(View::active(&world).offset.column, 10);
+    }
+
+    #[test]
+    fn shrinking_the_terminal_keeps_every_window_at_its_least_size() {
+        let mut world = world(25, 40);
+        command::run(&mut world, "split").unwrap();
+        command::run(&mut world, "resize 20").unwrap();
+        resize_to(&mut world, 25, 40);
+
+        resize_to(&mut world, 7, 40);
+        let windows = world.resource::<Windows>();
+        let rows: Vec<usize> = windows.areas.iter().map(|(_, it)| it.rows).collect();
+
+        assert_eq!(rows, [3, 1]);
+    }
+}
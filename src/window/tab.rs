@@ -0,0 +1,505 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use bevy_ecs::prelude::*;
+
+use crate::{
+    command::{CommandError, CommandRegistry, Invocation},
+    filetype,
+    input::data::RowBuffer,
+};
+
+use super::{clamp_view, layout::Node, no_arguments, Page, View, Windows};
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register("tabnew", 6, tab_new);
+    registry.register("tabedit", 4, tab_new);
+    registry.register("tabclose", 4, tab_close);
+    registry.register("tabonly", 4, tab_only);
+    registry.register("tabnext", 4, tab_next);
+    registry.register("tabprevious", 4, tab_previous);
+    registry.register("tabNext", 4, tab_previous);
+    registry.register("tabfirst", 6, tab_first);
+    registry.register("tabrewind", 4, tab_first);
+    registry.register("tablast", 4, tab_last);
+    registry.register("tabmove", 4, tab_move);
+}
+
+/// Shows the tab page at `index`, the cursor moves to where it was left in its active window.
+pub fn switch(world: &mut World, index: usize) {
+    let windows = world.resource::<Windows>();
+
+    if index == windows.page {
+        return;
+    }
+
+    let from = windows.current().buffer;
+    world.resource_mut::<Windows>().page = index;
+
+    show_buffer(world, from);
+    clamp_view(world);
+}
+
+/// Puts the buffer of the shown tab page in place of buffer `from`, which was shown before. `from`
+/// is kept for as long as a tab page shows it and dropped otherwise.
+fn show_buffer(world: &mut World, from: usize) {
+    let mut windows = world.resource_mut::<Windows>();
+    let to = windows.current().buffer;
+
+    if to == from {
+        return;
+    }
+
+    let shown = windows.buffers[to]
+        .take()
+        .expect("A tab page shows a dropped buffer");
+    let kept = windows.pages.iter().any(|it| it.buffer == from);
+    let hidden = std::mem::replace(&mut *world.resource_mut::<RowBuffer>(), shown);
+
+    if kept {
+        world.resource_mut::<Windows>().buffers[from] = Some(hidden);
+    }
+
+    // the options of a filetype belong to the buffer it was detected for
+    filetype::detect_buffer(world);
+}
+
+/// Opens a tab page after the shown one, with a single window showing the same view as the
+/// active window.
+fn open(world: &mut World) {
+    let window = world.spawn(View::active(world).clone()).id();
+    let buffer = world.resource::<Windows>().current().buffer;
+
+    insert(world, window, buffer);
+}
+
+/// Opens a tab page after the shown one, with a single window showing the file at `path`. A file
+/// some tab page already shows isn't read again, its buffer is shown instead.
+fn open_file(world: &mut World, path: &Path) -> Result<(), CommandError> {
+    let windows = world.resource::<Windows>();
+    let from = windows.current().buffer;
+    let same = |rows: &RowBuffer| rows.get_path() == Some(path);
+
+    let existing = match same(world.resource::<RowBuffer>()) {
+        true => Some(from),
+        false => windows
+            .buffers
+            .iter()
+            .position(|it| it.as_ref().is_some_and(same)),
+    };
+
+    let buffer = match existing {
+        Some(buffer) => buffer,
+        None => {
+            let rows = match RowBuffer::try_from(path) {
+                Ok(rows) => rows,
+                Err(error) if error.kind() == ErrorKind::NotFound => RowBuffer::new_file(path),
+                Err(error) => return Err(CommandError::Io(error)),
+            };
+
+            let mut windows = world.resource_mut::<Windows>();
+            windows.buffers.push(Some(rows));
+            windows.buffers.len() - 1
+        }
+    };
+
+    let window = world.spawn(View::default()).id();
+    insert(world, window, buffer);
+    show_buffer(world, from);
+
+    Ok(())
+}
+
+/// Adds a tab page with only `window` in it, showing `buffer`, after the shown one and shows it.
+/// The buffer isn't put in place, see [show_buffer].
+fn insert(world: &mut World, window: Entity, buffer: usize) {
+    let mut windows = world.resource_mut::<Windows>();
+    let index = windows.page + 1;
+
+    windows.pages.insert(
+        index,
+        Page {
+            layout: Node::Window(window),
+            active: window,
+            buffer,
+        },
+    );
+    windows.page = index;
+}
+
+/// Closes the shown tab page along with all of its windows, the tab page after it is shown
+/// instead, or the one before it when it was the last. A modified buffer no other tab page shows
+/// would be lost, so the tab page is only closed with `force`.
+pub fn close(world: &mut World, force: bool) -> Result<(), CommandError> {
+    let windows = world.resource::<Windows>();
+
+    if windows.pages.len() == 1 {
+        return Err(CommandError::LastTab);
+    }
+
+    let index = windows.page;
+    let buffer = windows.current().buffer;
+    let shared =
+        (windows.pages.iter().enumerate()).any(|(it, page)| it != index && page.buffer == buffer);
+
+    if !force && !shared && world.resource::<RowBuffer>().is_modified() {
+        return Err(CommandError::Unsaved);
+    }
+
+    let mut windows = world.resource_mut::<Windows>();
+    let closed = windows.pages.remove(index);
+    windows.page = index.min(windows.pages.len() - 1);
+
+    for window in closed.layout.windows() {
+        world.despawn(window);
+    }
+
+    show_buffer(world, buffer);
+    clamp_view(world);
+    Ok(())
+}
+
+/// Closes every tab page but the shown one, along with the buffers only they show. Those are
+/// only dropped with `force` when one of them is modified.
+fn only(world: &mut World, force: bool) -> Result<(), CommandError> {
+    let mut windows = world.resource_mut::<Windows>();
+
+    // the shown buffer isn't stored, every stored buffer belongs to another tab page
+    if !force && windows.buffers.iter().flatten().any(RowBuffer::is_modified) {
+        return Err(CommandError::Unsaved);
+    }
+
+    windows.buffers.iter_mut().for_each(|it| *it = None);
+
+    let index = windows.page;
+    let kept = windows.pages.remove(index);
+    let others = std::mem::replace(&mut windows.pages, vec![kept]);
+    windows.page = 0;
+
+    for window in others.iter().flat_map(|it| it.layout.windows()) {
+        world.despawn(window);
+    }
+
+    Ok(())
+}
+
+/// Moves the active window out of its tab page into a tab page of its own, which is opened after
+/// it. A window that's alone in its tab page stays where it is.
+pub fn move_window(world: &mut World) {
+    let windows = world.resource::<Windows>();
+    let active = windows.active();
+
+    let Some(next) = windows.current().successor() else {
+        return;
+    };
+
+    let mut windows = world.resource_mut::<Windows>();
+    let page = windows.current_mut();
+    page.layout.remove(active);
+    page.active = next;
+
+    let buffer = page.buffer;
+    insert(world, active, buffer);
+}
+
+/// `:tabnew` and `:tabedit` open a tab page showing the buffer, `:tabedit {file}` one showing
+/// `file`. A file that doesn't exist yet is created once its buffer is written.
+fn tab_new(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    match invocation.arguments().as_slice() {
+        [] => open(world),
+        [path] => open_file(world, &PathBuf::from(path))?,
+        [_, rest @ ..] => return Err(CommandError::TrailingCharacters(rest.join(" "))),
+    }
+
+    Ok(())
+}
+
+/// `:tabclose`, `:tabclose!` closes it even when its buffer is modified.
+fn tab_close(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    no_arguments(invocation)?;
+    close(world, invocation.bang)
+}
+
+/// `:tabonly`, `:tabonly!` closes the others even when their buffers are modified.
+fn tab_only(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    no_arguments(invocation)?;
+    only(world, invocation.bang)
+}
+
+/// `:tabnext` goes to the next tab page, `:tabnext {n}` to tab page `n`.
+fn tab_next(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let pages = world.resource::<Windows>().pages.len();
+
+    let index = match count(invocation)? {
+        None => (world.resource::<Windows>().page + 1) % pages,
+        Some(number) => number.clamp(1, pages) - 1,
+    };
+
+    switch(world, index);
+    Ok(())
+}
+
+/// `:tabprevious` and `:tabNext` go to the previous tab page, or `n` tab pages back with a
+/// count.
+fn tab_previous(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let windows = world.resource::<Windows>();
+    let pages = windows.pages.len();
+    let back = count(invocation)?.unwrap_or(1) % pages;
+
+    switch(world, (windows.page + pages - back) % pages);
+    Ok(())
+}
+
+/// `:tabfirst` and `:tabrewind`
+fn tab_first(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    no_arguments(invocation)?;
+    switch(world, 0);
+
+    Ok(())
+}
+
+/// `:tablast`
+fn tab_last(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    no_arguments(invocation)?;
+
+    let last = world.resource::<Windows>().pages.len() - 1;
+    switch(world, last);
+
+    Ok(())
+}
+
+/// `:tabmove {n}` moves the shown tab page after tab page `n`, where `0` makes it the first. With
+/// `+{n}` or `-{n}` it moves `n` places to the right or left, and without an argument it becomes
+/// the last.
+fn tab_move(world: &mut World, invocation: &Invocation) -> Result<(), CommandError> {
+    let mut windows = world.resource_mut::<Windows>();
+    let current = windows.page;
+    let last = windows.pages.len() - 1;
+
+    let index = match invocation.arguments().as_slice() {
+        [] => last,
+        [place] => {
+            let invalid = || CommandError::InvalidArgument(place.clone());
+
+            match place.chars().next() {
+                Some('+') => current.saturating_add(place[1..].parse().map_err(|_| invalid())?),
+                Some('-') => current.saturating_sub(place[1..].parse().map_err(|_| invalid())?),
+                // the tab pages after the shown one move a place to the left once it's taken
+                // out
+                _ => match place.parse::<usize>().map_err(|_| invalid())? {
+                    after if after > current => after - 1,
+                    after => after,
+                },
+            }
+        }
+        [_, rest @ ..] => return Err(CommandError::TrailingCharacters(rest.join(" "))),
+    };
+
+    let page = windows.pages.remove(current);
+    let index = index.min(last);
+    windows.pages.insert(index, page);
+    windows.page = index;
+
+    Ok(())
+}
+
+/// The count given to a tab page command as its argument.
+fn count(invocation: &Invocation) -> Result<Option<usize>, CommandError> {
+    match invocation.arguments().as_slice() {
+        [] => Ok(None),
+        [count] => count
+            .parse()
+            .map(Some)
+            .map_err(|_| CommandError::InvalidArgument(count.clone())),
+        [_, rest @ ..] => Err(CommandError::TrailingCharacters(rest.join(" "))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        command, filetype::FileType, options, options::Options, status::StatusBuffer, window,
+    };
+
+    fn world(rows: &[&str]) -> World {
+        let mut world = World::new();
+        let mut buffer = RowBuffer::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            buffer.insert_row(y, row);
+        }
+
+        world.insert_resource(buffer);
+        world.insert_resource(CommandRegistry::default());
+        world.insert_resource(StatusBuffer::default());
+        options::init(&mut world, &mut Schedule::default());
+        filetype::init(&mut world, &mut Schedule::default());
+        window::init(&mut world, &mut Schedule::default());
+
+        world
+    }
+
+    fn contents(world: &World) -> Vec<String> {
+        let rows = world.resource::<RowBuffer>();
+
+        (0..rows.rows_count())
+            .map(|y| rows.get_row_at(y).contents().to_string())
+            .collect()
+    }
+
+    /// A path in the temporary directory no other test uses, with the file removed.
+    fn temporary(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("heracross-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn opens_switches_and_closes_tab_pages() {
+        let mut world = world(&["one"]);
+
+        command::run(&mut world, "tabnew").unwrap();
+        command::run(&mut world, "tabnew").unwrap();
+        assert_eq!(world.resource::<Windows>().tabs(), [1, 1, 1]);
+        assert_eq!(world.resource::<Windows>().tab(), 2);
+
+        command::run(&mut world, "tabfirst").unwrap();
+        assert_eq!(world.resource::<Windows>().tab(), 0);
+        command::run(&mut world, "tabprevious").unwrap();
+        assert_eq!(world.resource::<Windows>().tab(), 2);
+        command::run(&mut world, "tabnext 2").unwrap();
+        assert_eq!(world.resource::<Windows>().tab(), 1);
+
+        command::run(&mut world, "tabclose").unwrap();
+        assert_eq!(world.resource::<Windows>().tabs(), [1, 1]);
+        assert_eq!(world.resource::<Windows>().tab(), 1);
+
+        command::run(&mut world, "tabclose").unwrap();
+        assert!(matches!(
+            command::run(&mut world, "tabclose"),
+            Err(CommandError::LastTab)
+        ));
+        assert_eq!(contents(&world), ["one"]);
+    }
+
+    #[test]
+    fn moves_the_shown_tab_page() {
+        let mut world = world(&["one"]);
+        let first = world.resource::<Windows>().active();
+
+        command::run(&mut world, "tabnew").unwrap();
+        command::run(&mut world, "tabnew").unwrap();
+        let last = world.resource::<Windows>().active();
+
+        command::run(&mut world, "tabmove 0").unwrap();
+        assert_eq!(world.resource::<Windows>().tab(), 0);
+
+        command::run(&mut world, "tabmove +1").unwrap();
+        assert_eq!(world.resource::<Windows>().tab(), 1);
+
+        command::run(&mut world, "tabmove").unwrap();
+        assert_eq!(world.resource::<Windows>().tab(), 2);
+        assert_eq!(world.resource::<Windows>().active(), last);
+
+        command::run(&mut world, "tabfirst").unwrap();
+        assert_eq!(world.resource::<Windows>().active(), first);
+    }
+
+    #[test]
+    fn edits_a_file_in_a_tab_page_of_its_own() {
+        let path = temporary("tab.py");
+        std::fs::write(&path, "def main():\n    pass\n").unwrap();
+        let mut world = world(&["one"]);
+
+        command::run(&mut world, &format!("tabedit {path}")).unwrap();
+        assert_eq!(contents(&world), ["def main():", "    pass"]);
+        assert_eq!(world.resource::<FileType>().name(), "python");
+        assert_eq!(world.resource::<Options>().indentopen, "{([:");
+
+        let windows = world.resource::<Windows>();
+        let rows = world.resource::<RowBuffer>();
+        assert_eq!(windows.buffer(0, rows).get_buffer_name(), "Empty Buffer");
+        assert_eq!(windows.buffer(1, rows).get_path(), Some(Path::new(&path)));
+
+        command::run(&mut world, "tabprevious").unwrap();
+        assert_eq!(contents(&world), ["one"]);
+        assert_eq!(world.resource::<FileType>().name(), "text");
+        assert_eq!(world.resource::<Options>().indentopen, "{([");
+
+        command::run(&mut world, "tabnext").unwrap();
+        assert_eq!(contents(&world), ["def main():", "    pass"]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_file_shown_in_two_tab_pages_shares_its_buffer() {
+        let path = temporary("shared");
+        std::fs::write(&path, "one\n").unwrap();
+        let mut world = world(&["main"]);
+
+        command::run(&mut world, &format!("tabedit {path}")).unwrap();
+        command::run(&mut world, "tabfirst").unwrap();
+        command::run(&mut world, &format!("tabedit {path}")).unwrap();
+        world.resource_mut::<RowBuffer>().insert_row(1, "two");
+
+        command::run(&mut world, "tablast").unwrap();
+        assert_eq!(contents(&world), ["one", "two"]);
+
+        // another tab page still shows the modified buffer, so nothing is lost
+        command::run(&mut world, "tabclose").unwrap();
+        assert_eq!(world.resource::<Windows>().tabs(), [1, 1]);
+        assert_eq!(contents(&world), ["one", "two"]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn edits_a_file_that_does_not_exist_yet() {
+        let path = temporary("new");
+        let mut world = world(&["one"]);
+
+        command::run(&mut world, &format!("tabedit {path}")).unwrap();
+        assert_eq!(contents(&world), [] as [String; 0]);
+        assert!(!world.resource::<RowBuffer>().is_modified());
+
+        let rows = world.resource::<RowBuffer>();
+        assert_eq!(rows.get_path(), Some(Path::new(&path)));
+        assert!(!Path::new(&path).exists());
+    }
+
+    #[test]
+    fn keeps_tab_pages_with_the_only_copy_of_a_modified_buffer_open() {
+        let path = temporary("modified");
+        let mut world = world(&["one"]);
+
+        command::run(&mut world, &format!("tabedit {path}")).unwrap();
+        world.resource_mut::<RowBuffer>().insert_row(0, "changed");
+
+        assert!(matches!(
+            command::run(&mut world, "tabclose"),
+            Err(CommandError::Unsaved)
+        ));
+        command::run(&mut world, "tabfirst").unwrap();
+        assert!(matches!(
+            command::run(&mut world, "tabonly"),
+            Err(CommandError::Unsaved)
+        ));
+        assert_eq!(world.resource::<Windows>().tabs(), [1, 1]);
+
+        command::run(&mut world, "tabonly!").unwrap();
+        assert_eq!(world.resource::<Windows>().tabs(), [1]);
+        assert_eq!(contents(&world), ["one"]);
+
+        command::run(&mut world, &format!("tabedit {path}")).unwrap();
+        assert_eq!(contents(&world), [] as [String; 0]);
+        world.resource_mut::<RowBuffer>().insert_row(0, "changed");
+
+        command::run(&mut world, "tabclose!").unwrap();
+        assert_eq!(contents(&world), ["one"]);
+    }
+}